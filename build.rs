@@ -1,7 +1,26 @@
 use anyhow::*;
 use glob::glob;
-use std::fs::{read_to_string, write};
-use std::path::PathBuf;
+use std::cell::RefCell;
+use std::collections::{hash_map::DefaultHasher, HashMap};
+use std::env;
+use std::fs::{create_dir_all, read, read_dir, read_to_string, remove_file, write};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Where `#include <...>` looks for files; `#include "..."` looks next to
+/// the including file first
+const SHADER_DIR: &str = "./src/shaders";
+
+/// What happens to a shader's source
+enum ShaderTarget {
+    /// GLSL, compiled into SPIR-V
+    Spirv {
+        /// Type of shader
+        kind: shaderc::ShaderKind,
+    },
+    /// WGSL, which wgpu loads as is, so it only gets validated
+    Wgsl,
+}
 
 /// An individual shader
 struct ShaderData {
@@ -9,10 +28,10 @@ struct ShaderData {
     src: String,
     /// Path to the source code file
     src_path: PathBuf,
-    /// Path to the compiled SPIR-V file
-    spv_path: PathBuf,
-    /// Type of shader
-    kind: shaderc::ShaderKind,
+    target: ShaderTarget,
+    /// Defines listed on a `// variants:` line, every combination of which
+    /// gets compiled separately
+    variants: Vec<String>,
 }
 
 impl ShaderData {
@@ -24,30 +43,431 @@ impl ShaderData {
             .context("Extension cannot be converted to &str")?;
 
         let kind = match extension {
-            "vert" => shaderc::ShaderKind::Vertex,
-            "frag" => shaderc::ShaderKind::Fragment,
-            "comp" => shaderc::ShaderKind::Compute,
+            "vert" => Some(shaderc::ShaderKind::Vertex),
+            "frag" => Some(shaderc::ShaderKind::Fragment),
+            "comp" => Some(shaderc::ShaderKind::Compute),
+            "wgsl" => None,
             _ => bail!("Unsupported shader: {}", src_path.display()),
         };
 
+        let target = match kind {
+            Some(kind) => ShaderTarget::Spirv { kind },
+            None => ShaderTarget::Wgsl,
+        };
+
         let src = read_to_string(src_path.clone())?;
-        let spv_path = src_path.with_extension(format!("{}.spv", extension));
+        let variants = src
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("// variants:"))
+            .map(|defines| defines.split_whitespace().map(str::to_owned).collect())
+            .unwrap_or_default();
 
         Ok(Self {
             src,
             src_path,
-            spv_path,
-            kind,
+            target,
+            variants,
+        })
+    }
+}
+
+/// Name of the file `ShaderCache` keeps track of its outputs in
+const MANIFEST: &str = "manifest";
+
+/// SPIR-V compiled by earlier builds, in `OUT_DIR/shaders`. Every output is
+/// recorded along with a hash of everything that went into it (source,
+/// defines and included files), so that unchanged shaders don't go through
+/// shaderc again
+struct ShaderCache {
+    dir: PathBuf,
+    /// Output file name -> (hash, included files), as of the last build
+    previous: HashMap<String, (u64, Vec<PathBuf>)>,
+    /// Same, for this build
+    current: HashMap<String, (u64, Vec<PathBuf>)>,
+}
+
+impl ShaderCache {
+    fn load(dir: PathBuf) -> Result<Self> {
+        create_dir_all(&dir)?;
+
+        // One output per line: file name, hash and included files, tab-separated
+        let previous = read_to_string(dir.join(MANIFEST))
+            .map(|manifest| {
+                manifest
+                    .lines()
+                    .filter_map(|line| {
+                        let mut fields = line.split('\t');
+                        let output = fields.next()?.to_owned();
+                        let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+                        Some((output, (hash, fields.map(PathBuf::from).collect())))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            dir,
+            previous,
+            current: HashMap::new(),
         })
     }
+
+    fn path(&self, output: &str) -> PathBuf {
+        self.dir.join(output)
+    }
+
+    /// Whether the last build's output is still up to date, in which case it
+    /// gets kept
+    fn reuse(&mut self, output: &str, src: &str, defines: &[&str]) -> bool {
+        let (hash, includes) = match self.previous.get(output) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if !self.path(output).is_file() || content_hash(src, defines, includes) != Some(*hash) {
+            return false;
+        }
+
+        self.current
+            .insert(output.to_owned(), (*hash, includes.clone()));
+        true
+    }
+
+    /// Writes a freshly compiled output, returning its path
+    fn insert(
+        &mut self,
+        output: &str,
+        src: &str,
+        defines: &[&str],
+        includes: Vec<PathBuf>,
+        spirv: &[u8],
+    ) -> Result<PathBuf> {
+        let hash = content_hash(src, defines, &includes)
+            .context("An included file went away while compiling")?;
+        let path = self.path(output);
+        write(&path, spirv)?;
+
+        self.current.insert(output.to_owned(), (hash, includes));
+        Ok(path)
+    }
+
+    /// Deletes whatever this build didn't output (left behind by deleted
+    /// shaders or variants, or by shaders that failed to compile) and saves
+    /// the manifest
+    fn finish(self) -> Result<()> {
+        for entry in read_dir(&self.dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            if file_name != MANIFEST && !self.current.contains_key(file_name.as_ref()) {
+                remove_file(entry.path())?;
+            }
+        }
+
+        let manifest = self
+            .current
+            .iter()
+            .map(|(output, (hash, includes))| {
+                let mut line = format!("{}\t{:016x}", output, hash);
+                for include in includes {
+                    line.push('\t');
+                    line.push_str(include.to_str().unwrap());
+                }
+                line
+            })
+            .collect::<Vec<_>>();
+        write(self.dir.join(MANIFEST), manifest.join("\n"))?;
+
+        Ok(())
+    }
+}
+
+/// Hashes a shader's source along with its defines and the current contents
+/// of the files it includes. `None` if one of those can't be read anymore
+fn content_hash(src: &str, defines: &[&str], includes: &[PathBuf]) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    src.hash(&mut hasher);
+    defines.hash(&mut hasher);
+    for include in includes {
+        read_to_string(include).ok()?.hash(&mut hasher);
+    }
+
+    Some(hasher.finish())
+}
+
+/// Resolves `#include`s to the contents of the file asked for. Included files
+/// are plain `.glsl`, which doesn't get compiled on its own
+fn resolve_include(
+    requested: &str,
+    include_type: shaderc::IncludeType,
+    requesting: &str,
+) -> std::result::Result<shaderc::ResolvedInclude, String> {
+    let relative = Path::new(requesting)
+        .parent()
+        .unwrap_or_else(|| Path::new(SHADER_DIR))
+        .join(requested);
+    let path = match include_type {
+        shaderc::IncludeType::Relative if relative.is_file() => relative,
+        _ => Path::new(SHADER_DIR).join(requested),
+    };
+
+    let content = read_to_string(&path)
+        .map_err(|err| format!("Unable to include {}: {}", path.display(), err))?;
+
+    Ok(shaderc::ResolvedInclude {
+        resolved_name: path.to_str().unwrap().to_owned(),
+        content,
+    })
+}
+
+/// Compiles a GLSL shader with the given defines set, along with the files
+/// it included
+fn compile_glsl(
+    compiler: &mut shaderc::Compiler,
+    shader: &ShaderData,
+    kind: shaderc::ShaderKind,
+    defines: &[&str],
+) -> Result<(shaderc::CompilationArtifact, Vec<PathBuf>)> {
+    let includes = RefCell::new(Vec::new());
+
+    let mut options =
+        shaderc::CompileOptions::new().context("Unable to create shader compiler options")?;
+    options.set_include_callback(|requested, include_type, requesting, _depth| {
+        let resolved = resolve_include(requested, include_type, requesting)?;
+        includes
+            .borrow_mut()
+            .push(PathBuf::from(&resolved.resolved_name));
+        Ok(resolved)
+    });
+    for define in defines {
+        options.add_macro_definition(define, None);
+    }
+
+    let compiled = compiler.compile_into_spirv(
+        &shader.src,
+        kind,
+        shader.src_path.to_str().unwrap(),
+        "main",
+        Some(&options),
+    )?;
+    drop(options);
+
+    let mut includes = includes.into_inner();
+    includes.sort();
+    includes.dedup();
+
+    Ok((compiled, includes))
+}
+
+/// Renders shaderc's `file:line: severity: message` lines along with the
+/// offending line of source, with carets under the token the message quotes
+/// (or the whole line if it doesn't quote one). Lines that aren't diagnostics,
+/// like the error count, are kept as they are.
+fn render_diagnostics(messages: &str) -> Vec<String> {
+    let mut rendered = Vec::new();
+
+    for message in messages.lines().filter(|line| !line.trim().is_empty()) {
+        let mut parts = message.splitn(3, ':');
+        let (file, line, text) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(file), Some(line), Some(text)) => match line.trim().parse::<usize>() {
+                Ok(line) => (file, line, text.trim()),
+                Err(_) => {
+                    rendered.push(message.to_owned());
+                    continue;
+                }
+            },
+            _ => {
+                rendered.push(message.to_owned());
+                continue;
+            }
+        };
+
+        rendered.push(text.to_owned());
+        rendered.push(format!("  --> {}:{}", file, line));
+
+        // Included files are named by their path, same as the shader itself
+        let source_line = read_to_string(file)
+            .ok()
+            .and_then(|src| src.lines().nth(line.saturating_sub(1)).map(str::to_owned));
+        if let Some(source_line) = source_line {
+            let gutter = " ".repeat(line.to_string().len());
+            let token = text.split('\'').nth(1).filter(|token| !token.is_empty());
+            let (start, len) = match token.and_then(|token| source_line.find(token)) {
+                Some(start) => (start, token.unwrap().len()),
+                None => {
+                    let trimmed = source_line.trim_start();
+                    (source_line.len() - trimmed.len(), trimmed.trim_end().len())
+                }
+            };
+
+            rendered.push(format!("{} |", gutter));
+            rendered.push(format!("{} | {}", line, source_line));
+            rendered.push(format!(
+                "{} | {}{}",
+                gutter,
+                " ".repeat(start),
+                "^".repeat(len.max(1))
+            ));
+        }
+    }
+
+    rendered
+}
+
+/// Compiles a GLSL shader like `compile_glsl` into the cache, unless it's
+/// already there, and returns the path to its SPIR-V. Warnings are reported
+/// as Cargo warnings and errors on stderr; the latter return `None` rather
+/// than failing, so that the other shaders still get their diagnostics
+/// reported
+fn compile_and_report(
+    compiler: &mut shaderc::Compiler,
+    cache: &mut ShaderCache,
+    shader: &ShaderData,
+    kind: shaderc::ShaderKind,
+    defines: &[&str],
+) -> Result<Option<PathBuf>> {
+    let name = shader.src_path.file_name().unwrap().to_str().unwrap();
+    let output = if defines.is_empty() {
+        format!("{}.spv", name)
+    } else {
+        format!("{}+{}.spv", name, defines.join("+"))
+    };
+    if cache.reuse(&output, &shader.src, defines) {
+        return Ok(Some(cache.path(&output)));
+    }
+
+    let variant = if defines.is_empty() {
+        String::new()
+    } else {
+        format!(" (with {})", defines.join(", "))
+    };
+
+    match compile_glsl(compiler, shader, kind, defines) {
+        Ok((compiled, includes)) => {
+            if compiled.get_num_warnings() > 0 {
+                println!(
+                    "cargo:warning={}{}: {} warning(s)",
+                    shader.src_path.display(),
+                    variant,
+                    compiled.get_num_warnings()
+                );
+                for line in render_diagnostics(&compiled.get_warning_messages()) {
+                    println!("cargo:warning={}", line);
+                }
+            }
+
+            let path = cache.insert(
+                &output,
+                &shader.src,
+                defines,
+                includes,
+                compiled.as_binary_u8(),
+            )?;
+            Ok(Some(path))
+        }
+        Err(err) => match err.downcast::<shaderc::Error>()? {
+            shaderc::Error::CompilationError(count, messages) => {
+                eprintln!(
+                    "{}{}: {} error(s)",
+                    shader.src_path.display(),
+                    variant,
+                    count
+                );
+                for line in render_diagnostics(&messages) {
+                    eprintln!("{}", line);
+                }
+                eprintln!();
+
+                Ok(None)
+            }
+            err => Err(err.into()),
+        },
+    }
+}
+
+/// Catches WGSL errors at build time rather than when the scene gets created,
+/// handing back the parsed module
+fn validate_wgsl(shader: &ShaderData) -> Result<naga::Module> {
+    let path = shader.src_path.display();
+    let module = naga::front::wgsl::parse_str(&shader.src)
+        .map_err(|err| anyhow!("Unable to parse {}: {:?}", path, err))?;
+
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|err| anyhow!("Invalid shader {}: {:?}", path, err))?;
+
+    Ok(module)
+}
+
+/// Writes down the location, scalar kind and component count of a vertex
+/// shader input, as an entry of `shader::VERTEX_INPUTS`. Builtins don't come
+/// out of vertex buffers, so they are left out
+fn reflect_input(binding: &naga::Binding, ty: &naga::TypeInner) -> Option<String> {
+    let location = match *binding {
+        naga::Binding::Location { location, .. } => location,
+        naga::Binding::BuiltIn(_) => return None,
+    };
+    let (kind, components) = match *ty {
+        naga::TypeInner::Scalar { kind, .. } => (kind, 1),
+        naga::TypeInner::Vector { kind, size, .. } => (kind, size as u8),
+        _ => return None,
+    };
+    let kind = match kind {
+        naga::ScalarKind::Float => "Float",
+        naga::ScalarKind::Sint => "Sint",
+        naga::ScalarKind::Uint => "Uint",
+        naga::ScalarKind::Bool => return None,
+    };
+
+    Some(format!(
+        "({}, InputKind::{}, {})",
+        location, kind, components
+    ))
+}
+
+/// Adds the inputs of every vertex entry point of a shader to the table
+/// `shader::validate_vertex_buffers` checks vertex buffer layouts against
+fn reflect_vertex_inputs(name: &str, module: &naga::Module, table: &mut String) {
+    let vertex_entry_points = module
+        .entry_points
+        .iter()
+        .filter(|entry_point| entry_point.stage == naga::ShaderStage::Vertex);
+
+    for entry_point in vertex_entry_points {
+        let mut inputs = Vec::new();
+        for argument in entry_point.function.arguments.iter() {
+            match (&argument.binding, &module.types[argument.ty].inner) {
+                (Some(binding), ty) => inputs.extend(reflect_input(binding, ty)),
+                // WGSL can take its inputs as the members of a struct
+                (None, naga::TypeInner::Struct { members, .. }) => {
+                    for member in members {
+                        if let Some(binding) = &member.binding {
+                            inputs.extend(reflect_input(binding, &module.types[member.ty].inner));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        table.push_str(&format!(
+            "    ({:?}, {:?}, &[{}]),\n",
+            name,
+            entry_point.name,
+            inputs.join(", ")
+        ));
+    }
 }
 
 fn main() -> Result<()> {
     // Collect all shaders
     let mut shader_paths = [
-        glob("./src/shaders/**/*.vert")?,
-        glob("./src/shaders/**/*.frag")?,
-        glob("./src/shaders/**/*.comp")?,
+        glob(&format!("{}/**/*.vert", SHADER_DIR))?,
+        glob(&format!("{}/**/*.frag", SHADER_DIR))?,
+        glob(&format!("{}/**/*.comp", SHADER_DIR))?,
+        glob(&format!("{}/**/*.wgsl", SHADER_DIR))?,
     ];
 
     let shaders = shader_paths
@@ -58,23 +478,156 @@ fn main() -> Result<()> {
         .into_iter()
         .collect::<Result<Vec<_>>>()?;
 
+    // Also watch the shader directory itself, so that newly added shaders
+    // (e.g. a scene's first compute shader) get picked up without a clean build
+    // (and, as it's watched recursively, changes to included files)
+    println!("cargo:rerun-if-changed={}", SHADER_DIR);
+
     let mut compiler = shaderc::Compiler::new().context("Unable to create shader compiler")?;
 
+    // SPIR-V goes to `OUT_DIR`, along with the generated tables that point
+    // into it
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    let mut cache = ShaderCache::load(out_dir.join("shaders"))?;
+    let mut variant_table = String::new();
+    let mut input_table = String::new();
+    // The `shader::shaders` module
+    let mut registry = String::new();
+    // Only given up on once every shader has been tried
+    let mut failed = Vec::new();
+
     for shader in shaders {
         println!(
             "cargo:rerun-if-changed={}",
             shader.src_path.as_os_str().to_str().unwrap()
         );
+        let name = shader.src_path.file_name().unwrap().to_str().unwrap();
+        let constant = name
+            .to_uppercase()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
 
-        let compiled = compiler.compile_into_spirv(
-            &shader.src,
-            shader.kind,
-            shader.src_path.to_str().unwrap(),
-            "main",
-            None,
-        )?;
+        match shader.target {
+            ShaderTarget::Spirv { kind } => {
+                match compile_and_report(&mut compiler, &mut cache, &shader, kind, &[])? {
+                    Some(spv_path) => {
+                        registry.push_str(&format!(
+                            "pub const {}: CompiledShader = CompiledShader::Spirv {{\n    \
+                             name: {:?},\n    \
+                             spirv: include_bytes!({:?}),\n}};\n",
+                            constant, name, spv_path
+                        ));
 
-        write(shader.spv_path, compiled.as_binary_u8())?;
+                        if matches!(kind, shaderc::ShaderKind::Vertex) {
+                            let module = naga::front::spv::parse_u8_slice(
+                                &read(&spv_path)?,
+                                &Default::default(),
+                            )
+                            .map_err(|err| {
+                                anyhow!(
+                                    "Unable to reflect {}: {:?}",
+                                    shader.src_path.display(),
+                                    err
+                                )
+                            })?;
+                            reflect_vertex_inputs(name, &module, &mut input_table);
+                        }
+                    }
+                    None => {
+                        // Variants would only report the same errors over again
+                        failed.push(shader.src_path.display().to_string());
+                        continue;
+                    }
+                }
+
+                if shader.variants.is_empty() {
+                    continue;
+                }
+
+                // Every combination of defines, none at all included
+                for mask in 0..1u32 << shader.variants.len() {
+                    let defines = shader
+                        .variants
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| mask & (1 << i) != 0)
+                        .map(|(_, define)| define.as_str())
+                        .collect::<Vec<_>>();
+
+                    let variant_path = match compile_and_report(
+                        &mut compiler,
+                        &mut cache,
+                        &shader,
+                        kind,
+                        &defines,
+                    )? {
+                        Some(variant_path) => variant_path,
+                        None => {
+                            failed.push(format!(
+                                "{} (with {})",
+                                shader.src_path.display(),
+                                defines.join(", ")
+                            ));
+                            continue;
+                        }
+                    };
+
+                    variant_table.push_str(&format!(
+                        "    ({:?}, &{:?}, || wgpu::include_spirv!({:?})),\n",
+                        name, defines, variant_path
+                    ));
+                }
+            }
+            ShaderTarget::Wgsl => match validate_wgsl(&shader) {
+                Ok(module) => {
+                    reflect_vertex_inputs(name, &module, &mut input_table);
+                    registry.push_str(&format!(
+                        "pub const {}: CompiledShader = CompiledShader::Wgsl {{\n    \
+                         name: {:?},\n    \
+                         source: include_str!({:?}),\n}};\n",
+                        constant,
+                        name,
+                        shader.src_path.canonicalize()?
+                    ));
+                }
+                Err(err) => {
+                    eprintln!("{}\n", err);
+                    failed.push(shader.src_path.display().to_string());
+                }
+            },
+        }
+    }
+
+    write(
+        out_dir.join("shader_variants.rs"),
+        format!(
+            "/// Generated by `build.rs`: every variant of the shaders with a `// variants:`\n\
+             /// line, as the shader's file name, the defines it got compiled with and\n\
+             /// its module\n\
+             pub const VARIANTS: &[ShaderVariant] = &[\n{}];\n",
+            variant_table
+        ),
+    )?;
+
+    write(
+        out_dir.join("shader_inputs.rs"),
+        format!(
+            "/// Generated by `build.rs`: the inputs of every vertex shader entry point, as\n\
+             /// the shader's file name, the entry point and its inputs' locations, kinds\n\
+             /// and component counts\n\
+             pub const VERTEX_INPUTS: &[VertexInputs] = &[\n{}];\n",
+            input_table
+        ),
+    )?;
+
+    write(out_dir.join("shaders.rs"), registry)?;
+    cache.finish()?;
+
+    if !failed.is_empty() {
+        bail!(
+            "{} shader(s) failed to compile:\n    {}",
+            failed.len(),
+            failed.join("\n    ")
+        );
     }
 
     Ok(())
@@ -0,0 +1,192 @@
+use std::ops::{Add, Mul};
+
+use cgmath::{InnerSpace, Matrix4, Quaternion, SquareMatrix, Vector3, VectorSpace};
+
+/// Local transform of a node, kept decomposed so that animations can
+/// replace any of its parts
+#[derive(Copy, Clone, Debug)]
+pub struct NodeTransform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl NodeTransform {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// Every node of a model, with their rest transforms
+#[derive(Clone, Debug)]
+pub struct NodeTree {
+    pub rest: Vec<NodeTransform>,
+    pub children: Vec<Vec<usize>>,
+    pub roots: Vec<usize>,
+}
+
+impl NodeTree {
+    /// World transform of every node, given the local transforms in `pose`
+    pub fn world_transforms(&self, pose: &[NodeTransform]) -> Vec<Matrix4<f32>> {
+        let mut world = vec![Matrix4::identity(); pose.len()];
+        let mut stack = self
+            .roots
+            .iter()
+            .map(|&root| (root, Matrix4::identity()))
+            .collect::<Vec<_>>();
+        while let Some((node, parent)) = stack.pop() {
+            world[node] = parent * pose[node].matrix();
+            stack.extend(
+                self.children[node]
+                    .iter()
+                    .map(|&child| (child, world[node])),
+            );
+        }
+        world
+    }
+}
+
+// Only ever imported from glTF files
+#[cfg_attr(not(feature = "models"), allow(dead_code))]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    /// Every keyframe holds an in-tangent, a value and an out-tangent
+    CubicSpline,
+}
+
+#[cfg_attr(not(feature = "models"), allow(dead_code))]
+#[derive(Clone, Debug)]
+pub enum ChannelValues {
+    Translations(Vec<Vector3<f32>>),
+    Rotations(Vec<Quaternion<f32>>),
+    Scales(Vec<Vector3<f32>>),
+}
+
+/// Keyframes animating a single property of a single node
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub node: usize,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+}
+
+impl Channel {
+    fn apply(&self, time: f32, transform: &mut NodeTransform) {
+        match &self.values {
+            ChannelValues::Translations(values) => {
+                transform.translation = self.sample(values, time, |a, b, s| a.lerp(b, s));
+            }
+            ChannelValues::Rotations(values) => {
+                transform.rotation = self
+                    .sample(values, time, |a, b, s| a.slerp(b, s))
+                    .normalize();
+            }
+            ChannelValues::Scales(values) => {
+                transform.scale = self.sample(values, time, |a, b, s| a.lerp(b, s));
+            }
+        }
+    }
+
+    fn sample<T>(&self, values: &[T], time: f32, lerp: impl Fn(T, T, f32) -> T) -> T
+    where
+        T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+    {
+        // Cubic splines store their tangents around every value
+        let value = |key: usize| match self.interpolation {
+            Interpolation::CubicSpline => values[key * 3 + 1],
+            _ => values[key],
+        };
+
+        let last = self.times.len() - 1;
+        let next = self.times.partition_point(|&t| t <= time);
+        if next == 0 {
+            return value(0);
+        }
+        if next > last {
+            return value(last);
+        }
+
+        let key = next - 1;
+        let dt = self.times[next] - self.times[key];
+        let s = (time - self.times[key]) / dt;
+        match self.interpolation {
+            Interpolation::Step => value(key),
+            Interpolation::Linear => lerp(value(key), value(next), s),
+            Interpolation::CubicSpline => {
+                let (s2, s3) = (s * s, s * s * s);
+                let out_tangent = values[key * 3 + 2];
+                let in_tangent = values[next * 3];
+                value(key) * (2.0 * s3 - 3.0 * s2 + 1.0)
+                    + out_tangent * ((s3 - 2.0 * s2 + s) * dt)
+                    + value(next) * (-2.0 * s3 + 3.0 * s2)
+                    + in_tangent * ((s3 - s2) * dt)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+    /// Time of the last keyframe of any channel, in seconds
+    pub duration: f32,
+}
+
+/// Plays one clip at a time out of a model's clips
+#[derive(Debug)]
+pub struct AnimationPlayer {
+    pub clip: usize,
+    pub time: f32,
+    pub looping: bool,
+}
+
+impl AnimationPlayer {
+    pub fn new() -> Self {
+        Self {
+            clip: 0,
+            time: 0.0,
+            looping: true,
+        }
+    }
+
+    /// Switches to the clip after the current one, starting it over
+    pub fn next_clip(&mut self, clips: &[AnimationClip]) {
+        if !clips.is_empty() {
+            self.clip = (self.clip + 1) % clips.len();
+        }
+        self.time = 0.0;
+    }
+
+    pub fn advance(&mut self, dt: f32, clips: &[AnimationClip]) {
+        let clip = match clips.get(self.clip) {
+            Some(clip) => clip,
+            None => return,
+        };
+
+        self.time += dt;
+        if self.time > clip.duration {
+            self.time = if self.looping && clip.duration > 0.0 {
+                self.time % clip.duration
+            } else {
+                clip.duration
+            };
+        }
+    }
+
+    /// Local transforms of every node at the current time
+    pub fn pose(&self, clips: &[AnimationClip], tree: &NodeTree) -> Vec<NodeTransform> {
+        let mut pose = tree.rest.clone();
+        if let Some(clip) = clips.get(self.clip) {
+            for channel in clip.channels.iter() {
+                channel.apply(self.time, &mut pose[channel.node]);
+            }
+        }
+        pose
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::*;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{
+    model::ModelData,
+    shader::CompiledShader,
+    texture::{ColorSpace, SamplerDesc, Texture},
+};
+
+/// What every handle to the same asset points at
+struct Slot<T> {
+    value: Mutex<Arc<T>>,
+    /// Bumped on every reload
+    generation: AtomicUsize,
+}
+
+/// Shared handle to something `Assets` loaded. Clones point at the same asset,
+/// which stays loaded for as long as any of them is around, and gets swapped
+/// out from under them when its file changes on disk
+pub struct Handle<T> {
+    slot: Arc<Slot<T>>,
+    /// Generation of the asset as of the last `reloaded` call
+    seen: usize,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            slot: self.slot.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+impl<T> Handle<T> {
+    fn new(value: T) -> Self {
+        Self {
+            slot: Arc::new(Slot {
+                value: Mutex::new(Arc::new(value)),
+                generation: AtomicUsize::new(0),
+            }),
+            seen: 0,
+        }
+    }
+
+    /// The asset as it is right now. Whatever gets built out of it (i.e. bind
+    /// groups) should be rebuilt when `reloaded` says so
+    pub fn get(&self) -> Arc<T> {
+        self.slot.value.lock().unwrap().clone()
+    }
+
+    /// Whether the asset got reloaded since the last time this handle asked
+    pub fn reloaded(&mut self) -> bool {
+        let generation = self.slot.generation.load(Ordering::Acquire);
+        let reloaded = generation != self.seen;
+        self.seen = generation;
+        reloaded
+    }
+
+    fn replace(&self, value: T) {
+        *self.slot.value.lock().unwrap() = Arc::new(value);
+        self.slot.generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// Whether nothing but the cache holds onto it
+    fn unused(&self) -> bool {
+        Arc::strong_count(&self.slot) == 1
+    }
+}
+
+type Cache<K, T> = Mutex<HashMap<K, Handle<T>>>;
+
+/// Loads whatever scenes need out of `assets/` (and shader modules out of
+/// `shader::shaders`), keyed by path so that every scene asking for the same
+/// file gets the same handle, rather than an upload of its own. Samplers are
+/// shared the same way, keyed by their description. Files are watched for
+/// changes, and reloaded by `reload_changed`
+pub struct Assets {
+    /// The same image may be read as color and as data, as separate textures
+    textures: Cache<(String, ColorSpace), Texture>,
+    models: Cache<String, ModelData>,
+    shaders: Cache<&'static str, wgpu::ShaderModule>,
+    samplers: Cache<SamplerDesc, wgpu::Sampler>,
+    /// Along with where it's watching, as the paths it reports are absolute.
+    /// Missing if watching failed, in which case nothing gets reloaded
+    watcher: Option<(RecommendedWatcher, Receiver<DebouncedEvent>, PathBuf)>,
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        let watcher = Self::watch().map_err(|e| {
+            println!("Not reloading assets: {:?}", e);
+        });
+
+        Self {
+            textures: Mutex::default(),
+            models: Mutex::default(),
+            shaders: Mutex::default(),
+            samplers: Mutex::default(),
+            watcher: watcher.ok(),
+        }
+    }
+}
+
+fn get_or_load<K: Eq + Hash, T>(
+    cache: &Cache<K, T>,
+    key: K,
+    load: impl FnOnce() -> Result<T>,
+) -> Result<Handle<T>> {
+    let mut cache = cache.lock().unwrap();
+    if let Some(handle) = cache.get(&key) {
+        return Ok(handle.clone());
+    }
+
+    let handle = Handle::new(load()?);
+    cache.insert(key, handle.clone());
+    Ok(handle)
+}
+
+/// Reloads the asset at `key` (out of the file at `path`) if there's one,
+/// keeping the old one around if the new one can't be loaded
+fn reload<K: Eq + Hash, T>(
+    cache: &Cache<K, T>,
+    key: &K,
+    path: &str,
+    load: impl FnOnce() -> Result<T>,
+) {
+    if let Some(handle) = cache.lock().unwrap().get(key) {
+        match load() {
+            Ok(value) => {
+                handle.replace(value);
+                println!("Reloaded {}", path);
+            }
+            Err(e) => println!("Couldn't reload {}: {:?}", path, e),
+        }
+    }
+}
+
+fn purge_unused<K, T>(cache: &Cache<K, T>) -> usize {
+    let mut cache = cache.lock().unwrap();
+    let before = cache.len();
+    cache.retain(|_, handle| !handle.unused());
+    before - cache.len()
+}
+
+fn load_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: &str,
+    color_space: ColorSpace,
+) -> Result<Texture> {
+    let bytes =
+        std::fs::read(Assets::path(path)).with_context(|| format!("Couldn't read {}", path))?;
+    Texture::from_bytes(device, queue, &bytes, color_space, path)
+}
+
+fn load_model(path: &str) -> Result<ModelData> {
+    let bytes =
+        std::fs::read(Assets::path(path)).with_context(|| format!("Couldn't read {}", path))?;
+    crate::model::import_gltf_slice(&bytes)
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where `path` (relative to `assets/`, e.g. `sanCheese.png`) is on disk
+    pub fn path(path: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "assets", path]
+            .iter()
+            .collect()
+    }
+
+    fn watch() -> Result<(RecommendedWatcher, Receiver<DebouncedEvent>, PathBuf)> {
+        let root = std::fs::canonicalize(Self::path(""))?;
+        let (sender, receiver) = mpsc::channel();
+        // Editors tend to write files more than once when saving them
+        let mut watcher = notify::watcher(sender, Duration::from_millis(200))?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+        Ok((watcher, receiver, root))
+    }
+
+    /// A texture out of an image, labeled with its path
+    pub fn texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &str,
+        color_space: ColorSpace,
+    ) -> Result<Handle<Texture>> {
+        get_or_load(&self.textures, (path.to_owned(), color_space), || {
+            load_texture(device, queue, path, color_space)
+        })
+    }
+
+    /// Meshes, materials, skins and animations out of a glTF file, left for
+    /// scenes to upload with whatever layouts they draw them with
+    pub fn model(&self, path: &str) -> Result<Handle<ModelData>> {
+        get_or_load(&self.models, path.to_owned(), || load_model(path))
+    }
+
+    pub fn shader(
+        &self,
+        device: &wgpu::Device,
+        shader: &CompiledShader,
+    ) -> Handle<wgpu::ShaderModule> {
+        get_or_load(&self.shaders, shader.name(), || {
+            Ok(shader.create_module(device))
+        })
+        .expect("Creating shader modules can't fail")
+    }
+
+    /// The one sampler every scene asking for `desc` shares
+    pub fn sampler(&self, device: &wgpu::Device, desc: &SamplerDesc) -> Handle<wgpu::Sampler> {
+        get_or_load(&self.samplers, *desc, || {
+            Ok(desc.create(device, Some("Shared Sampler")))
+        })
+        .expect("Creating samplers can't fail")
+    }
+
+    /// Reloads every loaded texture and model whose file changed since the
+    /// last call, for scenes holding handles to them to pick up with
+    /// `Handle::reloaded`. Meant to be called once a frame
+    pub fn reload_changed(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let (_, receiver, root) = match &self.watcher {
+            Some(watcher) => watcher,
+            None => return,
+        };
+
+        for event in receiver.try_iter() {
+            let changed = match event {
+                DebouncedEvent::Write(path) | DebouncedEvent::Create(path) => path,
+                // Saving through a temporary file and renaming it over
+                DebouncedEvent::Rename(_, path) => path,
+                _ => continue,
+            };
+            let path = match changed.strip_prefix(root) {
+                Ok(path) => asset_key(path),
+                Err(_) => continue,
+            };
+
+            for &color_space in &[ColorSpace::Srgb, ColorSpace::Linear] {
+                let key = (path.clone(), color_space);
+                reload(&self.textures, &key, &path, || {
+                    load_texture(device, queue, &path, color_space)
+                });
+            }
+            reload(&self.models, &path, &path, || load_model(&path));
+        }
+    }
+
+    /// Lets go of everything no scene holds a handle to anymore (i.e. shader
+    /// modules and samplers, once the pipelines and bind groups out of them
+    /// are built), returning how many
+    /// assets that was
+    pub fn purge(&self) -> usize {
+        purge_unused(&self.textures)
+            + purge_unused(&self.models)
+            + purge_unused(&self.shaders)
+            + purge_unused(&self.samplers)
+    }
+}
+
+/// Paths relative to `assets/` the way scenes write them, with forward slashes
+fn asset_key(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
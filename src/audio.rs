@@ -0,0 +1,274 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Samples per FFT, must be a power of two
+pub const FFT_SIZE: usize = 2048;
+
+/// Lowest and highest frequencies split into bands, in Hz
+const MIN_FREQUENCY: f32 = 40.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+/// Magnitudes at or below this many decibels map to an empty band
+const FLOOR_DB: f32 = -70.0;
+
+const SYNTHETIC_SAMPLE_RATE: u32 = 44100;
+
+type SampleQueue = Arc<Mutex<VecDeque<f32>>>;
+
+fn push_samples(queue: &SampleQueue, samples: impl Iterator<Item = f32>) {
+    let mut queue = queue.lock().unwrap();
+    queue.extend(samples);
+    let excess = queue.len().saturating_sub(FFT_SIZE);
+    queue.drain(..excess);
+}
+
+enum Backend {
+    /// Kept alive for as long as we want the callbacks to keep coming
+    #[cfg(feature = "audio")]
+    #[allow(dead_code)]
+    Capture(cpal::Stream),
+    /// A few sweeping tones and a beat, for when there is nothing to capture
+    Synthetic { start: Instant, generated: u64 },
+}
+
+/// Mono audio, either captured from the default input device or synthesized
+pub struct AudioSource {
+    backend: Backend,
+    sample_rate: u32,
+    samples: SampleQueue,
+}
+
+impl AudioSource {
+    /// Starts capturing, falling back to synthetic audio if that isn't possible
+    pub fn new() -> Self {
+        let samples: SampleQueue = Arc::new(Mutex::new(VecDeque::with_capacity(FFT_SIZE)));
+
+        #[cfg(feature = "audio")]
+        match Self::capture(samples.clone()) {
+            Ok((stream, sample_rate)) => {
+                return Self {
+                    backend: Backend::Capture(stream),
+                    sample_rate,
+                    samples,
+                }
+            }
+            Err(e) => println!("Couldn't capture audio, using a synthetic signal: {}", e),
+        }
+
+        #[cfg(not(feature = "audio"))]
+        println!("Built without the `audio` feature, using a synthetic signal");
+
+        Self {
+            backend: Backend::Synthetic {
+                start: Instant::now(),
+                generated: 0,
+            },
+            sample_rate: SYNTHETIC_SAMPLE_RATE,
+            samples,
+        }
+    }
+
+    #[cfg(feature = "audio")]
+    fn capture(samples: SampleQueue) -> anyhow::Result<(cpal::Stream, u32)> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+        use cpal::Sample;
+
+        let host = cpal::default_host();
+        let device = host
+            .default_input_device()
+            .ok_or_else(|| anyhow::anyhow!("no input device available"))?;
+        let config = device.default_input_config()?;
+        let sample_rate = config.sample_rate().0;
+        let channels = config.channels() as usize;
+        println!(
+            "Capturing audio from {} at {} Hz",
+            device
+                .name()
+                .unwrap_or_else(|_| "an unnamed device".to_owned()),
+            sample_rate
+        );
+
+        let err_fn = |e| eprintln!("Audio capture error: {}", e);
+
+        // Downmix every frame to mono
+        fn downmix<T: Sample>(data: &[T], channels: usize) -> impl Iterator<Item = f32> + '_ {
+            data.chunks(channels)
+                .map(move |frame| frame.iter().map(|s| s.to_f32()).sum::<f32>() / channels as f32)
+        }
+
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => device.build_input_stream(
+                &config.into(),
+                move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                    push_samples(&samples, downmix(data, channels))
+                },
+                err_fn,
+            )?,
+            cpal::SampleFormat::I16 => device.build_input_stream(
+                &config.into(),
+                move |data: &[i16], _: &cpal::InputCallbackInfo| {
+                    push_samples(&samples, downmix(data, channels))
+                },
+                err_fn,
+            )?,
+            cpal::SampleFormat::U16 => device.build_input_stream(
+                &config.into(),
+                move |data: &[u16], _: &cpal::InputCallbackInfo| {
+                    push_samples(&samples, downmix(data, channels))
+                },
+                err_fn,
+            )?,
+        };
+        stream.play()?;
+
+        Ok((stream, sample_rate))
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Copies the most recent samples into `out`, zero-padding at the front
+    /// if there aren't enough of them yet
+    pub fn latest(&mut self, out: &mut [f32]) {
+        // Only irrefutable when built without capture support
+        #[allow(irrefutable_let_patterns)]
+        if let Backend::Synthetic { start, generated } = &mut self.backend {
+            let target = (start.elapsed().as_secs_f64() * self.sample_rate as f64) as u64;
+            // Anything older than a window would get thrown away anyway
+            let first = (*generated).max(target.saturating_sub(FFT_SIZE as u64));
+            let sample_rate = self.sample_rate as f32;
+            push_samples(
+                &self.samples,
+                (first..target).map(|i| synthesize(i as f32 / sample_rate)),
+            );
+            *generated = target;
+        }
+
+        let samples = self.samples.lock().unwrap();
+        let count = samples.len().min(out.len());
+        let padding = out.len() - count;
+        for s in out[..padding].iter_mut() {
+            *s = 0.0;
+        }
+        for (o, s) in out[padding..]
+            .iter_mut()
+            .zip(samples.iter().skip(samples.len() - count))
+        {
+            *o = *s;
+        }
+    }
+}
+
+fn synthesize(t: f32) -> f32 {
+    use std::f32::consts::PI;
+
+    // A bass line, a tone sweeping up and down a few octaves and a hi-hat
+    // ish burst of noise twice a second
+    let bass = (2.0 * PI * 55.0 * (1.0 + (t * 0.5).floor() % 4.0 * 0.25) * t).sin();
+    let sweep_frequency = 220.0 * 2.0f32.powf(3.0 * (0.5 + 0.5 * (t * 0.3).sin()));
+    let sweep = (2.0 * PI * sweep_frequency * t).sin();
+    let beat = (t * 2.0).fract();
+    let noise = ((t * 12_345.679).sin() * 43_758.547).fract() * 2.0 - 1.0;
+    let hihat = noise * (-beat * 30.0).exp();
+
+    0.4 * bass + 0.3 * sweep + 0.3 * hihat
+}
+
+/// In-place iterative radix-2 FFT
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+
+    // Bit-reversal permutation
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut size = 2;
+    while size <= n {
+        let angle = -2.0 * std::f32::consts::PI / size as f32;
+        for start in (0..n).step_by(size) {
+            for k in 0..size / 2 {
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + size / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        size *= 2;
+    }
+}
+
+/// Turns windows of samples into logarithmically spaced frequency bands
+pub struct SpectrumAnalyzer {
+    window: Vec<f32>,
+    re: Vec<f32>,
+    im: Vec<f32>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new() -> Self {
+        // Hann window, to keep the edges of the window from leaking into
+        // every bin
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let x = i as f32 / (FFT_SIZE - 1) as f32;
+                0.5 - 0.5 * (2.0 * std::f32::consts::PI * x).cos()
+            })
+            .collect();
+
+        Self {
+            window,
+            re: vec![0.0; FFT_SIZE],
+            im: vec![0.0; FFT_SIZE],
+        }
+    }
+
+    /// Fills `bands` with levels between 0 and 1, lowest frequencies first
+    pub fn analyze(&mut self, samples: &[f32], sample_rate: u32, bands: &mut [f32]) {
+        for ((re, im), (s, w)) in self
+            .re
+            .iter_mut()
+            .zip(self.im.iter_mut())
+            .zip(samples.iter().zip(self.window.iter()))
+        {
+            *re = s * w;
+            *im = 0.0;
+        }
+        fft(&mut self.re, &mut self.im);
+
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        let max_frequency = MAX_FREQUENCY.min(sample_rate as f32 * 0.5);
+        let ratio = max_frequency / MIN_FREQUENCY;
+        let count = bands.len() as f32;
+
+        for (i, band) in bands.iter_mut().enumerate() {
+            let low = MIN_FREQUENCY * ratio.powf(i as f32 / count);
+            let high = MIN_FREQUENCY * ratio.powf((i + 1) as f32 / count);
+            let first = ((low / bin_width) as usize).max(1);
+            let last = ((high / bin_width) as usize).clamp(first + 1, FFT_SIZE / 2);
+
+            // Peak magnitude, normalized so that a full scale sine is ~1
+            let peak = (first..last)
+                .map(|k| (self.re[k] * self.re[k] + self.im[k] * self.im[k]).sqrt())
+                .fold(0.0, f32::max)
+                * 4.0
+                / FFT_SIZE as f32;
+
+            let db = 20.0 * peak.max(1e-9).log10();
+            *band = (1.0 - db / FLOOR_DB).clamp(0.0, 1.0);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::{marker::PhantomData, ops::Range};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    buffer::{DrawIndexedIndirectArgs, IndirectBuffer, Stager},
+    vertex::{Descriptable, VertexBufferable},
+};
+
+/// Where a mesh ended up inside the shared buffers of a `MeshBatch`
+#[derive(Copy, Clone, Debug)]
+pub struct BatchedMesh {
+    pub first_index: u32,
+    pub index_count: u32,
+    /// Added to every index, which leaves them relative to the mesh's own
+    /// vertices
+    pub base_vertex: i32,
+}
+
+/// Meshes collected on the CPU, before they all get uploaded together
+pub struct MeshBatchBuilder<V: VertexBufferable + Descriptable> {
+    vertices: Vec<V>,
+    indices: Vec<u32>,
+    meshes: Vec<BatchedMesh>,
+}
+
+impl<V> MeshBatchBuilder<V>
+where
+    V: VertexBufferable + Descriptable,
+{
+    pub fn new() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            meshes: Vec::new(),
+        }
+    }
+
+    /// Appends a mesh, returning the index `MeshBatch::draw` takes for it
+    pub fn push(&mut self, vertices: &[V], indices: &[u32]) -> usize {
+        self.meshes.push(BatchedMesh {
+            first_index: self.indices.len() as u32,
+            index_count: indices.len() as u32,
+            base_vertex: self.vertices.len() as i32,
+        });
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend_from_slice(indices);
+
+        self.meshes.len() - 1
+    }
+
+    /// Uploads every mesh, with room for up to `max_draws` draws per frame
+    pub fn build(self, device: &wgpu::Device, max_draws: u32, label: &str) -> MeshBatch<V> {
+        // Buffers can't be empty, so leave a placeholder nobody will draw
+        let vertices = if self.vertices.is_empty() {
+            vec![V::zeroed()]
+        } else {
+            self.vertices
+        };
+        let indices = if self.indices.is_empty() {
+            vec![0]
+        } else {
+            self.indices
+        };
+
+        MeshBatch {
+            vertices: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+            indices: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }),
+            meshes: self.meshes,
+            draws: Vec::with_capacity(max_draws as usize),
+            args: IndirectBuffer::with_capacity(
+                device,
+                max_draws.max(1),
+                wgpu::BufferUsages::empty(),
+                Some(label),
+            ),
+            multi_draw: device
+                .features()
+                .contains(wgpu::Features::MULTI_DRAW_INDIRECT),
+            _v: PhantomData,
+        }
+    }
+}
+
+impl<V> Default for MeshBatchBuilder<V>
+where
+    V: VertexBufferable + Descriptable,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Several meshes packed into shared vertex and index buffers, so that
+/// drawing any number of them takes a single bind and a run of indirect draws
+/// (or a single one, with `MULTI_DRAW_INDIRECT`)
+pub struct MeshBatch<V: VertexBufferable + Descriptable> {
+    pub vertices: wgpu::Buffer,
+    pub indices: wgpu::Buffer,
+    meshes: Vec<BatchedMesh>,
+    /// Queued since the last `clear`
+    draws: Vec<DrawIndexedIndirectArgs>,
+    args: IndirectBuffer<DrawIndexedIndirectArgs>,
+    multi_draw: bool,
+    _v: PhantomData<V>,
+}
+
+impl<V> MeshBatch<V>
+where
+    V: VertexBufferable + Descriptable,
+{
+    /// Whether `render` gets away with a single `multi_draw_indexed_indirect`
+    pub fn multi_draw(&self) -> bool {
+        self.multi_draw
+    }
+
+    pub fn draw_count(&self) -> u32 {
+        self.draws.len() as u32
+    }
+
+    pub fn clear(&mut self) {
+        self.draws.clear();
+    }
+
+    /// Queues a draw of the given instances of a mesh
+    pub fn draw(&mut self, mesh: usize, instances: Range<u32>) {
+        assert!(
+            self.draws.len() < self.args.capacity() as usize,
+            "mesh batch can't hold more than {} draws",
+            self.args.capacity()
+        );
+
+        let mesh = &self.meshes[mesh];
+        self.draws.push(DrawIndexedIndirectArgs {
+            index_count: mesh.index_count,
+            instance_count: instances.end - instances.start,
+            first_index: mesh.first_index,
+            base_vertex: mesh.base_vertex,
+            first_instance: instances.start,
+        });
+    }
+
+    /// Uploads the arguments of every queued draw
+    pub fn upload(&self, encoder: &mut wgpu::CommandEncoder, stager: &mut Stager) {
+        self.args.write(encoder, stager, 0, &self.draws);
+    }
+
+    /// Binds the shared vertices to `slot` and the shared indices
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, slot: u32) {
+        render_pass.set_vertex_buffer(slot, self.vertices.slice(..));
+        render_pass.set_index_buffer(self.indices.slice(..), wgpu::IndexFormat::Uint32);
+    }
+
+    /// Frees the shared buffers and the arguments right away, see
+    /// `Scene::destroy`
+    pub fn destroy(&self) {
+        self.vertices.destroy();
+        self.indices.destroy();
+        self.args.buffer.destroy();
+    }
+
+    /// Issues every queued draw out of the uploaded arguments
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.draws.is_empty() {
+            return;
+        }
+
+        self.bind(render_pass, 0);
+        if self.multi_draw {
+            render_pass.multi_draw_indexed_indirect(&self.args.buffer, 0, self.draw_count());
+        } else {
+            for draw in 0..self.draw_count() {
+                render_pass.draw_indexed_indirect(&self.args.buffer, self.args.offset(draw));
+            }
+        }
+    }
+
+    /// Same as `render`, with a plain `draw_indexed` per queued draw. Doesn't
+    /// need `upload`
+    pub fn render_direct<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.draws.is_empty() {
+            return;
+        }
+
+        self.bind(render_pass, 0);
+        for draw in self.draws.iter() {
+            render_pass.draw_indexed(
+                draw.first_index..draw.first_index + draw.index_count,
+                draw.base_vertex,
+                draw.first_instance..draw.first_instance + draw.instance_count,
+            );
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::*;
+use winit::event::VirtualKeyCode;
+
+/// Where bindings get overridden from, relative to the working directory
+pub const BINDINGS_PATH: &str = "keybindings.cfg";
+
+/// Maps keys to named actions, such as `camera.forward` or `scene.next`.
+/// Whatever comes before the dot is the scope of the action: scenes only look
+/// for actions in their own scope (and in `camera`, if they have one), so the
+/// same key can do something else on every scene. Keys bound to `app`
+/// actions and to `scene.next` are taken by the app before scenes get to see
+/// them, so they shouldn't be bound to scene actions too.
+///
+/// Starts off with the default bindings, which a file with lines such as
+/// `camera.forward = W, Up` can then override, one action at a time
+pub struct Bindings {
+    /// Every action along with the keys bound to it
+    actions: HashMap<String, Vec<VirtualKeyCode>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use VirtualKeyCode::*;
+
+        let defaults: &[(&str, &[VirtualKeyCode])] = &[
+            ("scene.next", &[Space]),
+            ("app.screenshot", &[F12]),
+            ("app.print_staging_stats", &[F9]),
+            ("app.toggle_parallel_encoding", &[F8]),
+            ("app.toggle_grid_mode", &[G]),
+            ("app.toggle_scene_menu", &[F1]),
+            ("app.toggle_unload_inactive", &[F2]),
+            ("app.pause", &[P]),
+            ("app.step", &[Period]),
+            ("app.slower", &[LBracket]),
+            ("app.faster", &[RBracket]),
+            ("app.quit", &[Escape]),
+            ("camera.up", &[Q]),
+            ("camera.down", &[E]),
+            ("camera.forward", &[W, Up]),
+            ("camera.backward", &[S, Down]),
+            ("camera.left", &[A, Left]),
+            ("camera.right", &[D, Right]),
+            ("camera.record_keyframe", &[F5]),
+            ("camera.play_path", &[F6]),
+            ("camera.clear_path", &[F7]),
+            ("textured.toggle_texture", &[N]),
+            ("cameras.toggle_texture", &[N]),
+            ("cameras.toggle_grid", &[Z]),
+            ("cameras.toggle_bounds", &[V]),
+            ("cameras.freeze_frustum", &[F]),
+            ("cameras.toggle_split_screen", &[X]),
+            ("cameras.switch_projection", &[O]),
+            ("cameras.switch_gizmo", &[T]),
+            ("instancing.toggle_texture", &[N]),
+            ("instancing.switch_animation", &[M]),
+            ("instancing.toggle_depth_view", &[B]),
+            ("instancing.switch_depth_view", &[K]),
+            ("instancing.switch_backend", &[C]),
+            ("instancing.switch_translucency", &[T]),
+            ("instancing.toggle_grid", &[Z]),
+            ("instancing.toggle_bounds", &[V]),
+            ("instancing.toggle_lod_tints", &[L]),
+            ("instancing.switch_culling", &[F]),
+            ("instancing.toggle_occlusion_culling", &[O]),
+            ("instancing.step_hiz_level", &[H]),
+            ("instancing.print_stats", &[U]),
+            ("instancing.toggle_compact_instances", &[I]),
+            ("instancing.toggle_depth_prepass", &[J]),
+            ("instancing.toggle_fly_camera", &[Tab]),
+            ("instancing.remove_instance", &[X]),
+            ("oit.toggle_sorting", &[O]),
+            ("life.reseed", &[R]),
+            ("boids.select_separation", &[Key1]),
+            ("boids.select_alignment", &[Key2]),
+            ("boids.select_cohesion", &[Key3]),
+            ("boids.select_max_speed", &[Key4]),
+            ("boids.select_visual_range", &[Key5]),
+            ("boids.increase", &[Up]),
+            ("boids.decrease", &[Down]),
+            ("nbody.switch_kernel", &[K]),
+            ("terrain.reseed", &[R]),
+            ("terrain.fewer_octaves", &[Key1]),
+            ("terrain.more_octaves", &[Key2]),
+            ("terrain.lower_frequency", &[Key3]),
+            ("terrain.raise_frequency", &[Key4]),
+            ("voxel.reseed", &[R]),
+            ("voxel.toggle_culling", &[F]),
+            ("voxel.toggle_indirect", &[B]),
+            ("lsystem.switch_plant", &[L]),
+            ("lsystem.fewer_iterations", &[Key1]),
+            ("lsystem.more_iterations", &[Key2]),
+            ("fractal.switch_fractal", &[J]),
+            ("fractal.switch_precision", &[D]),
+            ("fractal.switch_palette", &[C]),
+            ("fractal.reset_view", &[R]),
+            ("fractal.fewer_iterations", &[Key1]),
+            ("fractal.more_iterations", &[Key2]),
+            ("audio.pause", &[H]),
+            ("model.switch_environment", &[B]),
+            ("model.toggle_compact_vertices", &[C]),
+            ("model.next_clip", &[K]),
+            ("model.toggle_looping", &[L]),
+            ("model.restart", &[R]),
+            ("uniforms.toggle_wireframe", &[F]),
+            ("uniforms.toggle_msaa", &[M]),
+            ("uniforms.toggle_fog", &[O]),
+            ("uniforms.print_stats", &[U]),
+        ];
+
+        Self {
+            actions: defaults
+                .iter()
+                .map(|(action, keys)| (action.to_string(), keys.to_vec()))
+                .collect(),
+        }
+    }
+}
+
+impl Bindings {
+    /// The default bindings, overridden by whatever `BINDINGS_PATH` says if
+    /// it's there
+    pub fn load() -> Self {
+        let mut bindings = Self::default();
+        if Path::new(BINDINGS_PATH).exists() {
+            if let Err(e) = bindings.load_file(BINDINGS_PATH) {
+                println!("Not loading keybindings: {:?}", e);
+            }
+        }
+        bindings
+    }
+
+    fn load_file(&mut self, path: &str) -> Result<()> {
+        let text = std::fs::read_to_string(path)?;
+        self.parse(&text)
+            .with_context(|| format!("Failed to parse {}", path))
+    }
+
+    /// Overrides every action listed, one per line as `action = Key, Key`
+    /// (with key names as in `VirtualKeyCode`). Nothing after the `=` unbinds
+    /// the action, and everything after a `#` is a comment
+    fn parse(&mut self, text: &str) -> Result<()> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (action, keys) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => bail!("Line {}: expected `action = Key, Key`", number + 1),
+            };
+            let bound = match self.actions.get_mut(action) {
+                Some(bound) => bound,
+                None => bail!("Line {}: unknown action {:?}", number + 1, action),
+            };
+
+            let keys = keys
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(|key| {
+                    parse_key(key)
+                        .ok_or_else(|| anyhow!("Line {}: unknown key {:?}", number + 1, key))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            *bound = keys;
+        }
+
+        Ok(())
+    }
+
+    /// Every key bound to the action, if any
+    pub fn keys(&self, action: &str) -> &[VirtualKeyCode] {
+        self.actions.get(action).map_or(&[][..], Vec::as_slice)
+    }
+}
+
+macro_rules! parse_keys {
+    ($($key:ident),* $(,)?) => {
+        /// Key named as in `VirtualKeyCode`, for the keys worth binding
+        fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+            match name {
+                $(stringify!($key) => Some(VirtualKeyCode::$key),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+parse_keys! {
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Insert, Home, Delete, End, PageDown, PageUp,
+    Left, Up, Right, Down,
+    Back, Return, Space, Tab,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4,
+    Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    Add, Subtract, Multiply, Divide, NumpadEnter,
+    LAlt, LControl, LShift, RAlt, RControl, RShift,
+    Comma, Period, Slash, Semicolon, Apostrophe, Minus, Equals,
+    LBracket, RBracket, Backslash, Grave,
+}
@@ -1,14 +1,20 @@
 use std::{
-    collections::HashMap,
+    fmt,
     marker::PhantomData,
     num::NonZeroU64,
+    ops::{Deref, DerefMut, Range},
     sync::{Arc, Mutex, MutexGuard},
 };
 
-use crate::vertex::{Descriptable, VertexBufferable};
-use futures::executor::LocalPool;
+use crate::{
+    layout::{Std140, Std430},
+    vertex::{Descriptable, VertexBufferable},
+};
+use ring::{Pending, Ring};
 use wgpu::{util::DeviceExt, BufferViewMut, CommandEncoder};
 
+mod ring;
+
 // Instead of trying to impl Descriptable for TypedBuffer
 // I just define the `descriptor` function for `TypedBuffer` separatedly
 // Then constrain T to have `Descriptable`
@@ -19,10 +25,33 @@ pub trait VertexTypedBuffer<T: VertexBufferable + Descriptable> {
     }
 }
 
+/// Creates a buffer out of `contents`, copied into it through `stager` rather
+/// than through an init buffer of its own. The buffer gets zero-padded up to a
+/// multiple of `COPY_BUFFER_ALIGNMENT`, as `create_buffer_init` does
+fn create_buffer_staged(
+    device: &wgpu::Device,
+    encoder: &mut CommandEncoder,
+    stager: &mut Stager,
+    contents: &[u8],
+    usage: wgpu::BufferUsages,
+    label: Option<&str>,
+) -> wgpu::Buffer {
+    let align = wgpu::COPY_BUFFER_ALIGNMENT;
+    let size = (contents.len() as wgpu::BufferAddress).div_ceil(align) * align;
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label,
+        size,
+        usage: usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    stager.write_padded(encoder, &buffer, 0, contents);
+    buffer
+}
+
 pub struct VertexBuffer<T: VertexBufferable + Descriptable> {
     pub len: u32,
     pub buffer: wgpu::Buffer,
-    _t: PhantomData<*mut T>,
+    _t: PhantomData<T>,
 }
 
 impl<T> VertexBuffer<T>
@@ -37,28 +66,75 @@ where
                 contents: bytemuck::cast_slice(vertices),
                 usage: wgpu::BufferUsages::VERTEX,
             }),
-            _t: PhantomData::default(),
+            _t: PhantomData,
+        }
+    }
+
+    /// Same as `from_vertices`, but uploads the vertices through `stager`
+    pub fn from_vertices_staged(
+        device: &wgpu::Device,
+        encoder: &mut CommandEncoder,
+        stager: &mut Stager,
+        vertices: &[T],
+        label: Option<&str>,
+    ) -> Self {
+        Self {
+            len: vertices.len() as u32,
+            buffer: create_buffer_staged(
+                device,
+                encoder,
+                stager,
+                bytemuck::cast_slice(vertices),
+                wgpu::BufferUsages::VERTEX,
+                label,
+            ),
+            _t: PhantomData,
         }
     }
 }
 
 impl<T> VertexTypedBuffer<T> for VertexBuffer<T> where T: VertexBufferable + Descriptable {}
 
+/// Integer types an index buffer can be made of
+pub trait Index: bytemuck::Pod {
+    const FORMAT: wgpu::IndexFormat;
+
+    fn to_usize(self) -> usize;
+}
+
+impl Index for u16 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint16;
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl Index for u32 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint32;
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
 pub struct IndexedVertexBuffer<T: VertexBufferable + Descriptable> {
     pub num_indices: u32,
     pub vertices: wgpu::Buffer,
     pub indices: wgpu::Buffer,
-    _t: PhantomData<*mut T>,
+    /// Whether `indices` holds `u16`s or `u32`s
+    pub index_format: wgpu::IndexFormat,
+    _t: PhantomData<T>,
 }
 
 impl<T> IndexedVertexBuffer<T>
 where
     T: VertexBufferable + Descriptable,
 {
-    pub fn from_vertices_indexes(
+    pub fn from_vertices_indexes<I: Index>(
         device: &wgpu::Device,
         vertices: &[T],
-        indexes: &[u16],
+        indexes: &[I],
         vertices_label: Option<&str>,
         indexes_label: Option<&str>,
     ) -> Self {
@@ -74,17 +150,99 @@ where
                 contents: bytemuck::cast_slice(indexes),
                 usage: wgpu::BufferUsages::INDEX,
             }),
-            _t: PhantomData::default(),
+            index_format: I::FORMAT,
+            _t: PhantomData,
+        }
+    }
+
+    /// Same as `from_vertices_indexes`, but narrows the indices down to `u16`s
+    /// whenever they're enough to address every vertex
+    pub fn from_vertices_compact_indexes(
+        device: &wgpu::Device,
+        vertices: &[T],
+        indexes: &[u32],
+        vertices_label: Option<&str>,
+        indexes_label: Option<&str>,
+    ) -> Self {
+        if vertices.len() <= u16::MAX as usize + 1 {
+            let indexes = indexes.iter().map(|&i| i as u16).collect::<Vec<_>>();
+            Self::from_vertices_indexes(device, vertices, &indexes, vertices_label, indexes_label)
+        } else {
+            Self::from_vertices_indexes(device, vertices, indexes, vertices_label, indexes_label)
+        }
+    }
+
+    /// Same as `from_vertices_indexes`, but uploads the vertices and indices
+    /// through `stager`
+    pub fn from_vertices_indexes_staged<I: Index>(
+        device: &wgpu::Device,
+        encoder: &mut CommandEncoder,
+        stager: &mut Stager,
+        vertices: &[T],
+        indexes: &[I],
+        vertices_label: Option<&str>,
+        indexes_label: Option<&str>,
+    ) -> Self {
+        Self {
+            num_indices: indexes.len() as u32,
+            vertices: create_buffer_staged(
+                device,
+                encoder,
+                stager,
+                bytemuck::cast_slice(vertices),
+                wgpu::BufferUsages::VERTEX,
+                vertices_label,
+            ),
+            indices: create_buffer_staged(
+                device,
+                encoder,
+                stager,
+                bytemuck::cast_slice(indexes),
+                wgpu::BufferUsages::INDEX,
+                indexes_label,
+            ),
+            index_format: I::FORMAT,
+            _t: PhantomData,
         }
     }
+
+    /// Binds the vertices to `slot` and the indices, ready for `draw_indexed`
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, slot: u32) {
+        render_pass.set_vertex_buffer(slot, self.vertices.slice(..));
+        render_pass.set_index_buffer(self.indices.slice(..), self.index_format);
+    }
+
+    /// Frees both buffers right away, see `Scene::destroy`
+    pub fn destroy(&self) {
+        self.vertices.destroy();
+        self.indices.destroy();
+    }
 }
 
 impl<T> VertexTypedBuffer<T> for IndexedVertexBuffer<T> where T: VertexBufferable + Descriptable {}
 
+/// What the last `InstanceVertexBuffer::upload_dirty` sent to the GPU
+#[derive(Copy, Clone, Debug, Default)]
+pub struct UploadStats {
+    pub bytes: u64,
+    pub ranges: u32,
+}
+
+/// Instances live in `0..len`, with room for up to `capacity()` of them
+/// before the buffer has to be reallocated
 pub struct InstanceVertexBuffer<T: VertexBufferable + Descriptable> {
     pub len: u32,
+    capacity: u32,
     pub buffer: wgpu::Buffer,
-    _t: PhantomData<*mut T>,
+    /// Extra usages and label, kept around for reallocations
+    usage: wgpu::BufferUsages,
+    label: Option<String>,
+    /// Instances as of the last `set`, to tell which ones actually changed
+    staged: Vec<T>,
+    /// Ranges of `staged` that haven't been uploaded yet
+    dirty: Vec<Range<u32>>,
+    last_upload: UploadStats,
+    _t: PhantomData<T>,
 }
 
 impl<T> InstanceVertexBuffer<T>
@@ -100,21 +258,256 @@ where
         instances: &'a [U],
         label: Option<&str>,
     ) -> Self
+    where
+        T: From<&'a U>,
+    {
+        Self::from_instances_with_usage(device, instances, wgpu::BufferUsages::empty(), label)
+    }
+
+    /// Same as `from_instances`, but allows extra usages on the buffer
+    /// (e.g. `STORAGE`, so that compute shaders can write instances directly)
+    pub fn from_instances_with_usage<'a, U>(
+        device: &wgpu::Device,
+        instances: &'a [U],
+        usage: wgpu::BufferUsages,
+        label: Option<&str>,
+    ) -> Self
     where
         T: From<&'a U>,
     {
         let t = instances.iter().map(Into::into).collect::<Vec<T>>();
         Self {
             len: instances.len() as u32,
+            capacity: instances.len() as u32,
             buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label,
                 contents: bytemuck::cast_slice(&t),
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                usage: Self::usages(usage),
             }),
-            _t: PhantomData::default(),
+            usage,
+            label: label.map(str::to_owned),
+            staged: t,
+            dirty: Vec::new(),
+            last_upload: UploadStats::default(),
+            _t: PhantomData,
         }
     }
 
+    /// Same as `from_instances_with_usage`, but uploads the instances through
+    /// `stager`
+    pub fn from_instances_staged<'a, U>(
+        device: &wgpu::Device,
+        encoder: &mut CommandEncoder,
+        stager: &mut Stager,
+        instances: &'a [U],
+        usage: wgpu::BufferUsages,
+        label: Option<&str>,
+    ) -> Self
+    where
+        T: From<&'a U>,
+    {
+        let t = instances.iter().map(Into::into).collect::<Vec<T>>();
+        Self {
+            len: instances.len() as u32,
+            capacity: instances.len() as u32,
+            buffer: create_buffer_staged(
+                device,
+                encoder,
+                stager,
+                bytemuck::cast_slice(&t),
+                Self::usages(usage),
+                label,
+            ),
+            usage,
+            label: label.map(str::to_owned),
+            staged: t,
+            dirty: Vec::new(),
+            last_upload: UploadStats::default(),
+            _t: PhantomData,
+        }
+    }
+
+    /// Creates an empty instance buffer with room for `capacity` instances,
+    /// zeroed
+    pub fn with_capacity(device: &wgpu::Device, capacity: u32, label: Option<&str>) -> Self {
+        Self {
+            len: 0,
+            capacity,
+            buffer: Self::create_buffer(device, capacity, wgpu::BufferUsages::empty(), label),
+            usage: wgpu::BufferUsages::empty(),
+            label: label.map(str::to_owned),
+            staged: Vec::new(),
+            dirty: Vec::new(),
+            last_upload: UploadStats::default(),
+            _t: PhantomData,
+        }
+    }
+
+    /// Instances get copied out of the buffer when it grows
+    fn usages(usage: wgpu::BufferUsages) -> wgpu::BufferUsages {
+        wgpu::BufferUsages::VERTEX
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC
+            | usage
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        capacity: u32,
+        usage: wgpu::BufferUsages,
+        label: Option<&str>,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label,
+            size: capacity as wgpu::BufferAddress * std::mem::size_of::<T>() as wgpu::BufferAddress,
+            usage: Self::usages(usage),
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Makes room for at least `additional` more instances past `len`. If
+    /// they don't fit, a buffer twice as big (or as big as needed) replaces
+    /// the current one, and the live instances get copied over through
+    /// `encoder`. Returns whether that happened, in which case bind groups
+    /// pointing to the old buffer have to be recreated
+    pub fn reserve(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut CommandEncoder,
+        additional: u32,
+    ) -> bool {
+        let required = self.len + additional;
+        if required <= self.capacity {
+            return false;
+        }
+
+        let capacity = required.max(self.capacity * 2);
+        let buffer = Self::create_buffer(device, capacity, self.usage, self.label.as_deref());
+        if self.len > 0 {
+            encoder.copy_buffer_to_buffer(
+                &self.buffer,
+                0,
+                &buffer,
+                0,
+                self.len as wgpu::BufferAddress * std::mem::size_of::<T>() as wgpu::BufferAddress,
+            );
+        }
+
+        // The old buffer is kept alive by wgpu until the copy is done
+        self.buffer = buffer;
+        self.capacity = capacity;
+        // Only the live instances were copied over, anything staged past them
+        // gets staged again by the `set` or `push` that reaches it
+        self.staged.truncate(self.len as usize);
+        true
+    }
+
+    /// Appends an instance and returns its index. It's staged like `set`
+    /// does, getting uploaded by the next `upload_dirty`. May reallocate the
+    /// buffer, as `reserve` does
+    #[allow(dead_code)] // Instance counts are all fixed up front yet
+    pub fn push<'a, U>(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut CommandEncoder,
+        instance: &'a U,
+    ) -> u32
+    where
+        T: From<&'a U>,
+    {
+        self.reserve(device, encoder, 1);
+
+        let index = self.len;
+        self.len += 1;
+        self.set(index, instance);
+        index
+    }
+
+    /// Stages the instance at `index`, which only gets uploaded by the next
+    /// `upload_dirty` if it differs from what was there before
+    pub fn set<'a, U>(&mut self, index: u32, instance: &'a U)
+    where
+        T: From<&'a U>,
+    {
+        assert!(index < self.len);
+        let new: T = instance.into();
+
+        let i = index as usize;
+        if i >= self.staged.len() {
+            // Whatever the GPU has past the staged instances is unknown
+            let start = self.staged.len() as u32;
+            self.staged.resize(i + 1, T::zeroed());
+            self.mark_dirty(start..index + 1);
+        } else if bytemuck::bytes_of(&self.staged[i]) != bytemuck::bytes_of(&new) {
+            self.mark_dirty(index..index + 1);
+        }
+        self.staged[i] = new;
+    }
+
+    fn mark_dirty(&mut self, range: Range<u32>) {
+        match self.dirty.last_mut() {
+            Some(last) if range.start <= last.end && last.start <= range.end => {
+                last.start = last.start.min(range.start);
+                last.end = last.end.max(range.end);
+            }
+            _ => self.dirty.push(range),
+        }
+    }
+
+    /// Forgets what was staged, so that every instance gets uploaded again.
+    /// Needed whenever something else writes to the buffer, like a compute
+    /// shader or `copy_instance`
+    pub fn invalidate(&mut self) {
+        self.staged.clear();
+        self.dirty.clear();
+    }
+
+    /// Uploads every contiguous range of instances that changed since the
+    /// last upload
+    pub fn upload_dirty(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        stager: &mut Stager,
+    ) -> UploadStats {
+        self.dirty.sort_by_key(|range| range.start);
+        let mut ranges: Vec<Range<u32>> = Vec::with_capacity(self.dirty.len());
+        for range in self.dirty.drain(..) {
+            let range = range.start..range.end.min(self.len);
+            if range.is_empty() {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => ranges.push(range),
+            }
+        }
+
+        let mut stats = UploadStats::default();
+        for range in ranges.iter() {
+            let data = &self.staged[range.start as usize..range.end as usize];
+            stager.write_slice(
+                encoder,
+                &self.buffer,
+                range.start as wgpu::BufferAddress,
+                data,
+            );
+            stats.bytes += std::mem::size_of_val(data) as u64;
+            stats.ranges += 1;
+        }
+
+        self.last_upload = stats;
+        stats
+    }
+
+    pub fn last_upload(&self) -> UploadStats {
+        self.last_upload
+    }
+
+    #[allow(dead_code)] // Nothing copies instances around yet
     pub fn copy_instance<'a, U>(
         &self,
         queue: &wgpu::Queue,
@@ -158,99 +551,679 @@ where
     }
 }
 
-pub trait OldUniform: bytemuck::Pod + bytemuck::Zeroable {
-    fn into_buffer(self, device: &wgpu::Device, label: Option<&str>) -> wgpu::Buffer {
+/// Arguments of a single draw, laid out the way `draw_indirect` and
+/// `draw_indexed_indirect` read them
+pub trait IndirectArgs: bytemuck::Pod + bytemuck::Zeroable {}
+
+/// Arguments of `draw_indirect`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable, Std430)]
+pub struct DrawIndirectArgs {
+    pub vertex_count: u32,
+    pub instance_count: u32,
+    pub first_vertex: u32,
+    pub first_instance: u32,
+}
+
+impl IndirectArgs for DrawIndirectArgs {}
+
+/// Arguments of `draw_indexed_indirect`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable, Std430)]
+pub struct DrawIndexedIndirectArgs {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+impl IndirectArgs for DrawIndexedIndirectArgs {}
+
+/// Room for `capacity()` draws worth of arguments, which can be written by
+/// compute shaders (given `STORAGE` as an extra usage) or batched up on the
+/// CPU and uploaded through a staging belt
+pub struct IndirectBuffer<A: IndirectArgs> {
+    pub buffer: wgpu::Buffer,
+    capacity: u32,
+    _a: PhantomData<A>,
+}
+
+impl<A> IndirectBuffer<A>
+where
+    A: IndirectArgs,
+{
+    pub fn from_args(
+        device: &wgpu::Device,
+        args: &[A],
+        usage: wgpu::BufferUsages,
+        label: Option<&str>,
+    ) -> Self {
+        Self {
+            buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label,
+                contents: bytemuck::cast_slice(args),
+                usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST | usage,
+            }),
+            capacity: args.len() as u32,
+            _a: PhantomData,
+        }
+    }
+
+    /// Zeroed arguments, which draw nothing until they get written
+    pub fn with_capacity(
+        device: &wgpu::Device,
+        capacity: u32,
+        usage: wgpu::BufferUsages,
+        label: Option<&str>,
+    ) -> Self {
+        Self::from_args(device, &vec![A::zeroed(); capacity as usize], usage, label)
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Where the arguments of the `index`-th draw start, as taken by
+    /// `draw_indirect` and `draw_indexed_indirect`
+    pub fn offset(&self, index: u32) -> wgpu::BufferAddress {
+        index as wgpu::BufferAddress * std::mem::size_of::<A>() as wgpu::BufferAddress
+    }
+
+    /// Overwrites the arguments of the draws starting at `first`
+    pub fn write(&self, encoder: &mut CommandEncoder, stager: &mut Stager, first: u32, args: &[A]) {
+        assert!(
+            first as usize + args.len() <= self.capacity as usize,
+            "indirect buffer overflow: {} draws starting at {}, with room for {}",
+            args.len(),
+            first,
+            self.capacity
+        );
+        stager.write_slice(encoder, &self.buffer, first as wgpu::BufferAddress, args);
+    }
+}
+
+/// Anything that can be uploaded into a uniform buffer, as whatever
+/// `Uniform` it turns into. Plain old data laid out as std140 is its own
+/// uniform
+pub trait Uniformable {
+    type Uniform: bytemuck::Pod + bytemuck::Zeroable + Std140;
+
+    fn to_uniform(&self) -> Self::Uniform;
+
+    fn create_buffer(&self, device: &wgpu::Device, label: Option<&str>) -> wgpu::Buffer {
         device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label,
-            contents: bytemuck::bytes_of(&self),
+            contents: bytemuck::bytes_of(&self.to_uniform()),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         })
     }
 }
 
-pub trait Uniformable: Sized {
-    type Uniform: bytemuck::Pod + bytemuck::Zeroable;
+impl<T: bytemuck::Pod + Std140> Uniformable for T {
+    type Uniform = T;
 
-    fn into_uniform(self) -> Self::Uniform;
+    fn to_uniform(&self) -> T {
+        *self
+    }
+}
 
-    fn into_buffer(self, device: &wgpu::Device, label: Option<&str>) -> wgpu::Buffer {
-        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label,
-            contents: bytemuck::bytes_of(&self.into_uniform()),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+/// Uniform buffer holding a single `T`, which knows how to bind itself
+pub struct UniformBuffer<T: Uniformable> {
+    pub buffer: wgpu::Buffer,
+    _t: PhantomData<T>,
+}
+
+impl<T> UniformBuffer<T>
+where
+    T: Uniformable,
+{
+    pub fn new(device: &wgpu::Device, value: &T, label: Option<&str>) -> Self {
+        Self {
+            buffer: value.create_buffer(device, label),
+            _t: PhantomData,
+        }
+    }
+
+    pub fn binding_size() -> Option<NonZeroU64> {
+        NonZeroU64::new(std::mem::size_of::<T::Uniform>() as u64)
+    }
+
+    /// Uploads `value` over whatever the buffer held
+    pub fn update(&mut self, stager: &mut Stager, encoder: &mut CommandEncoder, value: &T) {
+        stager.write(encoder, &self.buffer, 0, &value.to_uniform());
+    }
+
+    pub fn layout_entry(
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: Self::binding_size(),
+            },
+            count: None,
+        }
+    }
+
+    /// Bind group entry matching `layout_entry`
+    pub fn entry(&self, binding: u32) -> wgpu::BindGroupEntry<'_> {
+        wgpu::BindGroupEntry {
+            binding,
+            resource: self.buffer.as_entire_binding(),
+        }
+    }
+}
+
+/// Many uniforms of the same type packed into a single buffer, each one at its
+/// own dynamic offset, so that a single bind group can serve all of them
+pub struct DynamicUniformBuffer<T: Uniformable> {
+    pub buffer: wgpu::Buffer,
+    capacity: u32,
+    stride: wgpu::BufferAddress,
+    /// Every uniform as of the last `set`, laid out as in the buffer
+    staged: Vec<u8>,
+    _t: PhantomData<T>,
+}
+
+impl<T> DynamicUniformBuffer<T>
+where
+    T: Uniformable,
+{
+    /// Room for `capacity` uniforms, all zeroed until they get `set`
+    pub fn new(device: &wgpu::Device, capacity: u32, label: Option<&str>) -> Self {
+        // Dynamic offsets have to be multiples of `BIND_BUFFER_ALIGNMENT`,
+        // which is as far as wgpu goes for `min_uniform_buffer_offset_alignment`
+        // in this version
+        let alignment = wgpu::BIND_BUFFER_ALIGNMENT;
+        let size = std::mem::size_of::<T::Uniform>() as wgpu::BufferAddress;
+        let stride = size.div_ceil(alignment) * alignment;
+        let staged = vec![0; (capacity.max(1) as wgpu::BufferAddress * stride) as usize];
+
+        Self {
+            buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label,
+                contents: &staged,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }),
+            capacity,
+            stride,
+            staged,
+            _t: PhantomData,
+        }
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Bytes between two consecutive uniforms
+    pub fn stride(&self) -> wgpu::BufferAddress {
+        self.stride
+    }
+
+    /// Size of the whole buffer, padding included
+    pub fn size(&self) -> wgpu::BufferAddress {
+        self.staged.len() as wgpu::BufferAddress
+    }
+
+    /// Dynamic offset binding the `index`-th uniform
+    pub fn offset(&self, index: u32) -> wgpu::DynamicOffset {
+        assert!(index < self.capacity);
+        (index as wgpu::BufferAddress * self.stride) as wgpu::DynamicOffset
+    }
+
+    /// Stages the `index`-th uniform, for the next `upload` to send over
+    pub fn set(&mut self, index: u32, uniform: &T) {
+        let start = self.offset(index) as usize;
+        self.staged[start..start + std::mem::size_of::<T::Uniform>()]
+            .copy_from_slice(bytemuck::bytes_of(&uniform.to_uniform()));
+    }
+
+    /// Uploads every staged uniform at once
+    pub fn upload(&self, encoder: &mut CommandEncoder, stager: &mut Stager) {
+        stager.write_buffer(encoder, &self.buffer, 0, &self.staged);
+    }
+
+    /// Layout entry for binding a single uniform out of the buffer, picked by
+    /// the dynamic offset passed to `set_bind_group`
+    pub fn layout_entry(
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: NonZeroU64::new(std::mem::size_of::<T::Uniform>() as u64),
+            },
+            count: None,
+        }
+    }
+
+    /// Bind group resource matching `layout_entry`
+    pub fn binding(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: NonZeroU64::new(std::mem::size_of::<T::Uniform>() as u64),
         })
     }
 }
 
+/// How many frames a belt has to go through using less than a quarter of its
+/// chunk size before its chunks get shrunk
+const SHRINK_AFTER_FRAMES: u32 = 600;
+
+/// Least chunk size a belt gets shrunk to
+const MIN_CHUNK_SIZE: u64 = 256;
+
+/// What went through a staging belt during the last frame it got written
+/// through
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StagingStats {
+    pub bytes: u64,
+    /// Staging areas handed out, one for every write
+    pub allocations: u32,
+    pub chunk_size: u64,
+    /// How many times the belt's chunks have been resized so far
+    pub resizes: u32,
+}
+
+impl fmt::Display for StagingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes in {} writes, {} byte chunks (resized {} times)",
+            self.bytes, self.allocations, self.chunk_size, self.resizes
+        )
+    }
+}
+
+/// Staging belt along with how much got written through it, so that its chunk
+/// size can be adjusted to what it actually needs
+struct Belt {
+    ring: Ring,
+    chunk_size: u64,
+    /// Bytes written and staging areas handed out since the belt was last
+    /// recalled
+    written: u64,
+    allocations: u32,
+    /// Most bytes written in a single frame since the chunk size was last
+    /// adjusted, and how many frames that was over
+    peak: u64,
+    frames: u32,
+    resizes: u32,
+    last_frame: StagingStats,
+}
+
+impl Belt {
+    fn new(chunk_size: u64) -> Self {
+        Self {
+            ring: Ring::new(chunk_size),
+            chunk_size,
+            written: 0,
+            allocations: 0,
+            peak: 0,
+            frames: 0,
+            resizes: 0,
+            last_frame: StagingStats {
+                chunk_size,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Picks a new chunk size if the last frame didn't fit in a single chunk,
+    /// or if chunks have been much larger than needed for a while. Belts that
+    /// weren't written to at all, like the ones of scenes that aren't being
+    /// shown, are left alone
+    fn adjust(&mut self) -> Option<u64> {
+        let written = std::mem::replace(&mut self.written, 0);
+        let allocations = std::mem::replace(&mut self.allocations, 0);
+        if written == 0 {
+            return None;
+        }
+        self.last_frame = StagingStats {
+            bytes: written,
+            allocations,
+            chunk_size: self.chunk_size,
+            resizes: self.resizes,
+        };
+        self.peak = self.peak.max(written);
+        self.frames += 1;
+
+        let chunk_size = if written > self.chunk_size {
+            written.next_power_of_two()
+        } else if self.frames >= SHRINK_AFTER_FRAMES && self.peak < self.chunk_size / 4 {
+            self.peak.next_power_of_two().max(MIN_CHUNK_SIZE)
+        } else if self.frames >= SHRINK_AFTER_FRAMES {
+            self.peak = 0;
+            self.frames = 0;
+            return None;
+        } else {
+            return None;
+        };
+
+        // The old chunks are done with, as the belt was just recalled
+        self.ring = Ring::new(chunk_size);
+        self.chunk_size = chunk_size;
+        self.peak = 0;
+        self.frames = 0;
+        self.resizes += 1;
+        Some(chunk_size)
+    }
+}
+
+/// How many frames can have their uploads in flight at once. Every frame
+/// writes through its own set of belts, which only get written through again
+/// once the GPU is done with that frame, so encoding a frame doesn't have to
+/// wait on the uploads of the one before it
+const FRAMES_IN_FLIGHT: usize = 2;
+
+/// Belts a single frame writes through, along with a fence on the work
+/// submitted along with that frame, until it's known to be done
+struct FrameBelts {
+    belts: Vec<Mutex<Belt>>,
+    /// Behind a mutex for the factory to be shared between threads encoding
+    /// in parallel, though only `recall_all` ever touches it
+    fence: Mutex<Option<Pending<()>>>,
+}
+
+/// Staging belt registered with `StagingFactory::create_stager`, to fetch a
+/// stager out of
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StagerHandle(usize);
+
+/// Why a staging belt couldn't be used
+#[derive(Debug)]
+pub enum StagingError {
+    /// The belt is still held by a stager that hasn't been dropped yet
+    InUse(String),
+    /// The handle didn't come out of this `StagingFactory`
+    Unknown(StagerHandle),
+}
+
+impl fmt::Display for StagingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StagingError::InUse(name) => write!(f, "staging belt \"{}\" is already in use", name),
+            StagingError::Unknown(handle) => write!(f, "no staging belt for {:?}", handle),
+        }
+    }
+}
+
+impl std::error::Error for StagingError {}
+
 pub struct StagingFactory {
-    belts: HashMap<String, Mutex<wgpu::util::StagingBelt>>,
+    /// Names of the belts, by handle, for printing them
+    names: Vec<String>,
+    frames: Vec<FrameBelts>,
+    /// Which of `frames` is being written through
+    frame: usize,
     device: Arc<wgpu::Device>,
-    local_pool: LocalPool,
+    /// Belt every `Uploader` writes through
+    uploads: Arc<Mutex<Belt>>,
 }
 
 impl StagingFactory {
     pub fn new(device: &Arc<wgpu::Device>) -> Self {
         Self {
-            belts: HashMap::new(),
+            names: Vec::new(),
+            frames: (0..FRAMES_IN_FLIGHT)
+                .map(|_| FrameBelts {
+                    belts: Vec::new(),
+                    fence: Mutex::new(None),
+                })
+                .collect(),
+            frame: 0,
             device: device.clone(),
-            local_pool: LocalPool::new(),
+            uploads: Arc::new(Mutex::new(Belt::new(UPLOAD_CHUNK_SIZE))),
         }
     }
 
-    pub fn create_stager(&mut self, name: String, chunk_size: u64) {
-        assert!(
-            !self.belts.contains_key(&name),
-            "Staging belt \"{}\" was already registered!",
-            name
-        );
-        self.belts
-            .insert(name, Mutex::new(wgpu::util::StagingBelt::new(chunk_size)));
+    /// Uploader for resources created outside of frames. Every uploader out
+    /// of the same factory shares its belt
+    pub fn uploader(&self) -> Uploader {
+        Uploader {
+            device: self.device.clone(),
+            belt: self.uploads.clone(),
+        }
+    }
+
+    /// Registers a belt, whose chunks start out `chunk_size` bytes long. It
+    /// only needs to be a first guess: belts get their chunks grown when a
+    /// frame writes more than that through them, and shrunk when frames have
+    /// been writing much less for a while. `name` is only there for printing
+    /// the belt, i.e. along with its stats. Registering a name again hands out
+    /// the belt it already has, for scenes that get created again after being
+    /// unloaded
+    pub fn create_stager(&mut self, name: &str, chunk_size: u64) -> StagerHandle {
+        if let Some(index) = self.names.iter().position(|n| n == name) {
+            return StagerHandle(index);
+        }
+        self.names.push(name.to_owned());
+        for frame in &mut self.frames {
+            frame.belts.push(Mutex::new(Belt::new(chunk_size)));
+        }
+        StagerHandle(self.names.len() - 1)
     }
 
-    pub fn fetch_stager(&'_ self, name: &str) -> Stager<'_> {
-        let belt = self
+    pub fn try_fetch(&'_ self, handle: StagerHandle) -> Result<Stager<'_>, StagingError> {
+        let belt = self.frames[self.frame]
             .belts
-            .get(name)
-            .expect("Staging belt \"{}\" not initialized")
+            .get(handle.0)
+            .ok_or(StagingError::Unknown(handle))?
             .try_lock()
-            .expect("Staging belt \"{}\" already in use");
+            .map_err(|_| StagingError::InUse(self.names[handle.0].clone()))?;
 
-        assert!(
-            self.belts.contains_key(name),
-            "Staging belt \"{}\" not initialized",
-            name
-        );
+        Ok(Stager {
+            device: self.device.clone(),
+            belt: StagerBelt::Registered(belt),
+            name: &self.names[handle.0],
+        })
+    }
+
+    /// Like `try_fetch`, but rather than failing it prints the error and
+    /// stages through a belt of its own, which only lives as long as the
+    /// stager does. Uploads still make it, just without reusing any chunks
+    pub fn fetch_stager(&'_ self, handle: StagerHandle) -> Stager<'_> {
+        self.try_fetch(handle).unwrap_or_else(|e| {
+            println!("Staging error: {}, staging through a temporary belt", e);
+            Stager {
+                device: self.device.clone(),
+                belt: StagerBelt::Temporary(Belt::new(MIN_CHUNK_SIZE)),
+                name: self.names.get(handle.0).map_or("temporary", String::as_str),
+            }
+        })
+    }
+
+    /// Gets every belt of the current frame ready for submission. A belt that
+    /// is still in use can't be, and neither can what got written through it,
+    /// but the rest still are
+    pub fn submit_all(&mut self) -> Result<(), StagingError> {
+        let mut result = Ok(());
+        for (name, belt) in self.names.iter().zip(&self.frames[self.frame].belts) {
+            match belt.try_lock() {
+                Ok(mut belt) => belt.ring.finish(),
+                Err(_) => result = result.and(Err(StagingError::InUse(name.clone()))),
+            }
+        }
+        result
+    }
+
+    /// What went through every belt during the last frame that wrote through
+    /// it, heaviest first. Belts something is still writing through can't be
+    /// looked at, and come last as `StagingError::InUse`
+    pub fn stats(&self) -> Vec<(&str, Result<StagingStats, StagingError>)> {
+        // The set that was just recalled
+        let frame = (self.frame + FRAMES_IN_FLIGHT - 1) % FRAMES_IN_FLIGHT;
+        let mut stats: Vec<_> = self
+            .names
+            .iter()
+            .zip(&self.frames[frame].belts)
+            .map(|(name, belt)| {
+                let stats = belt
+                    .try_lock()
+                    .map(|belt| belt.last_frame)
+                    .map_err(|_| StagingError::InUse(name.clone()));
+                (name.as_str(), stats)
+            })
+            .collect();
+        stats.sort_by(|(_, a), (_, b)| match (a, b) {
+            (Ok(a), Ok(b)) => b.bytes.cmp(&a.bytes),
+            (a, b) => a.is_err().cmp(&b.is_err()),
+        });
+        stats
+    }
+
+    /// Recalls the belts of the frame that was just submitted, and moves on
+    /// to the next set, waiting on the GPU only if it's still working through
+    /// the frame that set was last used for
+    pub fn recall_all(&mut self, queue: &wgpu::Queue) -> Result<(), StagingError> {
+        let mut result = Ok(());
+        let frame = &mut self.frames[self.frame];
+        for (name, belt) in self.names.iter().zip(&frame.belts) {
+            let mut belt = match belt.try_lock() {
+                Ok(belt) => belt,
+                Err(_) => {
+                    result = result.and(Err(StagingError::InUse(name.clone())));
+                    continue;
+                }
+            };
+            belt.ring.recall();
 
-        Stager {
+            let previous = belt.chunk_size;
+            if let Some(chunk_size) = belt.adjust() {
+                println!(
+                    "Staging belt \"{}\" resized its chunks from {} to {} bytes",
+                    name, previous, chunk_size
+                );
+            }
+        }
+        *frame.fence.get_mut().unwrap() = Some(Box::pin(queue.on_submitted_work_done()));
+
+        self.frame = (self.frame + 1) % FRAMES_IN_FLIGHT;
+        if let Some(mut fence) = self.frames[self.frame].fence.get_mut().unwrap().take() {
+            // Only blocks if the GPU is still working through that frame
+            self.device.poll(wgpu::Maintain::Poll);
+            while ring::poll_now(&mut fence).is_none() {
+                self.device.poll(wgpu::Maintain::Wait);
+            }
+        }
+
+        result
+    }
+}
+
+/// Chunk size of the belt uploads go through. Loads are much larger than
+/// what frames write, and only one happens at a time
+const UPLOAD_CHUNK_SIZE: u64 = 1 << 20;
+
+/// Uploads resources created outside of frames, i.e. while loading models or
+/// generating meshes, through a belt that is kept around for every load,
+/// rather than every resource allocating an init buffer of its own
+#[derive(Clone)]
+pub struct Uploader {
+    device: Arc<wgpu::Device>,
+    belt: Arc<Mutex<Belt>>,
+}
+
+impl Uploader {
+    /// Runs `f` on an encoder of its own, which gets submitted to `queue` as
+    /// soon as `f` returns. Waits on the GPU to be done with the upload, so
+    /// that the belt's chunks are free again for the next one
+    pub fn upload<R>(
+        &self,
+        queue: &wgpu::Queue,
+        f: impl FnOnce(&mut CommandEncoder, &mut Stager) -> R,
+    ) -> R {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Staging - Upload Encoder"),
+            });
+        // Only taken while uploading, so it's only in use if `f` uploads too
+        let belt = match self.belt.try_lock() {
+            Ok(belt) => StagerBelt::Registered(belt),
+            Err(_) => {
+                println!(
+                    "Staging error: {}, staging through a temporary belt",
+                    StagingError::InUse("uploads".to_owned())
+                );
+                StagerBelt::Temporary(Belt::new(UPLOAD_CHUNK_SIZE))
+            }
+        };
+        let mut stager = Stager {
             device: self.device.clone(),
             belt,
-        }
+            name: "uploads",
+        };
+
+        let result = f(&mut encoder, &mut stager);
+
+        stager.belt.ring.finish();
+        queue.submit(std::iter::once(encoder.finish()));
+        stager.belt.ring.recall();
+        self.device.poll(wgpu::Maintain::Wait);
+
+        // Uploads don't show up in the stats, nor get the belt resized
+        stager.belt.written = 0;
+        stager.belt.allocations = 0;
+        result
     }
+}
+
+/// Belt a stager writes through
+enum StagerBelt<'factory> {
+    Registered(MutexGuard<'factory, Belt>),
+    /// Handed out by `StagingFactory::fetch_stager` and `Uploader::upload`
+    /// when the registered belt couldn't be used, and finished as soon as the
+    /// stager is dropped
+    Temporary(Belt),
+}
 
-    pub fn submit_all(&mut self) {
-        for belt in self.belts.values() {
-            let mut belt = belt
-                .try_lock()
-                .expect("for some reason, this belt is still locked!");
-            belt.finish();
+impl<'factory> Deref for StagerBelt<'factory> {
+    type Target = Belt;
+
+    fn deref(&self) -> &Belt {
+        match self {
+            StagerBelt::Registered(belt) => belt,
+            StagerBelt::Temporary(belt) => belt,
         }
     }
+}
 
-    pub fn recall_all(&mut self) {
-        for belt in self.belts.values() {
-            let mut belt = belt
-                .try_lock()
-                .expect("for some reason, this belt is still locked!");
-            use futures::task::SpawnExt;
-            self.local_pool.spawner().spawn(belt.recall()).unwrap();
-            self.local_pool.run_until_stalled();
+impl<'factory> DerefMut for StagerBelt<'factory> {
+    fn deref_mut(&mut self) -> &mut Belt {
+        match self {
+            StagerBelt::Registered(belt) => belt,
+            StagerBelt::Temporary(belt) => belt,
         }
     }
 }
 
 pub struct Stager<'factory> {
     device: Arc<wgpu::Device>,
-    belt: MutexGuard<'factory, wgpu::util::StagingBelt>,
+    belt: StagerBelt<'factory>,
+    /// Name of the belt, marking every copy out of it in captures
+    name: &'factory str,
+}
+
+impl<'factory> Drop for Stager<'factory> {
+    fn drop(&mut self) {
+        // Nothing is going to finish it later on, and it has to be before
+        // the encoder gets submitted
+        if let StagerBelt::Temporary(belt) = &mut self.belt {
+            belt.ring.finish();
+        }
+    }
 }
 
 impl<'factory> Stager<'factory> {
@@ -260,8 +1233,16 @@ impl<'factory> Stager<'factory> {
         target: &wgpu::Buffer,
         offset: wgpu::BufferAddress,
         size: NonZeroU64,
-    ) -> BufferViewMut {
+    ) -> BufferViewMut<'_> {
+        // Every write starts aligned within its chunk
+        let align = wgpu::MAP_ALIGNMENT;
+        self.belt.written += size.get().div_ceil(align) * align;
+        self.belt.allocations += 1;
+        if cfg!(feature = "debug-labels") {
+            encoder.insert_debug_marker(&format!("Staging - {}", self.name));
+        }
         self.belt
+            .ring
             .write_buffer(encoder, target, offset, size, &self.device)
     }
 
@@ -280,4 +1261,51 @@ impl<'factory> Stager<'factory> {
         );
         staging_buffer.copy_from_slice(data);
     }
+
+    /// Same as `write_buffer`, but zero-pads `data` up to a multiple of
+    /// `COPY_BUFFER_ALIGNMENT`, which copies have to be. `target` needs room
+    /// for the padding. Writing nothing is fine
+    pub fn write_padded(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        target: &wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        data: &[u8],
+    ) {
+        let align = wgpu::COPY_BUFFER_ALIGNMENT;
+        let size = (data.len() as wgpu::BufferAddress).div_ceil(align) * align;
+        if let Some(size) = NonZeroU64::new(size) {
+            let mut staging_buffer = self.create_staging_area(encoder, target, offset, size);
+            staging_buffer[..data.len()].copy_from_slice(data);
+            staging_buffer[data.len()..].fill(0);
+        }
+    }
+
+    /// Writes `value` over the `index`th `T` of `target`
+    pub fn write<T: bytemuck::Pod>(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        target: &wgpu::Buffer,
+        index: wgpu::BufferAddress,
+        value: &T,
+    ) {
+        let size = std::mem::size_of::<T>() as wgpu::BufferAddress;
+        self.write_buffer(encoder, target, index * size, bytemuck::bytes_of(value));
+    }
+
+    /// Writes `values` over the `T`s of `target` starting from the `first`th
+    /// one. Writing nothing is fine, unlike with `write_buffer`
+    pub fn write_slice<T: bytemuck::Pod>(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        target: &wgpu::Buffer,
+        first: wgpu::BufferAddress,
+        values: &[T],
+    ) {
+        if values.is_empty() {
+            return;
+        }
+        let size = std::mem::size_of::<T>() as wgpu::BufferAddress;
+        self.write_buffer(encoder, target, first * size, bytemuck::cast_slice(values));
+    }
 }
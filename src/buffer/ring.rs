@@ -0,0 +1,168 @@
+//! Ring allocator staging belts write through, in place of
+//! `wgpu::util::StagingBelt`.
+//!
+//! A ring is a loop of staging chunks, every one of them a `MAP_WRITE` buffer
+//! that stays mapped for as long as the CPU is the one using it. Writes get
+//! carved out of the chunk at the head of the ring, aligned to
+//! `MAP_ALIGNMENT`, and the head moves on to the next chunk once it's full.
+//! Chunks only get unmapped when the frame writing through them gets
+//! submitted, and mapped again once the GPU is done copying out of them, so
+//! the next chunk along is usually free by the time the head gets to it. When
+//! it isn't, a new one is put in the ring right after the head.
+//!
+//! Nothing gets spawned to wait on mappings: they're futures that only make
+//! progress when the device gets polled, so they're polled by hand whenever a
+//! chunk is needed, and whatever isn't mapped yet is just skipped over.
+
+use std::{
+    future::Future,
+    num::NonZeroU64,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use wgpu::{BufferAddress, BufferViewMut, CommandEncoder};
+
+/// Future that gets polled by hand rather than spawned
+pub(super) type Pending<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Polls `future` once, which is all it takes for the futures out of wgpu as
+/// long as the device got polled since they became ready
+pub(super) fn poll_now<T>(future: &mut Pending<T>) -> Option<T> {
+    match future
+        .as_mut()
+        .poll(&mut Context::from_waker(futures::task::noop_waker_ref()))
+    {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => None,
+    }
+}
+
+enum State {
+    /// Mapped, taking writes from `Chunk::offset` on
+    Mapped,
+    /// Unmapped, with copies out of it submitted or about to be
+    Closed,
+    /// Waiting on the GPU to be done with it to be mapped again
+    Mapping(Pending<Result<(), wgpu::BufferAsyncError>>),
+}
+
+struct Chunk {
+    buffer: wgpu::Buffer,
+    size: BufferAddress,
+    /// Where the next write starts
+    offset: BufferAddress,
+    state: State,
+}
+
+impl Chunk {
+    /// Whether the chunk is mapped and has room for `size` more bytes
+    fn fits(&mut self, size: BufferAddress) -> bool {
+        if let State::Mapping(mapping) = &mut self.state {
+            match poll_now(mapping) {
+                Some(Ok(())) => {
+                    self.state = State::Mapped;
+                    self.offset = 0;
+                }
+                // Mapped again with the next recall
+                Some(Err(_)) => self.state = State::Closed,
+                None => {}
+            }
+        }
+        matches!(self.state, State::Mapped) && align(self.offset) + size <= self.size
+    }
+}
+
+fn align(offset: BufferAddress) -> BufferAddress {
+    let align = wgpu::MAP_ALIGNMENT;
+    offset.div_ceil(align) * align
+}
+
+pub(super) struct Ring {
+    chunk_size: BufferAddress,
+    chunks: Vec<Chunk>,
+    /// Chunk writes are being carved out of
+    head: usize,
+}
+
+impl Ring {
+    pub fn new(chunk_size: BufferAddress) -> Self {
+        Self {
+            chunk_size,
+            chunks: Vec::new(),
+            head: 0,
+        }
+    }
+
+    /// Hands out `size` bytes of staging memory, to be copied over to
+    /// `target` at `offset` when `encoder` gets submitted
+    pub fn write_buffer(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        target: &wgpu::Buffer,
+        offset: BufferAddress,
+        size: NonZeroU64,
+        device: &wgpu::Device,
+    ) -> BufferViewMut<'_> {
+        let size = size.get();
+
+        // The head, then every other chunk in the order the head gets to them
+        let (head, chunks) = (self.head, &mut self.chunks);
+        let len = chunks.len();
+        let found = (0..len)
+            .map(|i| (head + i) % len)
+            .find(|&i| chunks[i].fits(size));
+        self.head = match found {
+            Some(i) => i,
+            None => {
+                let chunk_size = self.chunk_size.max(size);
+                let chunk = Chunk {
+                    buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("Staging - Ring Chunk"),
+                        size: chunk_size,
+                        usage: wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+                        mapped_at_creation: true,
+                    }),
+                    size: chunk_size,
+                    offset: 0,
+                    state: State::Mapped,
+                };
+                let i = if len == 0 { 0 } else { head + 1 };
+                self.chunks.insert(i, chunk);
+                i
+            }
+        };
+
+        let chunk = &mut self.chunks[self.head];
+        let start = align(chunk.offset);
+        chunk.offset = start + size;
+        encoder.copy_buffer_to_buffer(&chunk.buffer, start, target, offset, size);
+        chunk
+            .buffer
+            .slice(start..start + size)
+            .get_mapped_range_mut()
+    }
+
+    /// Unmaps every chunk that got written to, so that the copies out of them
+    /// can be submitted
+    pub fn finish(&mut self) {
+        for chunk in self.chunks.iter_mut() {
+            if matches!(chunk.state, State::Mapped) && chunk.offset > 0 {
+                chunk.buffer.unmap();
+                chunk.state = State::Closed;
+            }
+        }
+    }
+
+    /// Starts mapping the chunks that were finished again, once whatever got
+    /// submitted along with them is done
+    pub fn recall(&mut self) {
+        for chunk in self.chunks.iter_mut() {
+            if let State::Closed = chunk.state {
+                chunk.state = State::Mapping(Box::pin(
+                    chunk.buffer.slice(..).map_async(wgpu::MapMode::Write),
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+use crate::shader::ShaderId;
+
+/// Addresses of a pipeline layout's bind group layouts, and its push constant
+/// ranges as stages, start and end
+type PipelineLayoutKey = (Vec<usize>, Vec<(u32, u32, u32)>);
+
+type Cache<K, T> = Mutex<HashMap<K, Arc<T>>>;
+
+/// Hands out a single bind group layout for every set of entries asked for,
+/// so that scenes describing the same bindings end up with the same layout
+/// (and pipelines built out of it can take each other's bind groups). Same
+/// for pipeline layouts, which also get an id for `PipelineCache` to tell
+/// them apart by
+#[derive(Default)]
+pub struct LayoutCache {
+    /// Keyed on the entries sorted by binding, labels left out
+    layouts: Mutex<HashMap<Vec<wgpu::BindGroupLayoutEntry>, Arc<wgpu::BindGroupLayout>>>,
+    /// Keyed on the addresses of their bind group layouts, which stay put as
+    /// every one of them is kept alive in `layouts`, and on their push
+    /// constant ranges
+    pipeline_layouts: Mutex<HashMap<PipelineLayoutKey, CachedPipelineLayout>>,
+}
+
+/// Identifies a pipeline layout out of a `LayoutCache`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LayoutId(usize);
+
+/// Pipeline layout out of a `LayoutCache`, along with its id
+#[derive(Clone)]
+pub struct CachedPipelineLayout {
+    pub id: LayoutId,
+    layout: Arc<wgpu::PipelineLayout>,
+}
+
+impl Deref for CachedPipelineLayout {
+    type Target = wgpu::PipelineLayout;
+
+    fn deref(&self) -> &wgpu::PipelineLayout {
+        &self.layout
+    }
+}
+
+impl LayoutCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Same as `Device::create_bind_group_layout`, unless a layout with the
+    /// same entries already exists, in which case it gets shared (label
+    /// included)
+    pub fn get(
+        &self,
+        device: &wgpu::Device,
+        desc: &wgpu::BindGroupLayoutDescriptor,
+    ) -> Arc<wgpu::BindGroupLayout> {
+        let mut key = desc.entries.to_vec();
+        key.sort_by_key(|entry| entry.binding);
+
+        self.layouts
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Arc::new(device.create_bind_group_layout(desc)))
+            .clone()
+    }
+
+    /// Same as `Device::create_pipeline_layout`, unless a layout with the
+    /// same bind group layouts and push constants already exists, in which
+    /// case it gets shared (label included). Every bind group layout has to
+    /// come out of `get`
+    pub fn pipeline_layout(
+        &self,
+        device: &wgpu::Device,
+        desc: &wgpu::PipelineLayoutDescriptor,
+    ) -> CachedPipelineLayout {
+        let bind_group_layouts = {
+            let layouts = self.layouts.lock().unwrap();
+            desc.bind_group_layouts
+                .iter()
+                .map(|&layout| {
+                    let address = layout as *const wgpu::BindGroupLayout as usize;
+                    assert!(
+                        layouts
+                            .values()
+                            .any(|cached| Arc::as_ptr(cached) as usize == address),
+                        "{:?} has a bind group layout that isn't out of the cache",
+                        desc.label
+                    );
+                    address
+                })
+                .collect()
+        };
+        let push_constant_ranges = desc
+            .push_constant_ranges
+            .iter()
+            .map(|range| (range.stages.bits(), range.range.start, range.range.end))
+            .collect();
+
+        let mut pipeline_layouts = self.pipeline_layouts.lock().unwrap();
+        let id = LayoutId(pipeline_layouts.len());
+        pipeline_layouts
+            .entry((bind_group_layouts, push_constant_ranges))
+            .or_insert_with(|| CachedPipelineLayout {
+                id,
+                layout: Arc::new(device.create_pipeline_layout(desc)),
+            })
+            .clone()
+    }
+}
+
+/// What a pipeline gets built out of, which wgpu has no way of comparing by
+/// itself: its layout and shaders, as ids of where they came from
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineSources {
+    pub layout: LayoutId,
+    pub vertex: ShaderId,
+    pub fragment: Option<ShaderId>,
+}
+
+/// Everything else in a `RenderPipelineDescriptor`, labels left out, owned
+/// so that it can be part of the key
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PipelineState {
+    vertex_entry_point: String,
+    /// Stride, step mode and attributes of every vertex buffer
+    buffers: Vec<(
+        wgpu::BufferAddress,
+        wgpu::VertexStepMode,
+        Vec<wgpu::VertexAttribute>,
+    )>,
+    fragment: Option<(String, Vec<wgpu::ColorTargetState>)>,
+    primitive: wgpu::PrimitiveState,
+    /// Depth bias is made of floats, so they're compared by their bits
+    depth_stencil: Option<(
+        wgpu::TextureFormat,
+        bool,
+        wgpu::CompareFunction,
+        wgpu::StencilState,
+        [u32; 3],
+    )>,
+    multisample: wgpu::MultisampleState,
+}
+
+impl PipelineState {
+    fn of(desc: &wgpu::RenderPipelineDescriptor) -> Self {
+        Self {
+            vertex_entry_point: desc.vertex.entry_point.to_owned(),
+            buffers: desc
+                .vertex
+                .buffers
+                .iter()
+                .map(|buffer| {
+                    (
+                        buffer.array_stride,
+                        buffer.step_mode,
+                        buffer.attributes.to_vec(),
+                    )
+                })
+                .collect(),
+            fragment: desc
+                .fragment
+                .as_ref()
+                .map(|fragment| (fragment.entry_point.to_owned(), fragment.targets.to_vec())),
+            primitive: desc.primitive,
+            depth_stencil: desc.depth_stencil.as_ref().map(|depth_stencil| {
+                (
+                    depth_stencil.format,
+                    depth_stencil.depth_write_enabled,
+                    depth_stencil.depth_compare,
+                    depth_stencil.stencil.clone(),
+                    [
+                        depth_stencil.bias.constant as u32,
+                        depth_stencil.bias.slope_scale.to_bits(),
+                        depth_stencil.bias.clamp.to_bits(),
+                    ],
+                )
+            }),
+            multisample: desc.multisample,
+        }
+    }
+}
+
+/// Hands out a single render pipeline for every combination of shaders,
+/// vertex layouts, color targets, depth/stencil and primitive state asked for,
+/// so that flipping an option back and forth (or coming back to a scene)
+/// doesn't build the same pipeline over again. Clones share the same
+/// pipelines, for scenes that need to keep building them after `Scene::new`
+#[derive(Clone, Default)]
+pub struct PipelineCache {
+    pipelines: Arc<Cache<(PipelineSources, PipelineState), wgpu::RenderPipeline>>,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Same as `Device::create_render_pipeline`, unless a pipeline with the
+    /// same sources and state already exists, in which case it gets shared
+    /// (label included). `desc`'s layout and shader modules have to be the
+    /// ones `sources` identifies
+    pub fn get(
+        &self,
+        device: &wgpu::Device,
+        sources: PipelineSources,
+        desc: &wgpu::RenderPipelineDescriptor,
+    ) -> Arc<wgpu::RenderPipeline> {
+        self.pipelines
+            .lock()
+            .unwrap()
+            .entry((sources, PipelineState::of(desc)))
+            .or_insert_with(|| Arc::new(device.create_render_pipeline(desc)))
+            .clone()
+    }
+}
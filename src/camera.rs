@@ -1,7 +1,7 @@
-use cgmath::Vector3;
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use std::time::Instant;
 
-use crate::buffer::OldUniform;
+use crate::{input::Input, layout::Std140, mesh::BoundingSphere};
+use cgmath::Vector3;
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -11,99 +11,240 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    /// Vertical field of view, in degrees
+    Perspective { fovy: f32 },
+    /// How much of the world fits from the bottom of the screen to the top,
+    /// however far away it is
+    Orthographic { height: f32 },
+}
+
 pub struct Camera {
     pub eye: cgmath::Point3<f32>,
     pub target: cgmath::Point3<f32>,
     pub up: cgmath::Vector3<f32>,
     pub aspect: f32,
-    pub fovy: f32,
+    pub projection: Projection,
     pub znear: f32,
     pub zfar: f32,
 }
 
 impl Camera {
+    /// World space to view space, looking from the eye towards the target
+    pub fn build_view_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_at(self.eye, self.target, self.up)
+    }
+
+    /// View space to clip space, already in wgpu's 0 to 1 depth range
+    pub fn build_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        let projection = match self.projection {
+            Projection::Perspective { fovy } => {
+                cgmath::perspective(cgmath::Deg(fovy), self.aspect, self.znear, self.zfar)
+            }
+            Projection::Orthographic { height } => {
+                let (half_width, half_height) = (height * self.aspect / 2.0, height / 2.0);
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.znear,
+                    self.zfar,
+                )
+            }
+        };
+
+        OPENGL_TO_WGPU_MATRIX * projection
+    }
+
     pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at(self.eye, self.target, self.up);
+        self.build_projection_matrix() * self.build_view_matrix()
+    }
+
+    /// Switches between perspective and orthographic projections, framing
+    /// whatever is at the target the same way in both
+    pub fn switch_projection(&mut self) {
+        use cgmath::InnerSpace;
+
+        let distance = (self.target - self.eye).magnitude();
+        self.projection = match self.projection {
+            Projection::Perspective { fovy } => Projection::Orthographic {
+                height: 2.0 * distance * (fovy.to_radians() / 2.0).tan(),
+            },
+            Projection::Orthographic { height } => Projection::Perspective {
+                fovy: 2.0 * (height / 2.0 / distance).atan().to_degrees(),
+            },
+        };
+    }
 
-        let projection =
-            cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.build_view_projection_matrix())
+    }
+
+    /// Ray going from the near plane through a point on screen, given in
+    /// normalized device coordinates (x right, y up, both from -1 to 1)
+    pub fn screen_ray(&self, x: f32, y: f32) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
+        use cgmath::{InnerSpace, SquareMatrix, Transform};
+
+        let inverse = self
+            .build_view_projection_matrix()
+            .invert()
+            .expect("Camera view-projection matrix should be invertible");
+        let near = inverse.transform_point(cgmath::Point3::new(x, y, 0.0));
+        let far = inverse.transform_point(cgmath::Point3::new(x, y, 1.0));
+        (near, (far - near).normalize())
+    }
+}
+
+/// How far dragging a finger across the screen orbits the camera, per pixel
+/// and unit of distance to the target
+const TOUCH_ORBIT: f32 = 0.005;
+
+/// Longest step the controllers take at once, so that coming back to a scene
+/// after a while doesn't throw the camera away
+const MAX_STEP: f32 = 0.1;
+
+/// How camera controllers ease into moving and back out of it, instead of
+/// starting and stopping the moment keys are pressed and released
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Smoothing {
+    /// How quickly movement catches up with the keys held down, per second
+    pub acceleration: f32,
+    /// How quickly movement dies down once they're released, per second
+    pub damping: f32,
+}
 
-        OPENGL_TO_WGPU_MATRIX * projection * view
+impl Default for Smoothing {
+    fn default() -> Self {
+        Self {
+            acceleration: 12.0,
+            damping: 8.0,
+        }
+    }
+}
+
+impl Smoothing {
+    /// Moves `velocity` towards the one the keys ask for. Exponentially, so
+    /// that it eases the same way whatever the frame rate
+    fn approach(&self, velocity: Vector3<f32>, wanted: Vector3<f32>, dt: f32) -> Vector3<f32> {
+        use cgmath::InnerSpace;
+
+        let rate = if wanted.magnitude2() > 0.0 {
+            self.acceleration
+        } else {
+            self.damping
+        };
+        velocity + (wanted - velocity) * (1.0 - (-rate * dt).exp())
     }
 }
 
+/// Orbits the camera around its target, and zooms towards it with the mouse
+/// wheel. On touch screens, dragging a finger orbits and pinching zooms
 pub struct CameraController {
+    /// Units per second
     speed: f32,
-    is_up_pressed: bool,
-    is_down_pressed: bool,
-    is_forward_pressed: bool,
-    is_backward_pressed: bool,
-    is_left_pressed: bool,
-    is_right_pressed: bool,
+    smoothing: Smoothing,
+    /// How much closer every line of the mouse wheel gets, as a fraction of
+    /// the distance left
+    zoom_sensitivity: f32,
+    /// Closest to the target zooming gets. Also the smallest height
+    /// orthographic projections zoom in to
+    min_distance: f32,
+    /// Right, up and forward, in units per second
+    velocity: Vector3<f32>,
+    /// Recorded with the `camera.*_path` bindings, and flown through instead
+    /// of following input while it plays
+    path: CameraPath,
+    last_update: Instant,
 }
 
 impl CameraController {
     pub fn new(speed: f32) -> Self {
+        use cgmath::Zero;
+
         Self {
             speed,
-            is_up_pressed: false,
-            is_down_pressed: false,
-            is_forward_pressed: false,
-            is_backward_pressed: false,
-            is_left_pressed: false,
-            is_right_pressed: false,
-        }
-    }
-
-    pub fn input(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        state,
-                        virtual_keycode: Some(keycode),
-                        ..
-                    },
-                ..
-            } => {
-                let is_pressed = *state == ElementState::Pressed;
-                match keycode {
-                    VirtualKeyCode::Q => {
-                        self.is_up_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::E => {
-                        self.is_down_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::W | VirtualKeyCode::Up => {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::A | VirtualKeyCode::Left => {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::S | VirtualKeyCode::Down => {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::D | VirtualKeyCode::Right => {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    _ => false,
-                }
-            }
-            _ => false,
+            smoothing: Smoothing::default(),
+            zoom_sensitivity: 0.1,
+            min_distance: 0.25,
+            velocity: Vector3::zero(),
+            path: CameraPath::new(2.0),
+            last_update: Instant::now(),
         }
     }
 
-    pub fn update(&self, camera: &mut Camera) {
-        use cgmath::InnerSpace;
+    pub fn with_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    pub fn with_zoom(mut self, sensitivity: f32, min_distance: f32) -> Self {
+        self.zoom_sensitivity = sensitivity;
+        self.min_distance = min_distance;
+        self
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &Input) {
+        use cgmath::{InnerSpace, Zero};
+
+        let now = Instant::now();
+        let dt = (now - self.last_update).as_secs_f32().min(MAX_STEP);
+        self.last_update = now;
+
+        if let Some(key) = input.just_pressed("camera.record_keyframe") {
+            println!("Pressed {:?}", key);
+            self.path.record(camera);
+            println!(
+                "Keyframe {}: eye {:?}, target {:?}",
+                self.path.keyframe_count(),
+                camera.eye,
+                camera.target
+            );
+        }
+        if let Some(key) = input.just_pressed("camera.play_path") {
+            println!("Pressed {:?}", key);
+            if self.path.playing() {
+                self.path.stop();
+            } else if self.path.keyframe_count() < 2 {
+                println!("Camera paths need at least two keyframes");
+            } else {
+                self.path.play();
+            }
+            println!("Playing camera path: {:?}", self.path.playing());
+        }
+        if let Some(key) = input.just_pressed("camera.clear_path") {
+            println!("Pressed {:?}", key);
+            self.path.clear();
+            println!("Camera path cleared");
+        }
+
+        if self.path.playing() {
+            self.velocity = Vector3::zero();
+            self.path.advance(camera, dt);
+            return;
+        }
+
+        let axis = |positive, negative| match (input.pressed(positive), input.pressed(negative)) {
+            (true, false) => 1.0,
+            (false, true) => -1.0,
+            _ => 0.0,
+        };
+        let wanted = Vector3::new(
+            axis("camera.right", "camera.left"),
+            axis("camera.up", "camera.down"),
+            axis("camera.forward", "camera.backward"),
+        ) * self.speed;
+        self.velocity = self.smoothing.approach(self.velocity, wanted, dt);
+        let mut step = self.velocity * dt;
 
         // Get the forward vector, and normalize it
         let forward: Vector3<f32> = camera.target - camera.eye;
+
+        // Dragging a finger drags the world along with it
+        let (touch_x, touch_y) = input.touch_drag();
+        step.x -= touch_x as f32 * TOUCH_ORBIT * forward.magnitude();
+        step.y += touch_y as f32 * TOUCH_ORBIT * forward.magnitude();
         let forward_norm = forward.normalize();
 
         // Get its length
@@ -111,11 +252,8 @@ impl CameraController {
 
         // Forward/backwards movement
         // Forward movement is limited in order to avoid near clipping issues
-        if self.is_forward_pressed && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
-        }
-        if self.is_backward_pressed {
-            camera.eye -= forward_norm * self.speed;
+        if forward_mag > step.z {
+            camera.eye += forward_norm * step.z;
         }
 
         // Redo radius calc in case the directional keys are pressed
@@ -123,55 +261,394 @@ impl CameraController {
         let forward_mag = forward.magnitude();
 
         // Up/down movement
-        camera.eye = match (self.is_up_pressed, self.is_down_pressed) {
-            (true, true) | (false, false) => camera.eye,
-            (true, false) => {
-                // Rescale the distance between the target and eye so
-                // that it doesn't change. The eye therefore still
-                // lies on the circle made by the target and eye.
-                camera.target - (forward + camera.up * self.speed).normalize() * forward_mag
-            }
-            (false, true) => {
-                camera.target - (forward - camera.up * self.speed).normalize() * forward_mag
-            }
-        };
+        // Rescale the distance between the target and eye so that it doesn't
+        // change. The eye therefore still lies on the circle made by the
+        // target and eye.
+        if step.y != 0.0 {
+            camera.eye = camera.target - (forward + camera.up * step.y).normalize() * forward_mag;
+        }
 
         // Get the right-facing vector as the cross product
         // of the forward and up vectors
         let right = forward_norm.cross(camera.up);
 
         // Left/right movement
-        camera.eye = match (self.is_left_pressed, self.is_right_pressed) {
-            (true, true) | (false, false) => camera.eye,
-            (true, false) => {
-                camera.target - (forward + right * self.speed).normalize() * forward_mag
-            }
-            (false, true) => {
-                camera.target - (forward - right * self.speed).normalize() * forward_mag
+        if step.x != 0.0 {
+            camera.eye = camera.target - (forward - right * step.x).normalize() * forward_mag;
+        }
+
+        // Zoom, scrolling up or spreading fingers apart to get closer.
+        // Perspective projections get closer by moving the eye, orthographic
+        // ones by seeing less of the world
+        let (lines, pinch) = (input.wheel(), input.pinch() as f32);
+        if lines != 0.0 || pinch != 1.0 {
+            let factor = (1.0 - self.zoom_sensitivity).powf(lines) / pinch;
+            match &mut camera.projection {
+                Projection::Perspective { .. } => {
+                    let offset = camera.eye - camera.target;
+                    let distance = (offset.magnitude() * factor).max(self.min_distance);
+                    camera.eye = camera.target + offset.normalize() * distance;
+                }
+                Projection::Orthographic { height } => {
+                    *height = (*height * factor).max(self.min_distance);
+                }
             }
+        }
+    }
+}
+
+/// Keyframed eye and target positions for the camera to fly through, as
+/// repeatable as it gets for benchmarking or capturing video. Goes through
+/// every keyframe along a Catmull-Rom spline, easing out of the first one and
+/// into the last one
+pub struct CameraPath {
+    keyframes: Vec<(cgmath::Point3<f32>, cgmath::Point3<f32>)>,
+    /// Seconds spent going from a keyframe to the next
+    segment_duration: f32,
+    /// Seconds since playback started, if it's playing
+    time: Option<f32>,
+}
+
+impl CameraPath {
+    pub fn new(segment_duration: f32) -> Self {
+        Self {
+            keyframes: Vec::new(),
+            segment_duration,
+            time: None,
+        }
+    }
+
+    /// Adds a keyframe wherever the camera is now
+    pub fn record(&mut self, camera: &Camera) {
+        self.keyframes.push((camera.eye, camera.target));
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.time = None;
+    }
+
+    pub fn keyframe_count(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    pub fn playing(&self) -> bool {
+        self.time.is_some()
+    }
+
+    /// Starts over from the first keyframe. Does nothing without at least two
+    pub fn play(&mut self) {
+        if self.keyframes.len() >= 2 {
+            self.time = Some(0.0);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.time = None;
+    }
+
+    /// Moves the camera `dt` seconds further along the path, stopping once
+    /// it gets to the last keyframe
+    pub fn advance(&mut self, camera: &mut Camera, dt: f32) {
+        let time = match &mut self.time {
+            Some(time) => time,
+            None => return,
         };
+        *time += dt;
+
+        let segments = self.keyframes.len() - 1;
+        let duration = self.segment_duration * segments as f32;
+        let t = (*time / duration).min(1.0);
+        if t >= 1.0 {
+            self.time = None;
+        }
+
+        // Smoothstep over the whole path, rather than every segment, so that
+        // it only slows down at the ends
+        let eased = t * t * (3.0 - 2.0 * t) * segments as f32;
+        let segment = (eased.floor() as usize).min(segments - 1);
+        let u = eased - segment as f32;
+
+        // The ends repeat themselves to have neighbours to curve towards
+        let keyframe = |i: isize| self.keyframes[i.clamp(0, segments as isize) as usize];
+        let i = segment as isize;
+        let (p0, p1, p2, p3) = (
+            keyframe(i - 1),
+            keyframe(i),
+            keyframe(i + 1),
+            keyframe(i + 2),
+        );
+        camera.eye = catmull_rom(p0.0, p1.0, p2.0, p3.0, u);
+        camera.target = catmull_rom(p0.1, p1.1, p2.1, p3.1, u);
     }
 }
 
+/// Point `u` of the way from `p1` to `p2`, along a curve that also passes
+/// through `p0` and `p3`
+fn catmull_rom(
+    p0: cgmath::Point3<f32>,
+    p1: cgmath::Point3<f32>,
+    p2: cgmath::Point3<f32>,
+    p3: cgmath::Point3<f32>,
+    u: f32,
+) -> cgmath::Point3<f32> {
+    use cgmath::EuclideanSpace;
+
+    let (p0, p1, p2, p3) = (p0.to_vec(), p1.to_vec(), p2.to_vec(), p3.to_vec());
+    let (u2, u3) = (u * u, u * u * u);
+    cgmath::Point3::from_vec(
+        (p1 * 2.0
+            + (p2 - p0) * u
+            + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * u2
+            + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * u3)
+            * 0.5,
+    )
+}
+
+/// Keeps the camera from looking straight up or down, where yaw stops making
+/// sense
+const MAX_PITCH: f32 = 1.5;
+
+/// First-person controls: looking around with raw mouse motion, and flying
+/// wherever the camera looks with the `camera` bindings (WASD, plus Q and E
+/// to go up and down), at the same speed whatever the frame rate. Assumes Y
+/// is up
+pub struct FlyCameraController {
+    /// Units per second
+    speed: f32,
+    /// Radians per unit of mouse motion
+    sensitivity: f32,
+    smoothing: Smoothing,
+    /// In units per second
+    velocity: Vector3<f32>,
+    /// Around Y, in radians, 0 looking towards +X
+    yaw: f32,
+    /// In radians, 0 looking at the horizon
+    pitch: f32,
+    last_update: Instant,
+}
+
+impl FlyCameraController {
+    pub fn new(speed: f32, sensitivity: f32, camera: &Camera) -> Self {
+        use cgmath::Zero;
+
+        let mut controller = Self {
+            speed,
+            sensitivity,
+            smoothing: Smoothing::default(),
+            velocity: Vector3::zero(),
+            yaw: 0.0,
+            pitch: 0.0,
+            last_update: Instant::now(),
+        };
+        controller.look_along(camera);
+        controller
+    }
+
+    pub fn with_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Picks up wherever the camera is looking, and starts timing anew, i.e.
+    /// when switching over from another controller
+    pub fn look_along(&mut self, camera: &Camera) {
+        use cgmath::InnerSpace;
+
+        let direction = (camera.target - camera.eye).normalize();
+        self.yaw = direction.z.atan2(direction.x);
+        self.pitch = direction.y.asin().clamp(-MAX_PITCH, MAX_PITCH);
+        self.velocity = cgmath::Zero::zero();
+        self.last_update = Instant::now();
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &Input) {
+        use cgmath::{InnerSpace, Zero};
+
+        let now = Instant::now();
+        let dt = (now - self.last_update).as_secs_f32().min(MAX_STEP);
+        self.last_update = now;
+
+        let (dx, dy) = input.mouse_delta();
+        self.yaw += dx as f32 * self.sensitivity;
+        self.pitch = (self.pitch - dy as f32 * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+
+        let forward = Vector3::new(
+            self.yaw.cos() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.sin() * self.pitch.cos(),
+        );
+        let right = forward.cross(Vector3::unit_y()).normalize();
+
+        let mut movement = Vector3::zero();
+        for (action, direction) in [
+            ("camera.forward", forward),
+            ("camera.backward", -forward),
+            ("camera.right", right),
+            ("camera.left", -right),
+            ("camera.up", Vector3::unit_y()),
+            ("camera.down", -Vector3::unit_y()),
+        ]
+        .iter()
+        {
+            if input.pressed(action) {
+                movement += *direction;
+            }
+        }
+        // Diagonals aren't any faster
+        if movement.magnitude2() > 0.0 {
+            movement = movement.normalize() * self.speed;
+        }
+        self.velocity = self.smoothing.approach(self.velocity, movement, dt);
+        camera.eye += self.velocity * dt;
+
+        camera.target = camera.eye + forward;
+        camera.up = Vector3::unit_y();
+    }
+}
+
+/// Everything shaders may want to know about the camera, as
+/// `shaders/common/camera.glsl` declares it. Every member is a `mat4` or a
+/// `vec4`, so std140 has nothing to pad in between
 #[repr(C, packed)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
 pub struct CameraUniform {
     pub view_proj: [[f32; 4]; 4],
+    pub view: [[f32; 4]; 4],
+    pub proj: [[f32; 4]; 4],
+    pub inv_view_proj: [[f32; 4]; 4],
+    pub inv_view: [[f32; 4]; 4],
+    pub inv_proj: [[f32; 4]; 4],
+    /// World-space position of the camera, with w = 1
+    pub eye: [f32; 4],
 }
 
 impl Default for CameraUniform {
     fn default() -> Self {
         use cgmath::SquareMatrix;
+        let identity = cgmath::Matrix4::identity().into();
         Self {
-            view_proj: cgmath::Matrix4::identity().into(),
+            view_proj: identity,
+            view: identity,
+            proj: identity,
+            inv_view_proj: identity,
+            inv_view: identity,
+            inv_proj: identity,
+            eye: [0.0, 0.0, 0.0, 1.0],
         }
     }
 }
 
-impl OldUniform for CameraUniform {}
-
 impl CameraUniform {
     pub fn update(&mut self, camera: &Camera) {
-        self.view_proj = camera.build_view_projection_matrix().into();
+        use cgmath::{EuclideanSpace, SquareMatrix};
+        let view = camera.build_view_matrix();
+        let proj = camera.build_projection_matrix();
+        let view_proj = proj * view;
+
+        self.view_proj = view_proj.into();
+        self.view = view.into();
+        self.proj = proj.into();
+        self.inv_view_proj = view_proj
+            .invert()
+            .expect("Camera view-projection matrix should be invertible")
+            .into();
+        self.inv_view = view
+            .invert()
+            .expect("Camera view matrix should be invertible")
+            .into();
+        self.inv_proj = proj
+            .invert()
+            .expect("Camera projection matrix should be invertible")
+            .into();
+        self.eye = camera.eye.to_vec().extend(1.0).into();
     }
 }
+
+/// The six planes bounding what a view-projection matrix can see, used to
+/// skip drawing things that are entirely off-screen
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    /// xyz: plane normal (pointing inwards), w: distance
+    planes: [cgmath::Vector4<f32>; 6],
+    /// World-space corners, for drawing the frustum
+    corners: [cgmath::Point3<f32>; 8],
+}
+
+impl Frustum {
+    pub fn from_matrix(view_proj: cgmath::Matrix4<f32>) -> Self {
+        use cgmath::{InnerSpace, Matrix, Point3, SquareMatrix, Transform, Vector4};
+
+        // cgmath matrices are column-major, the plane equations need rows
+        let m = view_proj.transpose();
+        let (r0, r1, r2, r3) = (m.x, m.y, m.z, m.w);
+
+        let planes = [
+            r3 + r0, // Left
+            r3 - r0, // Right
+            r3 + r1, // Bottom
+            r3 - r1, // Top
+            r2,      // Near (wgpu's clip space depth goes from 0 to 1)
+            r3 - r2, // Far
+        ]
+        .map(|p: Vector4<f32>| p / p.truncate().magnitude());
+
+        // The corners of clip space, brought back into the world
+        let inverse = view_proj
+            .invert()
+            .expect("Frustum matrix should be invertible");
+        let corner = |x, y, z| inverse.transform_point(Point3::new(x, y, z));
+        let corners = [
+            corner(-1.0, -1.0, 0.0),
+            corner(1.0, -1.0, 0.0),
+            corner(1.0, 1.0, 0.0),
+            corner(-1.0, 1.0, 0.0),
+            corner(-1.0, -1.0, 1.0),
+            corner(1.0, -1.0, 1.0),
+            corner(1.0, 1.0, 1.0),
+            corner(-1.0, 1.0, 1.0),
+        ];
+
+        Self { planes, corners }
+    }
+
+    /// xyz: plane normal (pointing inwards), w: distance. In the order left,
+    /// right, bottom, top, near and far
+    pub fn planes(&self) -> &[cgmath::Vector4<f32>; 6] {
+        &self.planes
+    }
+
+    /// Corners 0-3 are the near face and 4-7 the far one, in the same order
+    /// as `Aabb::corners`
+    pub fn corners(&self) -> &[cgmath::Point3<f32>; 8] {
+        &self.corners
+    }
+
+    /// Whether an axis-aligned box is at least partially inside the frustum
+    pub fn intersects_aabb(&self, min: cgmath::Point3<f32>, max: cgmath::Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal
+            let corner = cgmath::Point3::new(
+                if plane.x >= 0.0 { max.x } else { min.x },
+                if plane.y >= 0.0 { max.y } else { min.y },
+                if plane.z >= 0.0 { max.z } else { min.z },
+            );
+            distance(plane, corner) >= 0.0
+        })
+    }
+
+    /// Whether a sphere is at least partially inside the frustum. Cheaper
+    /// than the box test, but more conservative around the frustum's edges
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| distance(plane, sphere.center) >= -sphere.radius)
+    }
+}
+
+/// Signed distance from a point to a plane, positive on the side its normal
+/// points to
+fn distance(plane: &cgmath::Vector4<f32>, point: cgmath::Point3<f32>) -> f32 {
+    use cgmath::{EuclideanSpace, InnerSpace};
+    plane.truncate().dot(point.to_vec()) + plane.w
+}
@@ -0,0 +1,90 @@
+//! Simulation time, kept by the main loop and handed to every scene's
+//! `update`, so that all of them can be paused, stepped and sped up or slowed
+//! down at once. Only what scenes simulate or animate follows it: cameras keep
+//! moving while paused, for looking around a frozen scene.
+
+use std::time::Instant;
+
+/// What stepping while paused advances by, and what scenes that move by a
+/// fixed amount every update (i.e. the instancing animations) count in
+pub const TICK: f32 = 1.0 / 60.0;
+
+/// Longest a single update can advance by, so that hitches (or coming back
+/// from a breakpoint) don't make simulations jump ahead
+const MAX_DELTA: f32 = 0.1;
+
+/// What `Clock::faster` and `Clock::slower` go through
+const MULTIPLIERS: [f32; 6] = [0.1, 0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// Simulation time as of an update
+#[derive(Copy, Clone, Debug)]
+pub struct Time {
+    /// Seconds of simulation time since the last update
+    pub delta: f32,
+}
+
+impl Time {
+    /// `delta`, in ticks
+    pub fn ticks(&self) -> f32 {
+        self.delta / TICK
+    }
+}
+
+pub struct Clock {
+    last: Option<Instant>,
+    /// Index into `MULTIPLIERS`
+    multiplier: usize,
+    paused: bool,
+    /// Whether the next update advances by a tick, despite being paused
+    step: bool,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            last: None,
+            multiplier: 3,
+            paused: false,
+            step: false,
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        println!("Simulation paused: {:?}", self.paused);
+    }
+
+    /// Advances a single tick with the next update, pausing first if needed
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.step = true;
+    }
+
+    pub fn faster(&mut self) {
+        self.multiplier = (self.multiplier + 1).min(MULTIPLIERS.len() - 1);
+        println!("Simulation speed: {}x", MULTIPLIERS[self.multiplier]);
+    }
+
+    pub fn slower(&mut self) {
+        self.multiplier = self.multiplier.saturating_sub(1);
+        println!("Simulation speed: {}x", MULTIPLIERS[self.multiplier]);
+    }
+
+    /// Time for the next update, called once a frame
+    pub fn tick(&mut self) -> Time {
+        let now = Instant::now();
+        let real = match self.last.replace(now) {
+            Some(last) => (now - last).as_secs_f32().min(MAX_DELTA),
+            None => 0.0,
+        };
+
+        let delta = if !self.paused {
+            real * MULTIPLIERS[self.multiplier]
+        } else if std::mem::take(&mut self.step) {
+            TICK
+        } else {
+            0.0
+        };
+        Time { delta }
+    }
+}
@@ -0,0 +1,98 @@
+use std::cmp::Ordering;
+
+use cgmath::{MetricSpace, Point3};
+
+/// How a draw gets composited onto whatever was already rendered
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Blending {
+    /// Writes depth, order only matters for overdraw
+    Opaque,
+    /// Alpha-blended, doesn't write depth, must be drawn back-to-front
+    Translucent,
+}
+
+struct DrawItem<K> {
+    key: K,
+    position: Point3<f32>,
+    distance2: f32,
+}
+
+/// A per-frame list of draws, split by blending mode.
+///
+/// Scenes push whatever identifies a draw for them (an instance index,
+/// a mesh reference...) along with its world position, then sort it against
+/// the camera before issuing the draws: opaque draws first (front-to-back),
+/// then translucent draws (back-to-front).
+pub struct DrawList<K> {
+    opaque: Vec<DrawItem<K>>,
+    translucent: Vec<DrawItem<K>>,
+}
+
+impl<K> DrawList<K> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            opaque: Vec::with_capacity(capacity),
+            translucent: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.opaque.clear();
+        self.translucent.clear();
+    }
+
+    pub fn push(&mut self, key: K, position: Point3<f32>, blending: Blending) {
+        let item = DrawItem {
+            key,
+            position,
+            distance2: 0.0,
+        };
+
+        match blending {
+            Blending::Opaque => self.opaque.push(item),
+            Blending::Translucent => self.translucent.push(item),
+        }
+    }
+
+    /// Sorts the draws by their distance to `eye`
+    pub fn sort(&mut self, eye: Point3<f32>) {
+        for item in self.opaque.iter_mut().chain(self.translucent.iter_mut()) {
+            item.distance2 = item.position.distance2(eye);
+        }
+
+        // Front-to-back, so that the depth test discards as much as possible.
+        // Degenerate transforms can end up NaN, which sorts wherever it lands
+        self.opaque.sort_by(|a, b| {
+            a.distance2
+                .partial_cmp(&b.distance2)
+                .unwrap_or(Ordering::Equal)
+        });
+        // Back-to-front, so that blending composites in the right order
+        self.translucent.sort_by(|a, b| {
+            b.distance2
+                .partial_cmp(&a.distance2)
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.opaque.len() + self.translucent.len()
+    }
+
+    pub fn opaque_len(&self) -> usize {
+        self.opaque.len()
+    }
+
+    pub fn opaque(&self) -> impl Iterator<Item = &K> {
+        self.opaque.iter().map(|item| &item.key)
+    }
+
+    pub fn translucent(&self) -> impl Iterator<Item = &K> {
+        self.translucent.iter().map(|item| &item.key)
+    }
+
+    /// Every draw in submission order: opaque ones, then translucent ones
+    pub fn iter(&self) -> impl Iterator<Item = &K> {
+        self.opaque().chain(self.translucent())
+    }
+}
@@ -0,0 +1,85 @@
+//! Components and systems shared between scenes built out of entities. Such
+//! scenes keep a `hecs::World` of their own, holding these components along
+//! with whatever else only they care about.
+//!
+//! Meshes are drawn through prototypes: entities holding a `MeshHandle` and
+//! the mesh's model-space `Bounds`, which every entity drawing that mesh
+//! points to with an `InstanceOf`, next to its own `Transform`,
+//! `MaterialHandle`, `Blending` and `Visible`
+
+use cgmath::{EuclideanSpace, Point3};
+use hecs::{Entity, World};
+
+use crate::{
+    camera::Frustum,
+    draw::{Blending, DrawList},
+    mesh::{Aabb, Bounds, Transform},
+};
+
+/// Index into the scene's own table of meshes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MeshHandle(pub usize);
+
+/// Index into the scene's own table of materials, such as a layer of a
+/// texture array
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MaterialHandle(pub u32);
+
+/// Draws the mesh of the prototype it points to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InstanceOf(pub Entity);
+
+/// Whether the entity made it through the last `cull`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Visible(pub bool);
+
+pub fn spawn_prototype(world: &mut World, mesh: MeshHandle, bounds: Bounds) -> Entity {
+    world.spawn((mesh, bounds))
+}
+
+/// Moves every entity with a `C` according to it
+pub fn animate<C: hecs::Component>(world: &mut World, mut animate: impl FnMut(&C, &mut Transform)) {
+    for (_, (component, transform)) in world.query_mut::<(&C, &mut Transform)>() {
+        animate(component, transform);
+    }
+}
+
+/// Marks whether every instance's world-space bounding box is inside of the
+/// frustum, or every instance as visible without one. `grow` gets to enlarge
+/// the boxes first, i.e. to cover wherever an animation could take them.
+/// Returns how many instances got culled
+pub fn cull(world: &World, frustum: Option<&Frustum>, grow: impl Fn(Aabb) -> Aabb) -> usize {
+    let mut culled = 0;
+    // Prototypes don't have a `Visible`, so looking them up doesn't clash
+    // with the query
+    let mut query = world.query::<(&Transform, &InstanceOf, &mut Visible)>();
+    for (_, (transform, instance_of, visible)) in query.iter() {
+        visible.0 = match (frustum, world.get::<Bounds>(instance_of.0)) {
+            (Some(frustum), Ok(bounds)) => {
+                let aabb = grow(bounds.aabb.transformed(transform.model_matrix()));
+                frustum.intersects_aabb(aabb.min, aabb.max)
+            }
+            _ => true,
+        };
+        if !visible.0 {
+            culled += 1;
+        }
+    }
+    culled
+}
+
+/// Lists every visible entity at its translation, sorted against `eye`
+pub fn build_draw_list(world: &World, draw_list: &mut DrawList<Entity>, eye: Point3<f32>) {
+    draw_list.clear();
+    let mut query = world.query::<(&Transform, &Blending, &Visible)>();
+    for (entity, (transform, blending, visible)) in query.iter() {
+        if visible.0 {
+            draw_list.push(
+                entity,
+                Point3::from_vec(*transform.translation()),
+                *blending,
+            );
+        }
+    }
+    draw_list.sort(eye);
+}
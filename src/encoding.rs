@@ -0,0 +1,105 @@
+//! Encoding independent passes on command encoders of their own, across
+//! rayon's threads. Their command buffers get submitted right after the
+//! encoder the scene renders on, in the order the passes were handed over,
+//! so the GPU runs them just like it would if they had all been encoded one
+//! after the other on that encoder. Only the encoding itself is spread out.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rayon::prelude::*;
+
+/// Pass encoded by `ParallelEncoder::encode`, on an encoder of its own
+pub type Pass<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder) + Send + 'a>;
+
+/// How long encoding passes took, since the last `ParallelEncoder::toggle`
+#[derive(Default)]
+struct Timing {
+    passes: usize,
+    time: Duration,
+    frames: u32,
+}
+
+pub struct ParallelEncoder {
+    device: Arc<wgpu::Device>,
+    /// Otherwise, passes get encoded one after the other on the calling
+    /// thread, still on encoders of their own, to compare against
+    parallel: bool,
+    encoded: Mutex<Vec<wgpu::CommandBuffer>>,
+    timing: Mutex<Timing>,
+}
+
+impl ParallelEncoder {
+    pub fn new(device: &Arc<wgpu::Device>) -> Self {
+        Self {
+            device: device.clone(),
+            parallel: true,
+            encoded: Mutex::new(Vec::new()),
+            timing: Mutex::new(Timing::default()),
+        }
+    }
+
+    /// Encodes every pass on an encoder of its own, queuing their command
+    /// buffers to be submitted after whatever got encoded so far this frame.
+    /// Passes can't depend on each other's encoding, only on the order the
+    /// GPU runs them in
+    pub fn encode(&self, label: &str, passes: Vec<Pass<'_>>) {
+        let start = Instant::now();
+        let count = passes.len();
+        let device = &*self.device;
+
+        let buffers: Vec<_> = if self.parallel {
+            passes
+                .into_par_iter()
+                .map(|pass| encode_pass(device, label, pass))
+                .collect()
+        } else {
+            passes
+                .into_iter()
+                .map(|pass| encode_pass(device, label, pass))
+                .collect()
+        };
+        self.encoded.lock().unwrap().extend(buffers);
+
+        let mut timing = self.timing.lock().unwrap();
+        timing.passes += count;
+        timing.time += start.elapsed();
+    }
+
+    /// Takes every command buffer encoded during the frame, ready to be
+    /// submitted
+    pub fn finish_frame(&self) -> Vec<wgpu::CommandBuffer> {
+        self.timing.lock().unwrap().frames += 1;
+        self.take_encoded()
+    }
+
+    /// Takes the command buffers encoded so far, for whatever gets encoded
+    /// next to be submitted after them, without ending the frame
+    pub fn take_encoded(&self) -> Vec<wgpu::CommandBuffer> {
+        std::mem::take(&mut *self.encoded.lock().unwrap())
+    }
+
+    /// Switches between encoding in parallel and one pass after the other,
+    /// printing how long encoding took until now
+    pub fn toggle(&mut self) {
+        let timing = std::mem::take(&mut *self.timing.lock().unwrap());
+        let frames = timing.frames.max(1);
+        println!(
+            "Encoded {:.1} passes a frame in {:.3}ms on average ({})",
+            timing.passes as f64 / frames as f64,
+            timing.time.as_secs_f64() * 1000.0 / frames as f64,
+            if self.parallel { "parallel" } else { "serial" }
+        );
+        self.parallel = !self.parallel;
+        println!("Parallel encoding: {:?}", self.parallel);
+    }
+}
+
+fn encode_pass(device: &wgpu::Device, label: &str, pass: Pass<'_>) -> wgpu::CommandBuffer {
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some(label) });
+    pass(&mut encoder);
+    encoder.finish()
+}
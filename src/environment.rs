@@ -0,0 +1,466 @@
+use std::num::{NonZeroU32, NonZeroU64};
+
+use anyhow::*;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    buffer::{StagerHandle, StagingFactory, Uniformable},
+    camera::{Camera, CameraUniform},
+    layout::Std140,
+    shader::{shaders, ShaderVariants},
+    texture::{ColorSpace, SamplerDesc, Texture},
+};
+
+/// Uniforms with dynamic offsets have to start on multiples of this
+const FACE_STRIDE: u64 = 256;
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct FaceUniform {
+    face: u32,
+    size: f32,
+    #[padding]
+    _padding: [u32; 2],
+}
+
+/// A cube map of whatever surrounds a scene, for skyboxes and image-based
+/// lighting, converted on the GPU out of an equirectangular (latitude and
+/// longitude) image with one render pass per face
+pub struct EnvironmentMap {
+    /// `Cube` view, sampled linearly
+    pub cube: Texture,
+    /// Whether it goes past 1.0 and needs tonemapping, or is plain sRGB
+    pub hdr: bool,
+}
+
+impl EnvironmentMap {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    /// Straight out of a Radiance HDR (`.hdr`) file, with `face_size` texels
+    /// along every side of every face
+    pub fn from_hdr(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        face_size: u32,
+        label: &str,
+    ) -> Result<Self> {
+        let equirect = Texture::from_hdr(device, queue, bytes, label)?;
+        Ok(Self::from_equirect(
+            device, queue, &equirect, face_size, label,
+        ))
+    }
+
+    pub fn from_equirect(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        equirect: &Texture,
+        face_size: u32,
+        label: &str,
+    ) -> Self {
+        let vert_module = shaders::FULLSCREEN_VERT.create_module(device);
+        let frag_module = shaders::EQUIRECT_TO_CUBE_FRAG.create_module(device);
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: face_size,
+                height: face_size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+
+        let mut faces = vec![0u8; 6 * FACE_STRIDE as usize];
+        for face in 0..6 {
+            let uniform = FaceUniform {
+                face: face as u32,
+                size: face_size as f32,
+                _padding: [0; 2],
+            };
+            let offset = face * FACE_STRIDE as usize;
+            faces[offset..offset + std::mem::size_of::<FaceUniform>()]
+                .copy_from_slice(bytemuck::bytes_of(&uniform));
+        }
+        let face_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Environment - Face Uniform Buffer"),
+            contents: &faces,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        // Longitude wraps around, latitude doesn't
+        let equirect_sampler = SamplerDesc::default()
+            .address_modes(
+                wgpu::AddressMode::Repeat,
+                wgpu::AddressMode::ClampToEdge,
+                wgpu::AddressMode::ClampToEdge,
+            )
+            .mipmap_filter(wgpu::FilterMode::Nearest)
+            .create(device, Some("Environment - Equirectangular Sampler"));
+
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Environment - Conversion Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: NonZeroU64::new(
+                                std::mem::size_of::<FaceUniform>() as u64
+                            ),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment - Conversion Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &face_buffer,
+                        offset: 0,
+                        size: NonZeroU64::new(std::mem::size_of::<FaceUniform>() as u64),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&equirect.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&equirect_sampler),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Environment - Conversion Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Environment - Conversion Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Self::FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Environment - Conversion Encoder"),
+        });
+        for face in 0..6 {
+            let face_view = texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Environment - Face View"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: face,
+                array_layer_count: NonZeroU32::new(1),
+                ..Default::default()
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Environment - Conversion Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &face_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[face * FACE_STRIDE as u32]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(label),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = Texture::create_cube_sampler(device);
+
+        Self {
+            cube: Texture {
+                texture,
+                view,
+                sampler,
+            },
+            hdr: true,
+        }
+    }
+
+    /// Out of six images already laid out as cube map faces, see
+    /// `Texture::cubemap_from_faces`
+    pub fn from_faces(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[&[u8]; 6],
+        label: &str,
+    ) -> Result<Self> {
+        Ok(Self {
+            cube: Texture::cubemap_from_faces(device, queue, faces, ColorSpace::Srgb, label)?,
+            hdr: false,
+        })
+    }
+}
+
+/// Draws an `EnvironmentMap` behind everything else, with a fullscreen
+/// triangle that unprojects every pixel into the direction it looks at.
+///
+/// It is meant to be drawn first inside of a scene's own render pass, which
+/// it doesn't write depth into. `depth_format` has to match that pass' depth
+/// attachment, if it has one.
+pub struct SkyboxPass {
+    /// Out of `skybox.frag` as is and its `TONEMAP` variant, for HDR maps
+    pipeline: wgpu::RenderPipeline,
+    hdr_pipeline: wgpu::RenderPipeline,
+    hdr: bool,
+    uniform: CameraUniform,
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    belt: StagerHandle,
+}
+
+impl SkyboxPass {
+    /// `belt` names the staging belt the pass registers for its uniform,
+    /// which has to be unique for every scene using a skybox
+    pub fn new(
+        device: &wgpu::Device,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        belt: &str,
+        environment: &EnvironmentMap,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Self {
+        // Same unprojecting triangle as the grid's
+        let vert_module = shaders::GRID_VERT.create_module(device);
+        let frag_variants = ShaderVariants::new(device, "skybox.frag");
+
+        let uniform = CameraUniform::default();
+        let uniform_buffer = uniform.create_buffer(device, Some("Skybox - Uniform Buffer"));
+
+        let belt = staging.create_stager(belt, std::mem::size_of::<CameraUniform>() as u64);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Skybox - Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group =
+            Self::create_bind_group(device, &bind_group_layout, &uniform_buffer, environment);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Skybox - Render Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |label, frag_module| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &vert_module,
+                    entry_point: "main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: frag_module,
+                    entry_point: "main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: sc.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    clamp_depth: false,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    conservative: false,
+                },
+                depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                    format,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+            })
+        };
+        let pipeline = create_pipeline("Skybox - Render Pipeline", frag_variants.get(&[]));
+        let hdr_pipeline = create_pipeline(
+            "Skybox - HDR Render Pipeline",
+            frag_variants.get(&["TONEMAP"]),
+        );
+
+        Self {
+            pipeline,
+            hdr_pipeline,
+            hdr: environment.hdr,
+            uniform,
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            belt,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        environment: &EnvironmentMap,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Skybox - Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&environment.cube.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&environment.cube.sampler),
+                },
+            ],
+        })
+    }
+
+    /// Draws another environment map from now on
+    pub fn set_environment(&mut self, device: &wgpu::Device, environment: &EnvironmentMap) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            environment,
+        );
+        self.hdr = environment.hdr;
+    }
+
+    pub fn update(&mut self, camera: &Camera) {
+        self.uniform.update(camera);
+    }
+
+    /// Has to happen before the render pass the skybox gets drawn in begins
+    pub fn upload(&self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        let mut stager = staging.fetch_stager(self.belt);
+        stager.write(encoder, &self.uniform_buffer, 0, &self.uniform);
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.push_debug_group("Skybox");
+        if self.hdr {
+            render_pass.set_pipeline(&self.hdr_pipeline);
+        } else {
+            render_pass.set_pipeline(&self.pipeline);
+        }
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        render_pass.pop_debug_group();
+    }
+}
@@ -0,0 +1,480 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Rotation3, Vector2, Vector3};
+use winit::event::MouseButton;
+
+use crate::{
+    buffer::{StagerHandle, StagingFactory, Uniformable},
+    camera::{Camera, CameraUniform, Frustum},
+    input::Input,
+    mesh::{Aabb, BoundingSphere, Transform},
+    shader::shaders,
+    vertex::{Descriptable, FlatVertex},
+};
+
+/// Segments per circle when drawing spheres
+const CIRCLE_SEGMENTS: usize = 24;
+
+/// How far from a handle the cursor can be to grab it, in pixels
+const GRAB_DISTANCE: f64 = 8.0;
+/// Size of the handles, as a fraction of their distance to the camera, so
+/// that they look the same size wherever they are
+const HANDLE_SIZE: f32 = 0.2;
+/// Colors of the handles for the x, y and z axes, and of whichever is grabbed
+const AXIS_COLORS: [[f32; 3]; 3] = [[1.0, 0.2, 0.2], [0.2, 1.0, 0.2], [0.3, 0.4, 1.0]];
+const GRABBED_COLOR: [f32; 3] = [1.0, 0.9, 0.2];
+
+/// Immediate-mode debug lines: scenes queue up shapes every frame, which get
+/// uploaded and drawn in one go inside of the scene's own render pass
+pub struct GizmoRenderer {
+    pipeline: wgpu::RenderPipeline,
+    vertices: Vec<FlatVertex>,
+    /// Maximum number of vertices the vertex buffer can hold
+    capacity: usize,
+    vertex_buffer: wgpu::Buffer,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    belt: StagerHandle,
+}
+
+impl GizmoRenderer {
+    /// `belt` names the staging belt registered for the uploads, which has to
+    /// be unique for every scene using gizmos. Lines past `max_lines` in a
+    /// single frame get dropped.
+    pub fn new(
+        device: &wgpu::Device,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        belt: &str,
+        max_lines: usize,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Self {
+        let vert_module = shaders::GIZMO_VERT.create_module(device);
+        let frag_module = shaders::DIMA_FRAG.create_module(device);
+
+        let capacity = max_lines * 2;
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gizmo - Vertex Buffer"),
+            size: (capacity * std::mem::size_of::<FlatVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let camera_uniform = CameraUniform::default();
+        let camera_uniform_buffer =
+            camera_uniform.create_buffer(device, Some("Gizmo - Camera Uniform Buffer"));
+
+        let belt = staging.create_stager(
+            belt,
+            (std::mem::size_of::<CameraUniform>() + capacity * std::mem::size_of::<FlatVertex>())
+                as u64,
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Gizmo - Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Gizmo - Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Gizmo - Render Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Gizmo - Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[FlatVertex::descriptor()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            // Depth tested, so that it's clear what's in front of what, but
+            // without getting in the way of anything drawn afterwards
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            pipeline,
+            vertices: Vec::with_capacity(capacity),
+            capacity,
+            vertex_buffer,
+            camera_uniform,
+            camera_uniform_buffer,
+            bind_group,
+            belt,
+        }
+    }
+
+    /// Forgets every shape queued so far
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    pub fn line(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 3]) {
+        if self.vertices.len() + 2 > self.capacity {
+            return;
+        }
+
+        self.vertices.push(FlatVertex {
+            position: from.into(),
+            color,
+        });
+        self.vertices.push(FlatVertex {
+            position: to.into(),
+            color,
+        });
+    }
+
+    pub fn aabb(&mut self, aabb: &Aabb, color: [f32; 3]) {
+        self.hexahedron(&aabb.corners(), color);
+    }
+
+    /// Draws the frustum's edges, i.e. to see what another camera can see
+    pub fn frustum(&mut self, frustum: &Frustum, color: [f32; 3]) {
+        self.hexahedron(frustum.corners(), color);
+    }
+
+    /// Corners 0-3 are one face and 4-7 the opposite one, in the same order
+    fn hexahedron(&mut self, c: &[Point3<f32>; 8], color: [f32; 3]) {
+        for i in 0..4 {
+            self.line(c[i], c[(i + 1) % 4], color);
+            self.line(c[i + 4], c[(i + 1) % 4 + 4], color);
+            self.line(c[i], c[i + 4], color);
+        }
+    }
+
+    /// Draws a sphere as three circles, one around each axis
+    pub fn sphere(&mut self, sphere: &BoundingSphere, color: [f32; 3]) {
+        let axes = [
+            (Vector3::unit_x(), Vector3::unit_y()),
+            (Vector3::unit_y(), Vector3::unit_z()),
+            (Vector3::unit_z(), Vector3::unit_x()),
+        ];
+        for (u, v) in axes.iter() {
+            self.circle(sphere.center, *u, *v, sphere.radius, color);
+        }
+    }
+
+    /// Circle on the plane spanned by `u` and `v`, which should be
+    /// perpendicular unit vectors
+    fn circle(
+        &mut self,
+        center: Point3<f32>,
+        u: Vector3<f32>,
+        v: Vector3<f32>,
+        radius: f32,
+        color: [f32; 3],
+    ) {
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    pub fn update(&mut self, camera: &Camera) {
+        self.camera_uniform.update(camera);
+    }
+
+    /// Has to happen before the render pass the gizmos get drawn in begins
+    pub fn upload(&self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        let mut stager = staging.fetch_stager(self.belt);
+        stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+        if !self.vertices.is_empty() {
+            stager.write_slice(encoder, &self.vertex_buffer, 0, &self.vertices);
+        }
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        render_pass.push_debug_group("Gizmos");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertices.len() as u32, 0..1);
+        render_pass.pop_debug_group();
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GizmoMode {
+    /// Arrows, moving along the world axes
+    Translate,
+    /// Rings, turning around the world axes
+    Rotate,
+    /// Boxes, stretching along the world axes
+    Scale,
+}
+
+/// Where a drag started: the cursor, and the transform as it was back then
+struct Drag {
+    axis: usize,
+    cursor: (f64, f64),
+    translation: Vector3<f32>,
+    rotation: cgmath::Quaternion<f32>,
+    scale: Vector3<f32>,
+}
+
+/// Handles drawn over a `Transform`, one per world axis, which can be dragged
+/// around with the left mouse button to edit it
+pub struct TransformGizmo {
+    pub mode: GizmoMode,
+    drag: Option<Drag>,
+}
+
+impl TransformGizmo {
+    pub fn new(mode: GizmoMode) -> Self {
+        Self { mode, drag: None }
+    }
+
+    /// Grabs a handle, drags it or lets go of it. `screen_size` is the size
+    /// of the viewport the camera draws into, which the cursor is relative
+    /// to. Returns whether a handle is being dragged
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        screen_size: (u32, u32),
+        input: &Input,
+        transform: &mut Transform,
+    ) -> bool {
+        let cursor = match input.cursor() {
+            Some(cursor) => cursor,
+            None => return false,
+        };
+        let view_proj = camera.build_view_projection_matrix();
+        let project = |point: Point3<f32>| to_screen(&view_proj, screen_size, point);
+        let origin = Point3::from_vec(*transform.translation());
+        let size = self.size(camera, origin);
+
+        if input.mouse_just_pressed(MouseButton::Left) {
+            self.drag = self
+                .grabbed_axis(&project, origin, size, cursor)
+                .map(|axis| Drag {
+                    axis,
+                    cursor,
+                    translation: *transform.translation(),
+                    rotation: *transform.rotation(),
+                    scale: *transform.scale(),
+                });
+        }
+        if !input.mouse_pressed(MouseButton::Left) {
+            if self.drag.take().is_some() {
+                println!(
+                    "Transform: t {:?}, r {:?}, s {:?}",
+                    transform.translation(),
+                    transform.rotation(),
+                    transform.scale()
+                );
+            }
+            return false;
+        }
+        let drag = match &self.drag {
+            Some(drag) => drag,
+            None => return false,
+        };
+
+        let axis = axis(drag.axis);
+        let moved = Vector2::new(cursor.0 - drag.cursor.0, cursor.1 - drag.cursor.1);
+        let (center, tip) = match (project(origin), project(origin + axis * size)) {
+            (Some(center), Some(tip)) => (center, tip),
+            _ => return true,
+        };
+        // How much of the handle's length the cursor moved along it
+        let handle = tip - center;
+        let along = (moved.dot(handle) / handle.magnitude2().max(1.0)) as f32;
+
+        match self.mode {
+            GizmoMode::Translate => {
+                transform.set_translation(|t| *t = drag.translation + axis * along * size);
+            }
+            GizmoMode::Scale => {
+                transform.set_scale(|s| {
+                    *s = drag.scale;
+                    s[drag.axis] = (drag.scale[drag.axis] * (1.0 + along)).max(0.01);
+                });
+            }
+            GizmoMode::Rotate => {
+                // Angle swept around the handle's center, counter-clockwise
+                // on screen (where y goes down)
+                let from = Vector2::new(drag.cursor.0, drag.cursor.1) - center;
+                let to = Vector2::new(cursor.0, cursor.1) - center;
+                let swept = -(from.perp_dot(to)).atan2(from.dot(to)) as f32;
+                // Which is the other way around when looking at the axis
+                // from behind
+                let facing = (camera.eye - origin).dot(axis).signum();
+                let turn = cgmath::Quaternion::from_axis_angle(axis, cgmath::Rad(swept * facing));
+                transform.set_rotation(|r| *r = turn * drag.rotation);
+            }
+        }
+        true
+    }
+
+    /// Queues the handles up to be drawn by `gizmos`
+    pub fn draw(&self, gizmos: &mut GizmoRenderer, camera: &Camera, transform: &Transform) {
+        let origin = Point3::from_vec(*transform.translation());
+        let size = self.size(camera, origin);
+        for (i, color) in AXIS_COLORS.iter().enumerate() {
+            let color = match &self.drag {
+                Some(drag) if drag.axis == i => GRABBED_COLOR,
+                _ => *color,
+            };
+            let (axis, u, v) = (axis(i), axis((i + 1) % 3), axis((i + 2) % 3));
+            let tip = origin + axis * size;
+            match self.mode {
+                GizmoMode::Translate => {
+                    gizmos.line(origin, tip, color);
+                    let head = size * 0.15;
+                    gizmos.line(tip, tip - axis * head + u * head * 0.5, color);
+                    gizmos.line(tip, tip - axis * head - u * head * 0.5, color);
+                    gizmos.line(tip, tip - axis * head + v * head * 0.5, color);
+                    gizmos.line(tip, tip - axis * head - v * head * 0.5, color);
+                }
+                GizmoMode::Rotate => gizmos.circle(origin, u, v, size, color),
+                GizmoMode::Scale => {
+                    gizmos.line(origin, tip, color);
+                    let half = Vector3::new(1.0, 1.0, 1.0) * size * 0.06;
+                    gizmos.aabb(
+                        &Aabb {
+                            min: tip - half,
+                            max: tip + half,
+                        },
+                        color,
+                    );
+                }
+            }
+        }
+    }
+
+    fn size(&self, camera: &Camera, origin: Point3<f32>) -> f32 {
+        (camera.eye - origin).magnitude() * HANDLE_SIZE
+    }
+
+    /// The axis whose handle is closest to the cursor on screen, if it's
+    /// close enough to grab
+    fn grabbed_axis(
+        &self,
+        to_screen: &impl Fn(Point3<f32>) -> Option<Vector2<f64>>,
+        origin: Point3<f32>,
+        size: f32,
+        cursor: (f64, f64),
+    ) -> Option<usize> {
+        let cursor = Vector2::new(cursor.0, cursor.1);
+        let distance_to =
+            |from: Point3<f32>, to: Point3<f32>| match (to_screen(from), to_screen(to)) {
+                (Some(from), Some(to)) => segment_distance(cursor, from, to),
+                _ => f64::INFINITY,
+            };
+
+        (0..3)
+            .map(|i| {
+                let (axis, u, v) = (axis(i), axis((i + 1) % 3), axis((i + 2) % 3));
+                let distance = match self.mode {
+                    GizmoMode::Translate | GizmoMode::Scale => {
+                        distance_to(origin, origin + axis * size)
+                    }
+                    GizmoMode::Rotate => (0..CIRCLE_SEGMENTS)
+                        .map(|j| {
+                            let point = |j: usize| {
+                                let angle =
+                                    j as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                                origin + (u * angle.cos() + v * angle.sin()) * size
+                            };
+                            distance_to(point(j), point(j + 1))
+                        })
+                        .fold(f64::INFINITY, f64::min),
+                };
+                (i, distance)
+            })
+            .filter(|(_, distance)| *distance <= GRAB_DISTANCE)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(i, _)| i)
+    }
+}
+
+fn axis(i: usize) -> Vector3<f32> {
+    let mut axis = Vector3::new(0.0, 0.0, 0.0);
+    axis[i] = 1.0;
+    axis
+}
+
+/// Where a point lands on screen, in pixels from the top left corner, unless
+/// it's behind the camera
+fn to_screen(
+    view_proj: &cgmath::Matrix4<f32>,
+    (width, height): (u32, u32),
+    point: Point3<f32>,
+) -> Option<Vector2<f64>> {
+    let clip = *view_proj * point.to_homogeneous();
+    if clip.w <= 0.0 {
+        return None;
+    }
+    let (x, y) = ((clip.x / clip.w) as f64, (clip.y / clip.w) as f64);
+    Some(Vector2::new(
+        (x + 1.0) / 2.0 * width as f64,
+        (1.0 - y) / 2.0 * height as f64,
+    ))
+}
+
+/// Distance from a point to the segment between `from` and `to`
+fn segment_distance(point: Vector2<f64>, from: Vector2<f64>, to: Vector2<f64>) -> f64 {
+    let segment = to - from;
+    let t = ((point - from).dot(segment) / segment.magnitude2().max(f64::EPSILON)).clamp(0.0, 1.0);
+    (point - (from + segment * t)).magnitude()
+}
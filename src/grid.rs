@@ -0,0 +1,196 @@
+use cgmath::{EuclideanSpace, SquareMatrix, Vector3};
+
+use crate::{
+    buffer::{StagerHandle, StagingFactory, Uniformable},
+    camera::Camera,
+    layout::Std140,
+    shader::shaders,
+};
+
+/// Axis-aligned plane the grid gets drawn on, named after the axis it faces
+/// and holding its offset along that axis
+#[derive(Copy, Clone, Debug)]
+pub enum GridPlane {
+    Y(f32),
+    Z(f32),
+}
+
+impl GridPlane {
+    /// Normal, and the two directions the grid's lines run along
+    fn axes(&self) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+        match self {
+            GridPlane::Y(_) => (Vector3::unit_y(), Vector3::unit_x(), Vector3::unit_z()),
+            GridPlane::Z(_) => (Vector3::unit_z(), Vector3::unit_x(), Vector3::unit_y()),
+        }
+    }
+
+    fn offset(&self) -> f32 {
+        match self {
+            GridPlane::Y(offset) | GridPlane::Z(offset) => *offset,
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct GridUniform {
+    view_proj: [[f32; 4]; 4],
+    inv_view_proj: [[f32; 4]; 4],
+    eye: [f32; 4],
+    plane: [f32; 4],
+    tangent: [f32; 4],
+    bitangent: [f32; 4],
+    minor_spacing: f32,
+    major_spacing: f32,
+    fade_distance: f32,
+    #[padding]
+    _padding: f32,
+}
+
+/// An infinite grid with minor and major lines fading out with distance,
+/// drawn with a fullscreen triangle that unprojects every pixel onto a plane.
+///
+/// It is meant to be drawn inside of a scene's own render pass. If that pass
+/// has a depth attachment, the grid gets depth tested against (but doesn't
+/// write to) it; otherwise, draw it before anything it should go behind.
+pub struct GridPass {
+    pipeline: wgpu::RenderPipeline,
+    uniform: GridUniform,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    belt: StagerHandle,
+}
+
+impl GridPass {
+    /// `belt` names the staging belt the pass registers for its uniform,
+    /// which has to be unique for every scene using a grid
+    pub fn new(
+        device: &wgpu::Device,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        belt: &str,
+        plane: GridPlane,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Self {
+        let vert_module = shaders::GRID_VERT.create_module(device);
+        let frag_module = shaders::GRID_FRAG.create_module(device);
+
+        let (normal, tangent, bitangent) = plane.axes();
+        let uniform = GridUniform {
+            view_proj: cgmath::Matrix4::identity().into(),
+            inv_view_proj: cgmath::Matrix4::identity().into(),
+            eye: [0.0, 0.0, 0.0, 1.0],
+            plane: normal.extend(plane.offset()).into(),
+            tangent: tangent.extend(0.0).into(),
+            bitangent: bitangent.extend(0.0).into(),
+            minor_spacing: 1.0,
+            major_spacing: 10.0,
+            fade_distance: 60.0,
+            _padding: 0.0,
+        };
+        let uniform_buffer = uniform.create_buffer(device, Some("Grid - Uniform Buffer"));
+
+        let belt = staging.create_stager(belt, std::mem::size_of::<GridUniform>() as u64);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Grid - Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Grid - Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Grid - Render Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Grid - Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            pipeline,
+            uniform,
+            uniform_buffer,
+            bind_group,
+            belt,
+        }
+    }
+
+    pub fn update(&mut self, camera: &Camera) {
+        let view_proj = camera.build_view_projection_matrix();
+        self.uniform.view_proj = view_proj.into();
+        self.uniform.inv_view_proj = view_proj
+            .invert()
+            .expect("Camera view-projection matrix should be invertible")
+            .into();
+        self.uniform.eye = camera.eye.to_vec().extend(1.0).into();
+    }
+
+    /// Has to happen before the render pass the grid gets drawn in begins
+    pub fn upload(&self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        let mut stager = staging.fetch_stager(self.belt);
+        stager.write(encoder, &self.uniform_buffer, 0, &self.uniform);
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.push_debug_group("Grid");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        render_pass.pop_debug_group();
+    }
+}
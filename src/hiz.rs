@@ -0,0 +1,463 @@
+use std::num::NonZeroU32;
+
+use crate::{
+    buffer::{StagerHandle, StagingFactory, Uniformable},
+    layout::Std140,
+    shader::shaders,
+    texture::{DepthTexture, SamplerDesc},
+};
+
+const WORKGROUP_SIZE: u32 = 8;
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct HiZDebugUniform {
+    viewport: [f32; 2],
+    near: f32,
+    far: f32,
+    level: u32,
+    #[padding]
+    _padding: [u32; 3],
+}
+
+/// Everything that depends on the size of the depth buffer
+struct Levels {
+    _texture: wgpu::Texture,
+    /// Every level at once
+    view: wgpu::TextureView,
+    sizes: Vec<(u32, u32)>,
+    /// The first reads the depth buffer, the rest read the level before them
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+/// A mip pyramid out of a depth buffer, where every texel holds the furthest
+/// depth of the ones it covers one level below. Whatever lies behind a texel
+/// is hidden by everything drawn into that depth buffer, so culling shaders
+/// can test a whole bounding box with a handful of samples from the level it
+/// covers about two texels of.
+///
+/// Levels get rebuilt with `build` out of whatever the depth buffer holds at
+/// that point, usually the last frame's, which needs to have been stored.
+pub struct HiZPyramid {
+    /// Nearest, as `R32Float` textures can't be filtered
+    pub sampler: wgpu::Sampler,
+    levels: Levels,
+    copy_pipeline: wgpu::ComputePipeline,
+    copy_layout: wgpu::BindGroupLayout,
+    downsample_pipeline: wgpu::ComputePipeline,
+    downsample_layout: wgpu::BindGroupLayout,
+    debug_pipeline: wgpu::RenderPipeline,
+    debug_layout: wgpu::BindGroupLayout,
+    debug_bind_group: wgpu::BindGroup,
+    debug_uniform: HiZDebugUniform,
+    debug_buffer: wgpu::Buffer,
+    belt: StagerHandle,
+}
+
+impl HiZPyramid {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+    /// `belt` names the staging belt the pyramid registers for its debug
+    /// view, which has to be unique for every scene using one. `near` and
+    /// `far` are only used to shade the debug view
+    pub fn new(
+        device: &wgpu::Device,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        belt: &str,
+        depth: &DepthTexture,
+        near: f32,
+        far: f32,
+    ) -> Self {
+        let copy_module = shaders::HIZ_COPY_COMP.create_module(device);
+        let downsample_module = shaders::HIZ_DOWNSAMPLE_COMP.create_module(device);
+        let vert_module = shaders::FULLSCREEN_VERT.create_module(device);
+        let frag_module = shaders::HIZ_DEBUG_FRAG.create_module(device);
+
+        let level_layout = |label, sample_type| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: false,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: Self::FORMAT,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            })
+        };
+        let copy_layout = level_layout(
+            "Hi-Z - Copy Bind Group Layout",
+            wgpu::TextureSampleType::Depth,
+        );
+        let downsample_layout = level_layout(
+            "Hi-Z - Downsample Bind Group Layout",
+            wgpu::TextureSampleType::Float { filterable: false },
+        );
+
+        let compute_pipeline = |label, layout, module| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module,
+                entry_point: "main",
+            })
+        };
+        let copy_pipeline = compute_pipeline("Hi-Z - Copy Pipeline", &copy_layout, &copy_module);
+        let downsample_pipeline = compute_pipeline(
+            "Hi-Z - Downsample Pipeline",
+            &downsample_layout,
+            &downsample_module,
+        );
+
+        let debug_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Hi-Z - Debug Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: false,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let debug_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Hi-Z - Debug Pipeline Layout"),
+                bind_group_layouts: &[&debug_layout],
+                push_constant_ranges: &[],
+            });
+
+        let debug_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Hi-Z - Debug Pipeline"),
+            layout: Some(&debug_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        let debug_uniform = HiZDebugUniform {
+            viewport: [sc.width as f32, sc.height as f32],
+            near,
+            far,
+            level: 0,
+            _padding: [0; 3],
+        };
+        let debug_buffer = debug_uniform.create_buffer(device, Some("Hi-Z - Debug Uniform Buffer"));
+        let belt = staging.create_stager(belt, std::mem::size_of::<HiZDebugUniform>() as u64);
+
+        let sampler = SamplerDesc::nearest().create(device, Some("Hi-Z - Sampler"));
+
+        let levels = Self::create_levels(
+            device,
+            (sc.width, sc.height),
+            depth,
+            &sampler,
+            &copy_layout,
+            &downsample_layout,
+        );
+        let debug_bind_group =
+            Self::create_debug_bind_group(device, &debug_layout, &levels, &sampler, &debug_buffer);
+
+        Self {
+            sampler,
+            levels,
+            copy_pipeline,
+            copy_layout,
+            downsample_pipeline,
+            downsample_layout,
+            debug_pipeline,
+            debug_layout,
+            debug_bind_group,
+            debug_uniform,
+            debug_buffer,
+            belt,
+        }
+    }
+
+    fn create_levels(
+        device: &wgpu::Device,
+        size: (u32, u32),
+        depth: &DepthTexture,
+        sampler: &wgpu::Sampler,
+        copy_layout: &wgpu::BindGroupLayout,
+        downsample_layout: &wgpu::BindGroupLayout,
+    ) -> Levels {
+        let (width, height) = (size.0.max(1), size.1.max(1));
+        let level_count = 32 - width.max(height).leading_zeros();
+        let sizes = (0..level_count)
+            .map(|level| ((width >> level).max(1), (height >> level).max(1)))
+            .collect::<Vec<_>>();
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Hi-Z - Pyramid"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let level_views = (0..level_count)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Hi-Z - Level View"),
+                    base_mip_level: level,
+                    mip_level_count: NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let bind_groups = (0..level_count as usize)
+            .map(|level| {
+                let (layout, source) = if level == 0 {
+                    (copy_layout, &depth.view)
+                } else {
+                    (downsample_layout, &level_views[level - 1])
+                };
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Hi-Z - Level Bind Group"),
+                    layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(source),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(&level_views[level]),
+                        },
+                    ],
+                })
+            })
+            .collect();
+
+        Levels {
+            _texture: texture,
+            view,
+            sizes,
+            bind_groups,
+        }
+    }
+
+    fn create_debug_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        levels: &Levels,
+        sampler: &wgpu::Sampler,
+        debug_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Hi-Z - Debug Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&levels.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: debug_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Follows the depth buffer after it has been recreated for a new size.
+    /// The pyramid is left empty until the next `build`
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        size: winit::dpi::PhysicalSize<u32>,
+        depth: &DepthTexture,
+    ) {
+        self.levels = Self::create_levels(
+            device,
+            (size.width, size.height),
+            depth,
+            &self.sampler,
+            &self.copy_layout,
+            &self.downsample_layout,
+        );
+        self.debug_bind_group = Self::create_debug_bind_group(
+            device,
+            &self.debug_layout,
+            &self.levels,
+            &self.sampler,
+            &self.debug_buffer,
+        );
+        self.debug_uniform.viewport = [size.width as f32, size.height as f32];
+    }
+
+    /// Every level, for shaders to read with `texelFetch`
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.levels.view
+    }
+
+    pub fn level_count(&self) -> u32 {
+        self.levels.sizes.len() as u32
+    }
+
+    /// Size of the first level, same as the depth buffer's
+    pub fn size(&self) -> (u32, u32) {
+        self.levels.sizes[0]
+    }
+
+    /// Copies the depth buffer into the first level, then downsamples every
+    /// level into the next one
+    pub fn build(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.push_debug_group("Hi-Z - Build");
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Hi-Z - Build Pass"),
+            });
+
+            for (level, (bind_group, &(width, height))) in self
+                .levels
+                .bind_groups
+                .iter()
+                .zip(self.levels.sizes.iter())
+                .enumerate()
+            {
+                compute_pass.set_pipeline(if level == 0 {
+                    &self.copy_pipeline
+                } else {
+                    &self.downsample_pipeline
+                });
+                compute_pass.set_bind_group(0, bind_group, &[]);
+                compute_pass.dispatch(
+                    width.div_ceil(WORKGROUP_SIZE),
+                    height.div_ceil(WORKGROUP_SIZE),
+                    1,
+                );
+            }
+        }
+        encoder.pop_debug_group();
+    }
+
+    /// Draws the given level over the whole frame, in its own render pass
+    pub fn render_debug(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        staging: &StagingFactory,
+        level: u32,
+    ) {
+        encoder.push_debug_group("Hi-Z - Debug");
+        self.debug_uniform.level = level.min(self.level_count() - 1);
+        let mut stager = staging.fetch_stager(self.belt);
+        stager.write(encoder, &self.debug_buffer, 0, &self.debug_uniform);
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Hi-Z - Debug Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: frame_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.debug_pipeline);
+            render_pass.set_bind_group(0, &self.debug_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        encoder.pop_debug_group();
+    }
+}
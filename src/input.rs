@@ -0,0 +1,318 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use winit::event::{
+    DeviceEvent, ElementState, KeyboardInput, MouseButton, MouseScrollDelta, Touch, TouchPhase,
+    VirtualKeyCode, WindowEvent,
+};
+
+use crate::bindings::Bindings;
+
+/// Pixels that count as one line of a mouse wheel
+const PIXELS_PER_LINE: f32 = 100.0;
+/// Longest a finger can stay on the screen for it to count as a tap
+const TAP_TIME: Duration = Duration::from_millis(250);
+/// Furthest a finger can move for it to still count as a tap, in physical
+/// pixels
+const TAP_DISTANCE: f64 = 10.0;
+
+/// A finger on the screen
+struct TouchPoint {
+    /// In physical pixels
+    position: (f64, f64),
+    started: Instant,
+    /// Furthest it got from where it started, to tell taps from drags
+    travelled: f64,
+    start_position: (f64, f64),
+}
+
+/// Everything the keyboard and mouse did since the last frame, along with
+/// what's still held down. Gathered from window and device events as they come
+/// in, so that scenes can look at all of it at once in `Scene::update`, and
+/// through the bindings rather than key by key
+pub struct Input {
+    bindings: Bindings,
+    keys: HashSet<VirtualKeyCode>,
+    keys_pressed: HashSet<VirtualKeyCode>,
+    keys_released: HashSet<VirtualKeyCode>,
+    buttons: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    /// Last known cursor position, in physical pixels
+    cursor: Option<(f64, f64)>,
+    /// Where `cursor` gets measured from, i.e. the corner of the tile the
+    /// focused scene is drawn in
+    origin: (f64, f64),
+    /// How far the cursor moved over the window, in physical pixels
+    cursor_delta: (f64, f64),
+    /// How far the mouse itself moved, which keeps coming with the cursor
+    /// grabbed, unlike `cursor_delta`
+    mouse_delta: (f64, f64),
+    /// In lines, positive when scrolling up
+    wheel: f32,
+    /// Fingers on the screen, by id
+    touches: HashMap<u64, TouchPoint>,
+    /// How far a single finger dragged, in physical pixels
+    touch_drag: (f64, f64),
+    /// How much further apart two fingers got, as a ratio
+    pinch: f64,
+}
+
+impl Input {
+    pub fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            keys: HashSet::new(),
+            keys_pressed: HashSet::new(),
+            keys_released: HashSet::new(),
+            buttons: HashSet::new(),
+            buttons_pressed: HashSet::new(),
+            buttons_released: HashSet::new(),
+            cursor: None,
+            origin: (0.0, 0.0),
+            cursor_delta: (0.0, 0.0),
+            mouse_delta: (0.0, 0.0),
+            wheel: 0.0,
+            touches: HashMap::new(),
+            touch_drag: (0.0, 0.0),
+            pinch: 1.0,
+        }
+    }
+
+    pub fn window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => match state {
+                // Held keys repeat, but they only count as pressed once
+                ElementState::Pressed => {
+                    if self.keys.insert(*key) {
+                        self.keys_pressed.insert(*key);
+                    }
+                }
+                ElementState::Released => {
+                    self.keys.remove(key);
+                    self.keys_released.insert(*key);
+                }
+            },
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => {
+                    self.buttons.insert(*button);
+                    self.buttons_pressed.insert(*button);
+                }
+                ElementState::Released => {
+                    self.buttons.remove(button);
+                    self.buttons_released.insert(*button);
+                }
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some((x, y)) = self.cursor {
+                    self.cursor_delta.0 += position.x - x;
+                    self.cursor_delta.1 += position.y - y;
+                }
+                self.cursor = Some((position.x, position.y));
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            WindowEvent::MouseWheel { delta, .. } => self.wheel += wheel_lines(delta),
+            WindowEvent::Touch(touch) => self.touch(touch),
+            // Releases don't get through to unfocused windows, so nothing
+            // would ever let go of what was held
+            WindowEvent::Focused(false) => {
+                self.keys_released.extend(self.keys.drain());
+                self.buttons_released.extend(self.buttons.drain());
+            }
+            _ => {}
+        }
+    }
+
+    fn touch(&mut self, touch: &Touch) {
+        let position = (touch.location.x, touch.location.y);
+        match touch.phase {
+            TouchPhase::Started => {
+                self.touches.insert(
+                    touch.id,
+                    TouchPoint {
+                        position,
+                        started: Instant::now(),
+                        travelled: 0.0,
+                        start_position: position,
+                    },
+                );
+            }
+            TouchPhase::Moved => {
+                let previous = match self.touches.get_mut(&touch.id) {
+                    Some(point) => {
+                        let previous = point.position;
+                        point.position = position;
+                        point.travelled = point
+                            .travelled
+                            .max(distance(point.start_position, position));
+                        previous
+                    }
+                    None => return,
+                };
+
+                match self.touches.len() {
+                    1 => {
+                        self.touch_drag.0 += position.0 - previous.0;
+                        self.touch_drag.1 += position.1 - previous.1;
+                    }
+                    2 => {
+                        let other = self
+                            .touches
+                            .iter()
+                            .find(|(id, _)| **id != touch.id)
+                            .map(|(_, point)| point.position)
+                            .unwrap();
+                        let before = distance(previous, other);
+                        if before > 0.0 {
+                            self.pinch *= distance(position, other) / before;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            TouchPhase::Ended => {
+                // Taps click where they land, as the left mouse button
+                if let Some(point) = self.touches.remove(&touch.id) {
+                    if self.touches.is_empty()
+                        && point.started.elapsed() <= TAP_TIME
+                        && point.travelled <= TAP_DISTANCE
+                    {
+                        self.cursor = Some(position);
+                        self.buttons_pressed.insert(MouseButton::Left);
+                        self.buttons_released.insert(MouseButton::Left);
+                    }
+                }
+            }
+            TouchPhase::Cancelled => {
+                self.touches.remove(&touch.id);
+            }
+        }
+    }
+
+    pub fn device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_delta.0 += delta.0;
+            self.mouse_delta.1 += delta.1;
+        }
+    }
+
+    /// Forgets whatever only lasts a frame. Called once every scene got to
+    /// see it
+    pub fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.cursor_delta = (0.0, 0.0);
+        self.mouse_delta = (0.0, 0.0);
+        self.wheel = 0.0;
+        self.touch_drag = (0.0, 0.0);
+        self.pinch = 1.0;
+    }
+
+    /// Whether any key bound to the action is held down
+    pub fn pressed(&self, action: &str) -> bool {
+        self.bindings
+            .keys(action)
+            .iter()
+            .any(|key| self.keys.contains(key))
+    }
+
+    /// The key that triggered the action since the last frame, if any
+    pub fn just_pressed(&self, action: &str) -> Option<VirtualKeyCode> {
+        self.bindings
+            .keys(action)
+            .iter()
+            .copied()
+            .find(|key| self.keys_pressed.contains(key))
+    }
+
+    /// Same as `just_pressed`, but takes the key press for itself, so that
+    /// nothing checking for it afterwards sees it. How the app checks for its
+    /// own actions, before scenes get the input
+    pub fn consume(&mut self, action: &str) -> Option<VirtualKeyCode> {
+        let key = self.just_pressed(action)?;
+        self.keys_pressed.remove(&key);
+        Some(key)
+    }
+
+    /// Whether a key bound to the action was let go of since the last frame
+    #[allow(dead_code)] // Nothing acts on releases yet
+    pub fn just_released(&self, action: &str) -> bool {
+        self.bindings
+            .keys(action)
+            .iter()
+            .any(|key| self.keys_released.contains(key))
+    }
+
+    pub fn mouse_pressed(&self, button: MouseButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    pub fn mouse_just_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    #[allow(dead_code)] // Nothing acts on releases yet
+    pub fn mouse_just_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Relative to the origin, the top left corner of the window unless
+    /// set otherwise
+    pub fn cursor(&self) -> Option<(f64, f64)> {
+        self.cursor
+            .map(|(x, y)| (x - self.origin.0, y - self.origin.1))
+    }
+
+    /// Moves where the cursor is measured from, in physical pixels from the
+    /// top left corner of the window
+    pub fn set_origin(&mut self, origin: (f64, f64)) {
+        self.origin = origin;
+    }
+
+    pub fn cursor_delta(&self) -> (f64, f64) {
+        self.cursor_delta
+    }
+
+    pub fn mouse_delta(&self) -> (f64, f64) {
+        self.mouse_delta
+    }
+
+    pub fn wheel(&self) -> f32 {
+        self.wheel
+    }
+
+    /// How far a single finger dragged across the screen, in physical pixels
+    pub fn touch_drag(&self) -> (f64, f64) {
+        self.touch_drag
+    }
+
+    /// How much further apart two fingers on the screen got, as a ratio:
+    /// above 1 when spreading them, below when pinching
+    pub fn pinch(&self) -> f64 {
+        self.pinch
+    }
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+/// Normalizes a mouse wheel event to lines, positive when scrolling up
+fn wheel_lines(delta: &MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, y) => *y,
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+    }
+}
@@ -0,0 +1,25 @@
+//! Layouts of the structs shared with shaders. `#[repr(C, packed)]` puts
+//! every field right after the previous one, which only matches what GLSL
+//! expects as long as the padding is done by hand, so uniforms derive
+//! `Std140` and storage buffer elements `Std430`, which check at compile time
+//! that every field is where the shader will read it from. See the
+//! `vertex-derive` crate for the rules
+
+pub use vertex_derive::{Std140, Std430};
+
+/// Laid out following std140, the rules of uniform blocks
+pub trait Std140: bytemuck::Pod {
+    /// Alignment of the struct when nested in another one
+    const ALIGN: usize;
+}
+
+/// Laid out following std430, the rules of storage buffers
+pub trait Std430: bytemuck::Pod {
+    /// Alignment of the struct when nested in another one
+    const ALIGN: usize;
+}
+
+// A lone `mat4`, such as a model matrix
+impl Std140 for [[f32; 4]; 4] {
+    const ALIGN: usize = 16;
+}
@@ -0,0 +1,129 @@
+use cgmath::{Deg, Quaternion, Rotation, Rotation3, Vector3};
+
+use crate::vertex::FlatVertex;
+
+/// A deterministic, context-free L-system
+pub struct LSystem {
+    pub axiom: &'static str,
+    pub rules: &'static [(char, &'static str)],
+    /// Turning angle used by the turtle, in degrees
+    pub angle: f32,
+}
+
+impl LSystem {
+    /// Rewrites the axiom `iterations` times
+    pub fn expand(&self, iterations: u32) -> String {
+        let mut current = self.axiom.to_owned();
+        for _ in 0..iterations {
+            let mut next = String::with_capacity(current.len() * 2);
+            for c in current.chars() {
+                match self.rules.iter().find(|(from, _)| *from == c) {
+                    Some((_, to)) => next.push_str(to),
+                    None => next.push(c),
+                }
+            }
+            current = next;
+        }
+        current
+    }
+
+    /// Interprets an expanded string with a 3D turtle, returning a line list.
+    ///
+    /// - `F`: move forward, drawing a segment
+    /// - `+`/`-`: turn left/right
+    /// - `&`/`^`: pitch down/up
+    /// - `\`/`/`: roll left/right
+    /// - `[`/`]`: push/pop the turtle state
+    ///
+    /// Every other symbol is ignored. The result is scaled so that it is
+    /// `height` units tall, growing upwards from the origin.
+    pub fn to_lines(&self, expanded: &str, height: f32) -> Vec<FlatVertex> {
+        #[derive(Copy, Clone)]
+        struct Turtle {
+            position: Vector3<f32>,
+            orientation: Quaternion<f32>,
+            depth: u32,
+        }
+
+        let turn =
+            |axis: Vector3<f32>, degrees: f32| Quaternion::from_axis_angle(axis, Deg(degrees));
+
+        let mut turtle = Turtle {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            orientation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            depth: 0,
+        };
+        let mut stack = Vec::new();
+        let mut segments = Vec::new();
+        let mut max_depth = 0;
+
+        for c in expanded.chars() {
+            match c {
+                'F' => {
+                    // The turtle's heading is its local +Y
+                    let heading = turtle.orientation.rotate_vector(Vector3::unit_y());
+                    let start = turtle.position;
+                    turtle.position += heading;
+                    segments.push((start, turtle.position, turtle.depth));
+                }
+                '+' => {
+                    turtle.orientation = turtle.orientation * turn(Vector3::unit_z(), self.angle)
+                }
+                '-' => {
+                    turtle.orientation = turtle.orientation * turn(Vector3::unit_z(), -self.angle)
+                }
+                '&' => {
+                    turtle.orientation = turtle.orientation * turn(Vector3::unit_x(), self.angle)
+                }
+                '^' => {
+                    turtle.orientation = turtle.orientation * turn(Vector3::unit_x(), -self.angle)
+                }
+                '\\' => {
+                    turtle.orientation = turtle.orientation * turn(Vector3::unit_y(), self.angle)
+                }
+                '/' => {
+                    turtle.orientation = turtle.orientation * turn(Vector3::unit_y(), -self.angle)
+                }
+                '[' => {
+                    stack.push(turtle);
+                    turtle.depth += 1;
+                    max_depth = max_depth.max(turtle.depth);
+                }
+                ']' => {
+                    if let Some(saved) = stack.pop() {
+                        turtle = saved;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let top = segments
+            .iter()
+            .map(|(start, end, _)| start.y.max(end.y))
+            .fold(0.0f32, f32::max);
+        let scale = if top > 0.0 { height / top } else { 1.0 };
+
+        // Trunk brown, fading into green towards the deepest branches
+        let trunk = Vector3::new(0.45, 0.3, 0.15);
+        let leaves = Vector3::new(0.3, 0.8, 0.25);
+
+        segments
+            .into_iter()
+            .flat_map(|(start, end, depth)| {
+                let t = depth as f32 / max_depth.max(1) as f32;
+                let color: [f32; 3] = (trunk + (leaves - trunk) * t).into();
+                [
+                    FlatVertex {
+                        position: (start * scale).into(),
+                        color,
+                    },
+                    FlatVertex {
+                        position: (end * scale).into(),
+                        color,
+                    },
+                ]
+            })
+            .collect()
+    }
+}
@@ -1,8 +1,17 @@
 use std::sync::Arc;
 
+use assets::Assets;
+use bindings::Bindings;
 use buffer::StagingFactory;
+use cache::{LayoutCache, PipelineCache};
+use clock::{Clock, Time};
+use encoding::ParallelEncoder;
 use futures::executor::block_on;
-use scenes::Scene;
+use input::Input;
+use menu::SceneMenu;
+use scenes::{Scene, SceneLoader, SceneSlot};
+use texture::{DepthTexture, RenderTarget};
+use tiles::{TileGrid, TileSize};
 use wgpu::{TextureViewDescriptor, TextureViewDimension};
 use winit::{
     event::*,
@@ -10,42 +19,132 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
+mod animation;
+mod assets;
+mod audio;
+mod batch;
+mod bindings;
 mod buffer;
+mod cache;
 mod camera;
+mod clock;
+mod draw;
+mod ecs;
+mod encoding;
+mod environment;
+mod gizmo;
+mod grid;
+mod hiz;
+mod input;
+mod layout;
+mod lsystem;
+mod menu;
 mod mesh;
+mod model;
+mod outline;
+mod particles;
+mod pipeline;
 mod scene;
+mod shader;
+mod slab;
 mod texture;
+mod tiles;
 mod vertex;
+mod viewport;
+mod voxel;
 
 use crate::scene as scenes;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum CurrentDemo {
     Textured,
     Cameras,
     Instancing,
     ClownColors,
     Dima,
+    Oit,
+    Particles,
+    ComputeParticles,
+    Life,
+    Boids,
+    NBody,
+    Raymarch,
+    Terrain,
+    Voxel,
+    LSystem,
+    Fractal,
+    Audio,
+    Model,
+    Uniforms,
 }
 
 impl CurrentDemo {
+    /// Every scene, in the order `next` goes through them
+    const ALL: [CurrentDemo; 19] = [
+        CurrentDemo::Textured,
+        CurrentDemo::Cameras,
+        CurrentDemo::Instancing,
+        CurrentDemo::ClownColors,
+        CurrentDemo::Dima,
+        CurrentDemo::Oit,
+        CurrentDemo::Particles,
+        CurrentDemo::ComputeParticles,
+        CurrentDemo::Life,
+        CurrentDemo::Boids,
+        CurrentDemo::NBody,
+        CurrentDemo::Raymarch,
+        CurrentDemo::Terrain,
+        CurrentDemo::Voxel,
+        CurrentDemo::LSystem,
+        CurrentDemo::Fractal,
+        CurrentDemo::Audio,
+        CurrentDemo::Model,
+        CurrentDemo::Uniforms,
+    ];
+
+    /// Where the scene is in `ALL`, and so which tile it's drawn in
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|&demo| demo == self).unwrap()
+    }
+
     fn next(&mut self) {
         *self = match self {
             CurrentDemo::Textured => CurrentDemo::Cameras,
             CurrentDemo::Cameras => CurrentDemo::Instancing,
             CurrentDemo::Instancing => CurrentDemo::ClownColors,
             CurrentDemo::ClownColors => CurrentDemo::Dima,
-            CurrentDemo::Dima => CurrentDemo::Textured,
+            CurrentDemo::Dima => CurrentDemo::Oit,
+            CurrentDemo::Oit => CurrentDemo::Particles,
+            CurrentDemo::Particles => CurrentDemo::ComputeParticles,
+            CurrentDemo::ComputeParticles => CurrentDemo::Life,
+            CurrentDemo::Life => CurrentDemo::Boids,
+            CurrentDemo::Boids => CurrentDemo::NBody,
+            CurrentDemo::NBody => CurrentDemo::Raymarch,
+            CurrentDemo::Raymarch => CurrentDemo::Terrain,
+            CurrentDemo::Terrain => CurrentDemo::Voxel,
+            CurrentDemo::Voxel => CurrentDemo::LSystem,
+            CurrentDemo::LSystem => CurrentDemo::Fractal,
+            CurrentDemo::Fractal => CurrentDemo::Audio,
+            CurrentDemo::Audio => CurrentDemo::Model,
+            CurrentDemo::Model => CurrentDemo::Uniforms,
+            CurrentDemo::Uniforms => CurrentDemo::Textured,
         }
     }
 }
 
 pub(crate) struct GlobalState {
     bg_color: wgpu::Color,
+    /// Window-sized, for every scene drawing with depth to share rather than
+    /// have one each, since only one of them draws at a time and they all
+    /// clear it first. Recreated on resize
+    depth_texture: DepthTexture,
+    /// Encodes passes scenes hand over on encoders of their own, submitted
+    /// right after the one the scene renders on
+    parallel: ParallelEncoder,
 }
 
-impl Default for GlobalState {
-    fn default() -> Self {
+impl GlobalState {
+    fn new(device: &Arc<wgpu::Device>, width: u32, height: u32) -> Self {
         GlobalState {
             bg_color: wgpu::Color {
                 r: 0.0,
@@ -53,8 +152,18 @@ impl Default for GlobalState {
                 b: 0.0,
                 a: 1.0,
             },
+            depth_texture: Self::create_depth_texture(device, width, height),
+            parallel: ParallelEncoder::new(device),
         }
     }
+
+    fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32) -> DepthTexture {
+        DepthTexture::from_screen(device, width, height, Some("Shared Depth Texture"))
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.depth_texture = Self::create_depth_texture(device, width, height);
+    }
 }
 
 struct State {
@@ -66,12 +175,45 @@ struct State {
     user_state: GlobalState,
     current_pipeline: CurrentDemo,
     staging: StagingFactory,
+    /// Holds onto whatever scenes share, for as long as they do, and
+    /// reloads it when it changes on disk
+    assets: Assets,
+    /// What the keyboard and mouse did since the last frame
+    input: Input,
+    /// Whether to draw the next frame into a screenshot rather than the window
+    screenshot_requested: bool,
+    /// Set while every scene gets drawn at once, in a tile of its own
+    grid: Option<TileGrid>,
+    /// Set while picking a scene out of their thumbnails
+    menu: Option<SceneMenu>,
+    /// Simulation time handed to scenes, which can be paused or sped up
+    clock: Clock,
+    /// Kept around for creating unloaded scenes again
+    layouts: LayoutCache,
+    pipelines: PipelineCache,
+    /// Whether scenes that aren't shown get unloaded, freeing what they hold
+    /// onto on the GPU until they're shown again
+    unload_inactive: bool,
 
-    demo1: scenes::textured::TextureExampleScene,
-    demo2: scenes::clown::ClownColorsScene,
-    demo3: scenes::triangle::TriangleScene,
-    demo4: scenes::camera::CameraScene,
-    demo5: scenes::instancing::InstancesScene,
+    demo1: SceneSlot<scenes::textured::TextureExampleScene>,
+    demo2: SceneSlot<scenes::clown::ClownColorsScene>,
+    demo3: SceneSlot<scenes::triangle::TriangleScene>,
+    demo4: SceneSlot<scenes::camera::CameraScene>,
+    demo5: SceneSlot<scenes::instancing::InstancesScene>,
+    demo6: SceneSlot<scenes::oit::OitScene>,
+    demo7: SceneSlot<scenes::particles::ParticlesScene>,
+    demo8: SceneSlot<scenes::compute_particles::ComputeParticlesScene>,
+    demo9: SceneSlot<scenes::life::LifeScene>,
+    demo10: SceneSlot<scenes::boids::BoidsScene>,
+    demo11: SceneSlot<scenes::nbody::NBodyScene>,
+    demo12: SceneSlot<scenes::raymarch::RaymarchScene>,
+    demo13: SceneSlot<scenes::terrain::TerrainScene>,
+    demo14: SceneSlot<scenes::voxel::VoxelScene>,
+    demo15: SceneSlot<scenes::lsystem::LSystemScene>,
+    demo16: SceneSlot<scenes::fractal::FractalScene>,
+    demo17: SceneSlot<scenes::audio::AudioScene>,
+    demo18: SceneSlot<scenes::model::ModelScene>,
+    demo19: SceneSlot<scenes::uniforms::DynamicUniformsScene>,
 }
 
 impl State {
@@ -99,7 +241,10 @@ impl State {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("Main device descriptor"),
-                    features: wgpu::Features::empty(),
+                    // Optional features, which scenes check for before using
+                    features: adapter.features()
+                        & (wgpu::Features::MULTI_DRAW_INDIRECT
+                            | wgpu::Features::NON_FILL_POLYGON_MODE),
                     limits: wgpu::Limits::default(),
                 },
                 None,
@@ -121,17 +266,192 @@ impl State {
         //let swap_chain = device.create(&surface, &sc_desc);
         surface.configure(&device, &sc_desc);
 
-        let user_state = GlobalState::default();
+        let user_state = GlobalState::new(&device, size.width, size.height);
 
         let mut staging = StagingFactory::new(&device);
+        // Shared by every scene, so that identical layouts only get created once
+        let layouts = LayoutCache::new();
+        // Same for pipelines, which scenes can also keep building as options change
+        let pipelines = PipelineCache::new();
+        // And for textures, models and shader modules, loaded once for every
+        // scene asking for them
+        let assets = Assets::new();
+
+        let demo1 = scenes::textured::TextureExampleScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo2 = scenes::clown::ClownColorsScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo3 = scenes::triangle::TriangleScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo4 = scenes::camera::CameraScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo5 = scenes::instancing::InstancesScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo6 = scenes::oit::OitScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo7 = scenes::particles::ParticlesScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo8 = scenes::compute_particles::ComputeParticlesScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo9 = scenes::life::LifeScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo10 = scenes::boids::BoidsScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo11 = scenes::nbody::NBodyScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo12 = scenes::raymarch::RaymarchScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo13 = scenes::terrain::TerrainScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo14 = scenes::voxel::VoxelScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo15 = scenes::lsystem::LSystemScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo16 = scenes::fractal::FractalScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo17 = scenes::audio::AudioScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo18 = scenes::model::ModelScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
+        let demo19 = scenes::uniforms::DynamicUniformsScene::new(
+            &device,
+            &queue,
+            &sc_desc,
+            &mut staging,
+            &layouts,
+            &pipelines,
+            &assets,
+        );
 
-        let demo1 =
-            scenes::textured::TextureExampleScene::new(&device, &queue, &sc_desc, &mut staging);
-        let demo2 = scenes::clown::ClownColorsScene::new(&device, &queue, &sc_desc, &mut staging);
-        let demo3 = scenes::triangle::TriangleScene::new(&device, &queue, &sc_desc, &mut staging);
-        let demo4 = scenes::camera::CameraScene::new(&device, &queue, &sc_desc, &mut staging);
-        let demo5 =
-            scenes::instancing::InstancesScene::new(&device, &queue, &sc_desc, &mut staging);
+        // Only what scenes kept a handle to stays loaded
+        let purged = assets.purge();
+        println!("Unloaded {} assets no scene kept", purged);
 
         Self {
             surface,
@@ -141,11 +461,34 @@ impl State {
             size,
             user_state,
             staging,
-            demo1,
-            demo2,
-            demo3,
-            demo4,
-            demo5,
+            assets,
+            input: Input::new(Bindings::load()),
+            screenshot_requested: false,
+            grid: None,
+            menu: None,
+            clock: Clock::new(),
+            layouts,
+            pipelines,
+            unload_inactive: false,
+            demo1: SceneSlot::new(demo1),
+            demo2: SceneSlot::new(demo2),
+            demo3: SceneSlot::new(demo3),
+            demo4: SceneSlot::new(demo4),
+            demo5: SceneSlot::new(demo5),
+            demo6: SceneSlot::new(demo6),
+            demo7: SceneSlot::new(demo7),
+            demo8: SceneSlot::new(demo8),
+            demo9: SceneSlot::new(demo9),
+            demo10: SceneSlot::new(demo10),
+            demo11: SceneSlot::new(demo11),
+            demo12: SceneSlot::new(demo12),
+            demo13: SceneSlot::new(demo13),
+            demo14: SceneSlot::new(demo14),
+            demo15: SceneSlot::new(demo15),
+            demo16: SceneSlot::new(demo16),
+            demo17: SceneSlot::new(demo17),
+            demo18: SceneSlot::new(demo18),
+            demo19: SceneSlot::new(demo19),
             current_pipeline: CurrentDemo::Textured,
         }
     }
@@ -155,69 +498,435 @@ impl State {
         self.sc_desc.width = new_size.width;
         self.sc_desc.height = new_size.height;
         self.surface.configure(&self.device, &self.sc_desc);
+        if let Some(grid) = &mut self.grid {
+            grid.resize(&self.device, new_size);
+        }
+        if let Some(menu) = &mut self.menu {
+            menu.resize(&self.device, new_size);
+        }
+        self.resize_scenes();
+    }
 
-        match self.current_pipeline {
-            CurrentDemo::Textured => self.demo1.resize(&self.device, &self.queue, new_size),
-            CurrentDemo::ClownColors => self.demo2.resize(&self.device, &self.queue, new_size),
-            CurrentDemo::Dima => self.demo3.resize(&self.device, &self.queue, new_size),
-            CurrentDemo::Cameras => self.demo4.resize(&self.device, &self.queue, new_size),
-            CurrentDemo::Instancing => self.demo5.resize(&self.device, &self.queue, new_size),
+    /// What scenes are sized to: the window, or a tile in grid mode
+    fn scene_size(&self) -> winit::dpi::PhysicalSize<u32> {
+        match &self.grid {
+            Some(grid) => grid.tile_size(),
+            None => self.size,
+        }
+    }
+
+    /// Resizes every scene to the window, or to a tile in grid mode
+    fn resize_scenes(&mut self) {
+        let size = self.scene_size();
+        self.user_state
+            .resize(&self.device, size.width, size.height);
+
+        // Every loaded scene gets resized, not just the current one, since some
+        // of them own screen-sized render targets that must match the surface
+        // when switching over to them
+        self.demo1.resize(&self.device, &self.queue, size);
+        self.demo2.resize(&self.device, &self.queue, size);
+        self.demo3.resize(&self.device, &self.queue, size);
+        self.demo4.resize(&self.device, &self.queue, size);
+        self.demo5.resize(&self.device, &self.queue, size);
+        self.demo6.resize(&self.device, &self.queue, size);
+        self.demo7.resize(&self.device, &self.queue, size);
+        self.demo8.resize(&self.device, &self.queue, size);
+        self.demo9.resize(&self.device, &self.queue, size);
+        self.demo10.resize(&self.device, &self.queue, size);
+        self.demo11.resize(&self.device, &self.queue, size);
+        self.demo12.resize(&self.device, &self.queue, size);
+        self.demo13.resize(&self.device, &self.queue, size);
+        self.demo14.resize(&self.device, &self.queue, size);
+        self.demo15.resize(&self.device, &self.queue, size);
+        self.demo16.resize(&self.device, &self.queue, size);
+        self.demo17.resize(&self.device, &self.queue, size);
+        self.demo18.resize(&self.device, &self.queue, size);
+        self.demo19.resize(&self.device, &self.queue, size);
+    }
+
+    /// Loads every scene about to be shown: the current one, or all of them
+    /// in grid mode and in the scene menu. With `unload_inactive`, unloads
+    /// every other one
+    fn sync_scenes(&mut self) {
+        let all = self.grid.is_some() || self.menu.is_some();
+        for &demo in CurrentDemo::ALL.iter() {
+            let shown = all || demo == self.current_pipeline;
+            let loaded = self.is_loaded(demo);
+            if shown && !loaded {
+                println!("Loading {:?}", demo);
+                self.load_scene(demo);
+            } else if !shown && loaded && self.unload_inactive {
+                println!("Unloading {:?}", demo);
+                self.unload_scene(demo);
+            }
         }
     }
 
-    fn input(&mut self, event: &WindowEvent) -> bool {
-        let handled_input = match self.current_pipeline {
-            CurrentDemo::Textured => self.demo1.input(event),
-            CurrentDemo::ClownColors => self.demo2.input(event),
-            CurrentDemo::Dima => self.demo3.input(event),
-            CurrentDemo::Cameras => self.demo4.input(event),
-            CurrentDemo::Instancing => self.demo5.input(event),
+    fn is_loaded(&self, demo: CurrentDemo) -> bool {
+        match demo {
+            CurrentDemo::Textured => self.demo1.is_loaded(),
+            CurrentDemo::ClownColors => self.demo2.is_loaded(),
+            CurrentDemo::Dima => self.demo3.is_loaded(),
+            CurrentDemo::Cameras => self.demo4.is_loaded(),
+            CurrentDemo::Instancing => self.demo5.is_loaded(),
+            CurrentDemo::Oit => self.demo6.is_loaded(),
+            CurrentDemo::Particles => self.demo7.is_loaded(),
+            CurrentDemo::ComputeParticles => self.demo8.is_loaded(),
+            CurrentDemo::Life => self.demo9.is_loaded(),
+            CurrentDemo::Boids => self.demo10.is_loaded(),
+            CurrentDemo::NBody => self.demo11.is_loaded(),
+            CurrentDemo::Raymarch => self.demo12.is_loaded(),
+            CurrentDemo::Terrain => self.demo13.is_loaded(),
+            CurrentDemo::Voxel => self.demo14.is_loaded(),
+            CurrentDemo::LSystem => self.demo15.is_loaded(),
+            CurrentDemo::Fractal => self.demo16.is_loaded(),
+            CurrentDemo::Audio => self.demo17.is_loaded(),
+            CurrentDemo::Model => self.demo18.is_loaded(),
+            CurrentDemo::Uniforms => self.demo19.is_loaded(),
+        }
+    }
+
+    fn load_scene(&mut self, demo: CurrentDemo) {
+        let mut loader = SceneLoader {
+            size: self.scene_size(),
+            device: &self.device,
+            queue: &self.queue,
+            sc: &self.sc_desc,
+            staging: &mut self.staging,
+            layouts: &self.layouts,
+            pipelines: &self.pipelines,
+            assets: &self.assets,
         };
+        match demo {
+            CurrentDemo::Textured => self.demo1.load(&mut loader),
+            CurrentDemo::ClownColors => self.demo2.load(&mut loader),
+            CurrentDemo::Dima => self.demo3.load(&mut loader),
+            CurrentDemo::Cameras => self.demo4.load(&mut loader),
+            CurrentDemo::Instancing => self.demo5.load(&mut loader),
+            CurrentDemo::Oit => self.demo6.load(&mut loader),
+            CurrentDemo::Particles => self.demo7.load(&mut loader),
+            CurrentDemo::ComputeParticles => self.demo8.load(&mut loader),
+            CurrentDemo::Life => self.demo9.load(&mut loader),
+            CurrentDemo::Boids => self.demo10.load(&mut loader),
+            CurrentDemo::NBody => self.demo11.load(&mut loader),
+            CurrentDemo::Raymarch => self.demo12.load(&mut loader),
+            CurrentDemo::Terrain => self.demo13.load(&mut loader),
+            CurrentDemo::Voxel => self.demo14.load(&mut loader),
+            CurrentDemo::LSystem => self.demo15.load(&mut loader),
+            CurrentDemo::Fractal => self.demo16.load(&mut loader),
+            CurrentDemo::Audio => self.demo17.load(&mut loader),
+            CurrentDemo::Model => self.demo18.load(&mut loader),
+            CurrentDemo::Uniforms => self.demo19.load(&mut loader),
+        }
+    }
 
-        match event {
-            WindowEvent::CursorMoved { position, .. } => {
-                let color_x = position.x / (self.size.width as f64);
-                let color_y = position.y / (self.size.height as f64);
-
-                let bg_color = &mut self.user_state.bg_color;
-                bg_color.r = color_x;
-                bg_color.g = color_y;
-
-                true
-            }
-            WindowEvent::KeyboardInput { input, .. } => {
-                if let KeyboardInput {
-                    state: ElementState::Pressed,
-                    virtual_keycode: Some(VirtualKeyCode::Space),
-                    ..
-                } = input
-                {
-                    println!("Pressed spacebar");
-                    self.current_pipeline.next();
-                    println!("{:?}", self.current_pipeline);
-                }
+    /// Frees what the scene holds onto on the GPU, see `Scene::destroy`
+    fn unload_scene(&mut self, demo: CurrentDemo) {
+        match demo {
+            CurrentDemo::Textured => self.demo1.unload(),
+            CurrentDemo::ClownColors => self.demo2.unload(),
+            CurrentDemo::Dima => self.demo3.unload(),
+            CurrentDemo::Cameras => self.demo4.unload(),
+            CurrentDemo::Instancing => self.demo5.unload(),
+            CurrentDemo::Oit => self.demo6.unload(),
+            CurrentDemo::Particles => self.demo7.unload(),
+            CurrentDemo::ComputeParticles => self.demo8.unload(),
+            CurrentDemo::Life => self.demo9.unload(),
+            CurrentDemo::Boids => self.demo10.unload(),
+            CurrentDemo::NBody => self.demo11.unload(),
+            CurrentDemo::Raymarch => self.demo12.unload(),
+            CurrentDemo::Terrain => self.demo13.unload(),
+            CurrentDemo::Voxel => self.demo14.unload(),
+            CurrentDemo::LSystem => self.demo15.unload(),
+            CurrentDemo::Fractal => self.demo16.unload(),
+            CurrentDemo::Audio => self.demo17.unload(),
+            CurrentDemo::Model => self.demo18.unload(),
+            CurrentDemo::Uniforms => self.demo19.unload(),
+        }
+    }
 
-                true
+    /// Unloads every scene that's loaded, on the way out
+    fn unload_all(&mut self) {
+        for &demo in CurrentDemo::ALL.iter() {
+            if self.is_loaded(demo) {
+                self.unload_scene(demo);
             }
-            _ => handled_input,
         }
     }
 
-    fn update(&mut self) {
+    fn grabs_cursor(&self) -> bool {
+        if self.menu.is_some() {
+            return false;
+        }
+
         match self.current_pipeline {
-            CurrentDemo::Textured => self.demo1.update(&self.device, &self.queue),
-            CurrentDemo::ClownColors => self.demo2.update(&self.device, &self.queue),
-            CurrentDemo::Dima => self.demo3.update(&self.device, &self.queue),
-            CurrentDemo::Cameras => self.demo4.update(&self.device, &self.queue),
-            CurrentDemo::Instancing => self.demo5.update(&self.device, &self.queue),
+            CurrentDemo::Textured => self.demo1.grabs_cursor(),
+            CurrentDemo::ClownColors => self.demo2.grabs_cursor(),
+            CurrentDemo::Dima => self.demo3.grabs_cursor(),
+            CurrentDemo::Cameras => self.demo4.grabs_cursor(),
+            CurrentDemo::Instancing => self.demo5.grabs_cursor(),
+            CurrentDemo::Oit => self.demo6.grabs_cursor(),
+            CurrentDemo::Particles => self.demo7.grabs_cursor(),
+            CurrentDemo::ComputeParticles => self.demo8.grabs_cursor(),
+            CurrentDemo::Life => self.demo9.grabs_cursor(),
+            CurrentDemo::Boids => self.demo10.grabs_cursor(),
+            CurrentDemo::NBody => self.demo11.grabs_cursor(),
+            CurrentDemo::Raymarch => self.demo12.grabs_cursor(),
+            CurrentDemo::Terrain => self.demo13.grabs_cursor(),
+            CurrentDemo::Voxel => self.demo14.grabs_cursor(),
+            CurrentDemo::LSystem => self.demo15.grabs_cursor(),
+            CurrentDemo::Fractal => self.demo16.grabs_cursor(),
+            CurrentDemo::Audio => self.demo17.grabs_cursor(),
+            CurrentDemo::Model => self.demo18.grabs_cursor(),
+            CurrentDemo::Uniforms => self.demo19.grabs_cursor(),
+        }
+    }
+
+    fn cancel(&mut self) -> bool {
+        if self.menu.take().is_some() {
+            return true;
+        }
+
+        match self.current_pipeline {
+            CurrentDemo::Textured => self.demo1.cancel(),
+            CurrentDemo::ClownColors => self.demo2.cancel(),
+            CurrentDemo::Dima => self.demo3.cancel(),
+            CurrentDemo::Cameras => self.demo4.cancel(),
+            CurrentDemo::Instancing => self.demo5.cancel(),
+            CurrentDemo::Oit => self.demo6.cancel(),
+            CurrentDemo::Particles => self.demo7.cancel(),
+            CurrentDemo::ComputeParticles => self.demo8.cancel(),
+            CurrentDemo::Life => self.demo9.cancel(),
+            CurrentDemo::Boids => self.demo10.cancel(),
+            CurrentDemo::NBody => self.demo11.cancel(),
+            CurrentDemo::Raymarch => self.demo12.cancel(),
+            CurrentDemo::Terrain => self.demo13.cancel(),
+            CurrentDemo::Voxel => self.demo14.cancel(),
+            CurrentDemo::LSystem => self.demo15.cancel(),
+            CurrentDemo::Fractal => self.demo16.cancel(),
+            CurrentDemo::Audio => self.demo17.cancel(),
+            CurrentDemo::Model => self.demo18.cancel(),
+            CurrentDemo::Uniforms => self.demo19.cancel(),
+        }
+    }
+
+    fn update(&mut self) {
+        self.assets.reload_changed(&self.device, &self.queue);
+
+        if self.input.consume("scene.next").is_some() {
+            self.current_pipeline.next();
+        }
+
+        if let Some(key) = self.input.consume("app.screenshot") {
+            println!("Pressed {:?}", key);
+            self.screenshot_requested = true;
+        }
+
+        if let Some(key) = self.input.consume("app.print_staging_stats") {
+            println!("Pressed {:?}", key);
+            for (name, stats) in self.staging.stats() {
+                match stats {
+                    Ok(stats) => println!("Staging belt \"{}\": {}", name, stats),
+                    Err(e) => println!("Skipping: {}", e),
+                }
+            }
+        }
+
+        if let Some(key) = self.input.consume("app.toggle_parallel_encoding") {
+            println!("Pressed {:?}", key);
+            self.user_state.parallel.toggle();
+        }
+
+        if let Some(key) = self.input.consume("app.toggle_grid_mode") {
+            println!("Pressed {:?}", key);
+            self.grid = match self.grid {
+                Some(_) => None,
+                None => Some(TileGrid::new(
+                    &self.device,
+                    &self.sc_desc,
+                    CurrentDemo::ALL.len(),
+                    self.size,
+                    TileSize::Cell,
+                )),
+            };
+            self.menu = None;
+            self.resize_scenes();
+            println!("Grid mode: {:?}", self.grid.is_some());
+        }
+
+        if let Some(key) = self.input.consume("app.toggle_scene_menu") {
+            println!("Pressed {:?}", key);
+            self.menu = match self.menu {
+                Some(_) => None,
+                None => {
+                    // Thumbnails are of scenes the size of the window
+                    if self.grid.take().is_some() {
+                        self.resize_scenes();
+                    }
+                    Some(SceneMenu::new(
+                        &self.device,
+                        &self.sc_desc,
+                        CurrentDemo::ALL.len(),
+                        self.size,
+                    ))
+                }
+            };
+            println!("Scene menu: {:?}", self.menu.is_some());
+        }
+
+        if let Some(key) = self.input.consume("app.toggle_unload_inactive") {
+            println!("Pressed {:?}", key);
+            self.unload_inactive = !self.unload_inactive;
+            println!("Unloading inactive scenes: {:?}", self.unload_inactive);
+        }
+
+        if let Some(key) = self.input.consume("app.pause") {
+            println!("Pressed {:?}", key);
+            self.clock.toggle_pause();
+        }
+
+        if let Some(key) = self.input.consume("app.step") {
+            println!("Pressed {:?}", key);
+            self.clock.step();
+        }
+
+        if let Some(key) = self.input.consume("app.slower") {
+            println!("Pressed {:?}", key);
+            self.clock.slower();
+        }
+
+        if let Some(key) = self.input.consume("app.faster") {
+            println!("Pressed {:?}", key);
+            self.clock.faster();
+        }
+
+        // Whatever got switched to has to be loaded before it's updated
+        self.sync_scenes();
+
+        // Measured from the corner of the window, until the focused tile is
+        // known
+        self.input.set_origin((0.0, 0.0));
+
+        if let Some((x, y)) = self.input.cursor() {
+            let bg_color = &mut self.user_state.bg_color;
+            bg_color.r = x / (self.size.width as f64);
+            bg_color.g = y / (self.size.height as f64);
+        }
+
+        // Ticked even behind the menu, so that scenes don't jump ahead once
+        // it's closed
+        let time = self.clock.tick();
+
+        // Scenes wait behind the menu until one gets picked, which only gets
+        // to see input from the next frame on
+        if let Some(menu) = &self.menu {
+            let picked = match self.input.cursor() {
+                Some(cursor) if self.input.mouse_just_pressed(MouseButton::Left) => {
+                    menu.tiles.tile_at(cursor)
+                }
+                _ => None,
+            };
+            if let Some(tile) = picked {
+                self.current_pipeline = CurrentDemo::ALL[tile];
+                self.menu = None;
+                println!("{:?}", self.current_pipeline);
+                self.sync_scenes();
+            }
+
+            self.input.end_frame();
+            return;
+        }
+
+        // Clicking a tile focuses its scene, which only gets to see input
+        // from the next frame on
+        let mut focus_changed = false;
+        if let (Some(grid), Some(cursor)) = (&self.grid, self.input.cursor()) {
+            if self.input.mouse_just_pressed(MouseButton::Left) {
+                if let Some(tile) = grid.tile_at(cursor) {
+                    let demo = CurrentDemo::ALL[tile];
+                    if demo != self.current_pipeline {
+                        self.current_pipeline = demo;
+                        focus_changed = true;
+                        println!("{:?}", self.current_pipeline);
+                    }
+                }
+            }
+        }
+
+        let focused = self.current_pipeline;
+        match self.grid.as_ref().map(|grid| grid.cell(focused.index())) {
+            None => self.update_scene(focused, true, time),
+            Some(cell) => {
+                self.input.set_origin((cell.x as f64, cell.y as f64));
+                for &demo in CurrentDemo::ALL.iter() {
+                    self.update_scene(demo, demo == focused && !focus_changed, time);
+                }
+            }
+        }
+
+        self.input.end_frame();
+    }
+
+    /// Updates the scene with this frame's input if it's `focused`, or with
+    /// none at all otherwise, advancing it by `time`
+    fn update_scene(&mut self, demo: CurrentDemo, focused: bool, time: Time) {
+        let input = match (&self.grid, focused) {
+            (Some(grid), false) => &grid.idle_input,
+            _ => &self.input,
+        };
+        match demo {
+            CurrentDemo::Textured => self.demo1.update(&self.device, &self.queue, input, time),
+            CurrentDemo::ClownColors => self.demo2.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Dima => self.demo3.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Cameras => self.demo4.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Instancing => self.demo5.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Oit => self.demo6.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Particles => self.demo7.update(&self.device, &self.queue, input, time),
+            CurrentDemo::ComputeParticles => {
+                self.demo8.update(&self.device, &self.queue, input, time)
+            }
+            CurrentDemo::Life => self.demo9.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Boids => self.demo10.update(&self.device, &self.queue, input, time),
+            CurrentDemo::NBody => self.demo11.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Raymarch => self.demo12.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Terrain => self.demo13.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Voxel => self.demo14.update(&self.device, &self.queue, input, time),
+            CurrentDemo::LSystem => self.demo15.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Fractal => self.demo16.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Audio => self.demo17.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Model => self.demo18.update(&self.device, &self.queue, input, time),
+            CurrentDemo::Uniforms => self.demo19.update(&self.device, &self.queue, input, time),
         }
     }
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        // Screenshots are drawn offscreen instead of onto a frame, leaving the
+        // last one on the window
+        let screenshot = if std::mem::take(&mut self.screenshot_requested) {
+            Some(RenderTarget::new(
+                &self.device,
+                self.sc_desc.width,
+                self.sc_desc.height,
+                self.sc_desc.format,
+                false,
+                "Screenshot",
+            ))
+        } else {
+            None
+        };
+
         // Get the frame we are going to draw on
-        let frame = self.surface.get_current_frame()?.output;
+        let frame;
+        let target = match &screenshot {
+            Some(screenshot) => &screenshot.color.texture,
+            None => {
+                frame = self.surface.get_current_frame()?.output;
+                &frame.texture
+            }
+        };
 
-        let texture_view = frame.texture.create_view(&TextureViewDescriptor {
+        let texture_view = target.create_view(&TextureViewDescriptor {
             label: Some("Main Texture View"),
             format: Some(self.sc_desc.format),
             dimension: Some(TextureViewDimension::D2),
@@ -233,35 +942,224 @@ impl State {
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Command Encoder"),
             });
+        // Only more than one when drawing several scenes, with an encoder for
+        // every one of them
+        let mut command_buffers = Vec::new();
 
-        match self.current_pipeline {
+        match self.grid.take() {
+            // The menu covers the whole window instead
+            None if self.menu.is_some() => {}
+            None => {
+                self.draw_scene(self.current_pipeline, &mut encoder, &texture_view)?;
+            }
+            Some(grid) => {
+                let mut rendered = Ok(());
+                for (i, &demo) in CurrentDemo::ALL.iter().enumerate() {
+                    rendered = rendered.and(self.draw_scene(
+                        demo,
+                        &mut encoder,
+                        &grid.target(i).color.view,
+                    ));
+                    // Whatever the scene encoded in parallel has to run
+                    // before the next one clears the shared depth texture
+                    self.split_encoder(&mut encoder, &mut command_buffers);
+                }
+
+                grid.composite(&mut encoder, &texture_view, self.current_pipeline.index());
+                self.grid = Some(grid);
+                rendered?;
+            }
+        }
+
+        if let Some(mut menu) = self.menu.take() {
+            let mut rendered = Ok(());
+            for i in menu.due(CurrentDemo::ALL.len()) {
+                rendered = rendered.and(self.draw_scene(
+                    CurrentDemo::ALL[i],
+                    &mut encoder,
+                    &menu.scratch.color.view,
+                ));
+                // Scaled down once the scene is done with the scratch target
+                self.split_encoder(&mut encoder, &mut command_buffers);
+                menu.tiles
+                    .capture(&self.device, &mut encoder, i, &menu.scratch);
+            }
+
+            menu.tiles
+                .composite(&mut encoder, &texture_view, self.current_pipeline.index());
+            self.menu = Some(menu);
+            rendered?;
+        }
+
+        if let Err(e) = self.staging.submit_all() {
+            println!("Staging error: {}", e);
+        }
+        command_buffers.push(encoder.finish());
+        command_buffers.extend(self.user_state.parallel.finish_frame());
+        self.queue.submit(command_buffers);
+        if let Err(e) = self.staging.recall_all(&self.queue) {
+            println!("Staging error: {}", e);
+        }
+
+        if let Some(screenshot) = screenshot {
+            match self.save_screenshot(&screenshot) {
+                Ok(path) => println!("Saved a screenshot to {}", path),
+                Err(e) => println!("Couldn't take a screenshot: {:?}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finishes `encoder` into `command_buffers` along with whatever got
+    /// encoded in parallel so far, replacing it with a new one for what comes
+    /// after
+    fn split_encoder(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        command_buffers: &mut Vec<wgpu::CommandBuffer>,
+    ) {
+        let finished = std::mem::replace(
+            encoder,
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Command Encoder"),
+                }),
+        );
+        command_buffers.push(finished.finish());
+        command_buffers.extend(self.user_state.parallel.take_encoded());
+    }
+
+    /// Runs the scene's compute stage and renders it into `view`, every
+    /// scene's work grouped under its name in captures
+    fn draw_scene(
+        &mut self,
+        demo: CurrentDemo,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) -> Result<(), wgpu::SurfaceError> {
+        encoder.push_debug_group(&format!("{:?} - Compute", demo));
+        match demo {
+            CurrentDemo::Textured => self.demo1.compute(encoder, &self.staging),
+            CurrentDemo::ClownColors => self.demo2.compute(encoder, &self.staging),
+            CurrentDemo::Dima => self.demo3.compute(encoder, &self.staging),
+            CurrentDemo::Cameras => self.demo4.compute(encoder, &self.staging),
+            CurrentDemo::Instancing => self.demo5.compute(encoder, &self.staging),
+            CurrentDemo::Oit => self.demo6.compute(encoder, &self.staging),
+            CurrentDemo::Particles => self.demo7.compute(encoder, &self.staging),
+            CurrentDemo::ComputeParticles => self.demo8.compute(encoder, &self.staging),
+            CurrentDemo::Life => self.demo9.compute(encoder, &self.staging),
+            CurrentDemo::Boids => self.demo10.compute(encoder, &self.staging),
+            CurrentDemo::NBody => self.demo11.compute(encoder, &self.staging),
+            CurrentDemo::Raymarch => self.demo12.compute(encoder, &self.staging),
+            CurrentDemo::Terrain => self.demo13.compute(encoder, &self.staging),
+            CurrentDemo::Voxel => self.demo14.compute(encoder, &self.staging),
+            CurrentDemo::LSystem => self.demo15.compute(encoder, &self.staging),
+            CurrentDemo::Fractal => self.demo16.compute(encoder, &self.staging),
+            CurrentDemo::Audio => self.demo17.compute(encoder, &self.staging),
+            CurrentDemo::Model => self.demo18.compute(encoder, &self.staging),
+            CurrentDemo::Uniforms => self.demo19.compute(encoder, &self.staging),
+        }
+        encoder.pop_debug_group();
+
+        encoder.push_debug_group(&format!("{:?} - Render", demo));
+        let rendered = match demo {
             CurrentDemo::Textured => {
                 self.demo1
-                    .render(&mut encoder, &texture_view, &self.user_state, &self.staging)
+                    .render(encoder, view, &self.user_state, &self.staging)
             }
             CurrentDemo::ClownColors => {
                 self.demo2
-                    .render(&mut encoder, &texture_view, &self.user_state, &self.staging)
-            }
-            CurrentDemo::Dima => {
-                self.demo3
-                    .render(&mut encoder, &texture_view, &self.user_state, &self.staging)
+                    .render(encoder, view, &self.user_state, &self.staging)
             }
+            CurrentDemo::Dima => self
+                .demo3
+                .render(encoder, view, &self.user_state, &self.staging),
             CurrentDemo::Cameras => {
                 self.demo4
-                    .render(&mut encoder, &texture_view, &self.user_state, &self.staging)
+                    .render(encoder, view, &self.user_state, &self.staging)
             }
             CurrentDemo::Instancing => {
                 self.demo5
-                    .render(&mut encoder, &texture_view, &self.user_state, &self.staging)
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+            CurrentDemo::Oit => self
+                .demo6
+                .render(encoder, view, &self.user_state, &self.staging),
+            CurrentDemo::Particles => {
+                self.demo7
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+            CurrentDemo::ComputeParticles => {
+                self.demo8
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+            CurrentDemo::Life => self
+                .demo9
+                .render(encoder, view, &self.user_state, &self.staging),
+            CurrentDemo::Boids => {
+                self.demo10
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+            CurrentDemo::NBody => {
+                self.demo11
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+            CurrentDemo::Raymarch => {
+                self.demo12
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+            CurrentDemo::Terrain => {
+                self.demo13
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+            CurrentDemo::Voxel => {
+                self.demo14
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+            CurrentDemo::LSystem => {
+                self.demo15
+                    .render(encoder, view, &self.user_state, &self.staging)
             }
-        }?;
+            CurrentDemo::Fractal => {
+                self.demo16
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+            CurrentDemo::Audio => {
+                self.demo17
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+            CurrentDemo::Model => {
+                self.demo18
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+            CurrentDemo::Uniforms => {
+                self.demo19
+                    .render(encoder, view, &self.user_state, &self.staging)
+            }
+        };
+        encoder.pop_debug_group();
+        rendered
+    }
 
-        self.staging.submit_all();
-        self.queue.submit(std::iter::once(encoder.finish()));
-        self.staging.recall_all();
+    /// Reads the screenshot back and saves it next to wherever the demos
+    /// are run from, returning where
+    fn save_screenshot(&self, screenshot: &RenderTarget) -> anyhow::Result<String> {
+        let image = block_on(texture::read_to_image(
+            &self.device,
+            &self.queue,
+            &screenshot.color.texture,
+            screenshot.format,
+            screenshot.width,
+            screenshot.height,
+        ))?;
 
-        Ok(())
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let path = format!("screenshot-{}.png", timestamp);
+        image.save(&path)?;
+        Ok(path)
     }
 }
 
@@ -276,10 +1174,16 @@ fn main() {
 
     // Initialize our graphics state
     let mut state = block_on(State::new(&window));
+    let mut cursor_grabbed = false;
 
     // Run the event loop
     event_loop.run(move |event, _, control_flow| match event {
         Event::RedrawRequested(_) => {
+            // Checked before the update, which forgets what was just pressed
+            if state.input.consume("app.quit").is_some() && !state.cancel() {
+                *control_flow = ControlFlow::Exit;
+            }
+
             state.update();
             match state.render() {
                 Ok(_) => {}
@@ -292,40 +1196,45 @@ fn main() {
             }
         }
         Event::MainEventsCleared => {
+            // Follow whatever the current scene wants done with the cursor
+            let grab = state.grabs_cursor();
+            if grab != cursor_grabbed {
+                // Not every platform can grab the cursor, hiding it still helps
+                if let Err(e) = window.set_cursor_grab(grab) {
+                    eprintln!("Couldn't grab the cursor: {:?}", e);
+                }
+                window.set_cursor_visible(!grab);
+                cursor_grabbed = grab;
+            }
+
             // RedrawRequested will only trigger once, unless we manually
             // request it.
             window.request_redraw();
         }
+        // However the app quits, scenes get to free what they hold first
+        Event::LoopDestroyed => state.unload_all(),
+        Event::DeviceEvent { ref event, .. } => {
+            state.input.device_event(event);
+        }
         Event::WindowEvent {
             ref event,
             window_id,
             // Only match on our own window
         } if window_id == window.id() => {
-            // We handle the input received, and if it's not
-            // input, we keep propagating
-            if !state.input(event) {
-                match event {
-                    // If the user changes the window size, update the swap chain
-                    WindowEvent::Resized(physical_size) => {
-                        state.resize(*physical_size);
-                    }
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(**new_inner_size);
-                    }
-                    // If the user wants to exit, let them
-                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                    WindowEvent::KeyboardInput { input, .. } => {
-                        if let KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        } = input
-                        {
-                            *control_flow = ControlFlow::Exit
-                        }
-                    }
-                    _ => {}
+            // Every event goes into the input snapshot, for the next update
+            state.input.window_event(event);
+
+            match event {
+                // If the user changes the window size, update the swap chain
+                WindowEvent::Resized(physical_size) => {
+                    state.resize(*physical_size);
+                }
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    state.resize(**new_inner_size);
                 }
+                // If the user wants to exit, let them
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                _ => {}
             }
         }
         _ => {}
@@ -0,0 +1,72 @@
+//! Scene selection menu, showing a thumbnail of every scene to pick from.
+//!
+//! Thumbnails are drawn by rendering the scene at its own size into a
+//! scratch target, the way it would render into a frame, and scaling that
+//! down. All of them get drawn on the frame the menu opens, then one scene's
+//! a frame after that, so that they keep up with scenes that move.
+
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    texture::RenderTarget,
+    tiles::{TileGrid, TileSize},
+};
+
+/// Width of every thumbnail, in pixels
+const THUMBNAIL_WIDTH: u32 = 256;
+
+pub struct SceneMenu {
+    pub tiles: TileGrid,
+    /// What scenes render into before being scaled down into their
+    /// thumbnail, as big as the window
+    pub scratch: RenderTarget,
+    /// Thumbnail to refresh next
+    next: usize,
+    /// Whether every thumbnail has yet to be drawn
+    blank: bool,
+}
+
+impl SceneMenu {
+    pub fn new(
+        device: &wgpu::Device,
+        sc: &wgpu::SurfaceConfiguration,
+        count: usize,
+        window: PhysicalSize<u32>,
+    ) -> Self {
+        Self {
+            tiles: TileGrid::new(
+                device,
+                sc,
+                count,
+                window,
+                TileSize::Thumbnail(THUMBNAIL_WIDTH),
+            ),
+            scratch: RenderTarget::new(
+                device,
+                window.width,
+                window.height,
+                sc.format,
+                false,
+                "Menu - Scratch",
+            ),
+            next: 0,
+            blank: true,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, window: PhysicalSize<u32>) {
+        self.tiles.resize(device, window);
+        self.scratch.resize(device, window.width, window.height);
+    }
+
+    /// Thumbnails to draw this frame, out of `count`
+    pub fn due(&mut self, count: usize) -> Vec<usize> {
+        if std::mem::take(&mut self.blank) {
+            (0..count).collect()
+        } else {
+            let due = self.next;
+            self.next = (self.next + 1) % count;
+            vec![due]
+        }
+    }
+}
@@ -1,13 +1,17 @@
-use std::ops::Range;
+use std::{collections::HashMap, fmt, ops::Range};
 
-use cgmath::{Matrix4, Quaternion, SquareMatrix, Vector3};
-use wgpu::util::DeviceExt;
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix3, Matrix4, One, Point3, Quaternion, SquareMatrix,
+    Transform as _, Vector3, VectorSpace,
+};
 
 use crate::{
-    buffer::IndexedVertexBuffer,
-    vertex::{Descriptable, VertexBufferable},
+    buffer::{DrawIndexedIndirectArgs, Index, IndexedVertexBuffer, IndirectBuffer, Uniformable},
+    vertex::{Descriptable, Normaled, Positioned, Tangented, VertexBufferable, Weldable},
 };
 
+pub mod primitives;
+
 #[derive(Debug)]
 pub struct Transform {
     translation: Vector3<f32>,
@@ -28,6 +32,25 @@ impl Transform {
         myself
     }
 
+    /// Moved to `translation`, neither rotated nor scaled
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self::new(translation, Quaternion::one(), Vector3::new(1.0, 1.0, 1.0))
+    }
+
+    /// Placed at `eye`, and turned the way a camera there would be to look
+    /// at `target`: -Z pointing to it, and +Y as close to `up` as it gets
+    #[allow(dead_code)] // Nothing gets aimed yet
+    pub fn look_at(eye: Point3<f32>, target: Point3<f32>, up: Vector3<f32>) -> Self {
+        let back = (eye - target).normalize();
+        let right = up.cross(back).normalize();
+        let up = back.cross(right);
+        Self::new(
+            eye.to_vec(),
+            Quaternion::from(Matrix3::from_cols(right, up, back)),
+            Vector3::new(1.0, 1.0, 1.0),
+        )
+    }
+
     pub fn translation(&self) -> &Vector3<f32> {
         &self.translation
     }
@@ -37,6 +60,7 @@ impl Transform {
         self.regenerate_model_matrix();
     }
 
+    #[allow(dead_code)] // Nothing edits transforms in place yet
     pub unsafe fn translation_mut(&mut self) -> &mut Vector3<f32> {
         &mut self.translation
     }
@@ -50,6 +74,7 @@ impl Transform {
         self.regenerate_model_matrix();
     }
 
+    #[allow(dead_code)] // Nothing edits transforms in place yet
     pub unsafe fn rotation_mut(&mut self) -> &mut Quaternion<f32> {
         &mut self.rotation
     }
@@ -63,10 +88,29 @@ impl Transform {
         self.regenerate_model_matrix();
     }
 
+    #[allow(dead_code)] // Nothing edits transforms in place yet
     pub unsafe fn scale_mut(&mut self) -> &mut Vector3<f32> {
         &mut self.scale
     }
 
+    /// The transform `t` of the way from this one to `other`, turning along
+    /// the shortest arc between both rotations
+    #[allow(dead_code)] // Nothing interpolates transforms yet
+    pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        // Both quaternions stand for the same rotation, but only one of them
+        // is less than half a turn away from ours
+        let rotation = if self.rotation.dot(other.rotation) < 0.0 {
+            -other.rotation
+        } else {
+            other.rotation
+        };
+        Transform::new(
+            self.translation.lerp(other.translation, t),
+            self.rotation.slerp(rotation, t).normalize(),
+            self.scale.lerp(other.scale, t),
+        )
+    }
+
     pub fn regenerate_model_matrix(&mut self) {
         let t = self.translation;
         let s = self.scale;
@@ -82,61 +126,529 @@ impl Transform {
         &self.mat
     }
 
-    pub fn uniform_matrix2(&self) -> &[[f32; 4]; 4] {
-        self.mat.as_ref()
-    }
-
     pub fn uniform_matrix(&self) -> [[f32; 4]; 4] {
         self.mat.into()
     }
+}
 
-    pub fn as_buffer(&self, device: &wgpu::Device, label: Option<&str>) -> wgpu::Buffer {
-        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label,
-            contents: bytemuck::bytes_of(self.uniform_matrix2()),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        })
+/// Identity
+impl Default for Transform {
+    fn default() -> Self {
+        Self::from_translation(Vector3::new(0.0, 0.0, 0.0))
     }
 }
 
-/*impl Uniformable for Transform {
+impl Uniformable for Transform {
     type Uniform = [[f32; 4]; 4];
 
-    fn into_uniform(&self) -> Self::Uniform {
-        self.mat.into()
+    fn to_uniform(&self) -> Self::Uniform {
+        self.uniform_matrix()
     }
-}*/
+}
 
+/// Builds a `Transform` out of a translation, Euler angles in degrees and a
+/// scale, in that order. Any of them can be left out, i.e.
+/// `transform!(t: [x, y, 0.0])`, and defaults to the identity
 #[macro_export]
 macro_rules! transform {
+    (@or [$($value:tt)+] $default:expr) => { $($value)+ };
+    (@or [] $default:expr) => { $default };
     {
-        t: [$t1:expr, $t2:expr, $t3:expr],
-        r: [$r1:expr, $r2:expr, $r3:expr],
-        s: [$s1:expr, $s2:expr, $s3:expr]
+        $(t: [$t1:expr, $t2:expr, $t3:expr])? $(,)?
+        $(r: [$r1:expr, $r2:expr, $r3:expr])? $(,)?
+        $(s: [$s1:expr, $s2:expr, $s3:expr])? $(,)?
     } => {
         {
-            use cgmath::{Vector3, Quaternion, Euler, Deg};
-            Transform::new(
-                Vector3::new($t1, $t2, $t3),
-                Quaternion::from(Euler { x: Deg($r1), y: Deg($r2), z: Deg($r3)}),
-                Vector3::new($s1, $s2, $s3)
+            $crate::mesh::Transform::new(
+                $crate::transform!(
+                    @or [$(cgmath::Vector3::new($t1, $t2, $t3))?]
+                    cgmath::Vector3::new(0.0, 0.0, 0.0)
+                ),
+                $crate::transform!(
+                    @or [$(cgmath::Quaternion::from(cgmath::Euler {
+                        x: cgmath::Deg($r1),
+                        y: cgmath::Deg($r2),
+                        z: cgmath::Deg($r3),
+                    }))?]
+                    <cgmath::Quaternion<f32> as cgmath::One>::one()
+                ),
+                $crate::transform!(
+                    @or [$(cgmath::Vector3::new($s1, $s2, $s3))?]
+                    cgmath::Vector3::new(1.0, 1.0, 1.0)
+                ),
             )
         }
+    };
+}
+
+/// Axis-aligned bounding box
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Point3::new(a.x, a.y, a.z),
+            Point3::new(b.x, a.y, a.z),
+            Point3::new(b.x, b.y, a.z),
+            Point3::new(a.x, b.y, a.z),
+            Point3::new(a.x, a.y, b.z),
+            Point3::new(b.x, a.y, b.z),
+            Point3::new(b.x, b.y, b.z),
+            Point3::new(a.x, b.y, b.z),
+        ]
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    /// How far along the ray it first enters the box, or zero if it starts
+    /// inside of it. `None` if it misses the box, or the box is behind it
+    pub fn ray_intersection(&self, origin: Point3<f32>, direction: Vector3<f32>) -> Option<f32> {
+        // Where the ray crosses the two planes of every axis, the box being
+        // wherever it's between both on all three
+        let (mut near, mut far) = (0.0f32, f32::INFINITY);
+        for axis in 0..3 {
+            let inverse = 1.0 / direction[axis];
+            let a = (self.min[axis] - origin[axis]) * inverse;
+            let b = (self.max[axis] - origin[axis]) * inverse;
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+
+    /// Smallest box around this one once transformed by `matrix`
+    pub fn transformed(&self, matrix: &Matrix4<f32>) -> Aabb {
+        let corners = self.corners();
+        let mut corners = corners.iter().map(|c| matrix.transform_point(*c));
+        let first = corners.next().unwrap();
+        corners.fold(
+            Aabb {
+                min: first,
+                max: first,
+            },
+            |aabb, c| Aabb {
+                min: Point3::new(
+                    aabb.min.x.min(c.x),
+                    aabb.min.y.min(c.y),
+                    aabb.min.z.min(c.z),
+                ),
+                max: Point3::new(
+                    aabb.max.x.max(c.x),
+                    aabb.max.y.max(c.y),
+                    aabb.max.z.max(c.z),
+                ),
+            },
+        )
     }
 }
 
+#[derive(Copy, Clone, Debug)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Sphere containing this one once transformed by `matrix`, which stays
+    /// tight as long as the scale is uniform
+    pub fn transformed(&self, matrix: &Matrix4<f32>) -> BoundingSphere {
+        let scale = matrix
+            .x
+            .truncate()
+            .magnitude()
+            .max(matrix.y.truncate().magnitude())
+            .max(matrix.z.truncate().magnitude());
+        BoundingSphere {
+            center: matrix.transform_point(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// Model-space bounding volumes of a mesh
+#[derive(Copy, Clone, Debug)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Bounds {
+    /// The sphere is centered on the box rather than being the minimal one,
+    /// which is close enough for culling purposes
+    pub fn from_vertices<T: Positioned>(vertices: &[T]) -> Self {
+        let mut positions = vertices.iter().map(|v| Point3::from(v.position()));
+        let first = positions.next().unwrap_or_else(Point3::origin);
+        let aabb = positions.clone().fold(
+            Aabb {
+                min: first,
+                max: first,
+            },
+            |aabb, p| Aabb {
+                min: Point3::new(
+                    aabb.min.x.min(p.x),
+                    aabb.min.y.min(p.y),
+                    aabb.min.z.min(p.z),
+                ),
+                max: Point3::new(
+                    aabb.max.x.max(p.x),
+                    aabb.max.y.max(p.y),
+                    aabb.max.z.max(p.z),
+                ),
+            },
+        );
+
+        let center = aabb.center();
+        let radius = vertices
+            .iter()
+            .map(|v| (Point3::from(v.position()) - center).magnitude())
+            .fold(0.0, f32::max);
+
+        Self {
+            aabb,
+            sphere: BoundingSphere { center, radius },
+        }
+    }
+}
+
+/// Vertices and the indices of their triangles, still on the CPU side, for
+/// loaders and generators to fill in whatever attributes are missing before
+/// uploading them
+pub struct CpuMesh<T, I> {
+    pub vertices: Vec<T>,
+    pub indices: Vec<I>,
+}
+
+impl<T: Normaled, I: Index> CpuMesh<T, I> {
+    /// Replaces every vertex's normal with the average of the normals of the
+    /// triangles around it, weighted by their area so that thin slivers don't
+    /// skew it. Vertices that aren't part of any triangle end up with a zero
+    /// normal
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let (a, b, c) = (
+                triangle[0].to_usize(),
+                triangle[1].to_usize(),
+                triangle[2].to_usize(),
+            );
+            let pa = Point3::from(self.vertices[a].position());
+            let pb = Point3::from(self.vertices[b].position());
+            let pc = Point3::from(self.vertices[c].position());
+
+            // Left unnormalized, its length is twice the triangle's area
+            let face_normal = (pb - pa).cross(pc - pa);
+            normals[a] += face_normal;
+            normals[b] += face_normal;
+            normals[c] += face_normal;
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            let normal = if normal.magnitude2() > 0.0 {
+                normal.normalize()
+            } else {
+                normal
+            };
+            vertex.set_normal(normal.into());
+        }
+    }
+}
+
+impl<T: Tangented, I: Index> CpuMesh<T, I> {
+    /// Generates tangents following MikkTSpace's conventions (tangents along
+    /// +U, orthogonalized against the normal, and bitangents rebuilt in the
+    /// shader out of the sign in `w`), which is what normal maps are usually
+    /// baked against. Unlike MikkTSpace, vertices are never split at UV
+    /// mirroring seams.
+    ///
+    /// Needs normals to already be there. Vertices whose triangles have no
+    /// usable texture coordinates get a zero tangent
+    pub fn compute_tangents(&mut self) {
+        let mut tangents = vec![Vector3::new(0.0, 0.0, 0.0); self.vertices.len()];
+        let mut bitangents = vec![Vector3::new(0.0, 0.0, 0.0); self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let (a, b, c) = (
+                triangle[0].to_usize(),
+                triangle[1].to_usize(),
+                triangle[2].to_usize(),
+            );
+            let pa = Point3::from(self.vertices[a].position());
+            let edge1 = Point3::from(self.vertices[b].position()) - pa;
+            let edge2 = Point3::from(self.vertices[c].position()) - pa;
+
+            let uva = self.vertices[a].tex_coords();
+            let uvb = self.vertices[b].tex_coords();
+            let uvc = self.vertices[c].tex_coords();
+            let (du1, dv1) = (uvb[0] - uva[0], uvb[1] - uva[1]);
+            let (du2, dv2) = (uvc[0] - uva[0], uvc[1] - uva[1]);
+
+            let determinant = du1 * dv2 - du2 * dv1;
+            if determinant.abs() < f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge1 * dv2 - edge2 * dv1) / determinant;
+            let bitangent = (edge2 * du1 - edge1 * du2) / determinant;
+            for &i in [a, b, c].iter() {
+                tangents[i] += tangent;
+                bitangents[i] += bitangent;
+            }
+        }
+
+        let vertices = self.vertices.iter_mut();
+        for ((vertex, tangent), bitangent) in vertices.zip(tangents).zip(bitangents) {
+            let normal = Vector3::from(vertex.normal());
+            // Gram-Schmidt
+            let tangent = tangent - normal * normal.dot(tangent);
+            if tangent.magnitude2() <= f32::EPSILON * f32::EPSILON {
+                vertex.set_tangent([0.0, 0.0, 0.0, 0.0]);
+                continue;
+            }
+
+            let tangent = tangent.normalize();
+            let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            vertex.set_tangent(tangent.extend(handedness).into());
+        }
+    }
+}
+
+/// How much welding shrank a triangle soup
+#[derive(Copy, Clone, Debug)]
+pub struct WeldStats {
+    pub input_vertices: usize,
+    pub output_vertices: usize,
+}
+
+impl WeldStats {
+    /// Input vertices per output vertex
+    pub fn ratio(&self) -> f32 {
+        self.input_vertices as f32 / self.output_vertices.max(1) as f32
+    }
+}
+
+impl fmt::Display for WeldStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} vertices welded into {} ({:.2}:1)",
+            self.input_vertices,
+            self.output_vertices,
+            self.ratio()
+        )
+    }
+}
+
+/// Turns a triangle soup (three vertices per triangle, nothing shared) into
+/// indexed vertices, welding together vertices whose every attribute is
+/// within `epsilon` of each other.
+///
+/// Vertices are bucketed by position into cells `epsilon` wide, so only the
+/// ones in neighbouring cells ever get compared
+pub struct Welder<T: Weldable> {
+    epsilon: f32,
+    vertices: Vec<T>,
+    indices: Vec<u32>,
+    cells: HashMap<[i64; 3], Vec<u32>>,
+    input_vertices: usize,
+}
+
+impl<T: Weldable> Welder<T> {
+    pub fn new(epsilon: f32) -> Self {
+        Self {
+            epsilon,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            cells: HashMap::new(),
+            input_vertices: 0,
+        }
+    }
+
+    fn cell(&self, position: [f32; 3]) -> [i64; 3] {
+        let size = self.epsilon.max(f32::EPSILON);
+        position.map(|c| (c / size).floor() as i64)
+    }
+
+    fn push(&mut self, vertex: T) -> u32 {
+        self.input_vertices += 1;
+        let cell = self.cell(vertex.position());
+
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let neighbour = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                    let found = self.cells.get(&neighbour).and_then(|candidates| {
+                        candidates
+                            .iter()
+                            .copied()
+                            .find(|&i| self.vertices[i as usize].approx_eq(&vertex, self.epsilon))
+                    });
+                    if let Some(index) = found {
+                        return index;
+                    }
+                }
+            }
+        }
+
+        let index = self.vertices.len() as u32;
+        self.vertices.push(vertex);
+        self.cells.entry(cell).or_default().push(index);
+        index
+    }
+
+    pub fn push_triangle(&mut self, triangle: [T; 3]) {
+        for &vertex in triangle.iter() {
+            let index = self.push(vertex);
+            self.indices.push(index);
+        }
+    }
+
+    /// Adds every triangle out of `soup`, ignoring any leftover vertices
+    pub fn extend(&mut self, soup: &[T]) {
+        for triangle in soup.chunks_exact(3) {
+            self.push_triangle([triangle[0], triangle[1], triangle[2]]);
+        }
+    }
+
+    pub fn stats(&self) -> WeldStats {
+        WeldStats {
+            input_vertices: self.input_vertices,
+            output_vertices: self.vertices.len(),
+        }
+    }
+
+    pub fn finish(self) -> (Vec<T>, Vec<u32>) {
+        (self.vertices, self.indices)
+    }
+
+    #[allow(dead_code)] // The only soup so far needs normals generated first
+    pub fn to_buffer(&self, device: &wgpu::Device, label: Option<&str>) -> IndexedVertexBuffer<T>
+    where
+        T: VertexBufferable + Descriptable,
+    {
+        IndexedVertexBuffer::from_vertices_compact_indexes(
+            device,
+            &self.vertices,
+            &self.indices,
+            label,
+            label,
+        )
+    }
+}
+
+/// Tints telling levels of detail apart when debugging them, from the most
+/// detailed one onwards. Levels past the last one reuse it
+pub const LOD_DEBUG_TINTS: &[[f32; 4]] = &[
+    [1.0, 0.4, 0.4, 1.0],
+    [0.4, 1.0, 0.4, 1.0],
+    [0.4, 0.6, 1.0, 1.0],
+    [1.0, 1.0, 0.4, 1.0],
+];
+
+pub fn lod_debug_tint(level: usize) -> [f32; 4] {
+    LOD_DEBUG_TINTS[level.min(LOD_DEBUG_TINTS.len() - 1)]
+}
+
+/// Draws `data` with the `index`-th arguments out of `args`. Instances come
+/// from wherever the pipeline reads them: bind an instance buffer to slot 1
+/// beforehand to use them as vertex attributes
+pub fn render_indirect<'a, T: VertexBufferable + Descriptable>(
+    render_pass: &mut wgpu::RenderPass<'a>,
+    data: &'a IndexedVertexBuffer<T>,
+    args: &'a IndirectBuffer<DrawIndexedIndirectArgs>,
+    index: u32,
+) {
+    data.bind(render_pass, 0);
+    render_pass.draw_indexed_indirect(&args.buffer, args.offset(index))
+}
+
+/// The same mesh at decreasing levels of detail, each of them used up to some
+/// distance from the camera. The last level is used past every threshold
+pub struct Lod<T: VertexBufferable + Descriptable> {
+    levels: Vec<IndexedVertexBuffer<T>>,
+    /// Furthest distance every level but the last one gets used at
+    thresholds: Vec<f32>,
+}
+
+impl<T: VertexBufferable + Descriptable> Lod<T> {
+    /// `thresholds` must be increasing, with one less than there are `levels`
+    pub fn new(levels: Vec<IndexedVertexBuffer<T>>, thresholds: Vec<f32>) -> Self {
+        assert!(
+            !levels.is_empty(),
+            "A mesh needs at least one level of detail"
+        );
+        assert_eq!(
+            levels.len(),
+            thresholds.len() + 1,
+            "Every level of detail but the last one needs a threshold"
+        );
+        assert!(
+            thresholds.windows(2).all(|w| w[0] <= w[1]),
+            "Level of detail thresholds must be increasing"
+        );
+        Self { levels, thresholds }
+    }
+
+    /// A single level, used at any distance
+    pub fn single(data: IndexedVertexBuffer<T>) -> Self {
+        Self::new(vec![data], Vec::new())
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn level(&self, level: usize) -> &IndexedVertexBuffer<T> {
+        &self.levels[level]
+    }
+
+    /// Level to draw something `distance` away from the camera with
+    pub fn select(&self, distance: f32) -> usize {
+        self.thresholds
+            .iter()
+            .take_while(|&&threshold| distance > threshold)
+            .count()
+    }
+}
+
+#[allow(dead_code)] // Nothing renders through it yet
 pub struct MeshRenderData {
     next_vertex_idx: u32,
 }
 
 pub struct OldMesh<T: VertexBufferable + Descriptable> {
     data: IndexedVertexBuffer<T>,
+    bounds: Bounds,
     transform: Transform,
 }
 
 impl<T: VertexBufferable + Descriptable> OldMesh<T> {
-    pub fn new(data: IndexedVertexBuffer<T>, transform: Transform) -> Self {
-        Self { data, transform }
+    pub fn new(data: IndexedVertexBuffer<T>, bounds: Bounds, transform: Transform) -> Self {
+        Self {
+            data,
+            bounds,
+            transform,
+        }
+    }
+
+    /// Bounding volumes in world space, following the mesh's transform
+    pub fn world_bounds(&self) -> Bounds {
+        let matrix = self.transform.model_matrix();
+        Bounds {
+            aabb: self.bounds.aabb.transformed(matrix),
+            sphere: self.bounds.sphere.transformed(matrix),
+        }
     }
 
     pub fn transform(&self) -> &Transform {
@@ -148,8 +660,7 @@ impl<T: VertexBufferable + Descriptable> OldMesh<T> {
     }
 
     pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, instances: Range<u32>) {
-        render_pass.set_vertex_buffer(0, self.data.vertices.slice(..));
-        render_pass.set_index_buffer(self.data.indices.slice(..), wgpu::IndexFormat::Uint16);
+        self.data.bind(render_pass, 0);
         render_pass.draw_indexed(0..self.data.num_indices, 0, instances)
     }
 }
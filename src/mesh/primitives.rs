@@ -0,0 +1,352 @@
+//! Parametric generators for the usual primitive shapes, with positions,
+//! normals, texture coordinates and tangents, centered on the origin.
+//!
+//! Every generator follows the same conventions as the rest of the meshes:
+//! counter-clockwise front faces, +Y up, and texture coordinates with V going
+//! down, so images show up the right way around when seen from the outside.
+
+use std::{collections::HashMap, f32::consts::PI};
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::{buffer::IndexedVertexBuffer, mesh::CpuMesh, model::ModelVertex};
+
+/// Vertices and indices of a generated shape, still on the CPU side
+pub type Geometry = CpuMesh<ModelVertex, u16>;
+
+impl Geometry {
+    fn with_capacity(vertices: usize, indices: usize) -> Self {
+        Self {
+            vertices: Vec::with_capacity(vertices),
+            indices: Vec::with_capacity(indices),
+        }
+    }
+
+    fn push(&mut self, position: Vector3<f32>, normal: Vector3<f32>, tex_coords: [f32; 2]) -> u16 {
+        let index = self.vertices.len();
+        assert!(
+            index <= u16::MAX as usize,
+            "Too many vertices for 16-bit indices, lower the subdivisions"
+        );
+        self.vertices.push(ModelVertex {
+            position: position.into(),
+            tex_coords,
+            normal: normal.into(),
+            tangent: [0.0, 0.0, 0.0, 0.0],
+        });
+        index as u16
+    }
+
+    /// Splits the quads of a `columns` by `rows` grid of vertices laid out
+    /// row by row, starting at `first`, into two triangles each
+    fn push_grid_indices(&mut self, first: u16, columns: u16, rows: u16) {
+        for j in 0..rows - 1 {
+            for i in 0..columns - 1 {
+                let a = first + j * columns + i;
+                let b = a + 1;
+                let c = a + columns;
+                let d = c + 1;
+                self.indices.extend_from_slice(&[a, c, b, b, c, d]);
+            }
+        }
+    }
+
+    /// Subdivided square centered on `center`, facing `normal`, with `up`
+    /// pointing towards the top of its texture. Both are expected to be unit
+    /// length and perpendicular to each other
+    fn push_face(
+        &mut self,
+        center: Vector3<f32>,
+        normal: Vector3<f32>,
+        up: Vector3<f32>,
+        half_size: f32,
+        subdivisions: u16,
+    ) {
+        let right = up.cross(normal) * half_size;
+        let down = -up * half_size;
+        let steps = subdivisions + 1;
+
+        let first = self.vertices.len() as u16;
+        for j in 0..=steps {
+            for i in 0..=steps {
+                let s = i as f32 / steps as f32;
+                let t = j as f32 / steps as f32;
+                let position = center + right * (2.0 * s - 1.0) + down * (2.0 * t - 1.0);
+                self.push(position, normal, [s, t]);
+            }
+        }
+        self.push_grid_indices(first, steps + 1, steps + 1);
+    }
+
+    /// Generates tangents once everything else is in place
+    fn finish(mut self) -> Self {
+        self.compute_tangents();
+        self
+    }
+
+    pub fn to_buffer(
+        &self,
+        device: &wgpu::Device,
+        label: Option<&str>,
+    ) -> IndexedVertexBuffer<ModelVertex> {
+        IndexedVertexBuffer::from_vertices_indexes(
+            device,
+            &self.vertices,
+            &self.indices,
+            label,
+            label,
+        )
+    }
+}
+
+/// Square on the XZ plane facing +Y, split in `subdivisions + 1` quads per side
+pub fn plane(size: f32, subdivisions: u16) -> Geometry {
+    let steps = subdivisions as usize + 2;
+    let mut geometry = Geometry::with_capacity(steps * steps, (steps - 1) * (steps - 1) * 6);
+    geometry.push_face(
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::unit_y(),
+        -Vector3::unit_z(),
+        size * 0.5,
+        subdivisions,
+    );
+    geometry.finish()
+}
+
+/// Cube with hard edges, every face getting the whole texture
+pub fn cube(size: f32, subdivisions: u16) -> Geometry {
+    let steps = subdivisions as usize + 2;
+    let mut geometry =
+        Geometry::with_capacity(6 * steps * steps, 6 * (steps - 1) * (steps - 1) * 6);
+    let half_size = size * 0.5;
+
+    let (x, y, z) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+    for &(normal, up) in [(x, y), (-x, y), (z, y), (-z, y), (y, -z), (-y, z)].iter() {
+        geometry.push_face(normal * half_size, normal, up, half_size, subdivisions);
+    }
+    geometry.finish()
+}
+
+/// Point on the unit sphere, with `theta` going around the Y axis and `phi`
+/// going from the north pole to the south pole
+fn spherical(theta: f32, phi: f32) -> Vector3<f32> {
+    Vector3::new(phi.sin() * theta.cos(), phi.cos(), -phi.sin() * theta.sin())
+}
+
+/// Sphere made out of `sectors` meridians and `stacks` parallels, which
+/// bunches up triangles around the poles but maps textures without distortion
+/// along the equator
+pub fn uv_sphere(radius: f32, sectors: u16, stacks: u16) -> Geometry {
+    assert!(sectors >= 3 && stacks >= 2, "Not enough sectors or stacks");
+    let columns = sectors + 1;
+    let rows = stacks + 1;
+    let mut geometry = Geometry::with_capacity(
+        columns as usize * rows as usize,
+        sectors as usize * stacks as usize * 6,
+    );
+
+    for j in 0..rows {
+        for i in 0..columns {
+            let u = i as f32 / sectors as f32;
+            let v = j as f32 / stacks as f32;
+            let normal = spherical(u * 2.0 * PI, v * PI);
+            geometry.push(normal * radius, normal, [u, v]);
+        }
+    }
+
+    // Same as a grid, minus the triangles that collapse into the poles
+    for j in 0..stacks {
+        for i in 0..sectors {
+            let a = j * columns + i;
+            let b = a + 1;
+            let c = a + columns;
+            let d = c + 1;
+            if j != 0 {
+                geometry.indices.extend_from_slice(&[a, c, b]);
+            }
+            if j != stacks - 1 {
+                geometry.indices.extend_from_slice(&[b, c, d]);
+            }
+        }
+    }
+
+    geometry.finish()
+}
+
+/// Sphere made out of an icosahedron whose triangles get split in four
+/// `subdivisions` times, which spreads them evenly at the cost of a seam in
+/// its texture coordinates
+pub fn icosphere(radius: f32, subdivisions: u32) -> Geometry {
+    let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+    let mut positions = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .iter()
+    .map(|&p| Vector3::from(p).normalize())
+    .collect::<Vec<_>>();
+
+    #[rustfmt::skip]
+    let mut triangles: Vec<[u16; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Edges are shared by two triangles, which must share the midpoint too
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u16, b: u16| -> u16 {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let p = (positions[a as usize] + positions[b as usize]).normalize();
+                positions.push(p);
+                assert!(
+                    positions.len() <= u16::MAX as usize + 1,
+                    "Too many vertices for 16-bit indices, lower the subdivisions"
+                );
+                (positions.len() - 1) as u16
+            })
+        };
+
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let ab = midpoint(a, b);
+                let bc = midpoint(b, c);
+                let ca = midpoint(c, a);
+                vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let mut geometry = Geometry::with_capacity(positions.len(), triangles.len() * 3);
+    for &normal in positions.iter() {
+        let u = (-normal.z).atan2(normal.x) / (2.0 * PI);
+        let v = normal.y.clamp(-1.0, 1.0).acos() / PI;
+        geometry.push(normal * radius, normal, [u.rem_euclid(1.0), v]);
+    }
+
+    // Triangles straddling the seam would otherwise interpolate across the
+    // whole texture, so they get their own copies of the vertices on the
+    // wrong side of it
+    let mut seam_copies = HashMap::new();
+    for triangle in triangles.iter_mut() {
+        let us = triangle.map(|i| geometry.vertices[i as usize].tex_coords[0]);
+        let max = us.iter().cloned().fold(f32::MIN, f32::max);
+        let min = us.iter().cloned().fold(f32::MAX, f32::min);
+        if max - min <= 0.5 {
+            continue;
+        }
+
+        for index in triangle.iter_mut() {
+            let vertex = geometry.vertices[*index as usize];
+            if vertex.tex_coords[0] >= 0.5 {
+                continue;
+            }
+            *index = *seam_copies.entry(*index).or_insert_with(|| {
+                geometry.push(
+                    Vector3::from(vertex.position),
+                    Vector3::from(vertex.normal),
+                    [vertex.tex_coords[0] + 1.0, vertex.tex_coords[1]],
+                )
+            });
+        }
+    }
+
+    geometry.indices = triangles.iter().flatten().copied().collect();
+    geometry.finish()
+}
+
+/// Capped cylinder standing on the Y axis. The side gets the whole texture
+/// wrapped around it, the caps get a disc out of it
+pub fn cylinder(radius: f32, height: f32, sectors: u16) -> Geometry {
+    assert!(sectors >= 3, "Not enough sectors");
+    let columns = sectors + 1;
+    let mut geometry = Geometry::with_capacity(columns as usize * 4 + 2, sectors as usize * 12);
+    let half_height = height * 0.5;
+
+    // Side, as a grid with a single row of quads
+    for &(y, v) in [(half_height, 0.0), (-half_height, 1.0)].iter() {
+        for i in 0..columns {
+            let u = i as f32 / sectors as f32;
+            let normal = spherical(u * 2.0 * PI, PI * 0.5);
+            geometry.push(normal * radius + Vector3::unit_y() * y, normal, [u, v]);
+        }
+    }
+    geometry.push_grid_indices(0, columns, 2);
+
+    // Caps, as fans around their centers
+    for &(y, normal) in [(half_height, 1.0), (-half_height, -1.0)].iter() {
+        let normal = Vector3::unit_y() * normal;
+        let center = geometry.push(Vector3::unit_y() * y, normal, [0.5, 0.5]);
+        for i in 0..columns {
+            let direction = spherical(i as f32 / sectors as f32 * 2.0 * PI, PI * 0.5);
+            // Seen from outside, both caps have +X on their right
+            let tex_coords = [0.5 + direction.x * 0.5, 0.5 + direction.z * 0.5 * normal.y];
+            geometry.push(
+                direction * radius + Vector3::unit_y() * y,
+                normal,
+                tex_coords,
+            );
+        }
+
+        for i in 0..sectors {
+            let (a, b) = (center + 1 + i, center + 2 + i);
+            if normal.y > 0.0 {
+                geometry.indices.extend_from_slice(&[center, a, b]);
+            } else {
+                geometry.indices.extend_from_slice(&[center, b, a]);
+            }
+        }
+    }
+
+    geometry.finish()
+}
+
+/// Torus lying on the XZ plane, with `major_radius` going from its center to
+/// the center of the tube and `minor_radius` being the tube's
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: u16,
+    minor_segments: u16,
+) -> Geometry {
+    assert!(
+        major_segments >= 3 && minor_segments >= 3,
+        "Not enough segments"
+    );
+    let columns = major_segments + 1;
+    let rows = minor_segments + 1;
+    let mut geometry = Geometry::with_capacity(
+        columns as usize * rows as usize,
+        major_segments as usize * minor_segments as usize * 6,
+    );
+
+    for j in 0..rows {
+        for i in 0..columns {
+            let u = i as f32 / major_segments as f32;
+            let v = j as f32 / minor_segments as f32;
+            let outwards = spherical(u * 2.0 * PI, PI * 0.5);
+            // Goes down the outer side of the tube first, to keep the winding
+            // consistent with the sphere's
+            let (sin, cos) = (v * 2.0 * PI).sin_cos();
+            let normal = outwards * cos - Vector3::unit_y() * sin;
+            let position = outwards * major_radius + normal * minor_radius;
+            geometry.push(position, normal, [u, v]);
+        }
+    }
+    geometry.push_grid_indices(0, columns, rows);
+
+    geometry.finish()
+}
@@ -0,0 +1,1261 @@
+use std::{marker::PhantomData, ops::Range};
+
+use cgmath::{Matrix4, Point3, SquareMatrix, Transform as _};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    animation::{AnimationClip, AnimationPlayer, NodeTree},
+    buffer::{InstanceVertexBuffer, Stager, StagerHandle, StagingFactory, Uploader},
+    layout::Std140,
+    mesh::{Aabb, CpuMesh},
+    scene::instancing::InstanceVertex,
+    slab::{SlabAllocation, SlabAllocator},
+    texture::{ColorSpace, SamplerDesc, Texture},
+    vertex::{
+        self, Descriptable, Normaled, Positioned, Tangented, Textured, Vertex, VertexBufferable,
+        Weldable,
+    },
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
+pub struct ModelVertex {
+    #[location(0)]
+    pub position: [f32; 3],
+    #[location(1)]
+    pub tex_coords: [f32; 2],
+    #[location(2)]
+    pub normal: [f32; 3],
+    /// All zeroes if there isn't one, for shaders to fall back to
+    /// screen-space derivatives
+    #[location(3)]
+    pub tangent: [f32; 4],
+}
+
+impl VertexBufferable for ModelVertex {}
+
+impl Positioned for ModelVertex {
+    fn position(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
+impl Weldable for ModelVertex {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        vertex::f32s_approx_eq(self, other, epsilon)
+    }
+}
+
+impl Normaled for ModelVertex {
+    fn normal(&self) -> [f32; 3] {
+        self.normal
+    }
+
+    fn set_normal(&mut self, normal: [f32; 3]) {
+        self.normal = normal;
+    }
+}
+
+impl Textured for ModelVertex {
+    fn tex_coords(&self) -> [f32; 2] {
+        self.tex_coords
+    }
+}
+
+impl Tangented for ModelVertex {
+    fn set_tangent(&mut self, tangent: [f32; 4]) {
+        self.tangent = tangent;
+    }
+}
+
+/// A `ModelVertex` squeezed into 20 bytes instead of 48: halves for
+/// positions and texture coordinates, signed normalized bytes for normals and
+/// tangents. Positions carry a padding `w`, as there are no three-component
+/// half formats
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
+pub struct CompactModelVertex {
+    #[location(0)]
+    #[format(Float16x4)]
+    pub position: [u16; 4],
+    #[location(1)]
+    #[format(Float16x2)]
+    pub tex_coords: [u16; 2],
+    #[location(2)]
+    #[format(Snorm8x4)]
+    pub normal: [i8; 4],
+    #[location(3)]
+    #[format(Snorm8x4)]
+    pub tangent: [i8; 4],
+}
+
+impl VertexBufferable for CompactModelVertex {}
+
+impl From<&ModelVertex> for CompactModelVertex {
+    fn from(v: &ModelVertex) -> Self {
+        let [x, y, z] = v.position;
+        let [nx, ny, nz] = v.normal;
+        Self {
+            position: vertex::f32s_to_f16s([x, y, z, 1.0]),
+            tex_coords: vertex::f32s_to_f16s(v.tex_coords),
+            normal: vertex::f32s_to_snorm8s([nx, ny, nz, 0.0]),
+            tangent: vertex::f32s_to_snorm8s(v.tangent),
+        }
+    }
+}
+
+/// Up to four joints influencing a vertex, indexing into its skin's joints
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinWeights {
+    pub joints: [u16; 4],
+    pub weights: [f32; 4],
+}
+
+/// A `ModelVertex` followed by its `SkinWeights`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinnedVertex {
+    pub vertex: ModelVertex,
+    pub skin: SkinWeights,
+}
+
+impl VertexBufferable for SkinnedVertex {}
+
+impl Descriptable for SkinnedVertex {
+    fn descriptor<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SkinnedVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<ModelVertex>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Uint16x4,
+                },
+                // Skinned pipelines have no instance buffer, so nothing else
+                // is using location 5
+                wgpu::VertexAttribute {
+                    offset: (std::mem::size_of::<ModelVertex>() + std::mem::size_of::<[u16; 4]>())
+                        as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+/// A metallic-roughness material, with indices into `ModelData::images`
+#[derive(Clone, Debug)]
+pub struct MaterialData {
+    pub base_color_factor: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub base_color_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+    pub metallic_roughness_texture: Option<usize>,
+}
+
+impl Default for MaterialData {
+    /// What glTF uses for primitives without a material
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            base_color_texture: None,
+            normal_texture: None,
+            metallic_roughness_texture: None,
+        }
+    }
+}
+
+pub struct PrimitiveData {
+    /// Indices get narrowed down to `u16`s when uploading, if the vertices
+    /// allow for it
+    pub mesh: CpuMesh<ModelVertex, u32>,
+    /// One per vertex, for primitives that can be skinned
+    pub skin_weights: Option<Vec<SkinWeights>>,
+    pub material: usize,
+}
+
+pub struct MeshData {
+    pub name: Option<String>,
+    pub primitives: Vec<PrimitiveData>,
+}
+
+/// A mesh placed in the world, with its node hierarchy already flattened
+pub struct DrawData {
+    pub mesh: usize,
+    /// Node the mesh is attached to, which animations can move around
+    pub node: usize,
+    pub transform: Matrix4<f32>,
+}
+
+/// Joints deforming a skinned mesh, as node indices
+pub struct SkinData {
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+/// A mesh deformed by a skin, which ignores the transform of its own node
+pub struct SkinnedDrawData {
+    pub mesh: usize,
+    pub skin: usize,
+}
+
+/// Everything needed to draw a model, still on the CPU side
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    /// The last material is always the default one
+    pub materials: Vec<MaterialData>,
+    pub images: Vec<image::RgbaImage>,
+    pub draws: Vec<DrawData>,
+    pub nodes: NodeTree,
+    pub skins: Vec<SkinData>,
+    pub skinned_draws: Vec<SkinnedDrawData>,
+    pub animations: Vec<AnimationClip>,
+}
+
+impl ModelData {
+    /// World-space box around every draw, if there's anything to draw at all.
+    /// Skinned meshes are taken in their bind pose
+    pub fn aabb(&self) -> Option<Aabb> {
+        let identity = Matrix4::identity();
+        let draws = self
+            .draws
+            .iter()
+            .map(|draw| (draw.mesh, &draw.transform))
+            .chain(self.skinned_draws.iter().map(|draw| (draw.mesh, &identity)));
+        let mut points = draws.flat_map(|(mesh, transform)| {
+            self.meshes[mesh]
+                .primitives
+                .iter()
+                .flat_map(|p| p.mesh.vertices.iter())
+                .map(move |v| transform.transform_point(Point3::from(v.position())))
+        });
+
+        let first = points.next()?;
+        Some(points.fold(
+            Aabb {
+                min: first,
+                max: first,
+            },
+            |aabb, p| Aabb {
+                min: Point3::new(
+                    aabb.min.x.min(p.x),
+                    aabb.min.y.min(p.y),
+                    aabb.min.z.min(p.z),
+                ),
+                max: Point3::new(
+                    aabb.max.x.max(p.x),
+                    aabb.max.y.max(p.y),
+                    aabb.max.z.max(p.z),
+                ),
+            },
+        ))
+    }
+}
+
+#[cfg(feature = "models")]
+pub fn import_gltf_slice(bytes: &[u8]) -> anyhow::Result<ModelData> {
+    use anyhow::Context;
+
+    let (document, buffers, images) = gltf::import_slice(bytes)?;
+
+    let images = images
+        .into_iter()
+        .map(|data| {
+            let pixels = match data.format {
+                gltf::image::Format::R8G8B8A8 => data.pixels,
+                gltf::image::Format::R8G8B8 => data
+                    .pixels
+                    .chunks(3)
+                    .flat_map(|p| [p[0], p[1], p[2], 255])
+                    .collect(),
+                gltf::image::Format::R8G8 => data
+                    .pixels
+                    .chunks(2)
+                    .flat_map(|p| [p[0], p[1], 0, 255])
+                    .collect(),
+                gltf::image::Format::R8 => {
+                    data.pixels.iter().flat_map(|&p| [p, p, p, 255]).collect()
+                }
+                format => anyhow::bail!("unsupported image format {:?}", format),
+            };
+            image::RgbaImage::from_raw(data.width, data.height, pixels)
+                .context("image data doesn't match its size")
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let image_of = |texture: gltf::Texture| texture.source().index();
+    let mut materials = document
+        .materials()
+        .map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            MaterialData {
+                base_color_factor: pbr.base_color_factor(),
+                metallic_factor: pbr.metallic_factor(),
+                roughness_factor: pbr.roughness_factor(),
+                base_color_texture: pbr.base_color_texture().map(|i| image_of(i.texture())),
+                normal_texture: material.normal_texture().map(|i| image_of(i.texture())),
+                metallic_roughness_texture: pbr
+                    .metallic_roughness_texture()
+                    .map(|i| image_of(i.texture())),
+            }
+        })
+        .collect::<Vec<_>>();
+    let default_material = materials.len();
+    materials.push(MaterialData::default());
+
+    let meshes = document
+        .meshes()
+        .map(|mesh| {
+            let primitives = mesh
+                .primitives()
+                .filter(|p| p.mode() == gltf::mesh::Mode::Triangles)
+                .map(|primitive| {
+                    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+                    let positions = reader
+                        .read_positions()
+                        .context("primitive without positions")?
+                        .collect::<Vec<_>>();
+                    let mut normals = reader.read_normals();
+                    let mut tangents = reader.read_tangents();
+                    let mut tex_coords = reader.read_tex_coords(0).map(|t| t.into_f32());
+                    let (has_normals, has_tangents) = (normals.is_some(), tangents.is_some());
+                    let vertices = positions
+                        .iter()
+                        .map(|&position| ModelVertex {
+                            position,
+                            tex_coords: tex_coords
+                                .as_mut()
+                                .and_then(Iterator::next)
+                                .unwrap_or_default(),
+                            normal: normals
+                                .as_mut()
+                                .and_then(Iterator::next)
+                                .unwrap_or_default(),
+                            tangent: tangents
+                                .as_mut()
+                                .and_then(Iterator::next)
+                                .unwrap_or_default(),
+                        })
+                        .collect::<Vec<_>>();
+
+                    // Only the first set of joints and weights, so at most
+                    // four influences per vertex
+                    let skin_weights = match (reader.read_joints(0), reader.read_weights(0)) {
+                        (Some(joints), Some(weights)) => Some(
+                            joints
+                                .into_u16()
+                                .zip(weights.into_f32())
+                                .map(|(joints, weights)| SkinWeights { joints, weights })
+                                .collect::<Vec<_>>(),
+                        ),
+                        _ => None,
+                    };
+
+                    let indices: Vec<u32> = match reader.read_indices() {
+                        Some(indices) => indices.into_u32().collect(),
+                        None => (0..vertices.len() as u32).collect(),
+                    };
+
+                    let mut geometry = CpuMesh { vertices, indices };
+                    if !has_normals {
+                        geometry.compute_normals();
+                    }
+                    // Without texture coordinates, there's nothing to derive
+                    // tangents from
+                    if !has_tangents && tex_coords.is_some() {
+                        geometry.compute_tangents();
+                    }
+
+                    Ok(PrimitiveData {
+                        mesh: geometry,
+                        skin_weights,
+                        material: primitive.material().index().unwrap_or(default_material),
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            Ok(MeshData {
+                name: mesh.name().map(str::to_owned),
+                primitives,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let skins = document
+        .skins()
+        .map(|skin| {
+            let joints = skin.joints().map(|joint| joint.index()).collect::<Vec<_>>();
+            let inverse_bind_matrices = match skin
+                .reader(|buffer| Some(&buffers[buffer.index()]))
+                .read_inverse_bind_matrices()
+            {
+                Some(matrices) => matrices.map(Matrix4::from).collect(),
+                None => vec![Matrix4::identity(); joints.len()],
+            };
+            SkinData {
+                joints,
+                inverse_bind_matrices,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let animations = document
+        .animations()
+        .map(|animation| {
+            use crate::animation::{Channel, ChannelValues, Interpolation};
+            use gltf::animation::util::ReadOutputs;
+
+            let mut channels = Vec::new();
+            for channel in animation.channels() {
+                let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                let times = reader
+                    .read_inputs()
+                    .context("animation channel without keyframe times")?
+                    .collect::<Vec<_>>();
+                let values = match reader
+                    .read_outputs()
+                    .context("animation channel without keyframe values")?
+                {
+                    ReadOutputs::Translations(values) => {
+                        ChannelValues::Translations(values.map(Into::into).collect())
+                    }
+                    ReadOutputs::Rotations(values) => ChannelValues::Rotations(
+                        values
+                            .into_f32()
+                            .map(|[x, y, z, w]| cgmath::Quaternion::new(w, x, y, z))
+                            .collect(),
+                    ),
+                    ReadOutputs::Scales(values) => {
+                        ChannelValues::Scales(values.map(Into::into).collect())
+                    }
+                    // Morph targets aren't supported
+                    ReadOutputs::MorphTargetWeights(_) => continue,
+                };
+                if times.is_empty() {
+                    continue;
+                }
+
+                channels.push(Channel {
+                    node: channel.target().node().index(),
+                    interpolation: match channel.sampler().interpolation() {
+                        gltf::animation::Interpolation::Step => Interpolation::Step,
+                        gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                        gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+                    },
+                    times,
+                    values,
+                });
+            }
+
+            let duration = channels
+                .iter()
+                .filter_map(|channel| channel.times.last().copied())
+                .fold(0.0, f32::max);
+            Ok(AnimationClip {
+                name: animation.name().map(str::to_owned),
+                channels,
+                duration,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .context("file has no scenes")?;
+
+    let nodes = NodeTree {
+        rest: document
+            .nodes()
+            .map(|node| {
+                let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+                crate::animation::NodeTransform {
+                    translation: translation.into(),
+                    rotation: cgmath::Quaternion::new(w, x, y, z),
+                    scale: scale.into(),
+                }
+            })
+            .collect(),
+        children: document
+            .nodes()
+            .map(|node| node.children().map(|child| child.index()).collect())
+            .collect(),
+        roots: scene.nodes().map(|node| node.index()).collect(),
+    };
+
+    // Walk down the scene's hierarchy. Meshes are only skinned if every one
+    // of their primitives can be
+    let world = nodes.world_transforms(&nodes.rest);
+    let document_nodes = document.nodes().collect::<Vec<_>>();
+    let mut draws = Vec::new();
+    let mut skinned_draws = Vec::new();
+    let mut stack = nodes.roots.clone();
+    while let Some(index) = stack.pop() {
+        stack.extend(nodes.children[index].iter().copied());
+        let node = &document_nodes[index];
+        let mesh = match node.mesh() {
+            Some(mesh) => mesh.index(),
+            None => continue,
+        };
+        match node.skin() {
+            Some(skin)
+                if meshes[mesh]
+                    .primitives
+                    .iter()
+                    .all(|p| p.skin_weights.is_some()) =>
+            {
+                skinned_draws.push(SkinnedDrawData {
+                    mesh,
+                    skin: skin.index(),
+                })
+            }
+            _ => draws.push(DrawData {
+                mesh,
+                node: node.index(),
+                transform: world[node.index()],
+            }),
+        }
+    }
+
+    Ok(ModelData {
+        meshes,
+        materials,
+        images,
+        draws,
+        nodes,
+        skins,
+        skinned_draws,
+        animations,
+    })
+}
+
+#[cfg(not(feature = "models"))]
+pub fn import_gltf_slice(_bytes: &[u8]) -> anyhow::Result<ModelData> {
+    anyhow::bail!("built without the `models` feature")
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct MaterialUniform {
+    base_color_factor: [f32; 4],
+    metallic_factor: f32,
+    roughness_factor: f32,
+    /// Non-zero if the normal texture should be used
+    has_normal_texture: u32,
+    #[padding]
+    _padding: f32,
+}
+
+/// Slabs the vertices, indices and material uniforms of every model get
+/// carved out of, so that a model takes a handful of buffers no matter how
+/// many primitives and materials it has
+pub struct ModelSlabs {
+    vertices: SlabAllocator,
+    indices: SlabAllocator,
+    uniforms: SlabAllocator,
+}
+
+impl ModelSlabs {
+    pub fn new() -> Self {
+        Self {
+            vertices: SlabAllocator::new(
+                wgpu::BufferUsages::VERTEX,
+                MESH_SLAB_SIZE,
+                wgpu::VERTEX_STRIDE_ALIGNMENT,
+                "Model - Vertex Slab",
+            ),
+            indices: SlabAllocator::new(
+                wgpu::BufferUsages::INDEX,
+                MESH_SLAB_SIZE,
+                wgpu::COPY_BUFFER_ALIGNMENT,
+                "Model - Index Slab",
+            ),
+            uniforms: SlabAllocator::new(
+                wgpu::BufferUsages::UNIFORM,
+                UNIFORM_SLAB_SIZE,
+                // As far as wgpu goes for `min_uniform_buffer_offset_alignment`
+                wgpu::BIND_BUFFER_ALIGNMENT,
+                "Model - Uniform Slab",
+            ),
+        }
+    }
+
+    /// Frees every slab, which leaves models allocated out of them unusable
+    pub fn destroy(&mut self) {
+        self.vertices.destroy();
+        self.indices.destroy();
+        self.uniforms.destroy();
+    }
+
+    pub fn print_usage(&self) {
+        for (name, slabs) in [
+            ("Vertex", &self.vertices),
+            ("Index", &self.indices),
+            ("Uniform", &self.uniforms),
+        ]
+        .iter()
+        {
+            let (count, allocated, size) = slabs.usage();
+            println!(
+                "{} slabs: {} bytes allocated out of {} in {} slabs",
+                name, allocated, size, count
+            );
+        }
+    }
+}
+
+/// Size of the slabs vertices and indices get carved out of
+const MESH_SLAB_SIZE: wgpu::BufferAddress = 4 << 20;
+/// Size of the slabs material uniforms get carved out of. Each one takes up
+/// to `BIND_BUFFER_ALIGNMENT` bytes
+const UNIFORM_SLAB_SIZE: wgpu::BufferAddress = 64 << 10;
+
+struct Material {
+    bind_group: wgpu::BindGroup,
+    uniform: SlabAllocation,
+}
+
+struct Primitive<V: VertexBufferable + Descriptable> {
+    vertices: SlabAllocation,
+    indices: SlabAllocation,
+    num_indices: u32,
+    /// Whether `indices` holds `u16`s or `u32`s
+    index_format: wgpu::IndexFormat,
+    vertex_count: usize,
+    material: usize,
+    _v: PhantomData<V>,
+}
+
+impl<V: VertexBufferable + Descriptable> Primitive<V> {
+    /// Indices get narrowed down to `u16`s whenever they're enough to address
+    /// every vertex
+    fn new(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        stager: &mut Stager,
+        slabs: &mut ModelSlabs,
+        vertices: &[V],
+        data: &PrimitiveData,
+    ) -> Self {
+        let (indices, index_format) = if vertices.len() <= u16::MAX as usize + 1 {
+            let indices = data
+                .mesh
+                .indices
+                .iter()
+                .map(|&i| i as u16)
+                .collect::<Vec<_>>();
+            (
+                slabs.indices.upload(device, encoder, stager, &indices),
+                wgpu::IndexFormat::Uint16,
+            )
+        } else {
+            (
+                slabs
+                    .indices
+                    .upload(device, encoder, stager, &data.mesh.indices),
+                wgpu::IndexFormat::Uint32,
+            )
+        };
+
+        Self {
+            vertices: slabs.vertices.upload(device, encoder, stager, vertices),
+            indices,
+            num_indices: data.mesh.indices.len() as u32,
+            index_format,
+            vertex_count: vertices.len(),
+            material: data.material,
+            _v: PhantomData,
+        }
+    }
+
+    fn free(&self, slabs: &mut ModelSlabs) {
+        slabs.vertices.free(self.vertices);
+        slabs.indices.free(self.indices);
+    }
+
+    fn vertex_bytes(&self) -> u64 {
+        (self.vertex_count * std::mem::size_of::<V>()) as u64
+    }
+
+    fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        slabs: &'a ModelSlabs,
+        materials: &'a [Material],
+        material_group: u32,
+        instances: Range<u32>,
+    ) {
+        render_pass.set_bind_group(material_group, &materials[self.material].bind_group, &[]);
+        render_pass.set_vertex_buffer(0, slabs.vertices.slice(&self.vertices));
+        render_pass.set_index_buffer(slabs.indices.slice(&self.indices), self.index_format);
+        render_pass.draw_indexed(0..self.num_indices, 0, instances);
+    }
+}
+
+struct Skin {
+    joints: Vec<usize>,
+    inverse_bind_matrices: Vec<Matrix4<f32>>,
+    /// Joint matrices as of the last `animate`, ready to be uploaded
+    matrices: Vec<[[f32; 4]; 4]>,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// A model uploaded to the GPU, drawn with one instanced draw per primitive,
+/// plus one draw per primitive of every skinned mesh
+pub struct Model {
+    meshes: Vec<Vec<Primitive<ModelVertex>>>,
+    /// Same as `meshes`, out of `CompactModelVertex`es
+    compact_meshes: Vec<Vec<Primitive<CompactModelVertex>>>,
+    materials: Vec<Material>,
+    _textures: Vec<Texture>,
+    instances: InstanceVertexBuffer<InstanceVertex>,
+    /// Range of `instances` used by every mesh
+    instance_ranges: Vec<Range<u32>>,
+    /// Node every instance is attached to
+    instance_nodes: Vec<usize>,
+    instance_transforms: Vec<InstanceVertex>,
+    /// Only built for meshes that are drawn skinned
+    skinned_meshes: Vec<Option<Vec<Primitive<SkinnedVertex>>>>,
+    /// Mesh and skin of every skinned draw
+    skinned_draws: Vec<(usize, usize)>,
+    skins: Vec<Skin>,
+    nodes: NodeTree,
+    animations: Vec<AnimationClip>,
+}
+
+impl Model {
+    /// Layout of the per-material bind group: material uniform, sampler,
+    /// and base color, normal and metallic-roughness textures
+    pub fn material_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Model - Material Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                texture_entry(2),
+                texture_entry(3),
+                texture_entry(4),
+            ],
+        })
+    }
+
+    /// Layout of the per-skin bind group, holding every joint's matrix
+    pub fn joint_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Model - Joint Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uploader: &Uploader,
+        slabs: &mut ModelSlabs,
+        data: &ModelData,
+        material_layout: &wgpu::BindGroupLayout,
+        joint_layout: &wgpu::BindGroupLayout,
+    ) -> anyhow::Result<Self> {
+        // Base color maps are color data, normal and metallic-roughness maps
+        // aren't, so every image gets uploaded in the color space its users
+        // read it in
+        let mut color_spaces = vec![ColorSpace::Srgb; data.images.len()];
+        for material in data.materials.iter() {
+            if let Some(i) = material.normal_texture {
+                color_spaces[i] = ColorSpace::Linear;
+            }
+            if let Some(i) = material.metallic_roughness_texture {
+                color_spaces[i] = ColorSpace::Linear;
+            }
+        }
+
+        let mut textures = data
+            .images
+            .iter()
+            .zip(color_spaces)
+            .map(|(image, color_space)| {
+                Texture::from_image(
+                    device,
+                    queue,
+                    &image::DynamicImage::ImageRgba8(image.clone()),
+                    color_space,
+                    Some("Model - Texture"),
+                )
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // Stand-ins for missing textures, which leave the factors untouched
+        let white = textures.len();
+        let flat_normal = white + 1;
+        for (color, color_space) in [
+            ([255, 255, 255, 255], ColorSpace::Srgb),
+            ([128, 128, 255, 255], ColorSpace::Linear),
+        ]
+        .iter()
+        {
+            textures.push(Texture::from_image(
+                device,
+                queue,
+                &image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                    1,
+                    1,
+                    image::Rgba(*color),
+                )),
+                *color_space,
+                Some("Model - Fallback Texture"),
+            )?);
+        }
+
+        let sampler = SamplerDesc::default()
+            .address_mode(wgpu::AddressMode::Repeat)
+            .mipmap_filter(wgpu::FilterMode::Nearest)
+            .create(device, Some("Model - Sampler"));
+
+        let uniforms = data
+            .materials
+            .iter()
+            .map(|material| MaterialUniform {
+                base_color_factor: material.base_color_factor,
+                metallic_factor: material.metallic_factor,
+                roughness_factor: material.roughness_factor,
+                has_normal_texture: material.normal_texture.is_some() as u32,
+                _padding: 0.0,
+            })
+            .collect::<Vec<_>>();
+
+        let materials = data
+            .materials
+            .iter()
+            .map(|material| {
+                let uniform = slabs.uniforms.allocate(
+                    device,
+                    std::mem::size_of::<MaterialUniform>() as wgpu::BufferAddress,
+                );
+
+                let base_color = &textures[material.base_color_texture.unwrap_or(white)];
+                let normal = &textures[material.normal_texture.unwrap_or(flat_normal)];
+                let metallic_roughness =
+                    &textures[material.metallic_roughness_texture.unwrap_or(white)];
+
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Model - Material Bind Group"),
+                    layout: material_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: slabs.uniforms.binding(&uniform),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(&base_color.view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::TextureView(&normal.view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: wgpu::BindingResource::TextureView(&metallic_roughness.view),
+                        },
+                    ],
+                });
+
+                Material {
+                    bind_group,
+                    uniform,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Every mesh gets a contiguous range of instances, one per draw
+        let mut instance_nodes = Vec::with_capacity(data.draws.len());
+        let mut transforms = Vec::with_capacity(data.draws.len());
+        let mut instance_ranges = Vec::with_capacity(data.meshes.len());
+        for mesh in 0..data.meshes.len() {
+            let start = instance_nodes.len() as u32;
+            for draw in data.draws.iter().filter(|draw| draw.mesh == mesh) {
+                instance_nodes.push(draw.node);
+                transforms.push(draw.transform);
+            }
+            instance_ranges.push(start..instance_nodes.len() as u32);
+        }
+
+        // Vertices, indices, material uniforms and instances all go through
+        // the same belt
+        let (meshes, compact_meshes, skinned_meshes, instances) =
+            uploader.upload(queue, |encoder, stager| {
+                for (material, uniform) in materials.iter().zip(uniforms.iter()) {
+                    stager.write_padded(
+                        encoder,
+                        slabs.uniforms.buffer(&material.uniform),
+                        material.uniform.offset,
+                        bytemuck::bytes_of(uniform),
+                    );
+                }
+
+                let meshes: Vec<Vec<Primitive<ModelVertex>>> = data
+                    .meshes
+                    .iter()
+                    .map(|mesh| {
+                        mesh.primitives
+                            .iter()
+                            .map(|primitive| {
+                                Primitive::new(
+                                    device,
+                                    encoder,
+                                    stager,
+                                    slabs,
+                                    &primitive.mesh.vertices,
+                                    primitive,
+                                )
+                            })
+                            .collect()
+                    })
+                    .collect();
+
+                let compact_meshes: Vec<Vec<Primitive<CompactModelVertex>>> = data
+                    .meshes
+                    .iter()
+                    .map(|mesh| {
+                        mesh.primitives
+                            .iter()
+                            .map(|primitive| {
+                                let vertices = primitive
+                                    .mesh
+                                    .vertices
+                                    .iter()
+                                    .map(CompactModelVertex::from)
+                                    .collect::<Vec<_>>();
+                                Primitive::new(device, encoder, stager, slabs, &vertices, primitive)
+                            })
+                            .collect()
+                    })
+                    .collect();
+
+                let mut skinned_meshes = data.meshes.iter().map(|_| None).collect::<Vec<_>>();
+                for draw in data.skinned_draws.iter() {
+                    let mesh = &data.meshes[draw.mesh];
+                    skinned_meshes[draw.mesh].get_or_insert_with(|| {
+                        mesh.primitives
+                            .iter()
+                            .map(|primitive| {
+                                let vertices = primitive
+                                    .mesh
+                                    .vertices
+                                    .iter()
+                                    .zip(primitive.skin_weights.iter().flatten())
+                                    .map(|(&vertex, &skin)| SkinnedVertex { vertex, skin })
+                                    .collect::<Vec<_>>();
+                                Primitive::new(device, encoder, stager, slabs, &vertices, primitive)
+                            })
+                            .collect()
+                    });
+                }
+
+                let instances = if transforms.is_empty() {
+                    // Buffers can't be empty, so leave a placeholder nobody will draw
+                    InstanceVertexBuffer::from_instances_staged(
+                        device,
+                        encoder,
+                        stager,
+                        &[Matrix4::identity()],
+                        wgpu::BufferUsages::empty(),
+                        Some("Model - Instances"),
+                    )
+                } else {
+                    InstanceVertexBuffer::from_instances_staged(
+                        device,
+                        encoder,
+                        stager,
+                        &transforms,
+                        wgpu::BufferUsages::empty(),
+                        Some("Model - Instances"),
+                    )
+                };
+
+                (meshes, compact_meshes, skinned_meshes, instances)
+            });
+
+        let skins = data
+            .skins
+            .iter()
+            .map(|skin| {
+                let matrices = vec![Matrix4::<f32>::identity().into(); skin.joints.len().max(1)];
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Model - Joint Buffer"),
+                    contents: bytemuck::cast_slice(&matrices),
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Model - Joint Bind Group"),
+                    layout: joint_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                });
+                Skin {
+                    joints: skin.joints.clone(),
+                    inverse_bind_matrices: skin.inverse_bind_matrices.clone(),
+                    matrices,
+                    buffer,
+                    bind_group,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            meshes,
+            compact_meshes,
+            materials,
+            _textures: textures,
+            instances,
+            instance_ranges,
+            instance_nodes,
+            instance_transforms: transforms.iter().map(InstanceVertex::from).collect(),
+            skinned_meshes,
+            skinned_draws: data
+                .skinned_draws
+                .iter()
+                .map(|draw| (draw.mesh, draw.skin))
+                .collect(),
+            skins,
+            nodes: data.nodes.clone(),
+            animations: data.animations.clone(),
+        })
+    }
+
+    pub fn animations(&self) -> &[AnimationClip] {
+        &self.animations
+    }
+
+    /// Poses the model as `player` says, recomputing instance transforms and
+    /// joint matrices. They still have to be uploaded afterwards
+    pub fn animate(&mut self, player: &AnimationPlayer) {
+        let world = self
+            .nodes
+            .world_transforms(&player.pose(&self.animations, &self.nodes));
+
+        for (transform, &node) in self
+            .instance_transforms
+            .iter_mut()
+            .zip(self.instance_nodes.iter())
+        {
+            *transform = InstanceVertex::from(&world[node]);
+        }
+
+        for skin in self.skins.iter_mut() {
+            for ((matrix, &joint), inverse_bind) in skin
+                .matrices
+                .iter_mut()
+                .zip(skin.joints.iter())
+                .zip(skin.inverse_bind_matrices.iter())
+            {
+                *matrix = (world[joint] * inverse_bind).into();
+            }
+        }
+    }
+
+    /// Staging belt chunk size needed to upload everything at once
+    pub fn upload_size(&self) -> u64 {
+        let instances = std::mem::size_of::<InstanceVertex>() * self.instance_transforms.len();
+        let joints = self
+            .skins
+            .iter()
+            .map(|skin| std::mem::size_of::<[[f32; 4]; 4]>() * skin.matrices.len())
+            .sum::<usize>();
+        (instances + joints).max(1) as u64
+    }
+
+    /// Uploads whatever the last `animate` computed
+    pub fn upload(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        staging: &StagingFactory,
+        belt: StagerHandle,
+    ) {
+        let mut stager = staging.fetch_stager(belt);
+        if !self.instance_transforms.is_empty() {
+            stager.write_slice(
+                encoder,
+                &self.instances.buffer,
+                0,
+                &self.instance_transforms,
+            );
+        }
+        for skin in self.skins.iter() {
+            stager.write_slice(encoder, &skin.buffer, 0, &skin.matrices);
+        }
+    }
+
+    /// Size of the vertex buffers of every unskinned mesh, either made out
+    /// of `ModelVertex`es or `CompactModelVertex`es
+    pub fn vertex_bytes(&self, compact: bool) -> u64 {
+        if compact {
+            self.compact_meshes
+                .iter()
+                .flatten()
+                .map(Primitive::vertex_bytes)
+                .sum()
+        } else {
+            self.meshes
+                .iter()
+                .flatten()
+                .map(Primitive::vertex_bytes)
+                .sum()
+        }
+    }
+
+    /// Draws every mesh, binding materials at `material_group`. `slabs` have
+    /// to be the ones the model was created out of
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        slabs: &'a ModelSlabs,
+        material_group: u32,
+    ) {
+        self.render_meshes(render_pass, slabs, &self.meshes, material_group)
+    }
+
+    /// Same as `render`, but needs a pipeline taking `CompactModelVertex`
+    pub fn render_compact<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        slabs: &'a ModelSlabs,
+        material_group: u32,
+    ) {
+        self.render_meshes(render_pass, slabs, &self.compact_meshes, material_group)
+    }
+
+    fn render_meshes<'a, V: VertexBufferable + Descriptable>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        slabs: &'a ModelSlabs,
+        meshes: &'a [Vec<Primitive<V>>],
+        material_group: u32,
+    ) {
+        render_pass.set_vertex_buffer(1, self.instances.buffer.slice(..));
+        for (primitives, range) in meshes.iter().zip(self.instance_ranges.iter()) {
+            if range.is_empty() {
+                continue;
+            }
+
+            for primitive in primitives.iter() {
+                primitive.render(
+                    render_pass,
+                    slabs,
+                    &self.materials,
+                    material_group,
+                    range.clone(),
+                );
+            }
+        }
+    }
+
+    /// Draws every skinned mesh, binding materials at `material_group` and
+    /// joint matrices at `joint_group`. Needs a pipeline taking `SkinnedVertex`
+    pub fn render_skinned<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        slabs: &'a ModelSlabs,
+        material_group: u32,
+        joint_group: u32,
+    ) {
+        for &(mesh, skin) in self.skinned_draws.iter() {
+            render_pass.set_bind_group(joint_group, &self.skins[skin].bind_group, &[]);
+            for primitive in self.skinned_meshes[mesh].iter().flatten() {
+                primitive.render(render_pass, slabs, &self.materials, material_group, 0..1);
+            }
+        }
+    }
+
+    pub fn has_skinned_draws(&self) -> bool {
+        !self.skinned_draws.is_empty()
+    }
+
+    /// Hands the model's allocations back to `slabs`, which have to be the
+    /// ones it was created out of, once it's not going to be drawn anymore
+    pub fn free(self, slabs: &mut ModelSlabs) {
+        for primitive in self.meshes.iter().flatten() {
+            primitive.free(slabs);
+        }
+        for primitive in self.compact_meshes.iter().flatten() {
+            primitive.free(slabs);
+        }
+        for primitive in self.skinned_meshes.iter().flatten().flatten() {
+            primitive.free(slabs);
+        }
+        for material in self.materials.iter() {
+            slabs.uniforms.free(material.uniform);
+        }
+    }
+}
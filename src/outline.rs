@@ -0,0 +1,295 @@
+use cgmath::{EuclideanSpace, Matrix4, Point3};
+
+use crate::{
+    buffer::{IndexedVertexBuffer, StagerHandle, StagingFactory, Uniformable},
+    camera::{Camera, CameraUniform},
+    pipeline::PipelineBuilder,
+    shader::shaders,
+    texture::DepthTexture,
+    vertex::{Descriptable, Vertex, VertexBufferable},
+};
+
+/// How much bigger than what it outlines the outline gets drawn
+const OUTLINE_SCALE: f32 = 1.15;
+
+#[repr(C, packed)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
+#[vertex(instance)]
+struct OutlineInstance {
+    #[location(5)]
+    transform: [[f32; 4]; 4],
+}
+
+impl VertexBufferable for OutlineInstance {}
+
+/// Outlines instances of a mesh, i.e. to highlight what's selected, showing
+/// through whatever is in front of them. Every instance first marks the
+/// pixels it covers in a stencil buffer of the pass' own, then gets drawn
+/// again scaled up everywhere but there, which leaves a rim around it.
+/// Drawn in a pass of its own, on top of the frame
+pub struct OutlinePass {
+    mask_pipeline: wgpu::RenderPipeline,
+    outline_pipeline: wgpu::RenderPipeline,
+    stencil: DepthTexture,
+    /// Instances as they are, and scaled up for their outlines
+    masks: Vec<OutlineInstance>,
+    outlines: Vec<OutlineInstance>,
+    /// Maximum number of instances, the instance buffer holding twice that:
+    /// the masks first, and then the outlines
+    capacity: usize,
+    instance_buffer: wgpu::Buffer,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    belt: StagerHandle,
+}
+
+impl OutlinePass {
+    /// `belt` names the staging belt registered for the uploads, which has to
+    /// be unique for every scene outlining things. `mesh_layout` is the
+    /// layout of the outlined mesh's vertices, which need a position at
+    /// location 0. Instances past `max_instances` in a single frame don't
+    /// get outlined
+    pub fn new(
+        device: &wgpu::Device,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        belt: &str,
+        mesh_layout: wgpu::VertexBufferLayout,
+        max_instances: usize,
+    ) -> Self {
+        let vert_module = shaders::OUTLINE_VERT.create_module(device);
+        let frag_module = shaders::OUTLINE_FRAG.create_module(device);
+
+        let instance_size = std::mem::size_of::<OutlineInstance>();
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Outline - Instance Buffer"),
+            size: (2 * max_instances * instance_size) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let camera_uniform = CameraUniform::default();
+        let camera_uniform_buffer =
+            camera_uniform.create_buffer(device, Some("Outline - Camera Uniform Buffer"));
+
+        let belt = staging.create_stager(
+            belt,
+            (std::mem::size_of::<CameraUniform>() + 2 * max_instances * instance_size) as u64,
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Outline - Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Outline - Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Outline - Render Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Depth doesn't matter here, only whatever the masks leave in stencil
+        let stencil = |compare, pass_op| {
+            let face = wgpu::StencilFaceState {
+                compare,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op,
+            };
+            wgpu::StencilState {
+                front: face,
+                back: face,
+                read_mask: 0xff,
+                write_mask: 0xff,
+            }
+        };
+        let buffers = [mesh_layout, OutlineInstance::descriptor()];
+        let create_pipeline = |label, write_mask, stencil| {
+            PipelineBuilder::new(&pipeline_layout, &vert_module, &frag_module, sc.format)
+                .label(label)
+                .vertex_shader("outline.vert")
+                .buffers(&buffers)
+                .write_mask(write_mask)
+                .depth(false)
+                .depth_compare(wgpu::CompareFunction::Always)
+                .stencil(stencil)
+                .build(device)
+        };
+        let mask_pipeline = create_pipeline(
+            "Outline - Mask Pipeline",
+            wgpu::ColorWrites::empty(),
+            stencil(
+                wgpu::CompareFunction::Always,
+                wgpu::StencilOperation::Replace,
+            ),
+        );
+        let outline_pipeline = create_pipeline(
+            "Outline - Outline Pipeline",
+            wgpu::ColorWrites::ALL,
+            stencil(
+                wgpu::CompareFunction::NotEqual,
+                wgpu::StencilOperation::Keep,
+            ),
+        );
+
+        Self {
+            mask_pipeline,
+            outline_pipeline,
+            stencil: DepthTexture::with_stencil(
+                device,
+                sc.width,
+                sc.height,
+                Some("Outline - Stencil Texture"),
+            ),
+            masks: Vec::with_capacity(max_instances),
+            outlines: Vec::with_capacity(max_instances),
+            capacity: max_instances,
+            instance_buffer,
+            camera_uniform,
+            camera_uniform_buffer,
+            bind_group,
+            belt,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: winit::dpi::PhysicalSize<u32>) {
+        self.stencil = DepthTexture::with_stencil(
+            device,
+            size.width,
+            size.height,
+            Some("Outline - Stencil Texture"),
+        );
+    }
+
+    /// Forgets every instance queued so far
+    pub fn clear(&mut self) {
+        self.masks.clear();
+        self.outlines.clear();
+    }
+
+    /// Queues an instance to outline. Its outline grows out of `center`,
+    /// given in model space, which should be somewhere in the middle of the
+    /// mesh for the rim to be even all around
+    pub fn push(&mut self, model: &Matrix4<f32>, center: Point3<f32>) {
+        if self.masks.len() >= self.capacity {
+            return;
+        }
+
+        let center = center.to_vec();
+        let scaled = model
+            * Matrix4::from_translation(center)
+            * Matrix4::from_scale(OUTLINE_SCALE)
+            * Matrix4::from_translation(-center);
+        self.masks.push(OutlineInstance {
+            transform: (*model).into(),
+        });
+        self.outlines.push(OutlineInstance {
+            transform: scaled.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.masks.is_empty()
+    }
+
+    pub fn update(&mut self, camera: &Camera) {
+        self.camera_uniform.update(camera);
+    }
+
+    /// Has to happen before `render`'s render pass begins
+    pub fn upload(&self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        let mut stager = staging.fetch_stager(self.belt);
+        stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+        if !self.masks.is_empty() {
+            stager.write_slice(encoder, &self.instance_buffer, 0, &self.masks);
+            // Outlines go after every mask, in the second half of the buffer
+            stager.write_slice(
+                encoder,
+                &self.instance_buffer,
+                self.capacity as wgpu::BufferAddress,
+                &self.outlines,
+            );
+        }
+    }
+
+    /// Outlines every queued instance of `mesh` on top of what `frame_view`
+    /// already holds
+    pub fn render<T>(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        mesh: &IndexedVertexBuffer<T>,
+    ) where
+        T: VertexBufferable + Descriptable,
+    {
+        if self.masks.is_empty() {
+            return;
+        }
+
+        encoder.push_debug_group("Outline");
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Outline - Render Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: frame_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.stencil.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: false,
+                    }),
+                }),
+            });
+
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            render_pass.set_stencil_reference(1);
+            mesh.bind(&mut render_pass, 0);
+            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+            let count = self.masks.len() as u32;
+            render_pass.insert_debug_marker("Outline - Masks");
+            render_pass.set_pipeline(&self.mask_pipeline);
+            render_pass.draw_indexed(0..mesh.num_indices, 0, 0..count);
+
+            let first = self.capacity as u32;
+            render_pass.insert_debug_marker("Outline - Outlines");
+            render_pass.set_pipeline(&self.outline_pipeline);
+            render_pass.draw_indexed(0..mesh.num_indices, 0, first..first + count);
+        }
+        encoder.pop_debug_group();
+    }
+}
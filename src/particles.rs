@@ -0,0 +1,225 @@
+use cgmath::{InnerSpace, Vector3};
+
+use crate::{
+    camera::Camera,
+    layout::Std140,
+    vertex::{Vertex, VertexBufferable},
+};
+
+/// Something that can be linearly interpolated by a `Curve`
+pub trait Interpolate: Copy {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+}
+
+impl Interpolate for [f32; 4] {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self {
+        let mut out = a;
+        for (o, (a, b)) in out.iter_mut().zip(a.iter().zip(b.iter())) {
+            *o = f32::interpolate(*a, *b, t);
+        }
+        out
+    }
+}
+
+/// A piecewise linear function over a particle's normalized age (0 to 1)
+#[derive(Clone, Debug)]
+pub struct Curve<T: Interpolate> {
+    keys: Vec<(f32, T)>,
+}
+
+impl<T: Interpolate> Curve<T> {
+    /// Creates a curve out of `(age, value)` keys, which must be sorted by age
+    pub fn new(keys: Vec<(f32, T)>) -> Self {
+        assert!(!keys.is_empty(), "A curve needs at least one key");
+        assert!(
+            keys.windows(2).all(|w| w[0].0 <= w[1].0),
+            "Curve keys must be sorted by age"
+        );
+        Self { keys }
+    }
+
+    pub fn sample(&self, t: f32) -> T {
+        let first = self.keys[0];
+        if t <= first.0 {
+            return first.1;
+        }
+
+        for w in self.keys.windows(2) {
+            let (t0, v0) = w[0];
+            let (t1, v1) = w[1];
+            if t <= t1 {
+                let span = t1 - t0;
+                let local = if span > 0.0 { (t - t0) / span } else { 1.0 };
+                return T::interpolate(v0, v1, local);
+            }
+        }
+
+        self.keys[self.keys.len() - 1].1
+    }
+}
+
+/// Parameters of an `Emitter`
+#[derive(Clone, Debug)]
+pub struct EmitterDesc {
+    /// Particles spawned per second
+    pub spawn_rate: f32,
+    /// Seconds each particle lives for
+    pub lifetime: f32,
+    /// Upper bound on alive particles, also the instance buffer size
+    pub max_particles: usize,
+    /// Initial velocity of every particle
+    pub velocity: Vector3<f32>,
+    /// Random deviation added to the initial velocity, on every axis
+    pub velocity_spread: f32,
+    /// Constant acceleration (i.e. gravity)
+    pub acceleration: Vector3<f32>,
+    /// Velocity multiplier over the particle's lifetime
+    pub velocity_curve: Curve<f32>,
+    /// Billboard size over the particle's lifetime
+    pub size_curve: Curve<f32>,
+    /// RGBA tint over the particle's lifetime
+    pub color_curve: Curve<[f32; 4]>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Particle {
+    pub position: Vector3<f32>,
+    velocity: Vector3<f32>,
+    age: f32,
+    size: f32,
+    color: [f32; 4],
+}
+
+/// Spawns and simulates particles on the CPU
+pub struct Emitter {
+    desc: EmitterDesc,
+    pub position: Vector3<f32>,
+    particles: Vec<Particle>,
+    spawn_accumulator: f32,
+    rng: u32,
+}
+
+impl Emitter {
+    pub fn new(desc: EmitterDesc, position: Vector3<f32>) -> Self {
+        Self {
+            particles: Vec::with_capacity(desc.max_particles),
+            desc,
+            position,
+            spawn_accumulator: 0.0,
+            rng: 0x9E37_79B9,
+        }
+    }
+
+    pub fn desc(&self) -> &EmitterDesc {
+        &self.desc
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Xorshift, returns a number in [-1, 1]
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+
+    /// Advances the simulation by `dt` seconds
+    pub fn update(&mut self, dt: f32) {
+        let desc = &self.desc;
+
+        // Age and integrate the alive particles, dropping the dead ones
+        self.particles.retain_mut(|p| {
+            p.age += dt;
+            let t = p.age / desc.lifetime;
+            if t >= 1.0 {
+                return false;
+            }
+
+            p.velocity += desc.acceleration * dt;
+            p.position += p.velocity * desc.velocity_curve.sample(t) * dt;
+            p.size = desc.size_curve.sample(t);
+            p.color = desc.color_curve.sample(t);
+            true
+        });
+
+        // Spawn new ones, carrying over fractions of a particle between frames
+        self.spawn_accumulator += self.desc.spawn_rate * dt;
+        while self.spawn_accumulator >= 1.0 {
+            self.spawn_accumulator -= 1.0;
+            if self.particles.len() >= self.desc.max_particles {
+                continue;
+            }
+
+            let spread = Vector3::new(self.random(), self.random(), self.random());
+            let velocity = self.desc.velocity + spread * self.desc.velocity_spread;
+            self.particles.push(Particle {
+                position: self.position,
+                velocity,
+                age: 0.0,
+                size: self.desc.size_curve.sample(0.0),
+                color: self.desc.color_curve.sample(0.0),
+            });
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
+#[vertex(instance)]
+pub struct ParticleInstance {
+    #[location(5)]
+    position: [f32; 3],
+    #[location(6)]
+    size: f32,
+    #[location(7)]
+    color: [f32; 4],
+}
+
+impl From<&Particle> for ParticleInstance {
+    fn from(p: &Particle) -> Self {
+        ParticleInstance {
+            position: p.position.into(),
+            size: p.size,
+            color: p.color,
+        }
+    }
+}
+
+impl VertexBufferable for ParticleInstance {}
+
+/// Camera-space axes used to expand each particle into a camera-facing quad
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+pub struct BillboardUniform {
+    pub right: [f32; 4],
+    pub up: [f32; 4],
+}
+
+impl Default for BillboardUniform {
+    fn default() -> Self {
+        Self {
+            right: [1.0, 0.0, 0.0, 0.0],
+            up: [0.0, 1.0, 0.0, 0.0],
+        }
+    }
+}
+
+impl BillboardUniform {
+    pub fn update(&mut self, camera: &Camera) {
+        let forward = (camera.target - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward);
+
+        self.right = right.extend(0.0).into();
+        self.up = up.extend(0.0).into();
+    }
+}
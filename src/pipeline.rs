@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use crate::{
+    cache::{PipelineCache, PipelineSources},
+    shader,
+    texture::DepthTexture,
+};
+
+/// Builds the render pipelines most scenes draw with: a vertex and a fragment
+/// shader (both with `main` entry points, as GLSL has it), drawing triangle lists with their
+/// back faces culled into a single color target. Blending defaults to
+/// `REPLACE`, without depth testing or multisampling, and everything else
+/// can be changed along the way.
+pub struct PipelineBuilder<'a> {
+    label: Option<&'a str>,
+    layout: &'a wgpu::PipelineLayout,
+    vert_module: &'a wgpu::ShaderModule,
+    frag_module: &'a wgpu::ShaderModule,
+    /// File name of the vertex shader, if its inputs are to be checked
+    vert_name: Option<&'a str>,
+    vert_entry_point: &'a str,
+    frag_entry_point: &'a str,
+    buffers: Vec<wgpu::VertexBufferLayout<'a>>,
+    target: wgpu::ColorTargetState,
+    primitive: wgpu::PrimitiveState,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    sample_count: u32,
+    /// Off for depth-only pipelines, which leave the fragment shader out
+    fragment: bool,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    pub fn new(
+        layout: &'a wgpu::PipelineLayout,
+        vert_module: &'a wgpu::ShaderModule,
+        frag_module: &'a wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        Self {
+            label: None,
+            layout,
+            vert_module,
+            frag_module,
+            vert_name: None,
+            vert_entry_point: "main",
+            frag_entry_point: "main",
+            buffers: Vec::new(),
+            target: wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            sample_count: 1,
+            fragment: true,
+        }
+    }
+
+    pub fn label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// Names the vertex shader's file (e.g. `instancing.vert`), so that the
+    /// vertex buffers get checked against its inputs before building
+    pub fn vertex_shader(mut self, name: &'a str) -> Self {
+        self.vert_name = Some(name);
+        self
+    }
+
+    /// For modules with more than one entry point, like WGSL ones holding both
+    /// stages
+    pub fn entry_points(mut self, vertex: &'a str, fragment: &'a str) -> Self {
+        self.vert_entry_point = vertex;
+        self.frag_entry_point = fragment;
+        self
+    }
+
+    /// Vertex buffer layouts, one per slot
+    pub fn buffers(mut self, buffers: &[wgpu::VertexBufferLayout<'a>]) -> Self {
+        self.buffers = buffers.to_vec();
+        self
+    }
+
+    pub fn blend(mut self, blend: wgpu::BlendState) -> Self {
+        self.target.blend = Some(blend);
+        self
+    }
+
+    /// Which channels of the color target get written, i.e. none at all for
+    /// pipelines that only mark stencil
+    pub fn write_mask(mut self, write_mask: wgpu::ColorWrites) -> Self {
+        self.target.write_mask = write_mask;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.primitive.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Tests depth against a `DepthTexture`, keeping whatever is closer
+    pub fn depth(mut self, depth_write_enabled: bool) -> Self {
+        self.depth_stencil = Some(wgpu::DepthStencilState {
+            format: DepthTexture::DEPTH_FORMAT,
+            depth_write_enabled,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        });
+        self
+    }
+
+    /// Tests (and writes) stencil too, against a `DepthTexture::with_stencil`
+    pub fn stencil(mut self, stencil: wgpu::StencilState) -> Self {
+        let depth_stencil = self
+            .depth_stencil
+            .as_mut()
+            .expect("Depth testing has to be on to test stencil too");
+        depth_stencil.format = DepthTexture::DEPTH_STENCIL_FORMAT;
+        depth_stencil.stencil = stencil;
+        self
+    }
+
+    /// Changes how `depth` compares, i.e. to `Equal` to only shade what a
+    /// depth pre-pass left closest
+    pub fn depth_compare(mut self, depth_compare: wgpu::CompareFunction) -> Self {
+        let depth_stencil = self
+            .depth_stencil
+            .as_mut()
+            .expect("Depth testing has to be on to change how it compares");
+        depth_stencil.depth_compare = depth_compare;
+        self
+    }
+
+    /// Only writes depth, without a fragment shader or color target to draw
+    /// into, for depth pre-passes
+    pub fn depth_only(mut self) -> Self {
+        self.fragment = false;
+        self
+    }
+
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    fn descriptor(&self) -> wgpu::RenderPipelineDescriptor<'_> {
+        if let Some(name) = self.vert_name {
+            shader::validate_vertex_buffers(name, self.vert_entry_point, &self.buffers);
+        }
+
+        wgpu::RenderPipelineDescriptor {
+            label: self.label,
+            layout: Some(self.layout),
+            vertex: wgpu::VertexState {
+                module: self.vert_module,
+                entry_point: self.vert_entry_point,
+                buffers: &self.buffers,
+            },
+            fragment: if self.fragment {
+                Some(wgpu::FragmentState {
+                    module: self.frag_module,
+                    entry_point: self.frag_entry_point,
+                    targets: std::slice::from_ref(&self.target),
+                })
+            } else {
+                None
+            },
+            primitive: self.primitive,
+            depth_stencil: self.depth_stencil.clone(),
+            multisample: wgpu::MultisampleState {
+                count: self.sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        }
+    }
+
+    pub fn build(&self, device: &wgpu::Device) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&self.descriptor())
+    }
+
+    /// Same as `build`, through a `PipelineCache`
+    pub fn build_cached(
+        &self,
+        device: &wgpu::Device,
+        pipelines: &PipelineCache,
+        sources: PipelineSources,
+    ) -> Arc<wgpu::RenderPipeline> {
+        pipelines.get(device, sources, &self.descriptor())
+    }
+}
@@ -1,12 +1,33 @@
-use winit::event::WindowEvent;
+use std::ops::{Deref, DerefMut};
 
-use crate::{buffer::StagingFactory, GlobalState};
+use crate::{
+    assets::Assets,
+    buffer::StagingFactory,
+    cache::{LayoutCache, PipelineCache},
+    clock::Time,
+    input::Input,
+    GlobalState,
+};
 
+pub mod audio;
+pub mod boids;
 pub mod camera;
 pub mod clown;
+pub mod compute_particles;
+pub mod fractal;
 pub mod instancing;
+pub mod life;
+pub mod lsystem;
+pub mod model;
+pub mod nbody;
+pub mod oit;
+pub mod particles;
+pub mod raymarch;
+pub mod terrain;
 pub mod textured;
 pub mod triangle;
+pub mod uniforms;
+pub mod voxel;
 
 pub(crate) trait Scene {
     fn new(
@@ -14,9 +35,38 @@ pub(crate) trait Scene {
         queue: &wgpu::Queue,
         sc: &wgpu::SurfaceConfiguration,
         staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        pipelines: &PipelineCache,
+        assets: &Assets,
     ) -> Self;
-    fn input(&mut self, event: &WindowEvent) -> bool;
-    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue);
+    /// Whether the cursor should be grabbed and hidden while the scene is shown
+    fn grabs_cursor(&self) -> bool {
+        false
+    }
+    /// Backs out of whatever the scene has going on, i.e. a selection, when
+    /// `app.quit` gets pressed. Returns whether there was something to back
+    /// out of, in which case the app doesn't quit; it only quits when this
+    /// returns `false`
+    fn cancel(&mut self) -> bool {
+        false
+    }
+    /// Frees the scene's largest GPU resources right away, i.e. with
+    /// `Buffer::destroy` and `Texture::destroy`, rather than whenever the
+    /// scene gets dropped. Called as the scene gets unloaded (see
+    /// `SceneSlot::unload`), whether it goes inactive or the app quits; it
+    /// doesn't get updated or drawn again afterwards.
+    fn destroy(&mut self) {}
+    /// Called once a frame, with whatever the keyboard and mouse did since the
+    /// last one. Whatever the scene simulates or animates advances by `time`
+    /// rather than by real time, so that it stops while paused
+    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, input: &Input, time: Time);
+    /// Records compute work for the frame. Called right before `render`, on the
+    /// same command encoder, so whatever is written here is visible to it.
+    /// Compute passes (simulation steps, culling) go here rather than in
+    /// `render`, which only draws; scenes with none leave it out. In grid mode
+    /// and when drawing thumbnails it runs for every scene drawn, not only the
+    /// current one.
+    fn compute(&mut self, _encoder: &mut wgpu::CommandEncoder, _staging: &StagingFactory) {}
     fn render(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
@@ -24,6 +74,7 @@ pub(crate) trait Scene {
         state: &GlobalState,
         staging: &StagingFactory,
     ) -> Result<(), wgpu::SurfaceError>;
+    #[allow(dead_code)] // Nothing gets recalled per scene yet
     fn recall(&mut self) {}
     fn resize(
         &mut self,
@@ -32,3 +83,84 @@ pub(crate) trait Scene {
         size: winit::dpi::PhysicalSize<u32>,
     );
 }
+
+/// Whatever `Scene::new` takes, for `SceneSlot::load`, along with the size
+/// the scene has to be resized to afterwards
+pub(crate) struct SceneLoader<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub sc: &'a wgpu::SurfaceConfiguration,
+    pub staging: &'a mut StagingFactory,
+    pub layouts: &'a LayoutCache,
+    pub pipelines: &'a PipelineCache,
+    pub assets: &'a Assets,
+    pub size: winit::dpi::PhysicalSize<u32>,
+}
+
+/// Where the app keeps a scene, which can be unloaded while it isn't shown
+/// and created again once it is. Derefs to the scene, which has to be loaded
+/// by then
+pub(crate) struct SceneSlot<S: Scene> {
+    scene: Option<S>,
+}
+
+impl<S: Scene> SceneSlot<S> {
+    pub fn new(scene: S) -> Self {
+        Self { scene: Some(scene) }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.scene.is_some()
+    }
+
+    /// Creates the scene again if it was unloaded
+    pub fn load(&mut self, loader: &mut SceneLoader) {
+        if self.scene.is_none() {
+            let mut scene = S::new(
+                loader.device,
+                loader.queue,
+                loader.sc,
+                loader.staging,
+                loader.layouts,
+                loader.pipelines,
+                loader.assets,
+            );
+            scene.resize(loader.device, loader.queue, loader.size);
+            self.scene = Some(scene);
+        }
+    }
+
+    /// Destroys the scene and drops it, if it was loaded
+    pub fn unload(&mut self) {
+        if let Some(mut scene) = self.scene.take() {
+            scene.destroy();
+        }
+    }
+
+    /// Resizes the scene if it's loaded; otherwise it gets created at
+    /// whatever size it's loaded at
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        if let Some(scene) = &mut self.scene {
+            scene.resize(device, queue, size);
+        }
+    }
+}
+
+impl<S: Scene> Deref for SceneSlot<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        self.scene.as_ref().expect("Scene isn't loaded")
+    }
+}
+
+impl<S: Scene> DerefMut for SceneSlot<S> {
+    fn deref_mut(&mut self) -> &mut S {
+        self.scene.as_mut().expect("Scene isn't loaded")
+    }
+}
@@ -0,0 +1,452 @@
+use std::time::Instant;
+
+use cgmath::{Deg, Quaternion, Rotation3, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    assets::Assets,
+    audio::{AudioSource, SpectrumAnalyzer, FFT_SIZE},
+    buffer::{
+        IndexedVertexBuffer, InstanceVertexBuffer, StagerHandle, StagingFactory, Uniformable,
+    },
+    cache::{LayoutCache, PipelineCache},
+    camera::{Camera, CameraController, CameraUniform, Projection},
+    clock::Time,
+    input::Input,
+    layout::Std140,
+    mesh::{Bounds, Transform},
+    scene::instancing::{InstanceVertex, Mesh},
+    shader::shaders,
+    texture::DepthTexture,
+    vertex::{Descriptable, TexturedVertex},
+};
+
+use super::Scene;
+
+const VERTICES_1: &[TexturedVertex] = &[
+    // 0
+    TexturedVertex {
+        position: [0.0, 0.0, 0.5],
+        tex_coords: [0.5, 0.5],
+    },
+    TexturedVertex {
+        position: [-0.5, 0.0, 0.5],
+        tex_coords: [0.0, 0.5],
+    },
+    TexturedVertex {
+        position: [-0.25, -0.5, 0.5],
+        tex_coords: [0.25, 1.0],
+    },
+    TexturedVertex {
+        position: [0.25, -0.5, 0.5],
+        tex_coords: [0.75, 1.0],
+    },
+    TexturedVertex {
+        position: [0.5, 0.0, 0.5],
+        tex_coords: [1.0, 0.5],
+    },
+    TexturedVertex {
+        position: [0.25, 0.5, 0.5],
+        tex_coords: [0.75, 0.0],
+    },
+    TexturedVertex {
+        position: [-0.25, 0.5, 0.5],
+        tex_coords: [0.25, 0.0],
+    },
+    // 7
+    TexturedVertex {
+        position: [0.0, 0.0, -0.5],
+        tex_coords: [0.5, 0.5],
+    },
+    TexturedVertex {
+        position: [-0.5, 0.0, -0.5],
+        tex_coords: [1.0, 0.5],
+    },
+    TexturedVertex {
+        position: [-0.25, -0.5, -0.5],
+        tex_coords: [0.75, 1.0],
+    },
+    TexturedVertex {
+        position: [0.25, -0.5, -0.5],
+        tex_coords: [0.25, 1.0],
+    },
+    TexturedVertex {
+        position: [0.5, 0.0, -0.5],
+        tex_coords: [0.0, 0.5],
+    },
+    TexturedVertex {
+        position: [0.25, 0.5, -0.5],
+        tex_coords: [0.25, 0.0],
+    },
+    TexturedVertex {
+        position: [-0.25, 0.5, -0.5],
+        tex_coords: [0.75, 0.0],
+    },
+];
+
+#[rustfmt::skip]
+const INDICES_1: &[u16] = &[
+    0, 1, 2,
+    0, 2, 3,
+    0, 3, 4,
+    0, 4, 5,
+    0, 5, 6,
+    0, 6, 1,
+    7, 9, 8,
+    7, 10, 9,
+    7, 11, 10,
+    7, 12, 11,
+    7, 13, 12,
+    7, 8, 13,
+    1, 8, 9,
+    1, 9, 2,
+    2, 9, 10,
+    2, 10, 3,
+    3, 10, 11,
+    3, 11, 4,
+    4, 11, 12,
+    4, 12, 5,
+    5, 12, 13,
+    5, 13, 6,
+    6, 13, 8,
+    6, 8, 1
+];
+
+/// Frequency bands, one per column of hexagons
+const BANDS: u32 = 32;
+/// Rows of past spectrums kept on screen
+const HISTORY: u32 = 24;
+/// Rows scrolled back per second
+const ROW_RATE: f32 = 20.0;
+/// How much of a band's level is kept from one frame to the next, so that
+/// peaks fall off smoothly instead of flickering
+const DECAY: f32 = 0.85;
+const MAX_HEIGHT: f32 = 6.0;
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct AudioParamsUniform {
+    bands: u32,
+    history: u32,
+    max_height: f32,
+    #[padding]
+    _padding: f32,
+}
+
+pub struct AudioScene {
+    render_pipeline: wgpu::RenderPipeline,
+    mesh: Mesh,
+    instances: InstanceVertexBuffer<InstanceVertex>,
+    source: AudioSource,
+    analyzer: SpectrumAnalyzer,
+    samples: Vec<f32>,
+    /// `BANDS` levels per row, newest row first
+    levels: Vec<f32>,
+    levels_buffer: wgpu::Buffer,
+    last_row: Instant,
+    paused: bool,
+    camera: Camera,
+    camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    camera_belt: StagerHandle,
+    levels_belt: StagerHandle,
+}
+
+impl Scene for AudioScene {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        _assets: &Assets,
+    ) -> Self {
+        let vert_module = shaders::AUDIO_VERT.create_module(device);
+        let frag_module = shaders::DIMA_FRAG.create_module(device);
+
+        let mesh = Mesh::new(
+            IndexedVertexBuffer::from_vertices_indexes(
+                device,
+                VERTICES_1,
+                INDICES_1,
+                Some("Audio - Hexagon Vertex Buffer"),
+                Some("Audio - Hexagon Index Buffer"),
+            ),
+            Bounds::from_vertices(VERTICES_1),
+        );
+
+        // Tile the hexagons with their thickness pointing up: bands go from
+        // left to right, and older rows further back
+        let upright = Quaternion::from_angle_x(Deg(-90.0));
+        let transforms = (0..HISTORY)
+            .flat_map(|row| (0..BANDS).map(move |band| (row, band)))
+            .map(|(row, band)| {
+                let x = (band as f32 - BANDS as f32 * 0.5) * 0.75;
+                let z = -(row as f32) - (band & 1) as f32 * 0.5;
+                Transform::new(
+                    Vector3::new(x, 0.0, z),
+                    upright,
+                    Vector3::new(1.0, 1.0, 1.0),
+                )
+            })
+            .collect::<Vec<_>>();
+        let instances =
+            InstanceVertexBuffer::from_instances(device, &transforms, Some("Audio - Instances"));
+
+        let levels = vec![0.0; (BANDS * HISTORY) as usize];
+        let levels_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Audio - Levels Buffer"),
+            contents: bytemuck::cast_slice(&levels),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let params = AudioParamsUniform {
+            bands: BANDS,
+            history: HISTORY,
+            max_height: MAX_HEIGHT,
+            _padding: 0.0,
+        };
+        let params_buffer = params.create_buffer(device, Some("Audio - Params Uniform Buffer"));
+
+        let camera = Camera {
+            eye: (0.0, 8.0, 10.0).into(),
+            target: (0.0, 0.0, -8.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: sc.width as f32 / sc.height as f32,
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 100.0,
+        };
+
+        let camera_controller = CameraController::new(12.0);
+
+        let mut camera_uniform = CameraUniform::default();
+        camera_uniform.update(&camera);
+        let camera_uniform_buf =
+            camera_uniform.create_buffer(device, Some("Audio - Camera Uniform Buffer"));
+
+        let camera_belt =
+            staging.create_stager("audio.camera", std::mem::size_of::<CameraUniform>() as u64);
+        let levels_belt = staging.create_stager(
+            "audio.levels",
+            (levels.len() * std::mem::size_of::<f32>()) as u64,
+        );
+
+        let uniform_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Audio - Uniform Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Audio - Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_uniform_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: levels_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Audio - Render Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Audio - Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[TexturedVertex::descriptor(), InstanceVertex::descriptor()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DepthTexture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            render_pipeline,
+            mesh,
+            instances,
+            source: AudioSource::new(),
+            analyzer: SpectrumAnalyzer::new(),
+            samples: vec![0.0; FFT_SIZE],
+            levels,
+            levels_buffer,
+            last_row: Instant::now(),
+            paused: false,
+            camera,
+            camera_controller,
+            camera_uniform,
+            camera_uniform_buffer: camera_uniform_buf,
+            uniform_bind_group,
+            camera_belt,
+            levels_belt,
+        }
+    }
+
+    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, input: &Input, _time: Time) {
+        if let Some(key) = input.just_pressed("audio.pause") {
+            println!("Pressed {:?}", key);
+            self.paused = !self.paused;
+            println!("Paused: {:?}", self.paused);
+        }
+
+        self.camera_controller.update(&mut self.camera, input);
+        self.camera_uniform.update(&self.camera);
+
+        if self.paused {
+            return;
+        }
+
+        // Scroll the older rows back, leaving the newest one to be overwritten
+        let now = Instant::now();
+        if (now - self.last_row).as_secs_f32() >= 1.0 / ROW_RATE {
+            self.last_row = now;
+            let len = self.levels.len();
+            self.levels
+                .copy_within(..len - BANDS as usize, BANDS as usize);
+        }
+
+        let mut bands = [0.0; BANDS as usize];
+        self.source.latest(&mut self.samples);
+        self.analyzer
+            .analyze(&self.samples, self.source.sample_rate(), &mut bands);
+
+        for (level, band) in self.levels.iter_mut().zip(bands.iter()) {
+            *level = band.max(*level * DECAY);
+        }
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut camera_stager = staging.fetch_stager(self.camera_belt);
+        camera_stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+
+        let mut levels_stager = staging.fetch_stager(self.levels_belt);
+        levels_stager.write_slice(encoder, &self.levels_buffer, 0, &self.levels);
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("Audio - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &state.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        };
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        self.mesh
+            .render_instances(&mut render_pass, &self.instances, 0..self.instances.len);
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+    }
+}
@@ -0,0 +1,477 @@
+use cgmath::Vector2;
+
+use crate::{
+    assets::Assets,
+    buffer::{
+        InstanceVertexBuffer, StagerHandle, StagingFactory, Uniformable, VertexBuffer,
+        VertexTypedBuffer,
+    },
+    cache::{LayoutCache, PipelineCache},
+    clock::Time,
+    input::Input,
+    layout::{Std140, Std430},
+    shader::shaders,
+    vertex::{FlatVertex, Vertex, VertexBufferable},
+};
+
+use super::Scene;
+
+const BOID_COUNT: u32 = 4096;
+const WORKGROUP_SIZE: u32 = 64;
+
+/// A single boid, modelled facing +y
+const VERTICES: &[FlatVertex] = &[
+    FlatVertex {
+        position: [0.0, 0.02, 0.0],
+        color: [1.0, 1.0, 1.0],
+    },
+    FlatVertex {
+        position: [-0.008, -0.01, 0.0],
+        color: [0.3, 0.4, 0.6],
+    },
+    FlatVertex {
+        position: [0.008, -0.01, 0.0],
+        color: [0.3, 0.4, 0.6],
+    },
+];
+
+struct Boid {
+    position: Vector2<f32>,
+    velocity: Vector2<f32>,
+}
+
+/// A boid, as laid out in the storage buffers shared with the compute shader
+#[repr(C, packed)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Vertex, Std430)]
+#[vertex(instance)]
+struct BoidInstance {
+    #[location(5)]
+    position: [f32; 2],
+    #[location(6)]
+    velocity: [f32; 2],
+}
+
+impl From<&Boid> for BoidInstance {
+    fn from(boid: &Boid) -> Self {
+        BoidInstance {
+            position: boid.position.into(),
+            velocity: boid.velocity.into(),
+        }
+    }
+}
+
+impl VertexBufferable for BoidInstance {}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct BoidParamsUniform {
+    separation: f32,
+    alignment: f32,
+    cohesion: f32,
+    max_speed: f32,
+    separation_distance: f32,
+    visual_range: f32,
+    dt: f32,
+    count: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct ViewUniform {
+    aspect: f32,
+    #[padding]
+    _padding: [f32; 3],
+}
+
+/// Which simulation parameter gets adjusted with the arrow keys
+#[derive(Copy, Clone, Debug)]
+enum SelectedParameter {
+    Separation,
+    Alignment,
+    Cohesion,
+    MaxSpeed,
+    VisualRange,
+}
+
+impl SelectedParameter {
+    /// Scales the selected parameter by `factor`, returning its new value
+    fn scale(&self, params: &mut BoidParamsUniform, factor: f32) -> f32 {
+        match self {
+            SelectedParameter::Separation => {
+                params.separation *= factor;
+                params.separation
+            }
+            SelectedParameter::Alignment => {
+                params.alignment *= factor;
+                params.alignment
+            }
+            SelectedParameter::Cohesion => {
+                params.cohesion *= factor;
+                params.cohesion
+            }
+            SelectedParameter::MaxSpeed => {
+                params.max_speed *= factor;
+                params.max_speed
+            }
+            SelectedParameter::VisualRange => {
+                params.visual_range *= factor;
+                params.visual_range
+            }
+        }
+    }
+}
+
+pub struct BoidsScene {
+    compute_pipeline: wgpu::ComputePipeline,
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: VertexBuffer<FlatVertex>,
+    boids_buffers: [InstanceVertexBuffer<BoidInstance>; 2],
+    /// `compute_bind_groups[i]` reads `boids_buffers[i]` and writes the other one
+    compute_bind_groups: [wgpu::BindGroup; 2],
+    /// Index of the buffer holding the latest simulation state
+    current: usize,
+    params: BoidParamsUniform,
+    params_buffer: wgpu::Buffer,
+    view: ViewUniform,
+    view_buffer: wgpu::Buffer,
+    view_bind_group: wgpu::BindGroup,
+    selected_parameter: SelectedParameter,
+    params_belt: StagerHandle,
+    view_belt: StagerHandle,
+}
+
+impl Scene for BoidsScene {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        _assets: &Assets,
+    ) -> Self {
+        let comp_module = shaders::BOIDS_COMP.create_module(device);
+        let vert_module = shaders::BOIDS_VERT.create_module(device);
+        let frag_module = shaders::DIMA_FRAG.create_module(device);
+
+        let vertex_buffer =
+            VertexBuffer::from_vertices(device, VERTICES, Some("Boids - Vertex Buffer"));
+
+        // Scatter the boids around with random headings
+        let mut rng = 0x9E37_79B9u32;
+        let mut random = || {
+            rng ^= rng << 13;
+            rng ^= rng >> 17;
+            rng ^= rng << 5;
+            (rng as f32 / u32::MAX as f32) * 2.0 - 1.0
+        };
+        let boids = (0..BOID_COUNT)
+            .map(|_| Boid {
+                position: Vector2::new(random(), random()),
+                velocity: Vector2::new(random(), random()) * 0.2,
+            })
+            .collect::<Vec<_>>();
+
+        let boids_buffers = [
+            InstanceVertexBuffer::from_instances_with_usage(
+                device,
+                &boids,
+                wgpu::BufferUsages::STORAGE,
+                Some("Boids - Boids Buffer A"),
+            ),
+            InstanceVertexBuffer::from_instances_with_usage(
+                device,
+                &boids,
+                wgpu::BufferUsages::STORAGE,
+                Some("Boids - Boids Buffer B"),
+            ),
+        ];
+
+        let params = BoidParamsUniform {
+            separation: 40.0,
+            alignment: 2.0,
+            cohesion: 1.0,
+            max_speed: 0.4,
+            separation_distance: 0.025,
+            visual_range: 0.08,
+            dt: 0.0,
+            count: BOID_COUNT,
+        };
+        let params_buffer = params.create_buffer(device, Some("Boids - Params Buffer"));
+        let params_belt = staging.create_stager(
+            "boids.params",
+            std::mem::size_of::<BoidParamsUniform>() as u64,
+        );
+
+        let compute_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Boids - Compute Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let create_compute_bind_group = |label, from: &wgpu::Buffer, to: &wgpu::Buffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout: &compute_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: from.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: to.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let compute_bind_groups = [
+            create_compute_bind_group(
+                "Boids - Compute Bind Group A to B",
+                &boids_buffers[0].buffer,
+                &boids_buffers[1].buffer,
+            ),
+            create_compute_bind_group(
+                "Boids - Compute Bind Group B to A",
+                &boids_buffers[1].buffer,
+                &boids_buffers[0].buffer,
+            ),
+        ];
+
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Boids - Compute Pipeline Layout"),
+                bind_group_layouts: &[&compute_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Boids - Compute Pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &comp_module,
+            entry_point: "main",
+        });
+
+        let view = ViewUniform {
+            aspect: sc.width as f32 / sc.height as f32,
+            _padding: [0.0; 3],
+        };
+        let view_buffer = view.create_buffer(device, Some("Boids - View Buffer"));
+        let view_belt =
+            staging.create_stager("boids.view", std::mem::size_of::<ViewUniform>() as u64);
+
+        let view_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Boids - View Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let view_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Boids - View Bind Group"),
+            layout: &view_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: view_buffer.as_entire_binding(),
+            }],
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Boids - Render Pipeline Layout"),
+                bind_group_layouts: &[&view_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Boids - Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[vertex_buffer.descriptor(), boids_buffers[0].descriptor()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            compute_pipeline,
+            render_pipeline,
+            vertex_buffer,
+            boids_buffers,
+            compute_bind_groups,
+            current: 0,
+            params,
+            params_buffer,
+            view,
+            view_buffer,
+            view_bind_group,
+            selected_parameter: SelectedParameter::Separation,
+            params_belt,
+            view_belt,
+        }
+    }
+
+    fn destroy(&mut self) {
+        for boids in self.boids_buffers.iter() {
+            boids.buffer.destroy();
+        }
+    }
+
+    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, input: &Input, time: Time) {
+        let selections = [
+            ("boids.select_separation", SelectedParameter::Separation),
+            ("boids.select_alignment", SelectedParameter::Alignment),
+            ("boids.select_cohesion", SelectedParameter::Cohesion),
+            ("boids.select_max_speed", SelectedParameter::MaxSpeed),
+            ("boids.select_visual_range", SelectedParameter::VisualRange),
+        ];
+        for (action, selected) in selections.iter() {
+            if let Some(key) = input.just_pressed(action) {
+                println!("Pressed {:?}", key);
+                self.selected_parameter = *selected;
+                println!("{:?}", self.selected_parameter);
+            }
+        }
+        for (action, factor) in [("boids.increase", 1.1), ("boids.decrease", 1.0 / 1.1)].iter() {
+            if let Some(key) = input.just_pressed(action) {
+                println!("Pressed {:?}", key);
+                let value = self.selected_parameter.scale(&mut self.params, *factor);
+                println!("{:?}: {}", self.selected_parameter, value);
+            }
+        }
+
+        self.params.dt = time.delta;
+    }
+
+    fn compute(&mut self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        let mut params_stager = staging.fetch_stager(self.params_belt);
+        params_stager.write(encoder, &self.params_buffer, 0, &self.params);
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Boids - Simulation Pass"),
+            });
+            compute_pass.set_pipeline(&self.compute_pipeline);
+            compute_pass.set_bind_group(0, &self.compute_bind_groups[self.current], &[]);
+            compute_pass.dispatch(BOID_COUNT.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+
+        // The freshly written buffer is the one to draw from
+        self.current = 1 - self.current;
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut view_stager = staging.fetch_stager(self.view_belt);
+        view_stager.write(encoder, &self.view_buffer, 0, &self.view);
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("Boids - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        };
+
+        let boids = &self.boids_buffers[self.current];
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.view_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.buffer.slice(..));
+        render_pass.set_vertex_buffer(1, boids.buffer.slice(..));
+        render_pass.draw(0..self.vertex_buffer.len, 0..boids.len);
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.view.aspect = size.width as f32 / size.height as f32;
+    }
+}
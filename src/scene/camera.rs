@@ -1,13 +1,22 @@
+use std::sync::Arc;
+
 use cgmath::{Deg, Euler, Quaternion};
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 
 use crate::{
-    buffer::{IndexedVertexBuffer, OldUniform, StagingFactory},
-    camera::{Camera, CameraController, CameraUniform},
-    mesh::{OldMesh, Transform},
-    texture::Texture,
-    transform,
+    assets::{Assets, Handle},
+    buffer::{IndexedVertexBuffer, StagerHandle, StagingFactory, UniformBuffer},
+    cache::{LayoutCache, PipelineCache},
+    camera::{Camera, CameraController, CameraUniform, Frustum, Projection},
+    clock::Time,
+    gizmo::{GizmoMode, GizmoRenderer, TransformGizmo},
+    grid::{GridPass, GridPlane},
+    input::Input,
+    mesh::{Bounds, OldMesh, Transform},
+    pipeline::PipelineBuilder,
+    shader::shaders,
+    texture::{ColorSpace, SamplerDesc, Texture},
     vertex::{Descriptable, TexturedVertex},
+    viewport::Viewport,
 };
 
 use super::Scene;
@@ -101,28 +110,96 @@ const INDICES_1: &[u16] = &[
     6, 13, 1
 ];
 
-const UNIFORM_MATRIX_BELT: &str = "camera.belt";
-
 #[derive(Debug)]
 enum SelectedImage {
     SanCheese,
     Nnubes,
+    Mips,
 }
 
 pub struct CameraScene {
     pipeline: wgpu::RenderPipeline,
     epic_mesh: OldMesh<TexturedVertex>,
     diffuse1_bind_group: wgpu::BindGroup,
-    _diffuse1_texture: Texture,
+    diffuse1_texture: Handle<Texture>,
     diffuse2_bind_group: wgpu::BindGroup,
-    _diffuse2_texture: Texture,
+    diffuse2_texture: Handle<Texture>,
+    mips_bind_group: wgpu::BindGroup,
+    _mips_texture: Texture,
+    texture_bind_group_layout: Arc<wgpu::BindGroupLayout>,
     selected_image: SelectedImage,
     camera: Camera,
     camera_controller: CameraController,
     camera_uniform: CameraUniform,
-    camera_uniform_buffer: wgpu::Buffer,
-    epic_mesh_uniform_buffer: wgpu::Buffer,
+    camera_uniform_buffer: UniformBuffer<CameraUniform>,
+    epic_mesh_uniform_buffer: UniformBuffer<Transform>,
     uniform_bind_group: wgpu::BindGroup,
+    grid: GridPass,
+    show_grid: bool,
+    gizmos: GizmoRenderer,
+    show_bounds: bool,
+    /// What the camera could see when the frustum got frozen, drawn from
+    /// wherever the camera has moved on to since
+    frozen_frustum: Option<Frustum>,
+    /// Whether the mesh is outside of the camera's frustum, and so skipped
+    mesh_culled: bool,
+    /// Watches the camera from the right half of the screen when it's split,
+    /// along with its frustum and whatever it culls
+    observer: Camera,
+    observer_controller: CameraController,
+    observer_uniform: CameraUniform,
+    observer_uniform_buffer: UniformBuffer<CameraUniform>,
+    observer_bind_group: wgpu::BindGroup,
+    observer_grid: GridPass,
+    observer_gizmos: GizmoRenderer,
+    split_screen: bool,
+    size: winit::dpi::PhysicalSize<u32>,
+    /// Handles for editing the mesh's transform with the mouse. The mesh
+    /// stops spinning while they're out
+    transform_gizmo: Option<TransformGizmo>,
+    uniform_matrix_belt: StagerHandle,
+}
+
+impl CameraScene {
+    fn show_gizmos(&self) -> bool {
+        self.show_bounds || self.frozen_frustum.is_some() || self.transform_gizmo.is_some()
+    }
+
+    /// Draws the scene as the camera sees it, or as the observer does. The
+    /// observer sees the mesh even when the camera culls it
+    fn render_view<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, observer: bool) {
+        let (grid, uniform_bind_group, gizmos) = if observer {
+            (
+                &self.observer_grid,
+                &self.observer_bind_group,
+                &self.observer_gizmos,
+            )
+        } else {
+            (&self.grid, &self.uniform_bind_group, &self.gizmos)
+        };
+
+        if self.show_grid {
+            grid.render(render_pass);
+        }
+
+        if observer || !self.mesh_culled {
+            render_pass.set_pipeline(&self.pipeline);
+
+            let selected_bind_group = match self.selected_image {
+                SelectedImage::SanCheese => &self.diffuse1_bind_group,
+                SelectedImage::Nnubes => &self.diffuse2_bind_group,
+                SelectedImage::Mips => &self.mips_bind_group,
+            };
+            render_pass.set_bind_group(0, selected_bind_group, &[]);
+            render_pass.set_bind_group(1, uniform_bind_group, &[]);
+
+            self.epic_mesh.render(render_pass, 0..1);
+        }
+
+        if observer || self.show_gizmos() {
+            gizmos.render(render_pass);
+        }
+    }
 }
 
 impl Scene for CameraScene {
@@ -131,21 +208,29 @@ impl Scene for CameraScene {
         queue: &wgpu::Queue,
         sc: &wgpu::SurfaceConfiguration,
         staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        assets: &Assets,
     ) -> Self {
-        let diffuse1_bytes = include_bytes!("../../assets/sanCheese.png");
-        let diffuse1_texture =
-            Texture::from_bytes(device, queue, diffuse1_bytes, "San Cheese Is Watching You")
-                .unwrap();
-
-        let diffuse2_bytes = include_bytes!("../../assets/nnubes256.png");
-        let diffuse2_texture =
-            Texture::from_bytes(device, queue, diffuse2_bytes, "Nnubes256 Is Watching You")
-                .unwrap();
-
-        staging.create_stager(UNIFORM_MATRIX_BELT.to_owned(), 64);
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        let diffuse1_texture = assets
+            .texture(device, queue, "sanCheese.png", ColorSpace::Srgb)
+            .unwrap();
+        let diffuse2_texture = assets
+            .texture(device, queue, "nnubes256.png", ColorSpace::Srgb)
+            .unwrap();
+
+        // Every mip level is tinted differently, to tell which one is being
+        // sampled
+        let mips_bytes = include_bytes!("../../assets/mips.ktx2");
+        let mips_texture =
+            Texture::from_ktx2(device, queue, mips_bytes, "Mips Are Watching You").unwrap();
+
+        let uniform_matrix_belt =
+            staging.create_stager("camera.belt", std::mem::size_of::<CameraUniform>() as u64);
+
+        let texture_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
                 label: Some("San Cheese Is Laying Your Bounds"),
                 entries: &[
                     wgpu::BindGroupLayoutEntry {
@@ -168,37 +253,35 @@ impl Scene for CameraScene {
                         count: None,
                     },
                 ],
-            });
+            },
+        );
 
-        let diffuse1_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("San Cheese Is Binding You"),
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse1_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse1_texture.sampler),
-                },
-            ],
-        });
+        let diffuse1_bind_group = diffuse1_texture.get().create_bind_group(
+            device,
+            &texture_bind_group_layout,
+            "San Cheese Is Binding You",
+        );
 
-        let diffuse2_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Nnubes256 Is Binding You"),
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse2_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse2_texture.sampler),
-                },
-            ],
-        });
+        let diffuse2_bind_group = diffuse2_texture.get().create_bind_group(
+            device,
+            &texture_bind_group_layout,
+            "Nnubes256 Is Binding You",
+        );
+
+        // Blending between levels, and taking more samples as the quad turns
+        // away from the camera
+        let mips_sampler = assets.sampler(
+            device,
+            &SamplerDesc::default()
+                .address_mode(wgpu::AddressMode::Repeat)
+                .anisotropy(16),
+        );
+        let mips_bind_group = mips_texture.create_bind_group_with_sampler(
+            device,
+            &texture_bind_group_layout,
+            &mips_sampler.get(),
+            "Mips Are Binding You",
+        );
 
         let vertex_buffer = IndexedVertexBuffer::from_vertices_indexes(
             device,
@@ -208,91 +291,81 @@ impl Scene for CameraScene {
             Some("San Cheese Is Indexing You"),
         );
 
-        let mesh_transform = transform! {
-            t: [0.0, 0.0, 0.0],
-            r: [0.0, 0.0, 0.0],
-            s: [1.0, 1.0, 1.0]
-        };
-
-        let epic_mesh = OldMesh::new(vertex_buffer, mesh_transform);
+        let epic_mesh = OldMesh::new(
+            vertex_buffer,
+            Bounds::from_vertices(VERTICES_1),
+            Transform::default(),
+        );
 
-        let vert1_module =
-            device.create_shader_module(&wgpu::include_spirv!("../shaders/camerabois.vert.spv"));
-        let frag1_module =
-            device.create_shader_module(&wgpu::include_spirv!("../shaders/camerabois.frag.spv"));
+        let vert1_module = shaders::CAMERABOIS_VERT.create_module(device);
+        let frag1_module = shaders::CAMERABOIS_FRAG.create_module(device);
 
         let camera = Camera {
             eye: (0.0, 1.0, 2.0).into(),
             target: (0.0, 0.0, 0.0).into(),
             up: cgmath::Vector3::unit_y(),
             aspect: sc.width as f32 / sc.height as f32,
-            fovy: 45.0,
+            projection: Projection::Perspective { fovy: 45.0 },
             znear: 0.1,
             zfar: 100.0,
         };
 
-        let camera_controller = CameraController::new(0.2);
+        let camera_controller = CameraController::new(12.0);
 
         let mut camera_uniform = CameraUniform::default();
         camera_uniform.update(&camera);
 
-        let mesh_uniform_buf = epic_mesh.transform().as_buffer(
+        let mesh_uniform_buf = UniformBuffer::new(
             device,
+            epic_mesh.transform(),
             Some("Cameras - Epic Model Transform Uniform Buffer"),
         );
 
-        let camera_uniform_buf =
-            camera_uniform.into_buffer(device, Some("Cameras - Camera Uniform Buffer"));
+        let camera_uniform_buf = UniformBuffer::new(
+            device,
+            &camera_uniform,
+            Some("Cameras - Camera Uniform Buffer"),
+        );
 
-        let uniform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        let uniform_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
                 label: Some("Cameras - Camera Uniform Bind Group Layout"),
                 entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
+                    UniformBuffer::<CameraUniform>::layout_entry(0, wgpu::ShaderStages::VERTEX),
+                    UniformBuffer::<Transform>::layout_entry(1, wgpu::ShaderStages::VERTEX),
                 ],
-            });
+            },
+        );
 
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Cameras - Camera Uniform Bind Group Layout"),
-            layout: &uniform_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &camera_uniform_buf,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &mesh_uniform_buf,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-            ],
-        });
+        // Both cameras draw the same mesh, so they only differ in the camera
+        let create_uniform_bind_group = |label, camera_buffer: &UniformBuffer<CameraUniform>| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout: &uniform_bind_group_layout,
+                entries: &[camera_buffer.entry(0), mesh_uniform_buf.entry(1)],
+            })
+        };
+        let uniform_bind_group = create_uniform_bind_group(
+            "Cameras - Camera Uniform Bind Group Layout",
+            &camera_uniform_buf,
+        );
+
+        let observer = Camera {
+            eye: (4.0, 3.0, 6.0).into(),
+            ..camera
+        };
+        let mut observer_uniform = CameraUniform::default();
+        observer_uniform.update(&observer);
+        let observer_uniform_buffer = UniformBuffer::new(
+            device,
+            &observer_uniform,
+            Some("Cameras - Observer Uniform Buffer"),
+        );
+        let observer_bind_group = create_uniform_bind_group(
+            "Cameras - Observer Uniform Bind Group",
+            &observer_uniform_buffer,
+        );
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("San Cheese Is Planning Your Pipes"),
@@ -344,50 +417,27 @@ impl Scene for CameraScene {
             alpha_to_coverage_enabled: false,
         });*/
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("San Cheese Is Laying Your Pipes"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vert1_module,
-                entry_point: "main",
-                buffers: &[TexturedVertex::descriptor()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &frag1_module,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::OVER,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                clamp_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-        });
+        let pipeline =
+            PipelineBuilder::new(&pipeline_layout, &vert1_module, &frag1_module, sc.format)
+                .label("San Cheese Is Laying Your Pipes")
+                .vertex_shader("camerabois.vert")
+                .buffers(&[TexturedVertex::descriptor()])
+                .blend(wgpu::BlendState {
+                    color: wgpu::BlendComponent::OVER,
+                    alpha: wgpu::BlendComponent::REPLACE,
+                })
+                .build(device);
 
         Self {
             pipeline,
             epic_mesh,
             diffuse1_bind_group,
-            _diffuse1_texture: diffuse1_texture,
+            diffuse1_texture,
             diffuse2_bind_group,
-            _diffuse2_texture: diffuse2_texture,
+            diffuse2_texture,
+            mips_bind_group,
+            _mips_texture: mips_texture,
+            texture_bind_group_layout,
             selected_image: SelectedImage::Nnubes,
             camera,
             camera_controller,
@@ -395,48 +445,193 @@ impl Scene for CameraScene {
             camera_uniform_buffer: camera_uniform_buf,
             epic_mesh_uniform_buffer: mesh_uniform_buf,
             uniform_bind_group,
+            // There's no depth buffer here, so the grid just sits below the mesh
+            grid: GridPass::new(device, sc, staging, "camera.grid", GridPlane::Y(-1.0), None),
+            show_grid: true,
+            gizmos: GizmoRenderer::new(device, sc, staging, "camera.gizmos", 256, None),
+            show_bounds: false,
+            frozen_frustum: None,
+            mesh_culled: false,
+            observer,
+            observer_controller: CameraController::new(12.0),
+            observer_uniform,
+            observer_uniform_buffer,
+            observer_bind_group,
+            observer_grid: GridPass::new(
+                device,
+                sc,
+                staging,
+                "camera.observer_grid",
+                GridPlane::Y(-1.0),
+                None,
+            ),
+            observer_gizmos: GizmoRenderer::new(
+                device,
+                sc,
+                staging,
+                "camera.observer_gizmos",
+                256,
+                None,
+            ),
+            split_screen: false,
+            size: winit::dpi::PhysicalSize::new(sc.width, sc.height),
+            transform_gizmo: None,
+            uniform_matrix_belt,
         }
     }
 
-    fn input(&mut self, event: &winit::event::WindowEvent) -> bool {
-        let camera_handled = self.camera_controller.input(event);
-        match event {
-            WindowEvent::KeyboardInput { input, .. } => {
-                if let KeyboardInput {
-                    state: ElementState::Pressed,
-                    virtual_keycode: Some(VirtualKeyCode::N),
-                    ..
-                } = input
-                {
-                    println!("Pressed N");
-                    self.selected_image = match self.selected_image {
-                        SelectedImage::SanCheese => SelectedImage::Nnubes,
-                        SelectedImage::Nnubes => SelectedImage::SanCheese,
-                    };
-                    println!("{:?}", self.selected_image);
-                }
-
-                true
-            }
-            _ => camera_handled,
+    fn update(&mut self, device: &wgpu::Device, _queue: &wgpu::Queue, input: &Input, _time: Time) {
+        if let Some(key) = input.just_pressed("cameras.toggle_texture") {
+            println!("Pressed {:?}", key);
+            self.selected_image = match self.selected_image {
+                SelectedImage::SanCheese => SelectedImage::Nnubes,
+                SelectedImage::Nnubes => SelectedImage::Mips,
+                SelectedImage::Mips => SelectedImage::SanCheese,
+            };
+            println!("{:?}", self.selected_image);
+        }
+        if let Some(key) = input.just_pressed("cameras.toggle_grid") {
+            println!("Pressed {:?}", key);
+            self.show_grid = !self.show_grid;
+            println!("Grid: {:?}", self.show_grid);
+        }
+        if let Some(key) = input.just_pressed("cameras.toggle_bounds") {
+            println!("Pressed {:?}", key);
+            self.show_bounds = !self.show_bounds;
+            println!("Bounding volumes: {:?}", self.show_bounds);
+        }
+        if let Some(key) = input.just_pressed("cameras.freeze_frustum") {
+            println!("Pressed {:?}", key);
+            self.frozen_frustum = match self.frozen_frustum {
+                Some(_) => None,
+                None => Some(self.camera.frustum()),
+            };
+            println!("Frozen frustum: {:?}", self.frozen_frustum.is_some());
+        }
+        if let Some(key) = input.just_pressed("cameras.toggle_split_screen") {
+            println!("Pressed {:?}", key);
+            self.split_screen = !self.split_screen;
+            println!("Split screen: {:?}", self.split_screen);
+        }
+        if let Some(key) = input.just_pressed("cameras.switch_gizmo") {
+            println!("Pressed {:?}", key);
+            let mode = match self.transform_gizmo.as_ref().map(|gizmo| gizmo.mode) {
+                None => Some(GizmoMode::Translate),
+                Some(GizmoMode::Translate) => Some(GizmoMode::Rotate),
+                Some(GizmoMode::Rotate) => Some(GizmoMode::Scale),
+                Some(GizmoMode::Scale) => None,
+            };
+            self.transform_gizmo = mode.map(TransformGizmo::new);
+            println!("Transform gizmo: {:?}", mode);
+        }
+        if let Some(key) = input.just_pressed("cameras.switch_projection") {
+            println!("Pressed {:?}", key);
+            self.camera.switch_projection();
+            println!("{:?}", self.camera.projection);
+        }
+
+        if self.diffuse1_texture.reloaded() {
+            self.diffuse1_bind_group = self.diffuse1_texture.get().create_bind_group(
+                device,
+                &self.texture_bind_group_layout,
+                "San Cheese Is Binding You",
+            );
+        }
+        if self.diffuse2_texture.reloaded() {
+            self.diffuse2_bind_group = self.diffuse2_texture.get().create_bind_group(
+                device,
+                &self.texture_bind_group_layout,
+                "Nnubes256 Is Binding You",
+            );
         }
-    }
 
-    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue) {
-        // Update the camera based on the input state
-        self.camera_controller.update(&mut self.camera);
+        // Update the camera based on the input state. With the screen split,
+        // only the camera whose half the cursor is over follows it
+        if self.split_screen {
+            let [left, right] = Viewport::split(self.size);
+            self.camera.aspect = left.aspect();
+            self.observer.aspect = right.aspect();
+            if input.cursor().is_some_and(|cursor| right.contains(cursor)) {
+                self.observer_controller.update(&mut self.observer, input);
+            } else {
+                self.camera_controller.update(&mut self.camera, input);
+            }
+        } else {
+            self.camera.aspect = Viewport::full(self.size).aspect();
+            self.camera_controller.update(&mut self.camera, input);
+        }
 
         // Update the projection buffer based on the camera's updated state
         self.camera_uniform.update(&self.camera);
+        self.grid.update(&self.camera);
+        self.observer_uniform.update(&self.observer);
+        self.observer_grid.update(&self.observer);
+
+        if let Some(gizmo) = &mut self.transform_gizmo {
+            // The cursor is relative to the whole window, which the left half
+            // starts at anyway
+            let viewport = if self.split_screen {
+                Viewport::split(self.size)[0]
+            } else {
+                Viewport::full(self.size)
+            };
+            gizmo.update(
+                &self.camera,
+                (viewport.width, viewport.height),
+                input,
+                self.epic_mesh.transform_mut(),
+            );
+        } else {
+            self.epic_mesh.transform_mut().set_rotation(|r| {
+                *r = (*r)
+                    * Quaternion::from(Euler {
+                        x: Deg(0.0),
+                        y: Deg(1.0),
+                        z: Deg(0.0),
+                    });
+            });
+        }
 
-        self.epic_mesh.transform_mut().set_rotation(|r| {
-            *r = (*r)
-                * Quaternion::from(Euler {
-                    x: Deg(0.0),
-                    y: Deg(1.0),
-                    z: Deg(0.0),
-                });
-        });
+        let frustum = self.camera.frustum();
+        let bounds = self.epic_mesh.world_bounds();
+        self.mesh_culled = !frustum.intersects_aabb(bounds.aabb.min, bounds.aabb.max);
+
+        self.gizmos.clear();
+        self.gizmos.update(&self.camera);
+        self.observer_gizmos.clear();
+        self.observer_gizmos.update(&self.observer);
+        let mut both = [&mut self.gizmos, &mut self.observer_gizmos];
+        if self.show_bounds {
+            // Volumes turn red whenever the frozen frustum (or the camera's
+            // own, if none is) would cull them
+            let culling = self.frozen_frustum.unwrap_or(frustum);
+            let culled = [1.0, 0.2, 0.2];
+            let aabb_color = if culling.intersects_aabb(bounds.aabb.min, bounds.aabb.max) {
+                [1.0, 0.9, 0.2]
+            } else {
+                culled
+            };
+            let sphere_color = if culling.intersects_sphere(&bounds.sphere) {
+                [0.2, 0.9, 1.0]
+            } else {
+                culled
+            };
+            for gizmos in both.iter_mut() {
+                gizmos.aabb(&bounds.aabb, aabb_color);
+                gizmos.sphere(&bounds.sphere, sphere_color);
+            }
+        }
+        if let Some(frozen) = &self.frozen_frustum {
+            for gizmos in both.iter_mut() {
+                gizmos.frustum(frozen, [0.9, 0.9, 0.9]);
+            }
+        }
+        self.observer_gizmos.frustum(&frustum, [1.0, 0.5, 0.1]);
+        if let Some(gizmo) = &self.transform_gizmo {
+            let transform = self.epic_mesh.transform();
+            gizmo.draw(&mut self.gizmos, &self.camera, transform);
+            gizmo.draw(&mut self.observer_gizmos, &self.camera, transform);
+        }
     }
 
     fn render(
@@ -459,31 +654,40 @@ impl Scene for CameraScene {
             depth_stencil_attachment: None,
         };
 
-        let mut stager = staging.fetch_stager(UNIFORM_MATRIX_BELT);
-        stager.write_buffer(
-            encoder,
-            &self.camera_uniform_buffer,
-            0,
-            bytemuck::bytes_of(&self.camera_uniform),
-        );
-        stager.write_buffer(
-            encoder,
-            &self.epic_mesh_uniform_buffer,
-            0,
-            bytemuck::bytes_of(&self.epic_mesh.transform().uniform_matrix()),
-        );
+        let mut stager = staging.fetch_stager(self.uniform_matrix_belt);
+        self.camera_uniform_buffer
+            .update(&mut stager, encoder, &self.camera_uniform);
+        self.epic_mesh_uniform_buffer
+            .update(&mut stager, encoder, self.epic_mesh.transform());
 
-        let mut render_pass = encoder.begin_render_pass(rp_desc);
-        render_pass.set_pipeline(&self.pipeline);
+        if self.split_screen {
+            self.observer_uniform_buffer
+                .update(&mut stager, encoder, &self.observer_uniform);
+        }
 
-        let selected_bind_group = match self.selected_image {
-            SelectedImage::SanCheese => &self.diffuse1_bind_group,
-            SelectedImage::Nnubes => &self.diffuse2_bind_group,
-        };
-        render_pass.set_bind_group(0, selected_bind_group, &[]);
-        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        if self.show_grid {
+            self.grid.upload(encoder, staging);
+            if self.split_screen {
+                self.observer_grid.upload(encoder, staging);
+            }
+        }
+        if self.show_gizmos() {
+            self.gizmos.upload(encoder, staging);
+        }
+        if self.split_screen {
+            self.observer_gizmos.upload(encoder, staging);
+        }
 
-        self.epic_mesh.render(&mut render_pass, 0..1);
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        if self.split_screen {
+            let [left, right] = Viewport::split(self.size);
+            left.apply(&mut render_pass);
+            self.render_view(&mut render_pass, false);
+            right.apply(&mut render_pass);
+            self.render_view(&mut render_pass, true);
+        } else {
+            self.render_view(&mut render_pass, false);
+        }
 
         Ok(())
     }
@@ -492,7 +696,8 @@ impl Scene for CameraScene {
         &mut self,
         _device: &wgpu::Device,
         _queue: &wgpu::Queue,
-        _size: winit::dpi::PhysicalSize<u32>,
+        size: winit::dpi::PhysicalSize<u32>,
     ) {
+        self.size = size;
     }
 }
@@ -1,7 +1,11 @@
-use wgpu::MultisampleState;
-
 use crate::{
+    assets::Assets,
     buffer::{IndexedVertexBuffer, StagingFactory, VertexTypedBuffer},
+    cache::{LayoutCache, PipelineCache},
+    clock::Time,
+    input::Input,
+    pipeline::PipelineBuilder,
+    shader::shaders,
     vertex::TexturedVertex,
 };
 
@@ -51,11 +55,12 @@ impl Scene for ClownColorsScene {
         _queue: &wgpu::Queue,
         sc: &wgpu::SurfaceConfiguration,
         _staging: &mut StagingFactory,
+        _layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        _assets: &Assets,
     ) -> Self {
-        let vert2_module = device
-            .create_shader_module(&wgpu::include_spirv!("../shaders/mysecondshader.vert.spv"));
-        let frag2_module = device
-            .create_shader_module(&wgpu::include_spirv!("../shaders/mysecondshader.frag.spv"));
+        let vert2_module = shaders::MYSECONDSHADER_VERT.create_module(device);
+        let frag2_module = shaders::MYSECONDSHADER_FRAG.create_module(device);
 
         let vertex_buffer = IndexedVertexBuffer::from_vertices_indexes(
             device,
@@ -107,39 +112,12 @@ impl Scene for ClownColorsScene {
             alpha_to_coverage_enabled: false,
         });*/
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Clown - Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vert2_module,
-                entry_point: "main",
-                buffers: &[vertex_buffer.descriptor()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &frag2_module,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                clamp_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-        });
+        let pipeline =
+            PipelineBuilder::new(&pipeline_layout, &vert2_module, &frag2_module, sc.format)
+                .label("Clown - Render Pipeline")
+                .vertex_shader("mysecondshader.vert")
+                .buffers(&[vertex_buffer.descriptor()])
+                .build(device);
 
         Self {
             pipeline,
@@ -147,12 +125,15 @@ impl Scene for ClownColorsScene {
         }
     }
 
-    fn input(&mut self, _event: &winit::event::WindowEvent) -> bool {
-        false
+    fn update(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _input: &Input,
+        _time: Time,
+    ) {
     }
 
-    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue) {}
-
     fn render(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
@@ -178,7 +159,7 @@ impl Scene for ClownColorsScene {
         render_pass.set_vertex_buffer(0, self.vertex_buffer.vertices.slice(..));
         render_pass.set_index_buffer(
             self.vertex_buffer.indices.slice(..),
-            wgpu::IndexFormat::Uint16,
+            self.vertex_buffer.index_format,
         );
         render_pass.draw_indexed(0..self.vertex_buffer.num_indices, 0, 0..1);
 
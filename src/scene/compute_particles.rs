@@ -0,0 +1,409 @@
+use cgmath::Vector3;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    assets::Assets,
+    buffer::{StagerHandle, StagingFactory, Uniformable},
+    cache::{LayoutCache, PipelineCache},
+    camera::{Camera, CameraController, CameraUniform, Projection},
+    clock::Time,
+    input::Input,
+    layout::{Std140, Std430},
+    particles::BillboardUniform,
+    shader::shaders,
+};
+
+use super::Scene;
+
+const PARTICLE_COUNT: u32 = 16384;
+const MAX_LIFETIME: f32 = 3.0;
+const WORKGROUP_SIZE: u32 = 64;
+
+/// A particle, as laid out in the storage buffer
+#[repr(C, packed)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std430)]
+struct GpuParticle {
+    /// xyz: position, w: age
+    position: [f32; 4],
+    /// xyz: velocity, w: lifetime
+    velocity: [f32; 4],
+}
+
+impl GpuParticle {
+    /// The storage buffer doubles as an instance vertex buffer for rendering
+    fn descriptor<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GpuParticle>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct SimParamsUniform {
+    emitter: [f32; 4],
+    gravity: [f32; 4],
+    dt: f32,
+    time: f32,
+    count: u32,
+    #[padding]
+    _padding: u32,
+}
+
+pub struct ComputeParticlesScene {
+    compute_pipeline: wgpu::ComputePipeline,
+    compute_bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    particles_buffer: wgpu::Buffer,
+    params: SimParamsUniform,
+    params_buffer: wgpu::Buffer,
+    camera: Camera,
+    camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    billboard_uniform: BillboardUniform,
+    billboard_uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    params_belt: StagerHandle,
+    camera_belt: StagerHandle,
+}
+
+impl Scene for ComputeParticlesScene {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        _assets: &Assets,
+    ) -> Self {
+        let comp_module = shaders::COMPUTE_PARTICLES_COMP.create_module(device);
+        let vert_module = shaders::COMPUTE_PARTICLES_VERT.create_module(device);
+        let frag_module = shaders::COMPUTE_PARTICLES_FRAG.create_module(device);
+
+        // Every particle starts out unspawned, with a negative age staggering
+        // their first appearance over a whole lifetime
+        let initial = (0..PARTICLE_COUNT)
+            .map(|i| {
+                let t = i as f32 / PARTICLE_COUNT as f32;
+                GpuParticle {
+                    position: [0.0, 0.0, 0.0, -t * MAX_LIFETIME],
+                    velocity: [0.0, 0.0, 0.0, MAX_LIFETIME * (0.5 + 0.5 * t)],
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let particles_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Compute Particles - Particles Storage Buffer"),
+            contents: bytemuck::cast_slice(&initial),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+        });
+
+        let params = SimParamsUniform {
+            emitter: [0.0, 0.0, 0.0, 1.0],
+            gravity: [0.0, -2.5, 0.0, 0.0],
+            dt: 0.0,
+            time: 0.0,
+            count: PARTICLE_COUNT,
+            _padding: 0,
+        };
+        let params_buffer =
+            params.create_buffer(device, Some("Compute Particles - Sim Params Buffer"));
+        let params_belt = staging.create_stager(
+            "compute_particles.params",
+            std::mem::size_of::<SimParamsUniform>() as u64,
+        );
+
+        let compute_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Compute Particles - Compute Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compute Particles - Compute Bind Group"),
+            layout: &compute_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: particles_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Compute Particles - Compute Pipeline Layout"),
+                bind_group_layouts: &[&compute_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Particles - Compute Pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &comp_module,
+            entry_point: "main",
+        });
+
+        let camera = Camera {
+            eye: (0.0, 2.0, 8.0).into(),
+            target: (0.0, 2.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: sc.width as f32 / sc.height as f32,
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 100.0,
+        };
+
+        let camera_controller = CameraController::new(12.0);
+
+        let mut camera_uniform = CameraUniform::default();
+        camera_uniform.update(&camera);
+        let mut billboard_uniform = BillboardUniform::default();
+        billboard_uniform.update(&camera);
+        let camera_belt = staging.create_stager(
+            "compute_particles.camera",
+            std::mem::size_of::<CameraUniform>() as u64,
+        );
+
+        let camera_uniform_buf =
+            camera_uniform.create_buffer(device, Some("Compute Particles - Camera Uniform Buffer"));
+        let billboard_uniform_buf = billboard_uniform
+            .create_buffer(device, Some("Compute Particles - Billboard Uniform Buffer"));
+
+        let uniform_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Compute Particles - Uniform Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compute Particles - Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_uniform_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: billboard_uniform_buf.as_entire_binding(),
+                },
+            ],
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Compute Particles - Render Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Compute Particles - Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[GpuParticle::descriptor()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    // Additive, so that particles don't need sorting
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent::REPLACE,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            compute_pipeline,
+            compute_bind_group,
+            render_pipeline,
+            particles_buffer,
+            params,
+            params_buffer,
+            camera,
+            camera_controller,
+            camera_uniform,
+            camera_uniform_buffer: camera_uniform_buf,
+            billboard_uniform,
+            billboard_uniform_buffer: billboard_uniform_buf,
+            uniform_bind_group,
+            params_belt,
+            camera_belt,
+        }
+    }
+
+    fn destroy(&mut self) {
+        self.particles_buffer.destroy();
+    }
+
+    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, input: &Input, time: Time) {
+        self.camera_controller.update(&mut self.camera, input);
+        self.camera_uniform.update(&self.camera);
+        self.billboard_uniform.update(&self.camera);
+
+        let dt = time.delta;
+
+        // Move the emitter around in a circle
+        let time = self.params.time + dt;
+        let emitter = Vector3::new(time.cos() * 1.5, 0.0, time.sin() * 1.5);
+        self.params.emitter = emitter.extend(1.0).into();
+        self.params.dt = dt;
+        self.params.time = time;
+    }
+
+    fn compute(&mut self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        let mut params_stager = staging.fetch_stager(self.params_belt);
+        params_stager.write(encoder, &self.params_buffer, 0, &self.params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Particles - Simulation Pass"),
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+        compute_pass.dispatch(PARTICLE_COUNT.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut camera_stager = staging.fetch_stager(self.camera_belt);
+        camera_stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+        camera_stager.write(
+            encoder,
+            &self.billboard_uniform_buffer,
+            0,
+            &self.billboard_uniform,
+        );
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("Compute Particles - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        };
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.particles_buffer.slice(..));
+        render_pass.draw(0..6, 0..PARTICLE_COUNT);
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+    }
+}
@@ -0,0 +1,415 @@
+use winit::event::MouseButton;
+
+use crate::{
+    assets::Assets,
+    buffer::{StagerHandle, StagingFactory, Uniformable},
+    cache::{LayoutCache, PipelineCache},
+    clock::Time,
+    input::Input,
+    layout::Std140,
+    shader::shaders,
+};
+
+use super::Scene;
+
+/// Width of the complex plane shown when the view is reset
+const DEFAULT_SPAN: f64 = 3.5;
+const DEFAULT_ITERATIONS: u32 = 256;
+const MAX_ITERATIONS: u32 = 8192;
+/// How much each line of the mouse wheel zooms in
+const ZOOM_PER_LINE: f64 = 1.2;
+
+const FLAG_JULIA: u32 = 1;
+const FLAG_DOUBLE: u32 = 2;
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct FractalUniform {
+    center_hi: [f32; 2],
+    center_lo: [f32; 2],
+    julia_c: [f32; 2],
+    viewport: [f32; 2],
+    scale_hi: f32,
+    scale_lo: f32,
+    max_iterations: u32,
+    flags: u32,
+}
+
+/// Cosine palette, `a + b * cos(2π(c * t + d))`. The alpha channels are unused.
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct PaletteUniform {
+    a: [f32; 4],
+    b: [f32; 4],
+    c: [f32; 4],
+    d: [f32; 4],
+}
+
+const PALETTES: [PaletteUniform; 3] = [
+    // Rainbow
+    PaletteUniform {
+        a: [0.5, 0.5, 0.5, 0.0],
+        b: [0.5, 0.5, 0.5, 0.0],
+        c: [1.0, 1.0, 1.0, 0.0],
+        d: [0.0, 0.33, 0.67, 0.0],
+    },
+    // Fire
+    PaletteUniform {
+        a: [0.5, 0.3, 0.1, 0.0],
+        b: [0.5, 0.3, 0.1, 0.0],
+        c: [1.0, 1.0, 1.0, 0.0],
+        d: [0.0, 0.1, 0.2, 0.0],
+    },
+    // Ice
+    PaletteUniform {
+        a: [0.5, 0.5, 0.5, 0.0],
+        b: [0.5, 0.5, 0.5, 0.0],
+        c: [1.0, 0.7, 0.4, 0.0],
+        d: [0.0, 0.15, 0.2, 0.0],
+    },
+];
+
+#[derive(Copy, Clone, Debug)]
+enum SelectedFractal {
+    Mandelbrot,
+    Julia,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum SelectedPrecision {
+    Single,
+    /// Two floats per value, for roughly twice the mantissa bits
+    EmulatedDouble,
+}
+
+/// Splits a double into two floats whose sum approximates it
+fn split(value: f64) -> (f32, f32) {
+    let hi = value as f32;
+    (hi, (value - hi as f64) as f32)
+}
+
+pub struct FractalScene {
+    render_pipeline: wgpu::RenderPipeline,
+    selected_fractal: SelectedFractal,
+    selected_precision: SelectedPrecision,
+    selected_palette: usize,
+    /// View center on the complex plane
+    center: (f64, f64),
+    /// Size of a pixel on the complex plane
+    scale: f64,
+    julia_c: (f64, f64),
+    max_iterations: u32,
+    viewport: (f64, f64),
+    fractal_uniform: FractalUniform,
+    fractal_uniform_buffer: wgpu::Buffer,
+    palette_uniform_buffer: wgpu::Buffer,
+    palette_dirty: bool,
+    uniform_bind_group: wgpu::BindGroup,
+    fractal_belt: StagerHandle,
+}
+
+impl FractalScene {
+    /// Offset of a window position from the center of the view, in pixels
+    /// with Y pointing up
+    fn offset(&self, x: f64, y: f64) -> (f64, f64) {
+        (x - self.viewport.0 * 0.5, self.viewport.1 * 0.5 - y)
+    }
+
+    fn reset_view(&mut self) {
+        self.center = match self.selected_fractal {
+            SelectedFractal::Mandelbrot => (-0.75, 0.0),
+            SelectedFractal::Julia => (0.0, 0.0),
+        };
+        self.scale = DEFAULT_SPAN / self.viewport.0;
+    }
+
+    /// Zooms by `factor`, keeping the point under the cursor in place
+    fn zoom(&mut self, factor: f64, cursor: Option<(f64, f64)>) {
+        let (ox, oy) = match cursor {
+            Some((x, y)) => self.offset(x, y),
+            None => (0.0, 0.0),
+        };
+        let new_scale = self.scale / factor;
+        self.center.0 += ox * (self.scale - new_scale);
+        self.center.1 += oy * (self.scale - new_scale);
+        self.scale = new_scale;
+    }
+}
+
+impl Scene for FractalScene {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        assets: &Assets,
+    ) -> Self {
+        let vert_module = assets.shader(device, &shaders::FULLSCREEN_VERT).get();
+        let frag_module = shaders::FRACTAL_FRAG.create_module(device);
+
+        let fractal_uniform = FractalUniform {
+            center_hi: [0.0; 2],
+            center_lo: [0.0; 2],
+            julia_c: [0.0; 2],
+            viewport: [sc.width as f32, sc.height as f32],
+            scale_hi: 0.0,
+            scale_lo: 0.0,
+            max_iterations: DEFAULT_ITERATIONS,
+            flags: 0,
+        };
+        let fractal_uniform_buf =
+            fractal_uniform.create_buffer(device, Some("Fractal - Fractal Uniform Buffer"));
+        let palette_uniform_buf =
+            PALETTES[0].create_buffer(device, Some("Fractal - Palette Uniform Buffer"));
+
+        let fractal_belt = staging.create_stager(
+            "fractal.params",
+            (std::mem::size_of::<FractalUniform>() + std::mem::size_of::<PaletteUniform>()) as u64,
+        );
+
+        let uniform_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Fractal - Uniform Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Fractal - Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: fractal_uniform_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: palette_uniform_buf.as_entire_binding(),
+                },
+            ],
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Fractal - Render Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Fractal - Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        let mut scene = Self {
+            render_pipeline,
+            selected_fractal: SelectedFractal::Mandelbrot,
+            selected_precision: SelectedPrecision::EmulatedDouble,
+            selected_palette: 0,
+            center: (0.0, 0.0),
+            scale: 0.0,
+            julia_c: (-0.8, 0.156),
+            max_iterations: DEFAULT_ITERATIONS,
+            viewport: (sc.width as f64, sc.height as f64),
+            fractal_uniform,
+            fractal_uniform_buffer: fractal_uniform_buf,
+            palette_uniform_buffer: palette_uniform_buf,
+            palette_dirty: false,
+            uniform_bind_group,
+            fractal_belt,
+        };
+        scene.reset_view();
+        scene
+    }
+
+    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, input: &Input, _time: Time) {
+        if let Some(key) = input.just_pressed("fractal.switch_fractal") {
+            println!("Pressed {:?}", key);
+            self.selected_fractal = match self.selected_fractal {
+                SelectedFractal::Mandelbrot => {
+                    // Explore the Julia set of whatever is at the center
+                    self.julia_c = self.center;
+                    SelectedFractal::Julia
+                }
+                SelectedFractal::Julia => SelectedFractal::Mandelbrot,
+            };
+            self.reset_view();
+            println!("{:?}", self.selected_fractal);
+        }
+        if let Some(key) = input.just_pressed("fractal.switch_precision") {
+            println!("Pressed {:?}", key);
+            self.selected_precision = match self.selected_precision {
+                SelectedPrecision::Single => SelectedPrecision::EmulatedDouble,
+                SelectedPrecision::EmulatedDouble => SelectedPrecision::Single,
+            };
+            println!("{:?}", self.selected_precision);
+        }
+        if let Some(key) = input.just_pressed("fractal.switch_palette") {
+            println!("Pressed {:?}", key);
+            self.selected_palette = (self.selected_palette + 1) % PALETTES.len();
+            self.palette_dirty = true;
+            println!("Palette: {}", self.selected_palette);
+        }
+        if let Some(key) = input.just_pressed("fractal.reset_view") {
+            println!("Pressed {:?}", key);
+            self.reset_view();
+        }
+        if let Some(key) = input.just_pressed("fractal.fewer_iterations") {
+            println!("Pressed {:?}", key);
+            self.max_iterations = (self.max_iterations / 2).max(16);
+            println!("Max iterations: {}", self.max_iterations);
+        }
+        if let Some(key) = input.just_pressed("fractal.more_iterations") {
+            println!("Pressed {:?}", key);
+            self.max_iterations = (self.max_iterations * 2).min(MAX_ITERATIONS);
+            println!("Max iterations: {}", self.max_iterations);
+        }
+
+        if input.wheel() != 0.0 {
+            self.zoom(ZOOM_PER_LINE.powf(input.wheel() as f64), input.cursor());
+        }
+        if input.pinch() != 1.0 {
+            self.zoom(input.pinch(), None);
+        }
+
+        let (mut dx, mut dy) = input.touch_drag();
+        if input.mouse_pressed(MouseButton::Left) {
+            dx += input.cursor_delta().0;
+            dy += input.cursor_delta().1;
+        }
+        self.center.0 -= dx * self.scale;
+        self.center.1 += dy * self.scale;
+
+        let (cx_hi, cx_lo) = split(self.center.0);
+        let (cy_hi, cy_lo) = split(self.center.1);
+        let (scale_hi, scale_lo) = split(self.scale);
+
+        let mut flags = 0;
+        if let SelectedFractal::Julia = self.selected_fractal {
+            flags |= FLAG_JULIA;
+        }
+        if let SelectedPrecision::EmulatedDouble = self.selected_precision {
+            flags |= FLAG_DOUBLE;
+        }
+
+        self.fractal_uniform = FractalUniform {
+            center_hi: [cx_hi, cy_hi],
+            center_lo: [cx_lo, cy_lo],
+            julia_c: [self.julia_c.0 as f32, self.julia_c.1 as f32],
+            viewport: [self.viewport.0 as f32, self.viewport.1 as f32],
+            scale_hi,
+            scale_lo,
+            max_iterations: self.max_iterations,
+            flags,
+        };
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut fractal_stager = staging.fetch_stager(self.fractal_belt);
+        fractal_stager.write(
+            encoder,
+            &self.fractal_uniform_buffer,
+            0,
+            &self.fractal_uniform,
+        );
+        if self.palette_dirty {
+            self.palette_dirty = false;
+            fractal_stager.write(
+                encoder,
+                &self.palette_uniform_buffer,
+                0,
+                &PALETTES[self.selected_palette],
+            );
+        }
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("Fractal - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        };
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        // Keep the same horizontal span of the plane visible
+        self.scale *= self.viewport.0 / size.width as f64;
+        self.viewport = (size.width as f64, size.height as f64);
+    }
+}
@@ -1,16 +1,36 @@
-use std::num::NonZeroU64;
+use std::{collections::HashMap, num::NonZeroU64, ops::Range};
 
-use cgmath::MetricSpace;
-use wgpu::BufferBinding;
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use cgmath::{EuclideanSpace, MetricSpace};
+use hecs::{Entity, World};
+use wgpu::{util::DeviceExt, BufferBinding};
+use winit::event::MouseButton;
 
 use crate::{
-    buffer::{IndexedVertexBuffer, InstanceVertexBuffer, OldUniform, StagingFactory},
-    camera::{Camera, CameraController, CameraUniform},
-    mesh::Transform,
-    texture::{DepthTexture, Texture},
-    transform,
-    vertex::{Descriptable, TexturedVertex, VertexBufferable},
+    assets::Assets,
+    buffer::{
+        DrawIndexedIndirectArgs, IndexedVertexBuffer, IndirectBuffer, InstanceVertexBuffer,
+        StagerHandle, StagingFactory, UniformBuffer, Uniformable,
+    },
+    cache::{LayoutCache, PipelineCache},
+    camera::{
+        Camera, CameraController, CameraUniform, FlyCameraController, Frustum, Projection,
+        Smoothing,
+    },
+    clock::Time,
+    draw::{Blending, DrawList},
+    ecs::{self, InstanceOf, MaterialHandle, MeshHandle, Visible},
+    encoding::Pass,
+    gizmo::GizmoRenderer,
+    grid::{GridPass, GridPlane},
+    hiz::HiZPyramid,
+    input::Input,
+    layout::{Std140, Std430},
+    mesh::{self, BoundingSphere, Bounds, Lod, Transform},
+    outline::OutlinePass,
+    pipeline::PipelineBuilder,
+    shader::shaders,
+    texture::{ColorSpace, DepthTexture, SamplerDesc, Texture},
+    vertex::{Descriptable, TexturedVertex, Vertex, VertexBufferable},
 };
 
 use super::Scene;
@@ -104,86 +124,346 @@ const INDICES_1: &[u16] = &[
     6, 8, 1
 ];
 
-const CAMERA_BELT: &str = "instancing.camera";
-const INSTANCE_BELT: &str = "instancing.instances";
+const GRID_WIDTH: u32 = 33;
+/// Distances up to which each subdivided hexagon gets drawn
+const LOD_THRESHOLDS: &[f32] = &[6.0, 14.0];
+const LOD_SUBDIVISIONS: usize = 2;
+const WORKGROUP_SIZE: u32 = 64;
+/// Most hexagons outlined at once, past which selected ones go without
+const MAX_OUTLINED: usize = 64;
+/// How far down and up the grid animations move hexagons from their resting
+/// position
+const ANIMATION_Z_RANGE: (f32, f32) = (-1.0, 8.0);
+/// Heights at which hexagons get the first and the second of `HEIGHT_COLORS`,
+/// blending between them in between
+const HEIGHT_COLOR_RANGE: (f32, f32) = (-1.0, 3.0);
+const HEIGHT_COLORS: ([f32; 4], [f32; 4]) = ([0.45, 0.6, 1.0, 1.0], [1.0, 0.55, 0.35, 1.0]);
+/// Leaves colors untouched
+const WHITE: [f32; 4] = [1.0; 4];
+
+/// Grid cell a hexagon sits on, which drives its animation. Spawned ones can
+/// be outside of the initial grid
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct GridCell([i32; 2]);
+
+impl GridCell {
+    /// Coordinates fed to the grid animations, same as the compute shader's
+    fn animation_coords(&self) -> (i32, i32) {
+        let half_width = (GRID_WIDTH / 2) as i32;
+        (self.0[1] + half_width, self.0[0] + half_width)
+    }
+}
+
+/// Toggled by right-clicking a hexagon, which gets outlined while it's there
+#[derive(Copy, Clone, Debug)]
+struct Selected;
+
+/// Components of a hexagon at rest on the given cell
+fn hexagon(
+    prototype: Entity,
+    cell: [i32; 2],
+) -> (
+    Transform,
+    InstanceOf,
+    MaterialHandle,
+    Blending,
+    Visible,
+    GridCell,
+) {
+    let x = cell[0] as f32;
+    let y = cell[1] as f32;
+    (
+        Transform::from_translation(cgmath::Vector3::new(x, y, 0.0)),
+        InstanceOf(prototype),
+        MaterialHandle(0),
+        Blending::Opaque,
+        Visible(true),
+        GridCell(cell),
+    )
+}
+
+/// Whatever an instance's vertex gets built out of, gathered from its
+/// components
+struct InstanceData {
+    translation: cgmath::Vector3<f32>,
+    rotation: cgmath::Quaternion<f32>,
+    scale: cgmath::Vector3<f32>,
+    model: [[f32; 4]; 4],
+    layer: u32,
+}
+
+impl InstanceData {
+    fn new(transform: &Transform, material: MaterialHandle) -> Self {
+        Self {
+            translation: *transform.translation(),
+            rotation: *transform.rotation(),
+            scale: *transform.scale(),
+            model: transform.uniform_matrix(),
+            layer: material.0,
+        }
+    }
 
-#[derive(Debug)]
-pub struct Instance {
-    transform: Transform,
+    fn of(world: &World, entity: Entity) -> Option<Self> {
+        let mut query = world
+            .query_one::<(&Transform, &MaterialHandle)>(entity)
+            .ok()?;
+        let (transform, material) = query.get()?;
+        Some(Self::new(transform, *material))
+    }
+}
+
+/// Height of the hexagon on the given animation coordinates, same as the
+/// animation compute shader's
+fn animated_z(animation: SelectedAnimation, (x, y): (i32, i32), time: f64) -> f32 {
+    match animation {
+        SelectedAnimation::DoubleWave => {
+            ((time / 120.0) + (((x + y + 2) as f64) / 4.0)).sin() as f32
+        }
+        SelectedAnimation::Metaball => {
+            const MIN_DIST: f32 = 1e-3;
+            const RADIUS: f32 = 8.0;
+
+            let cx = ((time / 120.0) + std::f64::consts::PI / 2.0).sin() as f32 * 15.0 + 16.0;
+            let cy = (time / 120.0).sin() as f32 * 15.0 + 16.0;
+
+            let i_vector = cgmath::Vector2::new(x as f32, y as f32);
+            let center = cgmath::Vector2::new(cx, cy);
+            let distance = i_vector.distance(center);
+            ((2.0 * RADIUS) / distance.max(MIN_DIST)).min(8.0)
+        }
+    }
+}
+
+/// Color of a hexagon at the given height, same as the animation compute
+/// shader's
+fn height_color(z: f32) -> [f32; 4] {
+    let (low, high) = HEIGHT_COLOR_RANGE;
+    let t = ((z - low) / (high - low)).clamp(0.0, 1.0);
+    let (from, to) = HEIGHT_COLORS;
+    let mut color = [0.0; 4];
+    for (c, (a, b)) in color.iter_mut().zip(from.iter().zip(to.iter())) {
+        *c = a + (b - a) * t;
+    }
+    color
+}
+
+/// Changes to the instance set requested by input, applied on the next update
+#[derive(Copy, Clone, Debug)]
+enum InstanceEdit {
+    /// Spawns an instance on the grid cell under the cursor
+    Spawn((f64, f64)),
+    /// Removes the instance closest to the point under the cursor
+    RemoveNearest((f64, f64)),
 }
 
 #[repr(C, packed)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Vertex, Std430)]
+#[vertex(instance)]
 pub struct InstanceVertex {
+    // Locations 0 to 4 are left for the per-vertex attributes
+    #[location(5)]
     transform: [[f32; 4]; 4],
+    /// Multiplies whatever color the instance gets drawn with. Shaders that
+    /// don't care can leave it out
+    #[location(9)]
+    color: [f32; 4],
+    /// Layer of the bound texture array to sample from
+    #[location(10)]
+    layer: u32,
+    /// Storage buffers holding instances lay them out 16-byte aligned
+    #[padding]
+    _padding: [u32; 3],
+}
+
+impl From<&InstanceData> for InstanceVertex {
+    fn from(i: &InstanceData) -> Self {
+        InstanceVertex {
+            transform: i.model,
+            color: height_color(i.translation.z),
+            layer: i.layer,
+            _padding: [0; 3],
+        }
+    }
+}
+
+impl From<&Transform> for InstanceVertex {
+    fn from(t: &Transform) -> Self {
+        InstanceVertex {
+            transform: t.uniform_matrix(),
+            color: WHITE,
+            layer: 0,
+            _padding: [0; 3],
+        }
+    }
 }
 
-impl From<&Instance> for InstanceVertex {
-    fn from(i: &Instance) -> Self {
+impl From<&cgmath::Matrix4<f32>> for InstanceVertex {
+    fn from(m: &cgmath::Matrix4<f32>) -> Self {
         InstanceVertex {
-            transform: i.transform.uniform_matrix(),
+            transform: (*m).into(),
+            color: WHITE,
+            layer: 0,
+            _padding: [0; 3],
         }
     }
 }
 
 impl VertexBufferable for InstanceVertex {}
 
-impl Descriptable for InstanceVertex {
-    fn descriptor<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<InstanceVertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    // While our vertex shader only uses locations 0, and 1 now, in later tutorials we'll
-                    // be using 2, 3, and 4, for Vertex. We'll start at slot 5 not conflict with them later
-                    shader_location: 5,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                // A mat4 takes up 4 vertex slots as it is technically 4 vec4s. We need to define a slot
-                // for each vec4. We don't have to do this in code though.
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-            ],
+/// Same as `InstanceVertex`, with the transform left as translation, rotation
+/// and scale for the vertex shader to apply, and the color in bytes. Takes
+/// half the space, but compute shaders can't work with it
+#[repr(C, packed)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
+#[vertex(instance)]
+pub struct CompactInstanceVertex {
+    #[location(5)]
+    translation: [f32; 3],
+    /// Quaternion, as xyzw
+    #[location(6)]
+    rotation: [f32; 4],
+    #[location(7)]
+    scale: [f32; 3],
+    #[location(9)]
+    #[format(Unorm8x4)]
+    color: [u8; 4],
+    #[location(10)]
+    layer: u32,
+}
+
+impl From<&InstanceData> for CompactInstanceVertex {
+    fn from(i: &InstanceData) -> Self {
+        let (t, r, s) = (i.translation, i.rotation, i.scale);
+        let mut color = [0; 4];
+        for (c, f) in color.iter_mut().zip(height_color(t.z).iter()) {
+            *c = (f * 255.0).round() as u8;
+        }
+
+        CompactInstanceVertex {
+            translation: [t.x, t.y, t.z],
+            rotation: [r.v.x, r.v.y, r.v.z, r.s],
+            scale: [s.x, s.y, s.z],
+            color,
+            layer: i.layer,
         }
     }
 }
 
+impl VertexBufferable for CompactInstanceVertex {}
+
 pub struct Mesh {
-    data: IndexedVertexBuffer<TexturedVertex>,
+    lod: Lod<TexturedVertex>,
+    bounds: Bounds,
 }
 
 impl Mesh {
-    pub fn new(data: IndexedVertexBuffer<TexturedVertex>) -> Self {
-        Self { data }
+    pub fn new(data: IndexedVertexBuffer<TexturedVertex>, bounds: Bounds) -> Self {
+        Self::with_lod(Lod::single(data), bounds)
+    }
+
+    /// `bounds` should fit every level of detail
+    pub fn with_lod(lod: Lod<TexturedVertex>, bounds: Bounds) -> Self {
+        Self { lod, bounds }
+    }
+
+    /// Bounding volumes in model space, shared by every instance
+    pub fn bounds(&self) -> &Bounds {
+        &self.bounds
     }
 
-    pub fn render<'a>(
+    pub fn lod(&self) -> &Lod<TexturedVertex> {
+        &self.lod
+    }
+
+    /// Draws only the given range of instances out of the instance buffer
+    pub fn render_instances<'a, T>(
         &'a self,
         render_pass: &mut wgpu::RenderPass<'a>,
-        instances: Option<&'a InstanceVertexBuffer<InstanceVertex>>,
-    ) {
-        render_pass.set_vertex_buffer(0, self.data.vertices.slice(..));
-        render_pass.set_index_buffer(self.data.indices.slice(..), wgpu::IndexFormat::Uint16);
-        if let Some(instances) = instances {
-            render_pass.set_vertex_buffer(1, instances.buffer.slice(..));
-            render_pass.draw_indexed(0..self.data.num_indices, 0, 0..instances.len)
-        } else {
-            render_pass.draw_indexed(0..self.data.num_indices, 0, 0..1)
+        instances: &'a InstanceVertexBuffer<T>,
+        range: Range<u32>,
+    ) where
+        T: VertexBufferable + Descriptable,
+    {
+        self.render_level_instances(render_pass, 0, instances, range)
+    }
+
+    /// Same as `render_instances`, at the given level of detail
+    pub fn render_level_instances<'a, T>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        level: usize,
+        instances: &'a InstanceVertexBuffer<T>,
+        range: Range<u32>,
+    ) where
+        T: VertexBufferable + Descriptable,
+    {
+        if range.is_empty() {
+            return;
+        }
+
+        let data = self.lod.level(level);
+        data.bind(render_pass, 0);
+        render_pass.set_vertex_buffer(1, instances.buffer.slice(..));
+        render_pass.draw_indexed(0..data.num_indices, 0, range)
+    }
+}
+
+/// Splits every triangle in four, which leaves flat meshes looking the same
+/// while giving them more vertices to work with
+fn subdivide(vertices: &[TexturedVertex], indices: &[u16]) -> (Vec<TexturedVertex>, Vec<u16>) {
+    let mut vertices = vertices.to_vec();
+    let mut midpoints = HashMap::new();
+    let mut midpoint = |a: u16, b: u16| -> u16 {
+        *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+            let (va, vb) = (vertices[a as usize], vertices[b as usize]);
+            vertices.push(TexturedVertex {
+                position: [
+                    (va.position[0] + vb.position[0]) * 0.5,
+                    (va.position[1] + vb.position[1]) * 0.5,
+                    (va.position[2] + vb.position[2]) * 0.5,
+                ],
+                tex_coords: [
+                    (va.tex_coords[0] + vb.tex_coords[0]) * 0.5,
+                    (va.tex_coords[1] + vb.tex_coords[1]) * 0.5,
+                ],
+            });
+            (vertices.len() - 1) as u16
+        })
+    };
+
+    let mut subdivided = Vec::with_capacity(indices.len() * 4);
+    for triangle in indices.chunks_exact(3) {
+        let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+        let ab = midpoint(a, b);
+        let bc = midpoint(b, c);
+        let ca = midpoint(c, a);
+        subdivided.extend_from_slice(&[a, ab, ca, b, bc, ab, c, ca, bc, ab, bc, ca]);
+    }
+
+    (vertices, subdivided)
+}
+
+/// Which tint each level of detail gets drawn with. Lives at its own dynamic
+/// offset for every level, plus a last one leaving colors untouched
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct LodTintUniform {
+    tint: [f32; 4],
+}
+
+/// Level of detail and instance range of every draw, in submission order
+#[derive(Default)]
+struct LodDraws {
+    opaque: Vec<(usize, Range<u32>)>,
+    translucent: Vec<(usize, Range<u32>)>,
+}
+
+impl LodDraws {
+    /// Groups consecutive instances drawn at the same level of detail
+    fn push(runs: &mut Vec<(usize, Range<u32>)>, level: usize, slot: u32) {
+        match runs.last_mut() {
+            Some((last, range)) if *last == level && range.end == slot => range.end += 1,
+            _ => runs.push((level, slot..slot + 1)),
         }
     }
 }
@@ -192,6 +472,20 @@ impl Mesh {
 enum SelectedImage {
     SanCheese,
     Nnubes,
+    /// San Cheese and Nnubes256 on alternating rows
+    Rows,
+}
+
+impl SelectedImage {
+    /// Layer of the texture array the hexagons of the given grid row get
+    /// drawn with
+    fn layer(&self, row: i32) -> u32 {
+        match self {
+            SelectedImage::SanCheese => 0,
+            SelectedImage::Nnubes => 1,
+            SelectedImage::Rows => row.rem_euclid(2) as u32,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -200,17 +494,633 @@ enum SelectedAnimation {
     Metaball,
 }
 
+/// Where the grid animation gets evaluated
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SelectedBackend {
+    /// On the CPU, re-uploading every instance each frame
+    Cpu,
+    /// In a compute shader writing straight into the instance buffer
+    Gpu,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct AnimationParamsUniform {
+    time: f32,
+    animation: u32,
+    count: u32,
+    grid_width: u32,
+}
+
+/// What the animation compute shader needs to know about the instance drawn
+/// at every slot
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std430)]
+struct DrawCell {
+    cell: [i32; 2],
+    layer: u32,
+    #[padding]
+    _padding: u32,
+}
+
+/// Evaluates the grid animation in a compute shader
+struct AnimationPass {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    params: AnimationParamsUniform,
+    params_buffer: wgpu::Buffer,
+    /// Grid cell and texture layer of the instance drawn at every slot, in
+    /// draw order
+    cells: Vec<DrawCell>,
+    cells_buffer: wgpu::Buffer,
+    animation_belt: StagerHandle,
+}
+
+impl AnimationPass {
+    fn new(
+        device: &wgpu::Device,
+        staging: &mut StagingFactory,
+        instances_buffer: &InstanceVertexBuffer<InstanceVertex>,
+    ) -> Self {
+        let comp_module = shaders::INSTANCING_ANIMATION_COMP.create_module(device);
+
+        let count = instances_buffer.len;
+        let capacity = instances_buffer.capacity();
+        let params = AnimationParamsUniform {
+            time: 0.0,
+            animation: 0,
+            count,
+            grid_width: GRID_WIDTH,
+        };
+        let params_buffer =
+            params.create_buffer(device, Some("Instancing - Animation Params Buffer"));
+
+        let cells_buffer = Self::create_cells_buffer(device, capacity);
+
+        let animation_belt = staging.create_stager(
+            "instancing.animation",
+            std::mem::size_of::<AnimationParamsUniform>() as u64
+                + capacity as u64 * std::mem::size_of::<DrawCell>() as u64,
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Instancing - Animation Pass - Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            instances_buffer,
+            &cells_buffer,
+            &params_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Instancing - Animation Pass - Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Instancing - Animation Pass - Compute Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &comp_module,
+            entry_point: "main",
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            bind_group,
+            params,
+            params_buffer,
+            cells: Vec::with_capacity(capacity as usize),
+            cells_buffer,
+            animation_belt,
+        }
+    }
+
+    fn create_cells_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instancing - Draw Cells Buffer"),
+            size: capacity.max(1) as u64 * std::mem::size_of::<DrawCell>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        instances_buffer: &InstanceVertexBuffer<InstanceVertex>,
+        cells_buffer: &wgpu::Buffer,
+        params_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Instancing - Animation Pass - Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: instances_buffer.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: cells_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Follows the instance buffer after it has been reallocated
+    fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        instances_buffer: &InstanceVertexBuffer<InstanceVertex>,
+    ) {
+        self.cells_buffer = Self::create_cells_buffer(device, instances_buffer.capacity());
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            instances_buffer,
+            &self.cells_buffer,
+            &self.params_buffer,
+        );
+    }
+
+    fn update(
+        &mut self,
+        time: f64,
+        animation: SelectedAnimation,
+        draw_list: &DrawList<Entity>,
+        world: &World,
+    ) {
+        // Both animations have a period of 240π frames; wrapping the time
+        // around keeps it precise enough for the shader's f32s
+        self.params.time = (time % (240.0 * std::f64::consts::PI)) as f32;
+        self.params.animation = match animation {
+            SelectedAnimation::DoubleWave => 0,
+            SelectedAnimation::Metaball => 1,
+        };
+
+        self.cells.clear();
+        self.cells.extend(draw_list.iter().filter_map(|&entity| {
+            let mut query = world
+                .query_one::<(&GridCell, &MaterialHandle)>(entity)
+                .ok()?;
+            let (cell, material) = query.get()?;
+            Some(DrawCell {
+                cell: cell.0,
+                layer: material.0,
+                _padding: 0,
+            })
+        }));
+        self.params.count = self.cells.len() as u32;
+    }
+
+    fn compute(&mut self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        let mut stager = staging.fetch_stager(self.animation_belt);
+        stager.write(encoder, &self.params_buffer, 0, &self.params);
+        if self.cells.is_empty() {
+            return;
+        }
+        stager.write_slice(encoder, &self.cells_buffer, 0, &self.cells);
+
+        encoder.push_debug_group("Instancing - Animation");
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Instancing - Animation Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.dispatch(self.params.count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        encoder.pop_debug_group();
+    }
+}
+
+/// Which instances get left out when they are outside of the camera's frustum
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SelectedCulling {
+    None,
+    /// Against their bounding boxes while building the draw list
+    Cpu,
+    /// Against their bounding spheres in a compute shader, which only lets
+    /// the survivors through to an indirect draw
+    Gpu,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct CullParamsUniform {
+    planes: [[f32; 4]; 6],
+    /// xyz: center, w: radius, in model space
+    sphere: [f32; 4],
+    /// What the depth buffer behind the Hi-Z pyramid was drawn with
+    view_proj: [[f32; 4]; 4],
+    hiz_size: [f32; 2],
+    count: u32,
+    hiz_levels: u32,
+    /// Non-zero if instances should be tested against the Hi-Z pyramid
+    occlusion: u32,
+    #[padding]
+    _padding: [u32; 3],
+}
+
+/// Frustum-culls instances in a compute shader, and optionally occlusion-culls
+/// them against a Hi-Z pyramid of the last frame's depth, writing the slots of
+/// the survivors along with the arguments of the indirect draw that goes over
+/// them
+struct CullPass {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    /// What the vertex shader reads the survivors through
+    render_bind_group_layout: wgpu::BindGroupLayout,
+    render_bind_group: wgpu::BindGroup,
+    params: CullParamsUniform,
+    params_buffer: wgpu::Buffer,
+    args: DrawIndexedIndirectArgs,
+    args_buffer: IndirectBuffer<DrawIndexedIndirectArgs>,
+    visible_buffer: wgpu::Buffer,
+    cull_belt: StagerHandle,
+}
+
+impl CullPass {
+    fn new(
+        device: &wgpu::Device,
+        staging: &mut StagingFactory,
+        instances_buffer: &InstanceVertexBuffer<InstanceVertex>,
+        hiz: &HiZPyramid,
+        sphere: &BoundingSphere,
+        index_count: u32,
+    ) -> Self {
+        let comp_module = shaders::INSTANCING_CULL_COMP.create_module(device);
+
+        let params = CullParamsUniform {
+            planes: [[0.0; 4]; 6],
+            sphere: [
+                sphere.center.x,
+                sphere.center.y,
+                sphere.center.z,
+                sphere.radius,
+            ],
+            view_proj: [[0.0; 4]; 4],
+            hiz_size: [0.0; 2],
+            count: 0,
+            hiz_levels: 0,
+            occlusion: 0,
+            _padding: [0; 3],
+        };
+        let params_buffer = params.create_buffer(device, Some("Instancing - Cull Params Buffer"));
+
+        let args = DrawIndexedIndirectArgs {
+            index_count,
+            ..Default::default()
+        };
+        let args_buffer = IndirectBuffer::from_args(
+            device,
+            &[args],
+            wgpu::BufferUsages::STORAGE,
+            Some("Instancing - Cull Draw Args Buffer"),
+        );
+
+        let visible_buffer = Self::create_visible_buffer(device, instances_buffer.capacity());
+
+        let cull_belt = staging.create_stager(
+            "instancing.cull",
+            (std::mem::size_of::<CullParamsUniform>()
+                + std::mem::size_of::<DrawIndexedIndirectArgs>()) as u64,
+        );
+
+        let storage_entry = |binding, visibility, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Instancing - Cull Pass - Bind Group Layout"),
+            entries: &[
+                storage_entry(0, wgpu::ShaderStages::COMPUTE, true),
+                storage_entry(1, wgpu::ShaderStages::COMPUTE, false),
+                storage_entry(2, wgpu::ShaderStages::COMPUTE, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: false,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let render_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Instancing - Culled Instances Bind Group Layout"),
+                entries: &[
+                    storage_entry(0, wgpu::ShaderStages::VERTEX, true),
+                    storage_entry(1, wgpu::ShaderStages::VERTEX, true),
+                ],
+            });
+
+        let (bind_group, render_bind_group) = Self::create_bind_groups(
+            device,
+            &bind_group_layout,
+            &render_bind_group_layout,
+            instances_buffer,
+            hiz,
+            &visible_buffer,
+            &args_buffer,
+            &params_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Instancing - Cull Pass - Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Instancing - Cull Pass - Compute Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &comp_module,
+            entry_point: "main",
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            bind_group,
+            render_bind_group_layout,
+            render_bind_group,
+            params,
+            params_buffer,
+            args,
+            args_buffer,
+            visible_buffer,
+            cull_belt,
+        }
+    }
+
+    fn create_visible_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instancing - Visible Slots Buffer"),
+            size: capacity.max(1) as u64 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        render_layout: &wgpu::BindGroupLayout,
+        instances_buffer: &InstanceVertexBuffer<InstanceVertex>,
+        hiz: &HiZPyramid,
+        visible_buffer: &wgpu::Buffer,
+        args_buffer: &IndirectBuffer<DrawIndexedIndirectArgs>,
+        params_buffer: &wgpu::Buffer,
+    ) -> (wgpu::BindGroup, wgpu::BindGroup) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Instancing - Cull Pass - Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: instances_buffer.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: visible_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: args_buffer.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(hiz.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&hiz.sampler),
+                },
+            ],
+        });
+
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Instancing - Culled Instances Bind Group"),
+            layout: render_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: instances_buffer.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: visible_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        (bind_group, render_bind_group)
+    }
+
+    /// Follows the instance buffer or the Hi-Z pyramid after either has been
+    /// reallocated
+    fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        instances_buffer: &InstanceVertexBuffer<InstanceVertex>,
+        hiz: &HiZPyramid,
+    ) {
+        self.visible_buffer = Self::create_visible_buffer(device, instances_buffer.capacity());
+        let (bind_group, render_bind_group) = Self::create_bind_groups(
+            device,
+            &self.bind_group_layout,
+            &self.render_bind_group_layout,
+            instances_buffer,
+            hiz,
+            &self.visible_buffer,
+            &self.args_buffer,
+            &self.params_buffer,
+        );
+        self.bind_group = bind_group;
+        self.render_bind_group = render_bind_group;
+    }
+
+    /// Culls the first `count` slots of the instance buffer. `occlusion`
+    /// holds what the depth buffer behind the pyramid was drawn with, if
+    /// instances should be tested against it
+    fn update(
+        &mut self,
+        frustum: &Frustum,
+        count: u32,
+        occlusion: Option<([[f32; 4]; 4], &HiZPyramid)>,
+    ) {
+        // Packed, so its fields can't be borrowed in place
+        let mut planes = self.params.planes;
+        for (plane, frustum_plane) in planes.iter_mut().zip(frustum.planes()) {
+            *plane = (*frustum_plane).into();
+        }
+        self.params.planes = planes;
+        self.params.count = count;
+        self.params.occlusion = occlusion.is_some() as u32;
+        if let Some((view_proj, hiz)) = occlusion {
+            let (width, height) = hiz.size();
+            self.params.view_proj = view_proj;
+            self.params.hiz_size = [width as f32, height as f32];
+            self.params.hiz_levels = hiz.level_count();
+        }
+    }
+
+    fn compute(&mut self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        // The shader counts survivors up from zero every frame
+        let mut stager = staging.fetch_stager(self.cull_belt);
+        stager.write(encoder, &self.params_buffer, 0, &self.params);
+        self.args_buffer
+            .write(encoder, &mut stager, 0, std::slice::from_ref(&self.args));
+        if self.params.count == 0 {
+            return;
+        }
+
+        encoder.push_debug_group("Instancing - Cull");
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Instancing - Cull Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.dispatch(self.params.count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        encoder.pop_debug_group();
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum SelectedTranslucency {
+    None,
+    Checkerboard,
+    All,
+}
+
+impl SelectedTranslucency {
+    fn blending(&self, x: i32, y: i32) -> Blending {
+        match self {
+            SelectedTranslucency::None => Blending::Opaque,
+            SelectedTranslucency::Checkerboard if (x + y) & 1 == 0 => Blending::Opaque,
+            SelectedTranslucency::Checkerboard | SelectedTranslucency::All => Blending::Translucent,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum SelectedExtraPass {
     None,
     Depth,
 }
 
+/// How `DepthPass` shows depth
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum DepthView {
+    /// As stored, which is nearly all white
+    Raw,
+    /// Linearized between the near and far planes
+    Linear,
+    /// Linearized, from blue up close to red far away
+    Heatmap,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct DepthViewUniform {
+    near: f32,
+    far: f32,
+    mode: u32,
+    #[padding]
+    _padding: u32,
+}
+
 struct DepthPass {
     pipeline: wgpu::RenderPipeline,
     texture: DepthTexture,
+    sampler: wgpu::Sampler,
+    view: DepthView,
+    uniform: DepthViewUniform,
+    uniform_buffer: wgpu::Buffer,
     bind_group: wgpu::BindGroup,
     bind_group_layout: wgpu::BindGroupLayout,
+    depth_view_belt: StagerHandle,
 }
 
 impl DepthPass {
@@ -218,14 +1128,12 @@ impl DepthPass {
         device: &wgpu::Device,
         _queue: &wgpu::Queue,
         sc: &wgpu::SurfaceConfiguration,
-        _staging: &mut StagingFactory,
+        staging: &mut StagingFactory,
+        near: f32,
+        far: f32,
     ) -> Self {
-        let vert1_module = device.create_shader_module(&wgpu::include_spirv!(
-            "../shaders/instancing_depth.vert.spv"
-        ));
-        let frag1_module = device.create_shader_module(&wgpu::include_spirv!(
-            "../shaders/instancing_depth.frag.spv"
-        ));
+        let vert1_module = shaders::INSTANCING_DEPTH_VERT.create_module(device);
+        let frag1_module = shaders::INSTANCING_DEPTH_FRAG.create_module(device);
 
         let texture = DepthTexture::from_screen(
             device,
@@ -233,6 +1141,23 @@ impl DepthPass {
             sc.height,
             Some("Instancing - Depth Texture"),
         );
+        // Read texel by texel, rather than compared against
+        let sampler =
+            SamplerDesc::nearest().create(device, Some("Instancing - Depth Pass - Sampler"));
+
+        let view = DepthView::Linear;
+        let uniform = DepthViewUniform {
+            near,
+            far,
+            mode: view as u32,
+            _padding: 0,
+        };
+        let uniform_buffer =
+            uniform.create_buffer(device, Some("Instancing - Depth Pass - Uniform Buffer"));
+        let depth_view_belt = staging.create_stager(
+            "instancing.depth_view",
+            std::mem::size_of::<DepthViewUniform>() as u64,
+        );
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Instancing - Depth Pass - Bind Group Layout"),
@@ -251,29 +1176,32 @@ impl DepthPass {
                     binding: 1,
                     visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Sampler {
-                        filtering: true,
-                        comparison: true,
+                        filtering: false,
+                        comparison: false,
                     },
                     count: None,
                 },
-            ],
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Instancing - Depth Pass - Bind Group"),
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
             ],
         });
 
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &texture,
+            &sampler,
+            &uniform_buffer,
+        );
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Instancing - Depth Pass - Render Pipeline Layout"),
             bind_group_layouts: &[&bind_group_layout],
@@ -316,70 +1244,78 @@ impl DepthPass {
             alpha_to_coverage_enabled: false,
         });*/
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Instancing - Depth Pass - Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vert1_module,
-                entry_point: "main",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &frag1_module,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                clamp_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-        });
+        let pipeline =
+            PipelineBuilder::new(&pipeline_layout, &vert1_module, &frag1_module, sc.format)
+                .label("Instancing - Depth Pass - Render Pipeline")
+                .vertex_shader("instancing_depth.vert")
+                .build(device);
 
         Self {
             pipeline,
             texture,
+            sampler,
+            view,
+            uniform,
+            uniform_buffer,
             bind_group_layout,
             bind_group,
+            depth_view_belt,
         }
     }
 
-    pub fn resize(&mut self, device: &wgpu::Device, size: winit::dpi::PhysicalSize<u32>) {
-        self.texture = DepthTexture::from_screen(
-            device,
-            size.width,
-            size.height,
-            Some("Instancing - Depth Texture"),
-        );
-
-        self.bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture: &DepthTexture,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Instancing - Depth Pass - Bind Group"),
-            layout: &self.bind_group_layout,
+            layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&self.texture.view),
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.texture.sampler),
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
                 },
             ],
-        });
+        })
+    }
+
+    /// Switches to the next way of showing depth
+    fn next_view(&mut self) -> DepthView {
+        self.view = match self.view {
+            DepthView::Raw => DepthView::Linear,
+            DepthView::Linear => DepthView::Heatmap,
+            DepthView::Heatmap => DepthView::Raw,
+        };
+        self.uniform.mode = self.view as u32;
+        self.view
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: winit::dpi::PhysicalSize<u32>) {
+        self.texture = DepthTexture::from_screen(
+            device,
+            size.width,
+            size.height,
+            Some("Instancing - Depth Texture"),
+        );
+
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.texture,
+            &self.sampler,
+            &self.uniform_buffer,
+        );
     }
 
     pub fn render(
@@ -387,7 +1323,11 @@ impl DepthPass {
         encoder: &mut wgpu::CommandEncoder,
         frame_view: &wgpu::TextureView,
         _state: &crate::GlobalState,
+        staging: &StagingFactory,
     ) -> Result<(), wgpu::SurfaceError> {
+        let mut stager = staging.fetch_stager(self.depth_view_belt);
+        stager.write(encoder, &self.uniform_buffer, 0, &self.uniform);
+
         let rp_desc = &wgpu::RenderPassDescriptor {
             label: Some("Depth pass"),
             color_attachments: &[wgpu::RenderPassColorAttachment {
@@ -403,33 +1343,376 @@ impl DepthPass {
 
         let mut render_pass = encoder.begin_render_pass(rp_desc);
 
-        render_pass.set_pipeline(&self.pipeline);
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
-        render_pass.draw(0..6, 0..1);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+
+        Ok(())
+    }
+}
+
+/// How opaque instances get drawn
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum OpaquePass {
+    /// Depth-tested and shaded in one go
+    Main,
+    /// Only writing depth, without shading anything
+    DepthPrepass,
+    /// Shading only what the pre-pass left closest, without writing depth
+    EqualDepth,
+}
+
+impl OpaquePass {
+    /// For the plain, compact and indirect pipelines
+    fn labels(self) -> [&'static str; 3] {
+        match self {
+            OpaquePass::Main => [
+                "San Cheese Is Laying Your Pipes",
+                "Instancing - Compact Render Pipeline",
+                "Instancing - Indirect Render Pipeline",
+            ],
+            OpaquePass::DepthPrepass => [
+                "Instancing - Depth Pre-Pass Pipeline",
+                "Instancing - Compact Depth Pre-Pass Pipeline",
+                "Instancing - Indirect Depth Pre-Pass Pipeline",
+            ],
+            OpaquePass::EqualDepth => [
+                "Instancing - Equal Depth Render Pipeline",
+                "Instancing - Compact Equal Depth Render Pipeline",
+                "Instancing - Indirect Equal Depth Render Pipeline",
+            ],
+        }
+    }
+
+    fn configure(self, builder: PipelineBuilder) -> PipelineBuilder {
+        let builder = builder
+            .blend(wgpu::BlendState {
+                color: wgpu::BlendComponent::OVER,
+                alpha: wgpu::BlendComponent::REPLACE,
+            })
+            .depth(self != OpaquePass::EqualDepth);
+        match self {
+            OpaquePass::Main => builder,
+            OpaquePass::DepthPrepass => builder.depth_only(),
+            // Both passes run the same vertex shader over the same vertices,
+            // so they come up with the exact same depths
+            OpaquePass::EqualDepth => builder.depth_compare(wgpu::CompareFunction::Equal),
+        }
+    }
+}
+
+/// Pipelines for opaque instances, whichever instance buffer they're drawn
+/// out of
+struct OpaquePipelines {
+    plain: wgpu::RenderPipeline,
+    compact: wgpu::RenderPipeline,
+    indirect: wgpu::RenderPipeline,
+}
+
+pub struct InstancesScene {
+    opaque_pipelines: OpaquePipelines,
+    depth_prepass_pipelines: OpaquePipelines,
+    equal_depth_pipelines: OpaquePipelines,
+    /// Whether opaque instances get drawn to depth first, so that only the
+    /// closest ones get shaded
+    depth_prepass: bool,
+    translucent_pipeline: wgpu::RenderPipeline,
+    compact_translucent_pipeline: wgpu::RenderPipeline,
+    /// Every hexagon, along with the prototype they're all instances of
+    world: World,
+    hexagon_prototype: Entity,
+    /// Hexagon sitting on every grid cell
+    occupied_cells: HashMap<[i32; 2], Entity>,
+    instance_edits: Vec<InstanceEdit>,
+    /// Last known cursor position, in physical pixels
+    cursor: Option<(f64, f64)>,
+    screen_size: (u32, u32),
+    draw_list: DrawList<Entity>,
+    /// How many instances were left out of the draw list last frame, when
+    /// culling on the CPU
+    culled_instances: usize,
+    instances_buffer: InstanceVertexBuffer<InstanceVertex>,
+    /// Takes the place of `instances_buffer` when `compact_instances` is on,
+    /// on the CPU backend without GPU culling
+    compact_instances_buffer: InstanceVertexBuffer<CompactInstanceVertex>,
+    compact_instances: bool,
+    /// Indexed by the prototypes' `MeshHandle`s
+    meshes: Vec<Mesh>,
+    lod_draws: LodDraws,
+    lod_tint_bind_group: wgpu::BindGroup,
+    _lod_tint_buffer: wgpu::Buffer,
+    show_lods: bool,
+    diffuse_bind_group: wgpu::BindGroup,
+    _diffuse_texture: Texture,
+    depth_pass: DepthPass,
+    animation_pass: AnimationPass,
+    cull_pass: CullPass,
+    hiz: HiZPyramid,
+    /// Whether the GPU culling path also skips instances hidden behind the
+    /// last frame's depth, and whether that depth is there to be used
+    occlusion_culling: bool,
+    depth_history: bool,
+    /// Level of the Hi-Z pyramid drawn over the frame, if any
+    hiz_debug_level: Option<u32>,
+    grid: GridPass,
+    show_grid: bool,
+    gizmos: GizmoRenderer,
+    show_bounds: bool,
+    outline: OutlinePass,
+    selected_backend: SelectedBackend,
+    selected_culling: SelectedCulling,
+    selected_image: SelectedImage,
+    selected_animation: SelectedAnimation,
+    selected_pass: SelectedExtraPass,
+    selected_translucency: SelectedTranslucency,
+    camera: Camera,
+    camera_controller: CameraController,
+    fly_camera_controller: FlyCameraController,
+    /// Whether the fly camera is the one in control, instead of the orbiting one
+    flying: bool,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: UniformBuffer<CameraUniform>,
+    uniform_bind_group: wgpu::BindGroup,
+    time: f64,
+    camera_belt: StagerHandle,
+    instance_belt: StagerHandle,
+}
+
+impl InstancesScene {
+    /// World-space ray from the camera through the cursor
+    fn cursor_ray(&self, cursor: (f64, f64)) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
+        let (width, height) = self.screen_size;
+        let x = (2.0 * cursor.0 / width as f64 - 1.0) as f32;
+        let y = (1.0 - 2.0 * cursor.1 / height as f64) as f32;
+        self.camera.screen_ray(x, y)
+    }
+
+    /// Point of the z = 0 plane under the cursor, if the camera is looking at
+    /// it at all
+    fn cursor_on_plane(&self, cursor: (f64, f64)) -> Option<cgmath::Point2<f32>> {
+        let (origin, direction) = self.cursor_ray(cursor);
+        if direction.z.abs() < f32::EPSILON {
+            return None;
+        }
+        let t = -origin.z / direction.z;
+        if t < 0.0 {
+            return None;
+        }
+        let hit = origin + direction * t;
+        Some(cgmath::Point2::new(hit.x, hit.y))
+    }
+
+    /// The mesh every hexagon is drawn with, out of their prototype
+    fn mesh(&self) -> &Mesh {
+        let handle = self
+            .world
+            .get::<MeshHandle>(self.hexagon_prototype)
+            .expect("The hexagon prototype is never despawned");
+        &self.meshes[handle.0]
+    }
+
+    /// Places a hexagon on a free grid cell
+    fn spawn(&mut self, cell: [i32; 2]) {
+        if self.occupied_cells.contains_key(&cell) {
+            println!("Cell {:?} is already taken", cell);
+            return;
+        }
+
+        let entity = self.world.spawn(hexagon(self.hexagon_prototype, cell));
+        self.occupied_cells.insert(cell, entity);
+        println!("Spawned an instance on {:?}", cell);
+    }
+
+    fn remove_nearest(&mut self, point: cgmath::Point2<f32>) {
+        let nearest = self
+            .world
+            .query::<(&Transform, &GridCell)>()
+            .iter()
+            .map(|(entity, (transform, cell))| {
+                let t = transform.translation();
+                (
+                    entity,
+                    *cell,
+                    cgmath::Point2::new(t.x, t.y).distance2(point),
+                )
+            })
+            .min_by(|(_, _, a), (_, _, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        if let Some((entity, cell, _)) = nearest {
+            if self.world.despawn(entity).is_ok() {
+                self.occupied_cells.remove(&cell.0);
+                println!("Removed the instance on {:?}", cell.0);
+            }
+        }
+    }
+
+    /// Where the hexagon is being drawn. On the GPU path the CPU-side
+    /// transforms are at rest, so the animation gets worked out here too
+    fn animated_model_matrix(
+        &self,
+        transform: &Transform,
+        cell: &GridCell,
+    ) -> cgmath::Matrix4<f32> {
+        let mut model = *transform.model_matrix();
+        if self.selected_backend == SelectedBackend::Gpu {
+            let coords = cell.animation_coords();
+            model.w.z = animated_z(self.selected_animation, coords, self.time);
+        }
+        model
+    }
+
+    /// Toggles the selection of the closest hexagon whose bounding box is
+    /// under the cursor, if any
+    fn pick(&mut self, cursor: (f64, f64)) {
+        let (origin, direction) = self.cursor_ray(cursor);
+        let aabb = self.mesh().bounds().aabb;
+        let picked = self
+            .world
+            .query::<(&Transform, &GridCell)>()
+            .iter()
+            .filter_map(|(entity, (transform, cell))| {
+                let world = aabb.transformed(&self.animated_model_matrix(transform, cell));
+                Some((entity, *cell, world.ray_intersection(origin, direction)?))
+            })
+            .min_by(|(_, _, a), (_, _, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        match picked {
+            Some((entity, cell, _)) => {
+                if self.world.remove_one::<Selected>(entity).is_ok() {
+                    println!("Deselected the instance on {:?}", cell.0);
+                } else if self.world.insert_one(entity, Selected).is_ok() {
+                    println!("Selected the instance on {:?}", cell.0);
+                }
+            }
+            None => println!("Picked nothing"),
+        }
+    }
+
+    /// Whether the depth buffer has to outlive the frame, for the next one
+    /// to build a Hi-Z pyramid out of it
+    fn needs_depth_history(&self) -> bool {
+        (self.occlusion_culling && self.selected_culling == SelectedCulling::Gpu)
+            || self.hiz_debug_level.is_some()
+    }
+
+    /// Whether instances get drawn out of `compact_instances_buffer`. The
+    /// compute passes only know about `InstanceVertex`
+    fn uses_compact_instances(&self) -> bool {
+        self.compact_instances
+            && self.selected_backend == SelectedBackend::Cpu
+            && self.selected_culling != SelectedCulling::Gpu
+    }
+
+    /// Dynamic offset of the tint instances at the given level of detail get
+    fn tint_offset(&self, level: usize) -> wgpu::DynamicOffset {
+        let slot = if self.show_lods {
+            level
+        } else {
+            self.mesh().lod().level_count()
+        };
+        (slot as wgpu::BufferAddress * wgpu::BIND_BUFFER_ALIGNMENT) as wgpu::DynamicOffset
+    }
+
+    /// Draws the opaque instances with one of the sets of pipelines, however
+    /// they're being culled
+    fn render_opaque<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        pipelines: &'a OpaquePipelines,
+    ) {
+        render_pass.set_pipeline(if self.uses_compact_instances() {
+            &pipelines.compact
+        } else {
+            &pipelines.plain
+        });
+
+        render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+
+        if self.selected_culling == SelectedCulling::Gpu {
+            // The cull pass doesn't pick levels of detail, so every
+            // survivor gets the most detailed one
+            render_pass.set_pipeline(&pipelines.indirect);
+            render_pass.set_bind_group(2, &self.lod_tint_bind_group, &[self.tint_offset(0)]);
+            render_pass.set_bind_group(3, &self.cull_pass.render_bind_group, &[]);
+            mesh::render_indirect(
+                render_pass,
+                self.mesh().lod().level(0),
+                &self.cull_pass.args_buffer,
+                0,
+            );
+        } else {
+            self.render_lod_runs(render_pass, &self.lod_draws.opaque);
+        }
+    }
+
+    /// Draws runs of instances at their level of detail, out of whichever
+    /// instance buffer is in use
+    fn render_lod_runs<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        runs: &[(usize, Range<u32>)],
+    ) {
+        for (level, range) in runs.iter() {
+            render_pass.set_bind_group(2, &self.lod_tint_bind_group, &[self.tint_offset(*level)]);
+            if self.uses_compact_instances() {
+                self.mesh().render_level_instances(
+                    render_pass,
+                    *level,
+                    &self.compact_instances_buffer,
+                    range.clone(),
+                );
+            } else {
+                self.mesh().render_level_instances(
+                    render_pass,
+                    *level,
+                    &self.instances_buffer,
+                    range.clone(),
+                );
+            }
+        }
+    }
 
-        Ok(())
+    fn apply_instance_edits(&mut self) {
+        for edit in std::mem::take(&mut self.instance_edits) {
+            match edit {
+                InstanceEdit::Spawn(cursor) => {
+                    if let Some(point) = self.cursor_on_plane(cursor) {
+                        self.spawn([point.x.round() as i32, point.y.round() as i32]);
+                    }
+                }
+                InstanceEdit::RemoveNearest(cursor) => {
+                    if let Some(point) = self.cursor_on_plane(cursor) {
+                        self.remove_nearest(point);
+                    }
+                }
+            }
+        }
     }
 }
 
-pub struct InstancesScene {
-    pipeline: wgpu::RenderPipeline,
-    instances: Vec<Instance>,
-    instances_buffer: InstanceVertexBuffer<InstanceVertex>,
-    epic_mesh: Mesh,
-    diffuse1_bind_group: wgpu::BindGroup,
-    _diffuse1_texture: Texture,
-    diffuse2_bind_group: wgpu::BindGroup,
-    _diffuse2_texture: Texture,
-    depth_pass: DepthPass,
-    selected_image: SelectedImage,
-    selected_animation: SelectedAnimation,
-    selected_pass: SelectedExtraPass,
-    camera: Camera,
-    camera_controller: CameraController,
-    camera_uniform: CameraUniform,
-    camera_uniform_buffer: wgpu::Buffer,
-    uniform_bind_group: wgpu::BindGroup,
-    time: f64,
+/// Makes room for `live` instances, starting over from an empty buffer.
+/// Returns whether it got reallocated
+fn grow_instances<T>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &mut InstanceVertexBuffer<T>,
+    live: u32,
+) -> bool
+where
+    T: VertexBufferable + Descriptable,
+{
+    buffer.len = 0;
+    let grown = live > buffer.capacity();
+    if grown {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Instancing - Instance Growth Encoder"),
+        });
+        buffer.reserve(device, &mut encoder, live);
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+    buffer.len = live;
+    grown
 }
 
 impl Scene for InstancesScene {
@@ -438,26 +1721,34 @@ impl Scene for InstancesScene {
         queue: &wgpu::Queue,
         sc: &wgpu::SurfaceConfiguration,
         staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        _assets: &Assets,
     ) -> Self {
-        let diffuse1_bytes = include_bytes!("../../assets/sanCheese.png");
-        let diffuse1_texture =
-            Texture::from_bytes(device, queue, diffuse1_bytes, "San Cheese Is Watching You")
-                .unwrap();
-
-        let diffuse2_bytes = include_bytes!("../../assets/nnubes256.png");
-        let diffuse2_texture =
-            Texture::from_bytes(device, queue, diffuse2_bytes, "Nnubes256 Is Watching You")
-                .unwrap();
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        // Every hexagon picks its own layer
+        let diffuse_bytes: &[&[u8]] = &[
+            include_bytes!("../../assets/sanCheese.png"),
+            include_bytes!("../../assets/nnubes256.png"),
+        ];
+        let diffuse_texture = Texture::array_from_bytes(
+            device,
+            queue,
+            diffuse_bytes,
+            ColorSpace::Srgb,
+            "San Cheese And Nnubes256 Are Watching You",
+        )
+        .unwrap();
+
+        let texture_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
                 label: Some("San Cheese Is Laying Your Bounds"),
                 entries: &[
                     wgpu::BindGroupLayoutEntry {
                         binding: 0,
                         visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Texture {
-                            view_dimension: wgpu::TextureViewDimension::D2,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
                             sample_type: wgpu::TextureSampleType::Float { filterable: true },
                             multisampled: false,
                         },
@@ -473,128 +1764,191 @@ impl Scene for InstancesScene {
                         count: None,
                     },
                 ],
-            });
+            },
+        );
 
-        let diffuse1_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("San Cheese Is Binding You"),
+        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("San Cheese And Nnubes256 Are Binding You"),
             layout: &texture_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse1_texture.view),
+                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse1_texture.sampler),
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
                 },
             ],
         });
 
-        let diffuse2_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Nnubes256 Is Binding You"),
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse2_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse2_texture.sampler),
-                },
-            ],
+        // The most detailed hexagon comes first, and the plain one last
+        let mut lod_levels = Vec::with_capacity(LOD_SUBDIVISIONS + 1);
+        let (mut vertices, mut indices) = (VERTICES_1.to_vec(), INDICES_1.to_vec());
+        for subdivisions in 0..=LOD_SUBDIVISIONS {
+            if subdivisions > 0 {
+                let (more_vertices, more_indices) = subdivide(&vertices, &indices);
+                vertices = more_vertices;
+                indices = more_indices;
+            }
+            lod_levels.push(IndexedVertexBuffer::from_vertices_indexes(
+                device,
+                &vertices,
+                &indices,
+                Some("San Cheese Is Running Over Your Vertices"),
+                Some("San Cheese Is Indexing You"),
+            ));
+        }
+        lod_levels.reverse();
+
+        let epic_mesh = Mesh::with_lod(
+            Lod::new(lod_levels, LOD_THRESHOLDS.to_vec()),
+            Bounds::from_vertices(VERTICES_1),
+        );
+
+        let tint_slot = wgpu::BIND_BUFFER_ALIGNMENT as usize;
+        let tint_count = epic_mesh.lod().level_count() + 1;
+        let mut tint_contents = vec![0u8; tint_slot * tint_count];
+        for (level, slot) in tint_contents.chunks_exact_mut(tint_slot).enumerate() {
+            let tint = if level + 1 < tint_count {
+                mesh::lod_debug_tint(level)
+            } else {
+                [1.0, 1.0, 1.0, 1.0]
+            };
+            let uniform = LodTintUniform { tint };
+            slot[..std::mem::size_of::<LodTintUniform>()]
+                .copy_from_slice(bytemuck::bytes_of(&uniform));
+        }
+        let lod_tint_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Instancing - LOD Tint Buffer"),
+            contents: &tint_contents,
+            usage: wgpu::BufferUsages::UNIFORM,
         });
 
-        let vertex_buffer = IndexedVertexBuffer::from_vertices_indexes(
+        let lod_tint_bind_group_layout = layouts.get(
             device,
-            VERTICES_1,
-            INDICES_1,
-            Some("San Cheese Is Running Over Your Vertices"),
-            Some("San Cheese Is Indexing You"),
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Instancing - LOD Tint Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: NonZeroU64::new(
+                            std::mem::size_of::<LodTintUniform>() as u64
+                        ),
+                    },
+                    count: None,
+                }],
+            },
         );
 
-        let epic_mesh = Mesh::new(vertex_buffer);
+        let lod_tint_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Instancing - LOD Tint Bind Group"),
+            layout: &lod_tint_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(BufferBinding {
+                    buffer: &lod_tint_buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(std::mem::size_of::<LodTintUniform>() as u64),
+                }),
+            }],
+        });
 
-        let vert1_module =
-            device.create_shader_module(&wgpu::include_spirv!("../shaders/instancing.vert.spv"));
-        let frag1_module =
-            device.create_shader_module(&wgpu::include_spirv!("../shaders/instancing.frag.spv"));
+        let vert1_module = shaders::INSTANCING_VERT.create_module(device);
+        let frag1_module = shaders::INSTANCING_FRAG.create_module(device);
+        let frag2_module = shaders::INSTANCING_TRANSLUCENT_FRAG.create_module(device);
+        let vert2_module = shaders::INSTANCING_INDIRECT_VERT.create_module(device);
+        let vert3_module = shaders::INSTANCING_COMPACT_VERT.create_module(device);
 
         let camera = Camera {
             eye: (0.0, 1.0, 2.0).into(),
             target: (0.0, 0.0, 0.0).into(),
             up: cgmath::Vector3::unit_y(),
             aspect: sc.width as f32 / sc.height as f32,
-            fovy: 45.0,
+            projection: Projection::Perspective { fovy: 45.0 },
             znear: 0.1,
             zfar: 100.0,
         };
 
-        let camera_controller = CameraController::new(0.2);
+        let camera_controller = CameraController::new(12.0);
+        // Glides a bit once the keys are released, to make flying around feel smoother
+        let fly_camera_controller =
+            FlyCameraController::new(2.0, 0.003, &camera).with_smoothing(Smoothing {
+                acceleration: 10.0,
+                damping: 4.0,
+            });
 
         let mut camera_uniform = CameraUniform::default();
         camera_uniform.update(&camera);
-        staging.create_stager(CAMERA_BELT.to_owned(), 64);
-
-        let mut instances = Vec::with_capacity(128);
-        staging.create_stager(INSTANCE_BELT.to_owned(), 128 * 64);
-
-        for i in -16..=16 {
-            for j in -16..=16 {
-                let x = i as f32;
-                let y = j as f32;
-                instances.push(Instance {
-                    transform: transform!(
-                        t: [x, y, 0.0],
-                        r: [0.0, 0.0, 0.0],
-                        s: [1.0, 1.0, 1.0]
-                    ),
-                });
+        let camera_belt = staging.create_stager(
+            "instancing.camera",
+            std::mem::size_of::<CameraUniform>() as u64,
+        );
+
+        let instance_belt = staging.create_stager("instancing.instances", 128 * 64);
+
+        let mut world = World::new();
+        let hexagon_prototype =
+            ecs::spawn_prototype(&mut world, MeshHandle(0), *epic_mesh.bounds());
+        let mut occupied_cells = HashMap::new();
+        let half_width = (GRID_WIDTH / 2) as i32;
+        for x in -half_width..=half_width {
+            for y in -half_width..=half_width {
+                let entity = world.spawn(hexagon(hexagon_prototype, [x, y]));
+                occupied_cells.insert([x, y], entity);
             }
         }
+        let instances = world
+            .query::<(&Transform, &MaterialHandle)>()
+            .iter()
+            .map(|(_, (transform, material))| InstanceData::new(transform, *material))
+            .collect::<Vec<_>>();
 
-        //println!("{:?}", instances);
-
-        let instances_buffer = InstanceVertexBuffer::from_instances(
+        let instances_buffer = InstanceVertexBuffer::from_instances_with_usage(
             device,
             &instances,
+            wgpu::BufferUsages::STORAGE,
             Some("Instances - Instances Vertex Buffer"),
         );
+        let compact_instances_buffer = InstanceVertexBuffer::from_instances(
+            device,
+            &instances,
+            Some("Instancing - Compact Instances Vertex Buffer"),
+        );
 
-        let camera_uniform_buf =
-            camera_uniform.into_buffer(device, Some("Cameras - Camera Uniform Buffer"));
+        let camera_uniform_buf = UniformBuffer::new(
+            device,
+            &camera_uniform,
+            Some("Cameras - Camera Uniform Buffer"),
+        );
 
-        let uniform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        let uniform_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
                 label: Some("Cameras - Camera Uniform Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            });
+                entries: &[UniformBuffer::<CameraUniform>::layout_entry(
+                    0,
+                    wgpu::ShaderStages::VERTEX,
+                )],
+            },
+        );
 
         let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Cameras - Camera Uniform Bind Group Layout"),
             layout: &uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer(BufferBinding {
-                    buffer: &camera_uniform_buf,
-                    offset: 0,
-                    size: None,
-                }),
-            }],
+            entries: &[camera_uniform_buf.entry(0)],
         });
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("San Cheese Is Planning Your Pipes"),
-            bind_group_layouts: &[&texture_bind_group_layout, &uniform_bind_group_layout],
+            bind_group_layouts: &[
+                &texture_bind_group_layout,
+                &uniform_bind_group_layout,
+                &lod_tint_bind_group_layout,
+            ],
             push_constant_ranges: &[],
         });
 
@@ -647,177 +2001,604 @@ impl Scene for InstancesScene {
             alpha_to_coverage_enabled: false,
         });*/
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("San Cheese Is Laying Your Pipes"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vert1_module,
-                entry_point: "main",
-                buffers: &[TexturedVertex::descriptor(), InstanceVertex::descriptor()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &frag1_module,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::OVER,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                clamp_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: DepthTexture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-        });
+        let create_pipeline = |label: &str,
+                               (vert_name, vert_module): (&str, &wgpu::ShaderModule),
+                               instance_layout: wgpu::VertexBufferLayout,
+                               frag_module: &wgpu::ShaderModule,
+                               blend: wgpu::BlendState,
+                               depth_write_enabled: bool| {
+            PipelineBuilder::new(&pipeline_layout, vert_module, frag_module, sc.format)
+                .label(label)
+                .vertex_shader(vert_name)
+                .buffers(&[TexturedVertex::descriptor(), instance_layout])
+                .blend(blend)
+                .depth(depth_write_enabled)
+                .build(device)
+        };
+
+        // Translucent instances get alpha-blended on top of the opaque ones.
+        // They are still depth-tested against the opaque geometry, but they don't
+        // write depth themselves, otherwise they would hide whatever translucent
+        // instance is drawn behind them afterwards.
+        let translucent_pipeline = create_pipeline(
+            "San Cheese Is Laying Your Translucent Pipes",
+            ("instancing.vert", &vert1_module),
+            InstanceVertex::descriptor(),
+            &frag2_module,
+            wgpu::BlendState::ALPHA_BLENDING,
+            false,
+        );
+
+        // Same as above, out of `CompactInstanceVertex`es
+        let compact_translucent_pipeline = create_pipeline(
+            "Instancing - Compact Translucent Render Pipeline",
+            ("instancing_compact.vert", &vert3_module),
+            CompactInstanceVertex::descriptor(),
+            &frag2_module,
+            wgpu::BlendState::ALPHA_BLENDING,
+            false,
+        );
+
+        let depth_pass = DepthPass::new(device, queue, sc, staging, camera.znear, camera.zfar);
 
-        let depth_pass = DepthPass::new(device, queue, sc, staging);
+        // Draws whatever the cull pass let through, reading the instances
+        // straight out of storage buffers rather than as vertex attributes
+        let hiz = HiZPyramid::new(
+            device,
+            sc,
+            staging,
+            "instancing.hiz",
+            &depth_pass.texture,
+            camera.znear,
+            camera.zfar,
+        );
+        let cull_pass = CullPass::new(
+            device,
+            staging,
+            &instances_buffer,
+            &hiz,
+            &epic_mesh.bounds().sphere,
+            epic_mesh.lod().level(0).num_indices,
+        );
+
+        let indirect_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Instancing - Indirect Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &uniform_bind_group_layout,
+                    &lod_tint_bind_group_layout,
+                    &cull_pass.render_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        // Opaque instances get drawn one of three ways: as usual, or in two
+        // steps with a depth pre-pass, first only writing depth and then only
+        // shading what ended up closest
+        let create_opaque_pipelines = |pass: OpaquePass| {
+            let [plain_label, compact_label, indirect_label] = pass.labels();
+            OpaquePipelines {
+                plain: pass
+                    .configure(
+                        PipelineBuilder::new(
+                            &pipeline_layout,
+                            &vert1_module,
+                            &frag1_module,
+                            sc.format,
+                        )
+                        .label(plain_label)
+                        .vertex_shader("instancing.vert")
+                        .buffers(&[TexturedVertex::descriptor(), InstanceVertex::descriptor()]),
+                    )
+                    .build(device),
+                compact: pass
+                    .configure(
+                        PipelineBuilder::new(
+                            &pipeline_layout,
+                            &vert3_module,
+                            &frag1_module,
+                            sc.format,
+                        )
+                        .label(compact_label)
+                        .vertex_shader("instancing_compact.vert")
+                        .buffers(&[
+                            TexturedVertex::descriptor(),
+                            CompactInstanceVertex::descriptor(),
+                        ]),
+                    )
+                    .build(device),
+                indirect: pass
+                    .configure(
+                        PipelineBuilder::new(
+                            &indirect_pipeline_layout,
+                            &vert2_module,
+                            &frag1_module,
+                            sc.format,
+                        )
+                        .label(indirect_label)
+                        .vertex_shader("instancing_indirect.vert")
+                        .buffers(&[TexturedVertex::descriptor()]),
+                    )
+                    .build(device),
+            }
+        };
+        let opaque_pipelines = create_opaque_pipelines(OpaquePass::Main);
+        let depth_prepass_pipelines = create_opaque_pipelines(OpaquePass::DepthPrepass);
+        let equal_depth_pipelines = create_opaque_pipelines(OpaquePass::EqualDepth);
+
+        // Behind the grid of hexagons, out of the way of the animations
+        let grid = GridPass::new(
+            device,
+            sc,
+            staging,
+            "instancing.grid",
+            GridPlane::Z(-2.0),
+            Some(DepthTexture::DEPTH_FORMAT),
+        );
+
+        // A box per instance, twelve lines each, with room for as many
+        // spawned instances as there are in the initial grid
+        let gizmos = GizmoRenderer::new(
+            device,
+            sc,
+            staging,
+            "instancing.gizmos",
+            2 * instances.len() * 12,
+            Some(DepthTexture::DEPTH_FORMAT),
+        );
+        let animation_pass = AnimationPass::new(device, staging, &instances_buffer);
+
+        let draw_list = DrawList::with_capacity(instances.len());
 
         Self {
-            pipeline,
-            epic_mesh,
-            instances,
+            opaque_pipelines,
+            depth_prepass_pipelines,
+            equal_depth_pipelines,
+            depth_prepass: false,
+            translucent_pipeline,
+            compact_translucent_pipeline,
+            meshes: vec![epic_mesh],
+            lod_draws: LodDraws::default(),
+            lod_tint_bind_group,
+            _lod_tint_buffer: lod_tint_buffer,
+            show_lods: false,
+            world,
+            hexagon_prototype,
+            occupied_cells,
+            instance_edits: Vec::new(),
+            cursor: None,
+            screen_size: (sc.width, sc.height),
+            draw_list,
+            culled_instances: 0,
             instances_buffer,
-            diffuse1_bind_group,
-            _diffuse1_texture: diffuse1_texture,
-            diffuse2_bind_group,
-            _diffuse2_texture: diffuse2_texture,
+            compact_instances_buffer,
+            compact_instances: false,
+            diffuse_bind_group,
+            _diffuse_texture: diffuse_texture,
             depth_pass,
+            animation_pass,
+            cull_pass,
+            hiz,
+            occlusion_culling: false,
+            depth_history: false,
+            hiz_debug_level: None,
+            grid,
+            show_grid: true,
+            gizmos,
+            show_bounds: false,
+            outline: OutlinePass::new(
+                device,
+                sc,
+                staging,
+                "instancing.outline",
+                TexturedVertex::descriptor(),
+                MAX_OUTLINED,
+            ),
+            selected_backend: SelectedBackend::Gpu,
+            selected_culling: SelectedCulling::Cpu,
             selected_image: SelectedImage::Nnubes,
             selected_animation: SelectedAnimation::DoubleWave,
             selected_pass: SelectedExtraPass::None,
+            selected_translucency: SelectedTranslucency::None,
             camera,
             camera_controller,
+            fly_camera_controller,
+            flying: false,
             camera_uniform,
             camera_uniform_buffer: camera_uniform_buf,
             uniform_bind_group,
             time: 0.0,
+            camera_belt,
+            instance_belt,
         }
     }
 
-    fn input(&mut self, event: &winit::event::WindowEvent) -> bool {
-        let camera_handled = self.camera_controller.input(event);
-        match event {
-            WindowEvent::KeyboardInput { input, .. } => {
-                if let KeyboardInput {
-                    state: ElementState::Pressed,
-                    virtual_keycode: Some(keycode),
-                    ..
-                } = input
-                {
-                    match keycode {
-                        VirtualKeyCode::N => {
-                            println!("Pressed N");
-                            self.selected_image = match self.selected_image {
-                                SelectedImage::SanCheese => SelectedImage::Nnubes,
-                                SelectedImage::Nnubes => SelectedImage::SanCheese,
-                            };
-                            println!("{:?}", self.selected_image);
-
-                            true
-                        }
-                        VirtualKeyCode::M => {
-                            println!("Pressed M");
-                            self.selected_animation = match self.selected_animation {
-                                SelectedAnimation::DoubleWave => SelectedAnimation::Metaball,
-                                SelectedAnimation::Metaball => SelectedAnimation::DoubleWave,
-                            };
-                            println!("{:?}", self.selected_animation);
-
-                            true
-                        }
-                        VirtualKeyCode::B => {
-                            println!("Pressed B");
-                            self.selected_pass = match self.selected_pass {
-                                SelectedExtraPass::None => SelectedExtraPass::Depth,
-                                SelectedExtraPass::Depth => SelectedExtraPass::None,
-                            };
-                            println!("{:?}", self.selected_pass);
-
-                            true
-                        }
-                        _ => false,
-                    }
+    fn grabs_cursor(&self) -> bool {
+        self.flying
+    }
+
+    fn cancel(&mut self) -> bool {
+        let selected = self
+            .world
+            .query::<&Selected>()
+            .iter()
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+        for &entity in selected.iter() {
+            let _ = self.world.remove_one::<Selected>(entity);
+        }
+        let cleared = !selected.is_empty();
+        if cleared {
+            println!("Selection cleared");
+        }
+        cleared
+    }
+
+    fn destroy(&mut self) {
+        self.instances_buffer.buffer.destroy();
+        self.compact_instances_buffer.buffer.destroy();
+    }
+
+    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, input: &Input, time: Time) {
+        self.cursor = input.cursor();
+        if input.mouse_just_pressed(MouseButton::Left) {
+            self.instance_edits
+                .extend(self.cursor.map(InstanceEdit::Spawn));
+        }
+
+        if let Some(key) = input.just_pressed("instancing.toggle_texture") {
+            println!("Pressed {:?}", key);
+            self.selected_image = match self.selected_image {
+                SelectedImage::SanCheese => SelectedImage::Nnubes,
+                SelectedImage::Nnubes => SelectedImage::Rows,
+                SelectedImage::Rows => SelectedImage::SanCheese,
+            };
+            println!("{:?}", self.selected_image);
+        }
+        if let Some(key) = input.just_pressed("instancing.switch_animation") {
+            println!("Pressed {:?}", key);
+            self.selected_animation = match self.selected_animation {
+                SelectedAnimation::DoubleWave => SelectedAnimation::Metaball,
+                SelectedAnimation::Metaball => SelectedAnimation::DoubleWave,
+            };
+            println!("{:?}", self.selected_animation);
+        }
+        if let Some(key) = input.just_pressed("instancing.toggle_depth_view") {
+            println!("Pressed {:?}", key);
+            self.selected_pass = match self.selected_pass {
+                SelectedExtraPass::None => SelectedExtraPass::Depth,
+                SelectedExtraPass::Depth => SelectedExtraPass::None,
+            };
+            println!("{:?}", self.selected_pass);
+        }
+        if let Some(key) = input.just_pressed("instancing.switch_depth_view") {
+            println!("Pressed {:?}", key);
+            println!("Depth view: {:?}", self.depth_pass.next_view());
+        }
+        if let Some(key) = input.just_pressed("instancing.switch_backend") {
+            println!("Pressed {:?}", key);
+            self.selected_backend = match self.selected_backend {
+                SelectedBackend::Cpu => SelectedBackend::Gpu,
+                SelectedBackend::Gpu => SelectedBackend::Cpu,
+            };
+            println!("{:?}", self.selected_backend);
+        }
+        if let Some(key) = input.just_pressed("instancing.switch_translucency") {
+            println!("Pressed {:?}", key);
+            self.selected_translucency = match self.selected_translucency {
+                SelectedTranslucency::None => SelectedTranslucency::Checkerboard,
+                SelectedTranslucency::Checkerboard => SelectedTranslucency::All,
+                SelectedTranslucency::All => SelectedTranslucency::None,
+            };
+            println!("{:?}", self.selected_translucency);
+        }
+        if let Some(key) = input.just_pressed("instancing.toggle_grid") {
+            println!("Pressed {:?}", key);
+            self.show_grid = !self.show_grid;
+            println!("Grid: {:?}", self.show_grid);
+        }
+        if let Some(key) = input.just_pressed("instancing.toggle_bounds") {
+            println!("Pressed {:?}", key);
+            self.show_bounds = !self.show_bounds;
+            println!("Bounding volumes: {:?}", self.show_bounds);
+        }
+        if let Some(key) = input.just_pressed("instancing.toggle_lod_tints") {
+            println!("Pressed {:?}", key);
+            self.show_lods = !self.show_lods;
+            println!("LOD tints: {:?}", self.show_lods);
+        }
+        if let Some(key) = input.just_pressed("instancing.switch_culling") {
+            println!("Pressed {:?}", key);
+            self.selected_culling = match self.selected_culling {
+                SelectedCulling::None => SelectedCulling::Cpu,
+                SelectedCulling::Cpu => SelectedCulling::Gpu,
+                SelectedCulling::Gpu => SelectedCulling::None,
+            };
+            println!("Frustum culling: {:?}", self.selected_culling);
+        }
+        if let Some(key) = input.just_pressed("instancing.toggle_occlusion_culling") {
+            println!("Pressed {:?}", key);
+            self.occlusion_culling = !self.occlusion_culling;
+            println!("Occlusion culling: {:?}", self.occlusion_culling);
+            if self.selected_culling != SelectedCulling::Gpu {
+                println!("Only used when culling on the GPU");
+            }
+        }
+        if let Some(key) = input.just_pressed("instancing.step_hiz_level") {
+            println!("Pressed {:?}", key);
+            self.hiz_debug_level = match self.hiz_debug_level {
+                None => Some(0),
+                Some(level) if level + 1 < self.hiz.level_count() => Some(level + 1),
+                Some(_) => None,
+            };
+            println!("Hi-Z debug level: {:?}", self.hiz_debug_level);
+        }
+        if let Some(key) = input.just_pressed("instancing.print_stats") {
+            println!("Pressed {:?}", key);
+            if self.selected_culling == SelectedCulling::Gpu {
+                println!("Opaque instances are culled on the GPU");
+            }
+            println!(
+                "Drawn instances: {} of {} ({} culled)",
+                self.draw_list.len(),
+                self.draw_list.len() + self.culled_instances,
+                self.culled_instances
+            );
+            let (stats, full) = if self.uses_compact_instances() {
+                let buffer = &self.compact_instances_buffer;
+                let size = std::mem::size_of::<CompactInstanceVertex>();
+                (buffer.last_upload(), buffer.len as u64 * size as u64)
+            } else {
+                let buffer = &self.instances_buffer;
+                let size = std::mem::size_of::<InstanceVertex>();
+                (buffer.last_upload(), buffer.len as u64 * size as u64)
+            };
+            println!(
+                "Last instance upload: {} bytes in {} ranges ({:.1}% of {} bytes)",
+                stats.bytes,
+                stats.ranges,
+                100.0 * stats.bytes as f64 / full.max(1) as f64,
+                full
+            );
+        }
+        if let Some(key) = input.just_pressed("instancing.toggle_compact_instances") {
+            println!("Pressed {:?}", key);
+            self.compact_instances = !self.compact_instances;
+            println!("Compact instances: {:?}", self.compact_instances);
+            println!(
+                "Instance size: {} bytes",
+                if self.compact_instances {
+                    std::mem::size_of::<CompactInstanceVertex>()
                 } else {
-                    camera_handled
+                    std::mem::size_of::<InstanceVertex>()
                 }
+            );
+            if self.compact_instances && !self.uses_compact_instances() {
+                println!("Only used on the Cpu backend, without Gpu culling");
             }
-            _ => camera_handled,
         }
-    }
+        if let Some(key) = input.just_pressed("instancing.toggle_depth_prepass") {
+            println!("Pressed {:?}", key);
+            self.depth_prepass = !self.depth_prepass;
+            println!("Depth pre-pass: {:?}", self.depth_prepass);
+        }
+        if let Some(key) = input.just_pressed("instancing.toggle_fly_camera") {
+            println!("Pressed {:?}", key);
+            self.flying = !self.flying;
+            if self.flying {
+                self.fly_camera_controller.look_along(&self.camera);
+            }
+            println!("Fly camera: {:?}", self.flying);
+        }
+        if let Some(key) = input.just_pressed("instancing.remove_instance") {
+            println!("Pressed {:?}", key);
+            self.instance_edits
+                .extend(self.cursor.map(InstanceEdit::RemoveNearest));
+        }
 
-    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue) {
         // Update the camera based on the input state
-        self.camera_controller.update(&mut self.camera);
+        if self.flying {
+            self.fly_camera_controller.update(&mut self.camera, input);
+        } else {
+            self.camera_controller.update(&mut self.camera, input);
+        }
+        self.apply_instance_edits();
+        if input.mouse_just_pressed(MouseButton::Right) {
+            if let Some(cursor) = self.cursor {
+                self.pick(cursor);
+            }
+        }
 
-        // Update the projection buffer based on the camera's updated state
+        // Update the projection buffer based on the camera's updated state,
+        // keeping what the last frame was drawn with around for the Hi-Z test
+        let previous_view_proj = self.camera_uniform.view_proj;
         self.camera_uniform.update(&self.camera);
+        self.grid.update(&self.camera);
 
         // Write directly to the camera's uniform buffer
 
         // This makes Xcode cry
 
-        for (i, instance) in self.instances.iter_mut().enumerate() {
-            let i_x = i % 33;
-            let i_y = (i + 1) / 33;
-            #[inline]
-            fn metaballs(x: usize, y: usize, t: f64) -> f32 {
-                const MIN_DIST: f32 = 1e-3;
-                const RADIUS: f32 = 8.0;
-
-                let cx = ((t / 120.0) + std::f64::consts::PI / 2.0).sin() as f32 * 15.0 + 16.0;
-                let cy = (t / 120.0).sin() as f32 * 15.0 + 16.0;
-
-                let i_vector = cgmath::Vector2::new(x as f32, y as f32);
-                let center = cgmath::Vector2::new(cx, cy);
-                let distance = i_vector.distance(center);
-                ((2.0 * RADIUS) / distance.max(MIN_DIST)).min(8.0)
-            }
-            #[inline]
-            fn double_wave(x: usize, y: usize, t: f64) -> f32 {
-                ((t / 120.0) + (((x + y + 2) as f64) / 4.0)).sin() as f32
-            }
+        let (translucency, image) = (self.selected_translucency, self.selected_image);
+        for (_, (cell, blending, material)) in
+            self.world
+                .query_mut::<(&GridCell, &mut Blending, &mut MaterialHandle)>()
+        {
+            let (i_x, i_y) = cell.animation_coords();
+            *blending = translucency.blending(i_x, i_y);
+            *material = MaterialHandle(image.layer(i_x));
+        }
+
+        // The compute shader takes care of the animation on the GPU path
+        if self.selected_backend == SelectedBackend::Cpu {
+            let (sel, time) = (self.selected_animation, self.time);
+            ecs::animate(&mut self.world, |cell: &GridCell, transform| {
+                transform.set_translation(|t| {
+                    t.z = animated_z(sel, cell.animation_coords(), time);
+                });
+            });
+        }
 
-            let sel = self.selected_animation;
-            let time = self.time;
+        // Sort the instances against the camera so that translucent ones
+        // can be blended back-to-front. On the GPU path the CPU-side
+        // transforms don't carry the animated z, which is close enough
+        // for ordering purposes
+        // Culled instances don't even make it to the draw list, which leaves
+        // the survivors packed at the start of the instance buffer. On the
+        // GPU path the CPU-side transforms are at rest, so their bounds have
+        // to cover however far the animations move them. When culling on the
+        // GPU, everything makes it to the draw list, and the cull pass picks
+        // the opaque instances to draw out of it
+        let frustum = self.camera.frustum();
+        let animated = self.selected_backend == SelectedBackend::Gpu;
+        let culling = match self.selected_culling {
+            SelectedCulling::Cpu => Some(&frustum),
+            SelectedCulling::None | SelectedCulling::Gpu => None,
+        };
+        self.culled_instances = ecs::cull(&self.world, culling, |mut world| {
+            if animated {
+                world.min.z += ANIMATION_Z_RANGE.0;
+                world.max.z += ANIMATION_Z_RANGE.1;
+            }
+            world
+        });
+        ecs::build_draw_list(&self.world, &mut self.draw_list, self.camera.eye);
+
+        // Only live instances get drawn, packed at the start of the buffer.
+        // They are all uploaded or recomputed every frame, so there's nothing
+        // worth copying over when it grows
+        let live = self.draw_list.len() as u32;
+        let compact = self.uses_compact_instances();
+        if compact {
+            grow_instances(device, queue, &mut self.compact_instances_buffer, live);
+        } else if grow_instances(device, queue, &mut self.instances_buffer, live) {
+            self.animation_pass.resize(device, &self.instances_buffer);
+            self.cull_pass
+                .resize(device, &self.instances_buffer, &self.hiz);
+        }
 
-            instance.transform.set_translation(|t| match sel {
-                SelectedAnimation::DoubleWave => {
-                    t.z = double_wave(i_x, i_y, time);
+        // Stage the instances in draw order, so that every blending mode ends
+        // up in its own contiguous range of the buffer. Only the ones that
+        // moved or changed slots get uploaded
+        if compact {
+            for (slot, &entity) in self.draw_list.iter().enumerate() {
+                if let Some(instance) = InstanceData::of(&self.world, entity) {
+                    self.compact_instances_buffer.set(slot as u32, &instance);
                 }
-                SelectedAnimation::Metaball => {
-                    t.z = metaballs(i_x, i_y, time);
+            }
+        } else if self.selected_backend == SelectedBackend::Cpu {
+            for (slot, &entity) in self.draw_list.iter().enumerate() {
+                if let Some(instance) = InstanceData::of(&self.world, entity) {
+                    self.instances_buffer.set(slot as u32, &instance);
                 }
-            });
+            }
+        } else {
+            self.instances_buffer.invalidate();
+        }
+
+        // Draws are sorted by distance already, so instances sharing a level
+        // of detail mostly end up next to each other
+        self.lod_draws.opaque.clear();
+        self.lod_draws.translucent.clear();
+        let opaque_len = self.draw_list.opaque_len();
+        for (slot, &entity) in self.draw_list.iter().enumerate() {
+            let t = match self.world.get::<Transform>(entity) {
+                Ok(transform) => *transform.translation(),
+                Err(_) => continue,
+            };
+            let distance = cgmath::Point3::from_vec(t).distance(self.camera.eye);
+            let level = self.mesh().lod().select(distance);
+            let runs = if slot < opaque_len {
+                &mut self.lod_draws.opaque
+            } else {
+                &mut self.lod_draws.translucent
+            };
+            LodDraws::push(runs, level, slot as u32);
+        }
+
+        if self.selected_backend == SelectedBackend::Gpu {
+            self.animation_pass.update(
+                self.time,
+                self.selected_animation,
+                &self.draw_list,
+                &self.world,
+            );
+        }
 
-            /*self.instances_buffer
-            .copy_instance(queue, instance, i as wgpu::BufferAddress);*/
+        if self.selected_culling == SelectedCulling::Gpu {
+            let occlusion = if self.occlusion_culling && self.depth_history {
+                Some((previous_view_proj, &self.hiz))
+            } else {
+                None
+            };
+            self.cull_pass
+                .update(&frustum, self.draw_list.opaque_len() as u32, occlusion);
+        }
+
+        // On the GPU path these are the hexagons' resting positions, as the
+        // CPU never sees the animated ones. Selected ones get white boxes
+        self.gizmos.clear();
+        self.gizmos.update(&self.camera);
+        let aabb = self.mesh().bounds().aabb;
+        if self.show_bounds {
+            for (_, (transform, selected)) in
+                self.world.query::<(&Transform, Option<&Selected>)>().iter()
+            {
+                let world = aabb.transformed(transform.model_matrix());
+                let color = if selected.is_some() {
+                    [1.0, 1.0, 1.0]
+                } else {
+                    [1.0, 0.9, 0.2]
+                };
+                self.gizmos.aabb(&world, color);
+            }
+        }
+
+        // Selected hexagons get outlined wherever they're animated to
+        self.outline.clear();
+        self.outline.update(&self.camera);
+        let center = aabb.center();
+        let selected = self
+            .world
+            .query::<(&Transform, &GridCell)>()
+            .with::<Selected>()
+            .iter()
+            .map(|(_, (transform, cell))| self.animated_model_matrix(transform, cell))
+            .collect::<Vec<_>>();
+        for model in selected.iter() {
+            self.outline.push(model, center);
         }
 
-        self.time += 1.0;
+        self.time += time.ticks() as f64;
     }
 
     //fn recall(&mut self) {}
 
+    fn compute(&mut self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        // Instances have to be in place before the cull pass reads them
+        if self.uses_compact_instances() {
+            let mut instance_stager = staging.fetch_stager(self.instance_belt);
+            self.compact_instances_buffer
+                .upload_dirty(encoder, &mut instance_stager);
+        } else if self.selected_backend == SelectedBackend::Cpu {
+            let mut instance_stager = staging.fetch_stager(self.instance_belt);
+            self.instances_buffer
+                .upload_dirty(encoder, &mut instance_stager);
+        } else {
+            self.animation_pass.compute(encoder, staging);
+        }
+
+        // Out of the depth buffer as the last frame left it
+        if self.needs_depth_history() && self.depth_history {
+            self.hiz.build(encoder);
+        }
+
+        if self.selected_culling == SelectedCulling::Gpu {
+            self.cull_pass.compute(encoder, staging);
+        }
+    }
+
     fn render(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
@@ -825,33 +2606,49 @@ impl Scene for InstancesScene {
         state: &crate::GlobalState,
         staging: &StagingFactory,
     ) -> Result<(), wgpu::SurfaceError> {
-        let mut camera_stager = staging.fetch_stager(CAMERA_BELT);
-        camera_stager.write_buffer(
-            encoder,
-            &self.camera_uniform_buffer,
-            0,
-            bytemuck::bytes_of(&self.camera_uniform),
-        );
+        let mut camera_stager = staging.fetch_stager(self.camera_belt);
+        self.camera_uniform_buffer
+            .update(&mut camera_stager, encoder, &self.camera_uniform);
 
-        let potential_size = NonZeroU64::new(
-            self.instances.len() as wgpu::BufferAddress
-                * self.instances_buffer.descriptor().array_stride,
-        );
-        if let Some(size) = potential_size {
-            let mut instance_stager = staging.fetch_stager(INSTANCE_BELT);
-            let mut staging_buffer = instance_stager.create_staging_area(
-                encoder,
-                &self.instances_buffer.buffer,
-                0,
-                size,
-            );
-            for (i, instance) in self.instances.iter().enumerate() {
-                self.instances_buffer
-                    .copy_instance_into_view(&mut staging_buffer, instance, i);
-            }
+        if self.show_grid {
+            self.grid.upload(encoder, staging);
+        }
+        if self.show_bounds {
+            self.gizmos.upload(encoder, staging);
+        }
+        if !self.outline.is_empty() {
+            self.outline.upload(encoder, staging);
         }
 
-        {
+        // Whatever was stored this frame is what the next one builds its
+        // Hi-Z pyramid from
+        let hiz_built = self.depth_history && self.needs_depth_history();
+        self.depth_history = self.needs_depth_history();
+
+        // Uploads went on the scene's encoder, which gets submitted first.
+        // The pre-pass, main pass and outlines don't depend on each other's
+        // encoding, so each gets an encoder of its own
+        let scene = &*self;
+        let mut passes: Vec<Pass> = Vec::new();
+        if scene.depth_prepass {
+            passes.push(Box::new(move |encoder: &mut wgpu::CommandEncoder| {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Instancing - Depth Pre-Pass"),
+                    color_attachments: &[],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &scene.depth_pass.texture.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    }),
+                });
+                scene.render_opaque(&mut render_pass, &scene.depth_prepass_pipelines);
+            }));
+        }
+
+        passes.push(Box::new(move |encoder: &mut wgpu::CommandEncoder| {
             let rp_desc = &wgpu::RenderPassDescriptor {
                 label: Some("Instancing - Render Pass Descriptor"),
                 color_attachments: &[wgpu::RenderPassColorAttachment {
@@ -863,33 +2660,78 @@ impl Scene for InstancesScene {
                     },
                 }],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_pass.texture.view,
+                    view: &scene.depth_pass.texture.view,
                     depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: self.selected_pass == SelectedExtraPass::Depth,
+                        load: if scene.depth_prepass {
+                            wgpu::LoadOp::Load
+                        } else {
+                            wgpu::LoadOp::Clear(1.0)
+                        },
+                        store: scene.selected_pass == SelectedExtraPass::Depth
+                            || scene.needs_depth_history(),
                     }),
                     stencil_ops: None,
                 }),
             };
 
+            let compact = scene.uses_compact_instances();
             let mut render_pass = encoder.begin_render_pass(rp_desc);
-            render_pass.set_pipeline(&self.pipeline);
+            scene.render_opaque(
+                &mut render_pass,
+                if scene.depth_prepass {
+                    &scene.equal_depth_pipelines
+                } else {
+                    &scene.opaque_pipelines
+                },
+            );
 
-            let selected_bind_group = match self.selected_image {
-                SelectedImage::SanCheese => &self.diffuse1_bind_group,
-                SelectedImage::Nnubes => &self.diffuse2_bind_group,
-            };
-            render_pass.set_bind_group(0, selected_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+            // The grid is see-through, so it goes after the opaque instances
+            // but before the translucent ones
+            if scene.show_grid {
+                scene.grid.render(&mut render_pass);
+                render_pass.set_bind_group(0, &scene.diffuse_bind_group, &[]);
+                render_pass.set_bind_group(1, &scene.uniform_bind_group, &[]);
+            }
 
-            self.epic_mesh
-                .render(&mut render_pass, Some(&self.instances_buffer));
-        }
+            render_pass.set_pipeline(if compact {
+                &scene.compact_translucent_pipeline
+            } else {
+                &scene.translucent_pipeline
+            });
+            scene.render_lod_runs(&mut render_pass, &scene.lod_draws.translucent);
 
-        match self.selected_pass {
-            SelectedExtraPass::Depth => self.depth_pass.render(encoder, frame_view, state),
-            SelectedExtraPass::None => Ok(()),
+            if scene.show_bounds {
+                scene.gizmos.render(&mut render_pass);
+            }
+        }));
+
+        if !scene.outline.is_empty() {
+            passes.push(Box::new(move |encoder: &mut wgpu::CommandEncoder| {
+                scene
+                    .outline
+                    .render(encoder, frame_view, scene.mesh().lod().level(0));
+            }));
         }
+        state.parallel.encode("Instancing - Passes", passes);
+
+        // The debug views write to their own uniforms, so they're encoded
+        // once the passes above are done borrowing the scene
+        let mut result = Ok(());
+        let (hiz, depth_pass) = (&mut self.hiz, &mut self.depth_pass);
+        let (hiz_debug_level, selected_pass) = (self.hiz_debug_level, self.selected_pass);
+        let result_ref = &mut result;
+        let debug: Pass = Box::new(move |encoder: &mut wgpu::CommandEncoder| {
+            if let (Some(level), true) = (hiz_debug_level, hiz_built) {
+                hiz.render_debug(encoder, frame_view, staging, level);
+            }
+
+            *result_ref = match selected_pass {
+                SelectedExtraPass::Depth => depth_pass.render(encoder, frame_view, state, staging),
+                SelectedExtraPass::None => Ok(()),
+            };
+        });
+        state.parallel.encode("Instancing - Debug", vec![debug]);
+        result
     }
 
     fn resize(
@@ -899,7 +2741,13 @@ impl Scene for InstancesScene {
         size: winit::dpi::PhysicalSize<u32>,
     ) {
         self.camera.aspect = size.width as f32 / size.height as f32;
+        self.screen_size = (size.width, size.height);
 
         self.depth_pass.resize(device, size);
+        self.outline.resize(device, size);
+        self.hiz.resize(device, size, &self.depth_pass.texture);
+        self.cull_pass
+            .resize(device, &self.instances_buffer, &self.hiz);
+        self.depth_history = false;
     }
 }
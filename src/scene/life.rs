@@ -0,0 +1,507 @@
+use std::{collections::VecDeque, num::NonZeroU32};
+
+use winit::event::MouseButton;
+
+use crate::{
+    assets::Assets,
+    buffer::{StagerHandle, StagingFactory, Uniformable},
+    cache::{LayoutCache, PipelineCache},
+    clock::Time,
+    input::Input,
+    layout::Std140,
+    shader::shaders,
+    texture::SamplerDesc,
+};
+
+use super::Scene;
+
+const GRID_WIDTH: u32 = 192;
+const GRID_HEIGHT: u32 = 108;
+const WORKGROUP_SIZE: u32 = 8;
+/// Generations simulated per second
+const STEP_RATE: f32 = 20.0;
+const CELL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct LifeParamsUniform {
+    /// Cell flipped by the dispatch, `NO_TOGGLE` if none
+    toggle: [i32; 2],
+    /// 0 to only apply the toggle, without simulating a generation
+    step: u32,
+    #[padding]
+    _padding: u32,
+}
+
+const NO_TOGGLE: [i32; 2] = [-1, -1];
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct ViewportUniform {
+    size: [f32; 2],
+    #[padding]
+    _padding: [f32; 2],
+}
+
+/// Xorshift, returns either a dead (0) or alive (1) cell
+fn random_cell(state: &mut u32) -> u32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    // Roughly a quarter of the cells start out alive
+    (*state >> 30 == 0) as u32
+}
+
+pub struct LifeScene {
+    cells: [wgpu::Texture; 2],
+    /// `step_bind_groups[i]` reads `cells[i]` and writes the other one
+    step_bind_groups: [wgpu::BindGroup; 2],
+    /// `render_bind_groups[i]` displays `cells[i]`
+    render_bind_groups: [wgpu::BindGroup; 2],
+    /// Index of the texture holding the current generation
+    current: usize,
+    compute_pipeline: wgpu::ComputePipeline,
+    render_pipeline: wgpu::RenderPipeline,
+    params_buffer: wgpu::Buffer,
+    viewport: ViewportUniform,
+    viewport_buffer: wgpu::Buffer,
+    reseed: bool,
+    rng: u32,
+    /// Simulation time since the last step
+    since_step: f32,
+    step_pending: bool,
+    toggles: VecDeque<[i32; 2]>,
+    cursor: Option<[i32; 2]>,
+    params_belt: StagerHandle,
+    viewport_belt: StagerHandle,
+}
+
+impl LifeScene {
+    /// Grid cell under the given window position, if any
+    fn cell_at(&self, x: f64, y: f64) -> Option<[i32; 2]> {
+        let [width, height] = self.viewport.size;
+        let u = x / width as f64;
+        let v = y / height as f64;
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+
+        Some([
+            (u * GRID_WIDTH as f64) as i32,
+            (v * GRID_HEIGHT as f64) as i32,
+        ])
+    }
+
+    fn seed(&mut self, queue: &wgpu::Queue) {
+        let rng = &mut self.rng;
+        let cells = (0..GRID_WIDTH * GRID_HEIGHT)
+            .map(|_| random_cell(rng))
+            .collect::<Vec<u32>>();
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.cells[self.current],
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&cells),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * GRID_WIDTH),
+                rows_per_image: NonZeroU32::new(GRID_HEIGHT),
+            },
+            wgpu::Extent3d {
+                width: GRID_WIDTH,
+                height: GRID_HEIGHT,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+impl Scene for LifeScene {
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        assets: &Assets,
+    ) -> Self {
+        let comp_module = shaders::LIFE_COMP.create_module(device);
+        let vert_module = assets.shader(device, &shaders::FULLSCREEN_VERT).get();
+        let frag_module = shaders::LIFE_FRAG.create_module(device);
+
+        let create_cells = |label| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: GRID_WIDTH,
+                    height: GRID_HEIGHT,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: CELL_FORMAT,
+                usage: wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST,
+            })
+        };
+        let cells = [
+            create_cells("Life - Cells Texture A"),
+            create_cells("Life - Cells Texture B"),
+        ];
+        let cell_views = [
+            cells[0].create_view(&wgpu::TextureViewDescriptor::default()),
+            cells[1].create_view(&wgpu::TextureViewDescriptor::default()),
+        ];
+
+        let params = LifeParamsUniform {
+            toggle: NO_TOGGLE,
+            step: 0,
+            _padding: 0,
+        };
+        let params_buffer = params.create_buffer(device, Some("Life - Params Buffer"));
+        let params_belt = staging.create_stager(
+            "life.params",
+            std::mem::size_of::<LifeParamsUniform>() as u64,
+        );
+
+        let viewport = ViewportUniform {
+            size: [sc.width as f32, sc.height as f32],
+            _padding: [0.0; 2],
+        };
+        let viewport_buffer = viewport.create_buffer(device, Some("Life - Viewport Buffer"));
+        let viewport_belt = staging.create_stager(
+            "life.viewport",
+            std::mem::size_of::<ViewportUniform>() as u64,
+        );
+
+        let step_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Life - Step Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::ReadOnly,
+                            format: CELL_FORMAT,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: CELL_FORMAT,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let create_step_bind_group = |label, from: &wgpu::TextureView, to: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout: &step_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(from),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(to),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let step_bind_groups = [
+            create_step_bind_group(
+                "Life - Step Bind Group A to B",
+                &cell_views[0],
+                &cell_views[1],
+            ),
+            create_step_bind_group(
+                "Life - Step Bind Group B to A",
+                &cell_views[1],
+                &cell_views[0],
+            ),
+        ];
+
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Life - Compute Pipeline Layout"),
+                bind_group_layouts: &[&step_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Life - Compute Pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &comp_module,
+            entry_point: "main",
+        });
+
+        let cell_sampler = assets.sampler(device, &SamplerDesc::nearest()).get();
+
+        let render_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Life - Render Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Uint,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: false,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let create_render_bind_group = |label, cells: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout: &render_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(cells),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&cell_sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: viewport_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let render_bind_groups = [
+            create_render_bind_group("Life - Render Bind Group A", &cell_views[0]),
+            create_render_bind_group("Life - Render Bind Group B", &cell_views[1]),
+        ];
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Life - Render Pipeline Layout"),
+                bind_group_layouts: &[&render_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Life - Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        let mut scene = Self {
+            cells,
+            step_bind_groups,
+            render_bind_groups,
+            current: 0,
+            compute_pipeline,
+            render_pipeline,
+            params_buffer,
+            viewport,
+            viewport_buffer,
+            reseed: false,
+            rng: 0x9E37_79B9,
+            since_step: 0.0,
+            step_pending: false,
+            toggles: VecDeque::new(),
+            cursor: None,
+            params_belt,
+            viewport_belt,
+        };
+        scene.seed(queue);
+
+        scene
+    }
+
+    fn destroy(&mut self) {
+        for cells in self.cells.iter() {
+            cells.destroy();
+        }
+    }
+
+    fn update(&mut self, _device: &wgpu::Device, queue: &wgpu::Queue, input: &Input, time: Time) {
+        if let Some(key) = input.just_pressed("life.reseed") {
+            println!("Pressed {:?}", key);
+            self.reseed = true;
+        }
+
+        // Paint over every new cell the cursor drags through
+        let cell = input.cursor().and_then(|(x, y)| self.cell_at(x, y));
+        if input.mouse_just_pressed(MouseButton::Left)
+            || (input.mouse_pressed(MouseButton::Left) && cell != self.cursor)
+        {
+            self.toggles.extend(cell);
+        }
+        self.cursor = cell;
+
+        if self.reseed {
+            self.reseed = false;
+            self.seed(queue);
+        }
+
+        self.since_step += time.delta;
+        if self.since_step >= 1.0 / STEP_RATE {
+            self.since_step = 0.0;
+            self.step_pending = true;
+        }
+    }
+
+    fn compute(&mut self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        // One toggle per dispatch, so fast strokes might take a few frames
+        // to show up entirely
+        let toggle = self.toggles.pop_front();
+        if !self.step_pending && toggle.is_none() {
+            return;
+        }
+
+        let params = LifeParamsUniform {
+            toggle: toggle.unwrap_or(NO_TOGGLE),
+            step: self.step_pending as u32,
+            _padding: 0,
+        };
+        self.step_pending = false;
+
+        let mut params_stager = staging.fetch_stager(self.params_belt);
+        params_stager.write(encoder, &self.params_buffer, 0, &params);
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Life - Step Pass"),
+            });
+            compute_pass.set_pipeline(&self.compute_pipeline);
+            compute_pass.set_bind_group(0, &self.step_bind_groups[self.current], &[]);
+            compute_pass.dispatch(
+                GRID_WIDTH.div_ceil(WORKGROUP_SIZE),
+                GRID_HEIGHT.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+
+        // The next generation becomes the current one
+        self.current = 1 - self.current;
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut viewport_stager = staging.fetch_stager(self.viewport_belt);
+        viewport_stager.write(encoder, &self.viewport_buffer, 0, &self.viewport);
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("Life - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        };
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.render_bind_groups[self.current], &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.viewport.size = [size.width as f32, size.height as f32];
+    }
+}
@@ -0,0 +1,307 @@
+use crate::{
+    assets::Assets,
+    buffer::{StagerHandle, StagingFactory, Uniformable, Uploader, VertexBuffer},
+    cache::{LayoutCache, PipelineCache},
+    camera::{Camera, CameraController, CameraUniform, Projection},
+    clock::Time,
+    input::Input,
+    lsystem::LSystem,
+    shader::shaders,
+    texture::DepthTexture,
+    vertex::{Descriptable, FlatVertex},
+};
+
+use super::Scene;
+
+const PLANT_HEIGHT: f32 = 4.0;
+
+const TREE: LSystem = LSystem {
+    axiom: "X",
+    rules: &[('X', "F[&+X][&-X]/[^X]FX"), ('F', "FF")],
+    angle: 25.0,
+};
+
+const BUSH: LSystem = LSystem {
+    axiom: "F",
+    rules: &[('F', "FF-[-F+F+F]+[+F-F-F]&[&F^F^F]")],
+    angle: 22.5,
+};
+
+#[derive(Copy, Clone, Debug)]
+enum SelectedPlant {
+    Tree,
+    Bush,
+}
+
+impl SelectedPlant {
+    fn system(&self) -> &'static LSystem {
+        match self {
+            SelectedPlant::Tree => &TREE,
+            SelectedPlant::Bush => &BUSH,
+        }
+    }
+
+    /// Past this, the amount of geometry gets silly
+    fn max_iterations(&self) -> u32 {
+        match self {
+            SelectedPlant::Tree => 7,
+            SelectedPlant::Bush => 4,
+        }
+    }
+}
+
+pub struct LSystemScene {
+    render_pipeline: wgpu::RenderPipeline,
+    selected_plant: SelectedPlant,
+    iterations: u32,
+    /// Set whenever the plant needs to be regrown
+    rebuild: bool,
+    /// `None` until the first rebuild, or if the plant has no segments
+    vertex_buffer: Option<VertexBuffer<FlatVertex>>,
+    /// Plants get uploaded through it, rather than an init buffer each
+    uploader: Uploader,
+    camera: Camera,
+    camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    camera_belt: StagerHandle,
+}
+
+impl Scene for LSystemScene {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        _assets: &Assets,
+    ) -> Self {
+        let vert_module = shaders::LSYSTEM_VERT.create_module(device);
+        let frag_module = shaders::DIMA_FRAG.create_module(device);
+
+        let camera = Camera {
+            eye: (0.0, 2.5, 8.0).into(),
+            target: (0.0, 2.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: sc.width as f32 / sc.height as f32,
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 100.0,
+        };
+
+        let camera_controller = CameraController::new(12.0);
+
+        let mut camera_uniform = CameraUniform::default();
+        camera_uniform.update(&camera);
+        let camera_belt = staging.create_stager(
+            "lsystem.camera",
+            std::mem::size_of::<CameraUniform>() as u64,
+        );
+
+        let camera_uniform_buf =
+            camera_uniform.create_buffer(device, Some("L-System - Camera Uniform Buffer"));
+
+        let camera_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("L-System - Camera Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("L-System - Camera Bind Group"),
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_uniform_buf.as_entire_binding(),
+            }],
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("L-System - Render Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("L-System - Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[FlatVertex::descriptor()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DepthTexture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            render_pipeline,
+            selected_plant: SelectedPlant::Tree,
+            iterations: 4,
+            rebuild: true,
+            vertex_buffer: None,
+            uploader: staging.uploader(),
+            camera,
+            camera_controller,
+            camera_uniform,
+            camera_uniform_buffer: camera_uniform_buf,
+            camera_bind_group,
+            camera_belt,
+        }
+    }
+
+    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, input: &Input, _time: Time) {
+        if let Some(key) = input.just_pressed("lsystem.switch_plant") {
+            println!("Pressed {:?}", key);
+            self.selected_plant = match self.selected_plant {
+                SelectedPlant::Tree => SelectedPlant::Bush,
+                SelectedPlant::Bush => SelectedPlant::Tree,
+            };
+            self.iterations = self.iterations.min(self.selected_plant.max_iterations());
+            self.rebuild = true;
+            println!("{:?}", self.selected_plant);
+        }
+        if let Some(key) = input.just_pressed("lsystem.fewer_iterations") {
+            println!("Pressed {:?}", key);
+            self.iterations = self.iterations.saturating_sub(1);
+            self.rebuild = true;
+            println!("Iterations: {}", self.iterations);
+        }
+        if let Some(key) = input.just_pressed("lsystem.more_iterations") {
+            println!("Pressed {:?}", key);
+            self.iterations = (self.iterations + 1).min(self.selected_plant.max_iterations());
+            self.rebuild = true;
+            println!("Iterations: {}", self.iterations);
+        }
+
+        if self.rebuild {
+            self.rebuild = false;
+
+            let system = self.selected_plant.system();
+            let expanded = system.expand(self.iterations);
+            let vertices = system.to_lines(&expanded, PLANT_HEIGHT);
+            println!(
+                "Grew {:?}: {} symbols, {} segments",
+                self.selected_plant,
+                expanded.len(),
+                vertices.len() / 2
+            );
+
+            // The vertex count changes with every rebuild, so the buffer
+            // gets recreated rather than written to
+            self.vertex_buffer = if vertices.is_empty() {
+                None
+            } else {
+                Some(self.uploader.upload(queue, |encoder, stager| {
+                    VertexBuffer::from_vertices_staged(
+                        device,
+                        encoder,
+                        stager,
+                        &vertices,
+                        Some("L-System - Vertex Buffer"),
+                    )
+                }))
+            };
+        }
+
+        self.camera_controller.update(&mut self.camera, input);
+        self.camera_uniform.update(&self.camera);
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut camera_stager = staging.fetch_stager(self.camera_belt);
+        camera_stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("L-System - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &state.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        };
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        if let Some(vertex_buffer) = &self.vertex_buffer {
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertex_buffer.buffer.slice(..));
+            render_pass.draw(0..vertex_buffer.len, 0..1);
+        }
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+    }
+}
@@ -0,0 +1,846 @@
+use std::sync::Arc;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::{
+    animation::{AnimationPlayer, NodeTransform, NodeTree},
+    assets::{Assets, Handle},
+    buffer::{StagerHandle, StagingFactory, Uniformable, Uploader},
+    cache::{LayoutCache, PipelineCache},
+    camera::{Camera, CameraController, CameraUniform, Projection},
+    clock::Time,
+    environment::{EnvironmentMap, SkyboxPass},
+    input::Input,
+    layout::Std140,
+    mesh::{primitives, Aabb, CpuMesh, Welder},
+    model::{
+        CompactModelVertex, DrawData, MaterialData, MeshData, Model, ModelData, ModelSlabs,
+        ModelVertex, PrimitiveData, SkinnedVertex,
+    },
+    scene::instancing::InstanceVertex,
+    shader::shaders,
+    texture::DepthTexture,
+    vertex::{Descriptable, TexturedVertex},
+};
+
+use super::Scene;
+
+const VERTICES_1: &[TexturedVertex] = &[
+    // 0
+    TexturedVertex {
+        position: [0.0, 0.0, 0.5],
+        tex_coords: [0.5, 0.5],
+    },
+    TexturedVertex {
+        position: [-0.5, 0.0, 0.5],
+        tex_coords: [0.0, 0.5],
+    },
+    TexturedVertex {
+        position: [-0.25, -0.5, 0.5],
+        tex_coords: [0.25, 1.0],
+    },
+    TexturedVertex {
+        position: [0.25, -0.5, 0.5],
+        tex_coords: [0.75, 1.0],
+    },
+    TexturedVertex {
+        position: [0.5, 0.0, 0.5],
+        tex_coords: [1.0, 0.5],
+    },
+    TexturedVertex {
+        position: [0.25, 0.5, 0.5],
+        tex_coords: [0.75, 0.0],
+    },
+    TexturedVertex {
+        position: [-0.25, 0.5, 0.5],
+        tex_coords: [0.25, 0.0],
+    },
+    // 7
+    TexturedVertex {
+        position: [0.0, 0.0, -0.5],
+        tex_coords: [0.5, 0.5],
+    },
+    TexturedVertex {
+        position: [-0.5, 0.0, -0.5],
+        tex_coords: [1.0, 0.5],
+    },
+    TexturedVertex {
+        position: [-0.25, -0.5, -0.5],
+        tex_coords: [0.75, 1.0],
+    },
+    TexturedVertex {
+        position: [0.25, -0.5, -0.5],
+        tex_coords: [0.25, 1.0],
+    },
+    TexturedVertex {
+        position: [0.5, 0.0, -0.5],
+        tex_coords: [0.0, 0.5],
+    },
+    TexturedVertex {
+        position: [0.25, 0.5, -0.5],
+        tex_coords: [0.25, 0.0],
+    },
+    TexturedVertex {
+        position: [-0.25, 0.5, -0.5],
+        tex_coords: [0.75, 0.0],
+    },
+];
+
+#[rustfmt::skip]
+const INDICES_1: &[u16] = &[
+    0, 1, 2,
+    0, 2, 3,
+    0, 3, 4,
+    0, 4, 5,
+    0, 5, 6,
+    0, 6, 1,
+    7, 9, 8,
+    7, 10, 9,
+    7, 11, 10,
+    7, 12, 11,
+    7, 13, 12,
+    7, 8, 13,
+    1, 8, 9,
+    1, 8, 2,
+    2, 9, 10,
+    2, 9, 3,
+    3, 10, 11,
+    3, 10, 4,
+    4, 11, 12,
+    4, 11, 5,
+    5, 12, 13,
+    5, 12, 6,
+    6, 13, 8,
+    6, 13, 1
+];
+
+/// Loaded through `Assets`, out of `assets/`
+const MODELS: &[&str] = &["pedestal.gltf", "tentacle.gltf"];
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct LightUniform {
+    eye: [f32; 4],
+    /// Towards the light, not away from it
+    direction: [f32; 4],
+}
+
+/// The same hexagon the other scenes draw, which has no normals or tangents of
+/// its own, standing next to the pedestal. Doesn't need the `models` feature.
+///
+/// It gets unrolled into a triangle soup and welded back together first, the
+/// way a loader for a format without indices would have to
+fn hexagon() -> anyhow::Result<ModelData> {
+    let soup = INDICES_1
+        .iter()
+        .map(|&i| VERTICES_1[i as usize])
+        .collect::<Vec<_>>();
+    let mut welder = Welder::new(1e-5);
+    welder.extend(&soup);
+    println!("Hexagon: {}", welder.stats());
+    let (welded, indices) = welder.finish();
+
+    let mut hexagon = CpuMesh {
+        vertices: welded
+            .iter()
+            .map(|v| ModelVertex {
+                position: v.position,
+                tex_coords: v.tex_coords,
+                normal: [0.0, 0.0, 0.0],
+                tangent: [0.0, 0.0, 0.0, 0.0],
+            })
+            .collect::<Vec<_>>(),
+        indices,
+    };
+    hexagon.compute_normals();
+    hexagon.compute_tangents();
+
+    let image = image::load_from_memory(include_bytes!("../../assets/sanCheese.png"))?;
+    let node = NodeTransform {
+        translation: Vector3::new(-2.2, 0.3, 0.0),
+        rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+        scale: Vector3::new(1.0, 1.0, 1.0),
+    };
+
+    Ok(ModelData {
+        meshes: vec![MeshData {
+            name: Some("Model - Hexagon".to_owned()),
+            primitives: vec![PrimitiveData {
+                mesh: hexagon,
+                skin_weights: None,
+                material: 0,
+            }],
+        }],
+        materials: vec![
+            MaterialData {
+                base_color_texture: Some(0),
+                metallic_factor: 0.0,
+                roughness_factor: 0.5,
+                ..Default::default()
+            },
+            MaterialData::default(),
+        ],
+        images: vec![image.to_rgba8()],
+        draws: vec![DrawData {
+            mesh: 0,
+            node: 0,
+            transform: node.matrix(),
+        }],
+        nodes: NodeTree {
+            rest: vec![node],
+            children: vec![Vec::new()],
+            roots: vec![0],
+        },
+        skins: Vec::new(),
+        skinned_draws: Vec::new(),
+        animations: Vec::new(),
+    })
+}
+
+/// A row of generated primitives behind everything else, going from rough
+/// dielectrics to smooth metals. Doesn't need the `models` feature either
+fn primitive_shapes() -> anyhow::Result<ModelData> {
+    let shapes = vec![
+        ("Model - Plane", primitives::plane(1.0, 3)),
+        ("Model - Cube", primitives::cube(0.8, 0)),
+        ("Model - UV Sphere", primitives::uv_sphere(0.5, 32, 16)),
+        ("Model - Icosphere", primitives::icosphere(0.5, 3)),
+        ("Model - Cylinder", primitives::cylinder(0.4, 0.9, 32)),
+        ("Model - Torus", primitives::torus(0.35, 0.15, 32, 16)),
+    ];
+    let count = shapes.len();
+
+    let mut meshes = Vec::with_capacity(count);
+    let mut materials = Vec::with_capacity(count + 1);
+    let mut draws = Vec::with_capacity(count);
+    let mut rest = Vec::with_capacity(count);
+    for (i, (name, geometry)) in shapes.into_iter().enumerate() {
+        let t = i as f32 / (count - 1) as f32;
+        meshes.push(MeshData {
+            name: Some(name.to_owned()),
+            primitives: vec![PrimitiveData {
+                mesh: CpuMesh {
+                    vertices: geometry.vertices,
+                    indices: geometry.indices.iter().map(|&i| u32::from(i)).collect(),
+                },
+                skin_weights: None,
+                material: i,
+            }],
+        });
+        materials.push(MaterialData {
+            base_color_factor: [0.9 - 0.3 * t, 0.5, 0.2 + 0.6 * t, 1.0],
+            metallic_factor: t,
+            roughness_factor: 0.9 - 0.7 * t,
+            ..Default::default()
+        });
+
+        let node = NodeTransform {
+            translation: Vector3::new((i as f32 - (count - 1) as f32 * 0.5) * 1.2, 0.5, -2.0),
+            rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        };
+        draws.push(DrawData {
+            mesh: i,
+            node: i,
+            transform: node.matrix(),
+        });
+        rest.push(node);
+    }
+    materials.push(MaterialData::default());
+
+    Ok(ModelData {
+        meshes,
+        materials,
+        images: Vec::new(),
+        draws,
+        nodes: NodeTree {
+            children: vec![Vec::new(); count],
+            roots: (0..count).collect(),
+            rest,
+        },
+        skins: Vec::new(),
+        skinned_draws: Vec::new(),
+        animations: Vec::new(),
+    })
+}
+
+/// Uploads a model, saying what went into it
+#[allow(clippy::too_many_arguments)]
+fn load(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    uploader: &Uploader,
+    slabs: &mut ModelSlabs,
+    name: &str,
+    data: &ModelData,
+    material_layout: &wgpu::BindGroupLayout,
+    joint_layout: &wgpu::BindGroupLayout,
+) -> anyhow::Result<(Model, Option<Aabb>)> {
+    println!(
+        "Loaded {}: {} meshes, {} materials, {} images, {} draws, {} skinned draws, {} animations",
+        name,
+        data.meshes.len(),
+        data.materials.len(),
+        data.images.len(),
+        data.draws.len(),
+        data.skinned_draws.len(),
+        data.animations.len()
+    );
+    for mesh in data.meshes.iter() {
+        println!(
+            "  Mesh {}: {} primitives",
+            mesh.name.as_deref().unwrap_or("unnamed"),
+            mesh.primitives.len()
+        );
+    }
+    let model = Model::new(
+        device,
+        queue,
+        uploader,
+        slabs,
+        data,
+        material_layout,
+        joint_layout,
+    )?;
+    Ok((model, data.aabb()))
+}
+
+pub struct ModelScene {
+    render_pipeline: wgpu::RenderPipeline,
+    compact_render_pipeline: wgpu::RenderPipeline,
+    skinned_render_pipeline: wgpu::RenderPipeline,
+    /// Whether unskinned meshes are drawn out of `CompactModelVertex`es
+    compact_vertices: bool,
+    /// Whichever models could be loaded
+    models: Vec<Model>,
+    /// The ones out of files, by index into `models`, to upload again when
+    /// their files change
+    model_files: Vec<(usize, Handle<ModelData>)>,
+    /// Uploads models, including the ones reloaded out of their files
+    uploader: Uploader,
+    /// What every model's vertices, indices and material uniforms are
+    /// allocated out of
+    slabs: ModelSlabs,
+    material_bind_group_layout: wgpu::BindGroupLayout,
+    joint_bind_group_layout: wgpu::BindGroupLayout,
+    player: AnimationPlayer,
+    camera: Camera,
+    camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    light_uniform: LightUniform,
+    light_uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    skybox: SkyboxPass,
+    /// The HDR sky, and a cube map out of six debug faces
+    environments: Vec<EnvironmentMap>,
+    environment: usize,
+    environment_changed: bool,
+    model_belt: StagerHandle,
+    animation_belt: StagerHandle,
+}
+
+impl ModelScene {
+    /// The first model with animations is the one the player controls
+    fn animated_model(&self) -> Option<&Model> {
+        self.models
+            .iter()
+            .find(|model| !model.animations().is_empty())
+    }
+
+    fn print_vertex_bytes(&self) {
+        let bytes = self
+            .models
+            .iter()
+            .map(|model| model.vertex_bytes(self.compact_vertices))
+            .sum::<u64>();
+        let full = self
+            .models
+            .iter()
+            .map(|model| model.vertex_bytes(false))
+            .sum::<u64>();
+        println!(
+            "Vertex buffers: {} bytes ({:.0}% of full precision)",
+            bytes,
+            100.0 * bytes as f64 / full.max(1) as f64
+        );
+    }
+
+    fn print_clip(&self) {
+        if let Some(clip) = self
+            .animated_model()
+            .and_then(|model| model.animations().get(self.player.clip))
+        {
+            println!(
+                "Clip {}: {} ({:.2}s)",
+                self.player.clip,
+                clip.name.as_deref().unwrap_or("unnamed"),
+                clip.duration
+            );
+        }
+    }
+}
+
+impl Scene for ModelScene {
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        assets: &Assets,
+    ) -> Self {
+        let vert_module = shaders::MODEL_VERT.create_module(device);
+        let compact_vert_module = shaders::MODEL_COMPACT_VERT.create_module(device);
+        let skinned_vert_module = shaders::MODEL_SKINNED_VERT.create_module(device);
+        let frag_module = shaders::MODEL_FRAG.create_module(device);
+
+        let material_bind_group_layout = Model::material_bind_group_layout(device);
+        let joint_bind_group_layout = Model::joint_bind_group_layout(device);
+
+        let uploader = staging.uploader();
+        let mut slabs = ModelSlabs::new();
+        let mut models = Vec::new();
+        let mut aabb: Option<Aabb> = None;
+        let mut model_files = Vec::new();
+        let generated = vec![("hexagon", hexagon()), ("primitives", primitive_shapes())]
+            .into_iter()
+            .map(|(name, data)| (name, data.map(Arc::new), None));
+        let files = MODELS.iter().map(|&path| match assets.model(path) {
+            Ok(handle) => (path, Ok(handle.get()), Some(handle)),
+            Err(e) => (path, Err(e), None),
+        });
+        for (name, data, handle) in generated.chain(files) {
+            let loaded = data.and_then(|data| {
+                load(
+                    device,
+                    queue,
+                    &uploader,
+                    &mut slabs,
+                    name,
+                    &data,
+                    &material_bind_group_layout,
+                    &joint_bind_group_layout,
+                )
+            });
+            match loaded {
+                Ok((model, model_aabb)) => {
+                    if let Some(handle) = handle {
+                        model_files.push((models.len(), handle));
+                    }
+                    models.push(model);
+                    aabb = match (aabb, model_aabb) {
+                        (Some(a), Some(b)) => Some(Aabb {
+                            min: Point3::new(
+                                a.min.x.min(b.min.x),
+                                a.min.y.min(b.min.y),
+                                a.min.z.min(b.min.z),
+                            ),
+                            max: Point3::new(
+                                a.max.x.max(b.max.x),
+                                a.max.y.max(b.max.y),
+                                a.max.z.max(b.max.z),
+                            ),
+                        }),
+                        (a, b) => a.or(b),
+                    };
+                }
+                Err(e) => println!("Couldn't load {}: {}", name, e),
+            }
+        }
+
+        // Frame whatever got loaded
+        let (target, radius) = match aabb {
+            Some(aabb) => (
+                aabb.center(),
+                (aabb.max - aabb.min).magnitude().max(0.1) * 0.5,
+            ),
+            None => (cgmath::Point3::origin(), 1.0),
+        };
+        let camera = Camera {
+            eye: target + Vector3::new(0.0, 0.6, 1.0).normalize() * radius * 2.5,
+            target,
+            up: cgmath::Vector3::unit_y(),
+            aspect: sc.width as f32 / sc.height as f32,
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 100.0,
+        };
+
+        let camera_controller = CameraController::new(3.0);
+
+        let mut camera_uniform = CameraUniform::default();
+        camera_uniform.update(&camera);
+        let camera_uniform_buffer =
+            camera_uniform.create_buffer(device, Some("Model - Camera Uniform Buffer"));
+
+        let light_uniform = LightUniform {
+            eye: camera.eye.to_vec().extend(1.0).into(),
+            direction: Vector3::new(0.4, 1.0, 0.6).normalize().extend(0.0).into(),
+        };
+        let light_uniform_buffer =
+            light_uniform.create_buffer(device, Some("Model - Light Uniform Buffer"));
+
+        let model_belt = staging.create_stager(
+            "model.uniforms",
+            (std::mem::size_of::<CameraUniform>() + std::mem::size_of::<LightUniform>()) as u64,
+        );
+        let animation_belt = staging.create_stager(
+            "model.animation",
+            models.iter().map(Model::upload_size).max().unwrap_or(1),
+        );
+
+        let uniform_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Model - Uniform Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Model - Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: light_uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Model - Render Pipeline Layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout, &material_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let skinned_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Model - Skinned Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    &uniform_bind_group_layout,
+                    &material_bind_group_layout,
+                    &joint_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        // Both pipelines only differ in how vertices get to world space
+        let create_pipeline = |label, layout, module, buffers| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module,
+                    entry_point: "main",
+                    buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &frag_module,
+                    entry_point: "main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: sc.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    clamp_depth: false,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DepthTexture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+            })
+        };
+
+        let buffers = [ModelVertex::descriptor(), InstanceVertex::descriptor()];
+        let render_pipeline = create_pipeline(
+            "Model - Render Pipeline",
+            &pipeline_layout,
+            &vert_module,
+            &buffers,
+        );
+        let compact_buffers = [
+            CompactModelVertex::descriptor(),
+            InstanceVertex::descriptor(),
+        ];
+        let compact_render_pipeline = create_pipeline(
+            "Model - Compact Render Pipeline",
+            &pipeline_layout,
+            &compact_vert_module,
+            &compact_buffers,
+        );
+        let skinned_buffers = [SkinnedVertex::descriptor()];
+        let skinned_render_pipeline = create_pipeline(
+            "Model - Skinned Render Pipeline",
+            &skinned_pipeline_layout,
+            &skinned_vert_module,
+            &skinned_buffers,
+        );
+
+        let environments = vec![
+            EnvironmentMap::from_hdr(
+                device,
+                queue,
+                include_bytes!("../../assets/sky.hdr"),
+                256,
+                "Model - Environment Map",
+            )
+            .unwrap(),
+            // Every face has its own color, and a white band along its top edge
+            EnvironmentMap::from_faces(
+                device,
+                queue,
+                &[
+                    include_bytes!("../../assets/cube/px.png"),
+                    include_bytes!("../../assets/cube/nx.png"),
+                    include_bytes!("../../assets/cube/py.png"),
+                    include_bytes!("../../assets/cube/ny.png"),
+                    include_bytes!("../../assets/cube/pz.png"),
+                    include_bytes!("../../assets/cube/nz.png"),
+                ],
+                "Model - Debug Cube Map",
+            )
+            .unwrap(),
+        ];
+        let skybox = SkyboxPass::new(
+            device,
+            sc,
+            staging,
+            "model.skybox",
+            &environments[0],
+            Some(DepthTexture::DEPTH_FORMAT),
+        );
+
+        let scene = Self {
+            render_pipeline,
+            compact_render_pipeline,
+            skinned_render_pipeline,
+            compact_vertices: false,
+            models,
+            model_files,
+            uploader,
+            slabs,
+            material_bind_group_layout,
+            joint_bind_group_layout,
+            player: AnimationPlayer::new(),
+            camera,
+            camera_controller,
+            camera_uniform,
+            camera_uniform_buffer,
+            light_uniform,
+            light_uniform_buffer,
+            uniform_bind_group,
+            skybox,
+            environments,
+            environment: 0,
+            environment_changed: false,
+            model_belt,
+            animation_belt,
+        };
+        scene.print_clip();
+        scene.print_vertex_bytes();
+        scene.slabs.print_usage();
+        scene
+    }
+
+    fn destroy(&mut self) {
+        self.slabs.destroy();
+    }
+
+    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, input: &Input, time: Time) {
+        if let Some(key) = input.just_pressed("model.switch_environment") {
+            println!("Pressed {:?}", key);
+            self.environment = (self.environment + 1) % self.environments.len();
+            self.environment_changed = true;
+            if self.environments[self.environment].hdr {
+                println!("Environment: HDR sky");
+            } else {
+                println!("Environment: debug cube map");
+            }
+        }
+        if let Some(key) = input.just_pressed("model.toggle_compact_vertices") {
+            println!("Pressed {:?}", key);
+            self.compact_vertices = !self.compact_vertices;
+            println!("Compact vertices: {:?}", self.compact_vertices);
+            self.print_vertex_bytes();
+        }
+        if let Some(key) = input.just_pressed("model.next_clip") {
+            println!("Pressed {:?}", key);
+            let clips = self
+                .models
+                .iter()
+                .find(|model| !model.animations().is_empty())
+                .map_or(&[][..], Model::animations);
+            self.player.next_clip(clips);
+            self.print_clip();
+        }
+        if let Some(key) = input.just_pressed("model.toggle_looping") {
+            println!("Pressed {:?}", key);
+            self.player.looping = !self.player.looping;
+            println!("Looping: {:?}", self.player.looping);
+        }
+        if let Some(key) = input.just_pressed("model.restart") {
+            println!("Pressed {:?}", key);
+            self.player.time = 0.0;
+        }
+
+        for (index, data) in self.model_files.iter_mut() {
+            if data.reloaded() {
+                match Model::new(
+                    device,
+                    queue,
+                    &self.uploader,
+                    &mut self.slabs,
+                    &data.get(),
+                    &self.material_bind_group_layout,
+                    &self.joint_bind_group_layout,
+                ) {
+                    Ok(model) => {
+                        std::mem::replace(&mut self.models[*index], model).free(&mut self.slabs)
+                    }
+                    Err(e) => println!("Couldn't upload a reloaded model: {:?}", e),
+                }
+            }
+        }
+
+        if self.environment_changed {
+            self.skybox
+                .set_environment(device, &self.environments[self.environment]);
+            self.environment_changed = false;
+        }
+
+        let dt = time.delta;
+
+        let player = &mut self.player;
+        if let Some(model) = self
+            .models
+            .iter_mut()
+            .find(|model| !model.animations().is_empty())
+        {
+            player.advance(dt, model.animations());
+            model.animate(player);
+        }
+
+        self.camera_controller.update(&mut self.camera, input);
+        self.camera_uniform.update(&self.camera);
+        self.light_uniform.eye = self.camera.eye.to_vec().extend(1.0).into();
+        self.skybox.update(&self.camera);
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut stager = staging.fetch_stager(self.model_belt);
+        stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+        stager.write(encoder, &self.light_uniform_buffer, 0, &self.light_uniform);
+
+        if let Some(model) = self.animated_model() {
+            model.upload(encoder, staging, self.animation_belt);
+        }
+        self.skybox.upload(encoder, staging);
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("Model - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &state.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        };
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        self.skybox.render(&mut render_pass);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+
+        if self.compact_vertices {
+            render_pass.set_pipeline(&self.compact_render_pipeline);
+            for model in self.models.iter() {
+                model.render_compact(&mut render_pass, &self.slabs, 1);
+            }
+        } else {
+            render_pass.set_pipeline(&self.render_pipeline);
+            for model in self.models.iter() {
+                model.render(&mut render_pass, &self.slabs, 1);
+            }
+        }
+
+        render_pass.set_pipeline(&self.skinned_render_pipeline);
+        for model in self.models.iter().filter(|model| model.has_skinned_draws()) {
+            model.render_skinned(&mut render_pass, &self.slabs, 1, 2);
+        }
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+    }
+}
@@ -0,0 +1,509 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    assets::Assets,
+    buffer::{StagerHandle, StagingFactory, Uniformable},
+    cache::{LayoutCache, PipelineCache},
+    camera::{Camera, CameraController, CameraUniform, Projection},
+    clock::Time,
+    input::Input,
+    layout::{Std140, Std430},
+    particles::BillboardUniform,
+    shader::shaders,
+};
+
+use super::Scene;
+
+const BODY_COUNT: u32 = 4096;
+/// Must match the workgroup size (and tile size) of both kernels
+const WORKGROUP_SIZE: u32 = 256;
+const CENTRAL_MASS: f32 = 10.0;
+/// Total mass of the disk around the central body
+const DISK_MASS: f32 = 1.0;
+const DISK_INNER_RADIUS: f32 = 0.5;
+const DISK_OUTER_RADIUS: f32 = 4.0;
+
+/// A body, as laid out in the storage buffers
+#[repr(C, packed)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std430)]
+struct GpuBody {
+    /// xyz: position, w: mass
+    position: [f32; 4],
+    /// xyz: velocity, w: unused
+    velocity: [f32; 4],
+}
+
+impl GpuBody {
+    /// The storage buffers double as instance vertex buffers for rendering
+    fn descriptor<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GpuBody>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct NBodyParamsUniform {
+    dt: f32,
+    gravity: f32,
+    softening: f32,
+    count: u32,
+}
+
+/// Which compute kernel integrates the bodies
+#[derive(Copy, Clone, Debug)]
+enum SelectedKernel {
+    /// Every invocation reads every body straight from the storage buffer
+    BruteForce,
+    /// Bodies are loaded into workgroup shared memory one tile at a time
+    Tiled,
+}
+
+pub struct NBodyScene {
+    brute_force_pipeline: wgpu::ComputePipeline,
+    tiled_pipeline: wgpu::ComputePipeline,
+    render_pipeline: wgpu::RenderPipeline,
+    bodies_buffers: [wgpu::Buffer; 2],
+    /// `compute_bind_groups[i]` reads `bodies_buffers[i]` and writes the other one
+    compute_bind_groups: [wgpu::BindGroup; 2],
+    /// Index of the buffer holding the latest simulation state
+    current: usize,
+    params: NBodyParamsUniform,
+    params_buffer: wgpu::Buffer,
+    camera: Camera,
+    camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    billboard_uniform: BillboardUniform,
+    billboard_uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    selected_kernel: SelectedKernel,
+    params_belt: StagerHandle,
+    camera_belt: StagerHandle,
+}
+
+impl Scene for NBodyScene {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        _assets: &Assets,
+    ) -> Self {
+        let brute_force_module = shaders::NBODY_COMP.create_module(device);
+        let tiled_module = shaders::NBODY_TILED_COMP.create_module(device);
+        let vert_module = shaders::NBODY_VERT.create_module(device);
+        let frag_module = shaders::COMPUTE_PARTICLES_FRAG.create_module(device);
+
+        // A heavy body in the middle, orbited by a thin disk of light ones
+        let mut rng = 0x9E37_79B9u32;
+        let mut random = || {
+            rng ^= rng << 13;
+            rng ^= rng >> 17;
+            rng ^= rng << 5;
+            rng as f32 / u32::MAX as f32
+        };
+        let body_mass = DISK_MASS / (BODY_COUNT - 1) as f32;
+        let initial = (0..BODY_COUNT)
+            .map(|i| {
+                if i == 0 {
+                    return GpuBody {
+                        position: [0.0, 0.0, 0.0, CENTRAL_MASS],
+                        velocity: [0.0; 4],
+                    };
+                }
+
+                let radius = DISK_INNER_RADIUS + (DISK_OUTER_RADIUS - DISK_INNER_RADIUS) * random();
+                let angle = random() * std::f32::consts::TAU;
+                let height = (random() - 0.5) * 0.1;
+
+                // Roughly circular orbits around whatever mass lies inside them
+                let inner_mass = CENTRAL_MASS
+                    + DISK_MASS * (radius - DISK_INNER_RADIUS)
+                        / (DISK_OUTER_RADIUS - DISK_INNER_RADIUS);
+                let speed = (inner_mass / radius).sqrt();
+
+                GpuBody {
+                    position: [
+                        angle.cos() * radius,
+                        height,
+                        angle.sin() * radius,
+                        body_mass,
+                    ],
+                    velocity: [-angle.sin() * speed, 0.0, angle.cos() * speed, 0.0],
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let create_bodies = |label| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(&initial),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+            })
+        };
+        let bodies_buffers = [
+            create_bodies("N-Body - Bodies Storage Buffer A"),
+            create_bodies("N-Body - Bodies Storage Buffer B"),
+        ];
+
+        let params = NBodyParamsUniform {
+            dt: 0.0,
+            gravity: 1.0,
+            softening: 0.05,
+            count: BODY_COUNT,
+        };
+        let params_buffer = params.create_buffer(device, Some("N-Body - Params Buffer"));
+        let params_belt = staging.create_stager(
+            "nbody.params",
+            std::mem::size_of::<NBodyParamsUniform>() as u64,
+        );
+
+        let compute_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("N-Body - Compute Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let create_compute_bind_group = |label, from: &wgpu::Buffer, to: &wgpu::Buffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout: &compute_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: from.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: to.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let compute_bind_groups = [
+            create_compute_bind_group(
+                "N-Body - Compute Bind Group A to B",
+                &bodies_buffers[0],
+                &bodies_buffers[1],
+            ),
+            create_compute_bind_group(
+                "N-Body - Compute Bind Group B to A",
+                &bodies_buffers[1],
+                &bodies_buffers[0],
+            ),
+        ];
+
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("N-Body - Compute Pipeline Layout"),
+                bind_group_layouts: &[&compute_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let brute_force_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("N-Body - Brute Force Compute Pipeline"),
+                layout: Some(&compute_pipeline_layout),
+                module: &brute_force_module,
+                entry_point: "main",
+            });
+
+        let tiled_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("N-Body - Tiled Compute Pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &tiled_module,
+            entry_point: "main",
+        });
+
+        let camera = Camera {
+            eye: (0.0, 6.0, 10.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: sc.width as f32 / sc.height as f32,
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 100.0,
+        };
+
+        let camera_controller = CameraController::new(12.0);
+
+        let mut camera_uniform = CameraUniform::default();
+        camera_uniform.update(&camera);
+        let mut billboard_uniform = BillboardUniform::default();
+        billboard_uniform.update(&camera);
+        let camera_belt =
+            staging.create_stager("nbody.camera", std::mem::size_of::<CameraUniform>() as u64);
+
+        let camera_uniform_buf =
+            camera_uniform.create_buffer(device, Some("N-Body - Camera Uniform Buffer"));
+        let billboard_uniform_buf =
+            billboard_uniform.create_buffer(device, Some("N-Body - Billboard Uniform Buffer"));
+
+        let uniform_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("N-Body - Uniform Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("N-Body - Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_uniform_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: billboard_uniform_buf.as_entire_binding(),
+                },
+            ],
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("N-Body - Render Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("N-Body - Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[GpuBody::descriptor()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    // Additive, so that bodies don't need sorting
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent::REPLACE,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            brute_force_pipeline,
+            tiled_pipeline,
+            render_pipeline,
+            bodies_buffers,
+            compute_bind_groups,
+            current: 0,
+            params,
+            params_buffer,
+            camera,
+            camera_controller,
+            camera_uniform,
+            camera_uniform_buffer: camera_uniform_buf,
+            billboard_uniform,
+            billboard_uniform_buffer: billboard_uniform_buf,
+            uniform_bind_group,
+            selected_kernel: SelectedKernel::Tiled,
+            params_belt,
+            camera_belt,
+        }
+    }
+
+    fn destroy(&mut self) {
+        for bodies in self.bodies_buffers.iter() {
+            bodies.destroy();
+        }
+    }
+
+    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, input: &Input, time: Time) {
+        if let Some(key) = input.just_pressed("nbody.switch_kernel") {
+            println!("Pressed {:?}", key);
+            self.selected_kernel = match self.selected_kernel {
+                SelectedKernel::BruteForce => SelectedKernel::Tiled,
+                SelectedKernel::Tiled => SelectedKernel::BruteForce,
+            };
+            println!("{:?}", self.selected_kernel);
+        }
+
+        self.camera_controller.update(&mut self.camera, input);
+        self.camera_uniform.update(&self.camera);
+        self.billboard_uniform.update(&self.camera);
+
+        // Capped lower than the other scenes, the integration isn't stable
+        // enough for big steps
+        self.params.dt = time.delta.min(1.0 / 30.0);
+    }
+
+    fn compute(&mut self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        let mut params_stager = staging.fetch_stager(self.params_belt);
+        params_stager.write(encoder, &self.params_buffer, 0, &self.params);
+
+        let pipeline = match self.selected_kernel {
+            SelectedKernel::BruteForce => &self.brute_force_pipeline,
+            SelectedKernel::Tiled => &self.tiled_pipeline,
+        };
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("N-Body - Simulation Pass"),
+            });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &self.compute_bind_groups[self.current], &[]);
+            compute_pass.dispatch(BODY_COUNT.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+
+        // The freshly written buffer is the one to draw from
+        self.current = 1 - self.current;
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut camera_stager = staging.fetch_stager(self.camera_belt);
+        camera_stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+        camera_stager.write(
+            encoder,
+            &self.billboard_uniform_buffer,
+            0,
+            &self.billboard_uniform,
+        );
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("N-Body - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        };
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.bodies_buffers[self.current].slice(..));
+        render_pass.draw(0..6, 0..BODY_COUNT);
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+    }
+}
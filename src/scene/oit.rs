@@ -0,0 +1,697 @@
+use std::{num::NonZeroU64, sync::Arc};
+
+use cgmath::{Deg, Euler, Quaternion};
+
+use crate::{
+    assets::Assets,
+    buffer::{
+        IndexedVertexBuffer, InstanceVertexBuffer, StagerHandle, StagingFactory, Uniformable,
+    },
+    cache::{LayoutCache, PipelineCache},
+    camera::{Camera, CameraController, CameraUniform, Projection},
+    clock::Time,
+    draw::{Blending, DrawList},
+    input::Input,
+    mesh::Transform,
+    shader::shaders,
+    texture::{RenderTarget, SamplerDesc},
+    vertex::{Descriptable, FlatVertex, Vertex, VertexBufferable},
+};
+
+use super::Scene;
+
+const VERTICES_1: &[FlatVertex] = &[
+    FlatVertex {
+        position: [-0.5, -0.5, 0.0],
+        color: [1.0, 1.0, 1.0],
+    },
+    FlatVertex {
+        position: [0.5, -0.5, 0.0],
+        color: [1.0, 1.0, 1.0],
+    },
+    FlatVertex {
+        position: [0.5, 0.5, 0.0],
+        color: [1.0, 1.0, 1.0],
+    },
+    FlatVertex {
+        position: [-0.5, 0.5, 0.0],
+        color: [1.0, 1.0, 1.0],
+    },
+];
+
+const INDICES_1: &[u16] = &[0, 1, 2, 0, 2, 3];
+
+const COLORS: &[[f32; 4]] = &[
+    [1.0, 0.1, 0.1, 0.5],
+    [0.1, 1.0, 0.1, 0.5],
+    [0.1, 0.1, 1.0, 0.5],
+    [1.0, 1.0, 0.1, 0.5],
+];
+
+const ACCUM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const REVEAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+pub struct Quad {
+    transform: Transform,
+    color: [f32; 4],
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
+#[vertex(instance)]
+pub struct ColoredInstanceVertex {
+    #[location(5)]
+    transform: [[f32; 4]; 4],
+    #[location(9)]
+    color: [f32; 4],
+}
+
+impl From<&Quad> for ColoredInstanceVertex {
+    fn from(q: &Quad) -> Self {
+        ColoredInstanceVertex {
+            transform: q.transform.uniform_matrix(),
+            color: q.color,
+        }
+    }
+}
+
+impl VertexBufferable for ColoredInstanceVertex {}
+
+/// Render targets for the weighted-blended OIT pass
+struct OitTargets {
+    accum: RenderTarget,
+    reveal: RenderTarget,
+    bind_group: wgpu::BindGroup,
+}
+
+impl OitTargets {
+    fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let accum = RenderTarget::new(
+            device,
+            width,
+            height,
+            ACCUM_FORMAT,
+            false,
+            "OIT - Accumulation Texture",
+        );
+        let reveal = RenderTarget::new(
+            device,
+            width,
+            height,
+            REVEAL_FORMAT,
+            false,
+            "OIT - Revealage Texture",
+        );
+        let bind_group = Self::create_bind_group(device, layout, sampler, &accum, &reveal);
+
+        Self {
+            accum,
+            reveal,
+            bind_group,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        accum: &RenderTarget,
+        reveal: &RenderTarget,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("OIT - Composite Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&accum.color.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&reveal.color.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        width: u32,
+        height: u32,
+    ) {
+        self.accum.resize(device, width, height);
+        self.reveal.resize(device, width, height);
+        self.bind_group =
+            Self::create_bind_group(device, layout, sampler, &self.accum, &self.reveal);
+    }
+}
+
+/// Compares sorted alpha blending (left half of the screen) with
+/// weighted blended order-independent transparency (right half)
+pub struct OitScene {
+    sorted_pipeline: wgpu::RenderPipeline,
+    accum_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    composite_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    target_sampler: Arc<wgpu::Sampler>,
+    targets: OitTargets,
+    quad: IndexedVertexBuffer<FlatVertex>,
+    quads: Vec<Quad>,
+    draw_list: DrawList<usize>,
+    sorted_instances: InstanceVertexBuffer<ColoredInstanceVertex>,
+    unsorted_instances: InstanceVertexBuffer<ColoredInstanceVertex>,
+    sorting_enabled: bool,
+    camera: Camera,
+    camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    size: winit::dpi::PhysicalSize<u32>,
+    instance_belt: StagerHandle,
+    camera_belt: StagerHandle,
+}
+
+impl OitScene {
+    fn half_aspect(size: winit::dpi::PhysicalSize<u32>) -> f32 {
+        (size.width as f32 / 2.0) / size.height as f32
+    }
+
+    fn set_half_viewport(&self, render_pass: &mut wgpu::RenderPass, right: bool) {
+        let half_width = self.size.width as f32 / 2.0;
+        let x = if right { half_width } else { 0.0 };
+        render_pass.set_viewport(x, 0.0, half_width, self.size.height as f32, 0.0, 1.0);
+    }
+}
+
+impl Scene for OitScene {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        assets: &Assets,
+    ) -> Self {
+        let vert_module = shaders::OIT_VERT.create_module(device);
+        let sorted_frag_module = shaders::OIT_SORTED_FRAG.create_module(device);
+        let accum_frag_module = shaders::OIT_ACCUM_FRAG.create_module(device);
+        let fullscreen_vert_module = assets.shader(device, &shaders::FULLSCREEN_VERT).get();
+        let composite_frag_module = shaders::OIT_COMPOSITE_FRAG.create_module(device);
+
+        let quad = IndexedVertexBuffer::from_vertices_indexes(
+            device,
+            VERTICES_1,
+            INDICES_1,
+            Some("OIT - Quad Vertex Buffer"),
+            Some("OIT - Quad Index Buffer"),
+        );
+
+        // A few stacks of overlapping quads, each layer in a different color
+        let mut quads = Vec::new();
+        for stack in -1..=1 {
+            for (layer, color) in COLORS.iter().enumerate() {
+                let layer = layer as f32;
+                quads.push(Quad {
+                    transform: Transform::from_translation(cgmath::Vector3::new(
+                        stack as f32 * 1.2 + layer * 0.15,
+                        layer * 0.1,
+                        layer * -0.3,
+                    )),
+                    color: *color,
+                });
+            }
+        }
+
+        let sorted_instances = InstanceVertexBuffer::from_instances(
+            device,
+            &quads,
+            Some("OIT - Sorted Instances Vertex Buffer"),
+        );
+        let unsorted_instances = InstanceVertexBuffer::from_instances(
+            device,
+            &quads,
+            Some("OIT - Unsorted Instances Vertex Buffer"),
+        );
+        let instance_belt = staging.create_stager(
+            "oit.instances",
+            2 * quads.len() as u64 * ColoredInstanceVertex::descriptor().array_stride,
+        );
+
+        let size = winit::dpi::PhysicalSize::new(sc.width, sc.height);
+
+        let camera = Camera {
+            eye: (0.0, 0.5, 4.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: Self::half_aspect(size),
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 100.0,
+        };
+
+        let camera_controller = CameraController::new(12.0);
+
+        let mut camera_uniform = CameraUniform::default();
+        camera_uniform.update(&camera);
+        let camera_belt =
+            staging.create_stager("oit.camera", std::mem::size_of::<CameraUniform>() as u64);
+
+        let camera_uniform_buf =
+            camera_uniform.create_buffer(device, Some("OIT - Camera Uniform Buffer"));
+
+        let uniform_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("OIT - Camera Uniform Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("OIT - Camera Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &camera_uniform_buf,
+                    offset: 0,
+                    size: None,
+                }),
+            }],
+        });
+
+        let composite_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("OIT - Composite Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: false,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let target_sampler = assets.sampler(device, &SamplerDesc::nearest()).get();
+
+        let targets = OitTargets::new(
+            device,
+            &composite_bind_group_layout,
+            &target_sampler,
+            sc.width,
+            sc.height,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("OIT - Render Pipeline Layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let primitive = wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // Quads are seen from both sides while the camera orbits around them
+            cull_mode: None,
+            clamp_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        };
+
+        let sorted_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("OIT - Sorted Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[
+                    FlatVertex::descriptor(),
+                    ColoredInstanceVertex::descriptor(),
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &sorted_frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive,
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        // Accumulation adds up the weighted colors, while revealage multiplies
+        // together how much of the background is still visible
+        let accum_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("OIT - Accumulation Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[
+                    FlatVertex::descriptor(),
+                    ColoredInstanceVertex::descriptor(),
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &accum_frag_module,
+                entry_point: "main",
+                targets: &[
+                    wgpu::ColorTargetState {
+                        format: ACCUM_FORMAT,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    },
+                    wgpu::ColorTargetState {
+                        format: REVEAL_FORMAT,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::Zero,
+                                dst_factor: wgpu::BlendFactor::OneMinusSrc,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::Zero,
+                                dst_factor: wgpu::BlendFactor::OneMinusSrc,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    },
+                ],
+            }),
+            primitive,
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        let composite_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("OIT - Composite Pipeline Layout"),
+                bind_group_layouts: &[&composite_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let composite_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("OIT - Composite Render Pipeline"),
+            layout: Some(&composite_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &fullscreen_vert_module,
+                entry_point: "main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &composite_frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        let draw_list = DrawList::with_capacity(quads.len());
+
+        Self {
+            sorted_pipeline,
+            accum_pipeline,
+            composite_pipeline,
+            composite_bind_group_layout,
+            target_sampler,
+            targets,
+            quad,
+            quads,
+            draw_list,
+            sorted_instances,
+            unsorted_instances,
+            sorting_enabled: true,
+            camera,
+            camera_controller,
+            camera_uniform,
+            camera_uniform_buffer: camera_uniform_buf,
+            uniform_bind_group,
+            size,
+            instance_belt,
+            camera_belt,
+        }
+    }
+
+    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, input: &Input, _time: Time) {
+        if let Some(key) = input.just_pressed("oit.toggle_sorting") {
+            println!("Pressed {:?}", key);
+            self.sorting_enabled = !self.sorting_enabled;
+            println!("Sorting enabled: {}", self.sorting_enabled);
+        }
+
+        self.camera_controller.update(&mut self.camera, input);
+        self.camera_uniform.update(&self.camera);
+
+        // Spin every quad so that their relative order keeps changing
+        for quad in self.quads.iter_mut() {
+            quad.transform.set_rotation(|r| {
+                *r = (*r)
+                    * Quaternion::from(Euler {
+                        x: Deg(0.0),
+                        y: Deg(0.5),
+                        z: Deg(0.0),
+                    });
+            });
+        }
+
+        self.draw_list.clear();
+        for (i, quad) in self.quads.iter().enumerate() {
+            let t = quad.transform.translation();
+            self.draw_list
+                .push(i, cgmath::Point3::new(t.x, t.y, t.z), Blending::Translucent);
+        }
+        if self.sorting_enabled {
+            self.draw_list.sort(self.camera.eye);
+        }
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut camera_stager = staging.fetch_stager(self.camera_belt);
+        camera_stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+
+        let potential_size = NonZeroU64::new(
+            self.quads.len() as wgpu::BufferAddress
+                * self.sorted_instances.descriptor().array_stride,
+        );
+        if let Some(size) = potential_size {
+            let mut instance_stager = staging.fetch_stager(self.instance_belt);
+            {
+                let mut staging_buffer = instance_stager.create_staging_area(
+                    encoder,
+                    &self.sorted_instances.buffer,
+                    0,
+                    size,
+                );
+                for (slot, &i) in self.draw_list.iter().enumerate() {
+                    self.sorted_instances.copy_instance_into_view(
+                        &mut staging_buffer,
+                        &self.quads[i],
+                        slot,
+                    );
+                }
+            }
+
+            let mut staging_buffer = instance_stager.create_staging_area(
+                encoder,
+                &self.unsorted_instances.buffer,
+                0,
+                size,
+            );
+            for (i, quad) in self.quads.iter().enumerate() {
+                self.unsorted_instances
+                    .copy_instance_into_view(&mut staging_buffer, quad, i);
+            }
+        }
+
+        // Left half: classic alpha blending, in whatever order the draw list says
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("OIT - Sorted Render Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: frame_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(state.bg_color),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            self.set_half_viewport(&mut render_pass, false);
+            render_pass.set_pipeline(&self.sorted_pipeline);
+            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.quad.vertices.slice(..));
+            render_pass.set_vertex_buffer(1, self.sorted_instances.buffer.slice(..));
+            render_pass.set_index_buffer(self.quad.indices.slice(..), self.quad.index_format);
+            render_pass.draw_indexed(0..self.quad.num_indices, 0, 0..self.sorted_instances.len);
+        }
+
+        // Right half: weighted blended OIT, fed with the quads unsorted
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("OIT - Accumulation Render Pass"),
+                color_attachments: &[
+                    self.targets
+                        .accum
+                        .color_attachment(Some(wgpu::Color::TRANSPARENT)),
+                    self.targets
+                        .reveal
+                        .color_attachment(Some(wgpu::Color::WHITE)),
+                ],
+                depth_stencil_attachment: None,
+            });
+
+            self.set_half_viewport(&mut render_pass, true);
+            render_pass.set_pipeline(&self.accum_pipeline);
+            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.quad.vertices.slice(..));
+            render_pass.set_vertex_buffer(1, self.unsorted_instances.buffer.slice(..));
+            render_pass.set_index_buffer(self.quad.indices.slice(..), self.quad.index_format);
+            render_pass.draw_indexed(0..self.quad.num_indices, 0, 0..self.unsorted_instances.len);
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("OIT - Composite Render Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: frame_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            self.set_half_viewport(&mut render_pass, true);
+            render_pass.set_pipeline(&self.composite_pipeline);
+            render_pass.set_bind_group(0, &self.targets.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.size = size;
+        self.camera.aspect = Self::half_aspect(size);
+
+        self.targets.resize(
+            device,
+            &self.composite_bind_group_layout,
+            &self.target_sampler,
+            size.width,
+            size.height,
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use std::{num::NonZeroU64, sync::Arc};
+
+use cgmath::Vector3;
+
+use crate::{
+    assets::{Assets, Handle},
+    buffer::{
+        IndexedVertexBuffer, InstanceVertexBuffer, StagerHandle, StagingFactory, Uniformable,
+    },
+    cache::{LayoutCache, PipelineCache},
+    camera::{Camera, CameraController, CameraUniform, Projection},
+    clock::Time,
+    draw::{Blending, DrawList},
+    input::Input,
+    particles::{BillboardUniform, Curve, Emitter, EmitterDesc, ParticleInstance},
+    shader::shaders,
+    texture::{ColorSpace, Texture},
+    vertex::{Descriptable, TexturedVertex},
+};
+
+use super::Scene;
+
+const VERTICES_1: &[TexturedVertex] = &[
+    TexturedVertex {
+        position: [-0.5, -0.5, 0.0],
+        tex_coords: [0.0, 1.0],
+    },
+    TexturedVertex {
+        position: [0.5, -0.5, 0.0],
+        tex_coords: [1.0, 1.0],
+    },
+    TexturedVertex {
+        position: [0.5, 0.5, 0.0],
+        tex_coords: [1.0, 0.0],
+    },
+    TexturedVertex {
+        position: [-0.5, 0.5, 0.0],
+        tex_coords: [0.0, 0.0],
+    },
+];
+
+const INDICES_1: &[u16] = &[0, 1, 2, 0, 2, 3];
+
+/// Room for instances before the buffer has to grow for the first time
+const INITIAL_INSTANCE_CAPACITY: u32 = 64;
+
+fn fountain() -> EmitterDesc {
+    EmitterDesc {
+        spawn_rate: 240.0,
+        lifetime: 2.5,
+        max_particles: 1024,
+        velocity: Vector3::new(0.0, 6.0, 0.0),
+        velocity_spread: 1.2,
+        acceleration: Vector3::new(0.0, -4.9, 0.0),
+        velocity_curve: Curve::new(vec![(0.0, 1.0), (1.0, 0.7)]),
+        size_curve: Curve::new(vec![(0.0, 0.1), (0.2, 0.4), (1.0, 0.15)]),
+        color_curve: Curve::new(vec![
+            (0.0, [1.0, 1.0, 1.0, 1.0]),
+            (0.6, [1.0, 0.8, 0.5, 0.8]),
+            (1.0, [1.0, 0.4, 0.2, 0.0]),
+        ]),
+    }
+}
+
+pub struct ParticlesScene {
+    pipeline: wgpu::RenderPipeline,
+    quad: IndexedVertexBuffer<TexturedVertex>,
+    diffuse_bind_group: wgpu::BindGroup,
+    diffuse_texture: Handle<Texture>,
+    texture_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    emitter: Emitter,
+    draw_list: DrawList<usize>,
+    instances_buffer: InstanceVertexBuffer<ParticleInstance>,
+    camera: Camera,
+    camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    billboard_uniform: BillboardUniform,
+    billboard_uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    instance_belt: StagerHandle,
+    camera_belt: StagerHandle,
+}
+
+impl Scene for ParticlesScene {
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        assets: &Assets,
+    ) -> Self {
+        let diffuse_texture = assets
+            .texture(device, queue, "sanCheese.png", ColorSpace::Srgb)
+            .unwrap();
+
+        let texture_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Particles - Texture Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let diffuse_bind_group = diffuse_texture.get().create_bind_group(
+            device,
+            &texture_bind_group_layout,
+            "Particles - Texture Bind Group",
+        );
+
+        let quad = IndexedVertexBuffer::from_vertices_indexes(
+            device,
+            VERTICES_1,
+            INDICES_1,
+            Some("Particles - Quad Vertex Buffer"),
+            Some("Particles - Quad Index Buffer"),
+        );
+
+        let emitter = Emitter::new(fountain(), Vector3::new(0.0, 0.0, 0.0));
+        let max_particles = emitter.desc().max_particles;
+
+        // The instance buffer grows along with the fountain, and only the
+        // alive particles are uploaded and drawn every frame
+        let instances_buffer = InstanceVertexBuffer::with_capacity(
+            device,
+            INITIAL_INSTANCE_CAPACITY,
+            Some("Particles - Instances Vertex Buffer"),
+        );
+        let instance_belt = staging.create_stager(
+            "particles.instances",
+            max_particles as u64 * ParticleInstance::descriptor().array_stride,
+        );
+
+        let vert_module = shaders::PARTICLES_VERT.create_module(device);
+        let frag_module = shaders::PARTICLES_FRAG.create_module(device);
+
+        let camera = Camera {
+            eye: (0.0, 3.0, 9.0).into(),
+            target: (0.0, 3.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: sc.width as f32 / sc.height as f32,
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 100.0,
+        };
+
+        let camera_controller = CameraController::new(12.0);
+
+        let mut camera_uniform = CameraUniform::default();
+        camera_uniform.update(&camera);
+        let mut billboard_uniform = BillboardUniform::default();
+        billboard_uniform.update(&camera);
+        let camera_belt = staging.create_stager(
+            "particles.camera",
+            std::mem::size_of::<CameraUniform>() as u64,
+        );
+
+        let camera_uniform_buf =
+            camera_uniform.create_buffer(device, Some("Particles - Camera Uniform Buffer"));
+        let billboard_uniform_buf =
+            billboard_uniform.create_buffer(device, Some("Particles - Billboard Uniform Buffer"));
+
+        let uniform_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Particles - Uniform Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particles - Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &camera_uniform_buf,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &billboard_uniform_buf,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particles - Render Pipeline Layout"),
+            bind_group_layouts: &[&texture_bind_group_layout, &uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Particles - Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[TexturedVertex::descriptor(), ParticleInstance::descriptor()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            pipeline,
+            quad,
+            diffuse_bind_group,
+            diffuse_texture,
+            texture_bind_group_layout,
+            emitter,
+            draw_list: DrawList::with_capacity(max_particles),
+            instances_buffer,
+            camera,
+            camera_controller,
+            camera_uniform,
+            camera_uniform_buffer: camera_uniform_buf,
+            billboard_uniform,
+            billboard_uniform_buffer: billboard_uniform_buf,
+            uniform_bind_group,
+            instance_belt,
+            camera_belt,
+        }
+    }
+
+    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, input: &Input, time: Time) {
+        if self.diffuse_texture.reloaded() {
+            self.diffuse_bind_group = self.diffuse_texture.get().create_bind_group(
+                device,
+                &self.texture_bind_group_layout,
+                "Particles - Texture Bind Group",
+            );
+        }
+
+        self.camera_controller.update(&mut self.camera, input);
+        self.camera_uniform.update(&self.camera);
+        self.billboard_uniform.update(&self.camera);
+
+        // Simulation time is already clamped, so the fountain doesn't jump
+        // ahead after a hitch
+        let dt = time.delta;
+
+        self.emitter.update(dt);
+
+        // Every alive particle is uploaded again anyway, so there's nothing
+        // worth copying over when the instance buffer grows
+        let alive = self.emitter.particles().len() as u32;
+        self.instances_buffer.len = 0;
+        if alive > self.instances_buffer.capacity() {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Particles - Instance Growth Encoder"),
+            });
+            self.instances_buffer.reserve(device, &mut encoder, alive);
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+        self.instances_buffer.len = alive;
+
+        // Particles are blended, so they have to be drawn back-to-front
+        self.draw_list.clear();
+        for (i, particle) in self.emitter.particles().iter().enumerate() {
+            let p = particle.position;
+            self.draw_list
+                .push(i, cgmath::Point3::new(p.x, p.y, p.z), Blending::Translucent);
+        }
+        self.draw_list.sort(self.camera.eye);
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut camera_stager = staging.fetch_stager(self.camera_belt);
+        camera_stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+        camera_stager.write(
+            encoder,
+            &self.billboard_uniform_buffer,
+            0,
+            &self.billboard_uniform,
+        );
+
+        let particles = self.emitter.particles();
+        let potential_size = NonZeroU64::new(
+            particles.len() as wgpu::BufferAddress
+                * self.instances_buffer.descriptor().array_stride,
+        );
+        if let Some(size) = potential_size {
+            let mut instance_stager = staging.fetch_stager(self.instance_belt);
+            let mut staging_buffer = instance_stager.create_staging_area(
+                encoder,
+                &self.instances_buffer.buffer,
+                0,
+                size,
+            );
+            for (slot, &i) in self.draw_list.iter().enumerate() {
+                self.instances_buffer.copy_instance_into_view(
+                    &mut staging_buffer,
+                    &particles[i],
+                    slot,
+                );
+            }
+        }
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("Particles - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        };
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.quad.vertices.slice(..));
+        render_pass.set_vertex_buffer(1, self.instances_buffer.buffer.slice(..));
+        render_pass.set_index_buffer(self.quad.indices.slice(..), self.quad.index_format);
+        render_pass.draw_indexed(0..self.quad.num_indices, 0, 0..self.instances_buffer.len);
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+    }
+}
@@ -0,0 +1,479 @@
+use crate::{
+    assets::Assets,
+    buffer::{IndexedVertexBuffer, StagerHandle, StagingFactory, Uniformable, VertexTypedBuffer},
+    cache::{LayoutCache, PipelineCache},
+    camera::{Camera, CameraController, CameraUniform, Projection},
+    clock::Time,
+    input::Input,
+    layout::Std140,
+    shader::shaders,
+    texture::DepthTexture,
+    vertex::TexturedVertex,
+};
+
+use super::Scene;
+
+/// Vertices per side of the terrain grid. 256 is the most that still fits
+/// `u16` indices
+const RESOLUTION: u32 = 256;
+const CELL_SIZE: f32 = 0.1;
+const WORKGROUP_SIZE: u32 = 8;
+const MAX_OCTAVES: u32 = 12;
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct TerrainParamsUniform {
+    seed: u32,
+    octaves: u32,
+    resolution: u32,
+    #[padding]
+    _padding0: u32,
+    frequency: f32,
+    amplitude: f32,
+    cell_size: f32,
+    #[padding]
+    _padding1: f32,
+}
+
+/// A flat grid centered around the origin. Heights are filled in by the
+/// vertex shader, from whatever the compute pass last generated
+fn grid() -> (Vec<TexturedVertex>, Vec<u16>) {
+    let half_extent = (RESOLUTION - 1) as f32 * CELL_SIZE / 2.0;
+
+    let vertices = (0..RESOLUTION * RESOLUTION)
+        .map(|i| {
+            let x = i % RESOLUTION;
+            let z = i / RESOLUTION;
+            TexturedVertex {
+                position: [
+                    x as f32 * CELL_SIZE - half_extent,
+                    0.0,
+                    z as f32 * CELL_SIZE - half_extent,
+                ],
+                tex_coords: [
+                    x as f32 / (RESOLUTION - 1) as f32,
+                    z as f32 / (RESOLUTION - 1) as f32,
+                ],
+            }
+        })
+        .collect();
+
+    let mut indices = Vec::with_capacity(((RESOLUTION - 1) * (RESOLUTION - 1) * 6) as usize);
+    for z in 0..RESOLUTION - 1 {
+        for x in 0..RESOLUTION - 1 {
+            let i = (z * RESOLUTION + x) as u16;
+            let below = i + RESOLUTION as u16;
+            indices.extend_from_slice(&[i, below, i + 1, i + 1, below, below + 1]);
+        }
+    }
+
+    (vertices, indices)
+}
+
+pub struct TerrainScene {
+    compute_pipeline: wgpu::ComputePipeline,
+    compute_bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    mesh: IndexedVertexBuffer<TexturedVertex>,
+    /// Read by the vertex shader, so it stays alive alongside the bind groups
+    heights_buffer: wgpu::Buffer,
+    params: TerrainParamsUniform,
+    params_buffer: wgpu::Buffer,
+    /// Set whenever the parameters change, so that heights get regenerated
+    dirty: bool,
+    terrain_bind_group: wgpu::BindGroup,
+    camera: Camera,
+    camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    params_belt: StagerHandle,
+    camera_belt: StagerHandle,
+}
+
+impl Scene for TerrainScene {
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        _assets: &Assets,
+    ) -> Self {
+        let comp_module = shaders::TERRAIN_COMP.create_module(device);
+        let vert_module = shaders::TERRAIN_VERT.create_module(device);
+        let frag_module = shaders::TERRAIN_FRAG.create_module(device);
+
+        let (vertices, indices) = grid();
+        let mesh = staging.uploader().upload(queue, |encoder, stager| {
+            IndexedVertexBuffer::from_vertices_indexes_staged(
+                device,
+                encoder,
+                stager,
+                &vertices,
+                &indices,
+                Some("Terrain - Vertex Buffer"),
+                Some("Terrain - Index Buffer"),
+            )
+        });
+
+        let heights_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Terrain - Heights Storage Buffer"),
+            size: (RESOLUTION * RESOLUTION) as wgpu::BufferAddress
+                * std::mem::size_of::<f32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let params = TerrainParamsUniform {
+            seed: 1,
+            octaves: 6,
+            resolution: RESOLUTION,
+            _padding0: 0,
+            frequency: 0.15,
+            amplitude: 3.0,
+            cell_size: CELL_SIZE,
+            _padding1: 0.0,
+        };
+        let params_buffer = params.create_buffer(device, Some("Terrain - Params Buffer"));
+        let params_belt = staging.create_stager(
+            "terrain.params",
+            std::mem::size_of::<TerrainParamsUniform>() as u64,
+        );
+
+        let compute_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Terrain - Compute Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Terrain - Compute Bind Group"),
+            layout: &compute_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: heights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Terrain - Compute Pipeline Layout"),
+                bind_group_layouts: &[&compute_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Terrain - Compute Pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &comp_module,
+            entry_point: "main",
+        });
+
+        let camera = Camera {
+            eye: (0.0, 12.0, 22.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: sc.width as f32 / sc.height as f32,
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 100.0,
+        };
+
+        let camera_controller = CameraController::new(12.0);
+
+        let mut camera_uniform = CameraUniform::default();
+        camera_uniform.update(&camera);
+        let camera_belt = staging.create_stager(
+            "terrain.camera",
+            std::mem::size_of::<CameraUniform>() as u64,
+        );
+
+        let camera_uniform_buf =
+            camera_uniform.create_buffer(device, Some("Terrain - Camera Uniform Buffer"));
+
+        let camera_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Terrain - Camera Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Terrain - Camera Bind Group"),
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_uniform_buf.as_entire_binding(),
+            }],
+        });
+
+        let terrain_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Terrain - Terrain Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let terrain_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Terrain - Terrain Bind Group"),
+            layout: &terrain_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: heights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Terrain - Render Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout, &terrain_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Terrain - Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[mesh.descriptor()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DepthTexture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            compute_pipeline,
+            compute_bind_group,
+            render_pipeline,
+            mesh,
+            heights_buffer,
+            params,
+            params_buffer,
+            dirty: true,
+            terrain_bind_group,
+            camera,
+            camera_controller,
+            camera_uniform,
+            camera_uniform_buffer: camera_uniform_buf,
+            camera_bind_group,
+            params_belt,
+            camera_belt,
+        }
+    }
+
+    fn destroy(&mut self) {
+        self.mesh.destroy();
+        self.heights_buffer.destroy();
+    }
+
+    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, input: &Input, _time: Time) {
+        if let Some(key) = input.just_pressed("terrain.reseed") {
+            println!("Pressed {:?}", key);
+            self.params.seed = self.params.seed.wrapping_add(1);
+            println!("Seed: {}", { self.params.seed });
+            self.dirty = true;
+        }
+        if let Some(key) = input.just_pressed("terrain.fewer_octaves") {
+            println!("Pressed {:?}", key);
+            self.params.octaves = self.params.octaves.saturating_sub(1).max(1);
+            println!("Octaves: {}", { self.params.octaves });
+            self.dirty = true;
+        }
+        if let Some(key) = input.just_pressed("terrain.more_octaves") {
+            println!("Pressed {:?}", key);
+            self.params.octaves = (self.params.octaves + 1).min(MAX_OCTAVES);
+            println!("Octaves: {}", { self.params.octaves });
+            self.dirty = true;
+        }
+        if let Some(key) = input.just_pressed("terrain.lower_frequency") {
+            println!("Pressed {:?}", key);
+            self.params.frequency /= 1.25;
+            println!("Frequency: {}", { self.params.frequency });
+            self.dirty = true;
+        }
+        if let Some(key) = input.just_pressed("terrain.raise_frequency") {
+            println!("Pressed {:?}", key);
+            self.params.frequency *= 1.25;
+            println!("Frequency: {}", { self.params.frequency });
+            self.dirty = true;
+        }
+
+        self.camera_controller.update(&mut self.camera, input);
+        self.camera_uniform.update(&self.camera);
+    }
+
+    fn compute(&mut self, encoder: &mut wgpu::CommandEncoder, staging: &StagingFactory) {
+        // Heights only change along with the parameters
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let mut params_stager = staging.fetch_stager(self.params_belt);
+        params_stager.write(encoder, &self.params_buffer, 0, &self.params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Terrain - Generation Pass"),
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+        compute_pass.dispatch(
+            RESOLUTION.div_ceil(WORKGROUP_SIZE),
+            RESOLUTION.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut camera_stager = staging.fetch_stager(self.camera_belt);
+        camera_stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("Terrain - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &state.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        };
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.terrain_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.mesh.vertices.slice(..));
+        render_pass.set_index_buffer(self.mesh.indices.slice(..), self.mesh.index_format);
+        render_pass.draw_indexed(0..self.mesh.num_indices, 0, 0..1);
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+    }
+}
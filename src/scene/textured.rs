@@ -1,8 +1,14 @@
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use std::sync::Arc;
 
 use crate::{
+    assets::{Assets, Handle},
     buffer::{IndexedVertexBuffer, StagingFactory},
-    texture::Texture,
+    cache::{LayoutCache, PipelineCache},
+    clock::Time,
+    input::Input,
+    pipeline::PipelineBuilder,
+    shader::shaders,
+    texture::{ColorSpace, Texture, TextureLoader},
     vertex::{Descriptable, TexturedVertex},
 };
 
@@ -45,15 +51,22 @@ const INDICES_1: &[u16] = &[0, 1, 2, 0, 2, 3, 0, 3, 4, 0, 4, 5, 0, 5, 6, 0, 6, 1
 enum SelectedImage {
     SanCheese,
     Nnubes,
+    /// Streamed in from disk, see `TextureLoader`
+    NnubesJpg,
 }
 
 pub struct TextureExampleScene {
     pipeline: wgpu::RenderPipeline,
     vertex_buffer: IndexedVertexBuffer<TexturedVertex>,
     diffuse1_bind_group: wgpu::BindGroup,
-    _diffuse1_texture: Texture,
+    diffuse1_texture: Handle<Texture>,
     diffuse2_bind_group: wgpu::BindGroup,
-    _diffuse2_texture: Texture,
+    diffuse2_texture: Handle<Texture>,
+    texture_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    /// A checkerboard until the loader is done
+    diffuse3_bind_group: wgpu::BindGroup,
+    _diffuse3_texture: Texture,
+    diffuse3_loader: TextureLoader,
     selected_image: SelectedImage,
 }
 
@@ -63,19 +76,20 @@ impl Scene for TextureExampleScene {
         queue: &wgpu::Queue,
         sc: &wgpu::SurfaceConfiguration,
         _staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        assets: &Assets,
     ) -> Self {
-        let diffuse1_bytes = include_bytes!("../../assets/sanCheese.png");
-        let diffuse1_texture =
-            Texture::from_bytes(device, queue, diffuse1_bytes, "San Cheese Is Watching You")
-                .unwrap();
+        let diffuse1_texture = assets
+            .texture(device, queue, "sanCheese.png", ColorSpace::Srgb)
+            .unwrap();
+        let diffuse2_texture = assets
+            .texture(device, queue, "nnubes256.png", ColorSpace::Srgb)
+            .unwrap();
 
-        let diffuse2_bytes = include_bytes!("../../assets/nnubes256.png");
-        let diffuse2_texture =
-            Texture::from_bytes(device, queue, diffuse2_bytes, "Nnubes256 Is Watching You")
-                .unwrap();
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        let texture_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
                 label: Some("San Cheese Is Laying Your Bounds"),
                 entries: &[
                     wgpu::BindGroupLayoutEntry {
@@ -98,37 +112,32 @@ impl Scene for TextureExampleScene {
                         count: None,
                     },
                 ],
-            });
+            },
+        );
 
-        let diffuse1_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("San Cheese Is Binding You"),
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse1_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse1_texture.sampler),
-                },
-            ],
-        });
+        let diffuse1_bind_group = diffuse1_texture.get().create_bind_group(
+            device,
+            &texture_bind_group_layout,
+            "San Cheese Is Binding You",
+        );
+        let diffuse2_bind_group = diffuse2_texture.get().create_bind_group(
+            device,
+            &texture_bind_group_layout,
+            "San Cheese Is Binding You",
+        );
 
-        let diffuse2_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("San Cheese Is Binding You"),
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse2_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse2_texture.sampler),
-                },
-            ],
-        });
+        // Unlike the others, read and decoded off this thread
+        let diffuse3_loader = TextureLoader::spawn(
+            Assets::path("nnubes256.jpg"),
+            ColorSpace::Srgb,
+            "Nnubes256 Is Streaming Into You",
+        );
+        let diffuse3_texture = Texture::checkerboard(device, queue, "Nnubes256 Is Loading");
+        let diffuse3_bind_group = diffuse3_texture.create_bind_group(
+            device,
+            &texture_bind_group_layout,
+            "Nnubes256 Is Loading",
+        );
 
         let vertex_buffer = IndexedVertexBuffer::from_vertices_indexes(
             device,
@@ -138,10 +147,8 @@ impl Scene for TextureExampleScene {
             Some("San Cheese Is Indexing You"),
         );
 
-        let vert1_module =
-            device.create_shader_module(&wgpu::include_spirv!("../shaders/myfirstshader.vert.spv"));
-        let frag1_module =
-            device.create_shader_module(&wgpu::include_spirv!("../shaders/myfirstshader.frag.spv"));
+        let vert1_module = shaders::MYFIRSTSHADER_VERT.create_module(device);
+        let frag1_module = shaders::MYFIRSTSHADER_FRAG.create_module(device);
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("San Cheese Is Planning Your Pipes"),
@@ -193,79 +200,73 @@ impl Scene for TextureExampleScene {
             alpha_to_coverage_enabled: false,
         });*/
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("San Cheese Is Laying Your Pipes"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vert1_module,
-                entry_point: "main",
-                buffers: &[TexturedVertex::descriptor()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &frag1_module,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::OVER,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                clamp_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-        });
+        let pipeline =
+            PipelineBuilder::new(&pipeline_layout, &vert1_module, &frag1_module, sc.format)
+                .label("San Cheese Is Laying Your Pipes")
+                .vertex_shader("myfirstshader.vert")
+                .buffers(&[TexturedVertex::descriptor()])
+                .blend(wgpu::BlendState {
+                    color: wgpu::BlendComponent::OVER,
+                    alpha: wgpu::BlendComponent::REPLACE,
+                })
+                .build(device);
 
         Self {
             pipeline,
             vertex_buffer,
             diffuse1_bind_group,
-            _diffuse1_texture: diffuse1_texture,
+            diffuse1_texture,
             diffuse2_bind_group,
-            _diffuse2_texture: diffuse2_texture,
+            diffuse2_texture,
+            texture_bind_group_layout,
+            diffuse3_bind_group,
+            _diffuse3_texture: diffuse3_texture,
+            diffuse3_loader,
             selected_image: SelectedImage::Nnubes,
         }
     }
 
-    fn input(&mut self, event: &winit::event::WindowEvent) -> bool {
-        match event {
-            WindowEvent::KeyboardInput { input, .. } => {
-                if let KeyboardInput {
-                    state: ElementState::Pressed,
-                    virtual_keycode: Some(VirtualKeyCode::N),
-                    ..
-                } = input
-                {
-                    println!("Pressed N");
-                    self.selected_image = match self.selected_image {
-                        SelectedImage::SanCheese => SelectedImage::Nnubes,
-                        SelectedImage::Nnubes => SelectedImage::SanCheese,
-                    };
-                    println!("{:?}", self.selected_image);
-                }
+    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, input: &Input, _time: Time) {
+        if let Some(key) = input.just_pressed("textured.toggle_texture") {
+            println!("Pressed {:?}", key);
+            self.selected_image = match self.selected_image {
+                SelectedImage::SanCheese => SelectedImage::Nnubes,
+                SelectedImage::Nnubes => SelectedImage::NnubesJpg,
+                SelectedImage::NnubesJpg => SelectedImage::SanCheese,
+            };
+            println!("{:?}", self.selected_image);
+        }
+
+        if self.diffuse1_texture.reloaded() {
+            self.diffuse1_bind_group = self.diffuse1_texture.get().create_bind_group(
+                device,
+                &self.texture_bind_group_layout,
+                "San Cheese Is Binding You",
+            );
+        }
+        if self.diffuse2_texture.reloaded() {
+            self.diffuse2_bind_group = self.diffuse2_texture.get().create_bind_group(
+                device,
+                &self.texture_bind_group_layout,
+                "San Cheese Is Binding You",
+            );
+        }
 
-                true
+        match self.diffuse3_loader.poll(device, queue) {
+            Some(Ok(texture)) => {
+                self.diffuse3_bind_group = texture.create_bind_group(
+                    device,
+                    &self.texture_bind_group_layout,
+                    "Nnubes256 Is Binding You",
+                );
+                self._diffuse3_texture = texture;
             }
-            _ => false,
+            // The checkerboard stays then
+            Some(Err(e)) => println!("{:?}", e),
+            None => {}
         }
     }
 
-    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue) {}
-
     fn render(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
@@ -294,12 +295,13 @@ impl Scene for TextureExampleScene {
         let selected_bind_group = match self.selected_image {
             SelectedImage::SanCheese => &self.diffuse1_bind_group,
             SelectedImage::Nnubes => &self.diffuse2_bind_group,
+            SelectedImage::NnubesJpg => &self.diffuse3_bind_group,
         };
 
         render_pass.set_bind_group(0, selected_bind_group, &[]);
         render_pass.set_index_buffer(
             self.vertex_buffer.indices.slice(..),
-            wgpu::IndexFormat::Uint16,
+            self.vertex_buffer.index_format,
         );
         render_pass.draw_indexed(0..self.vertex_buffer.num_indices, 0, 0..1);
 
@@ -1,5 +1,11 @@
 use crate::{
+    assets::Assets,
     buffer::{StagingFactory, VertexBuffer, VertexTypedBuffer},
+    cache::{LayoutCache, PipelineCache},
+    clock::Time,
+    input::Input,
+    pipeline::PipelineBuilder,
+    shader::shaders,
     vertex::FlatVertex,
     GlobalState,
 };
@@ -44,11 +50,12 @@ impl Scene for TriangleScene {
         _queue: &wgpu::Queue,
         sc: &wgpu::SurfaceConfiguration,
         _staging: &mut StagingFactory,
+        _layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        _assets: &Assets,
     ) -> Self {
-        let vert3_module =
-            device.create_shader_module(&wgpu::include_spirv!("../shaders/dima.vert.spv"));
-        let frag3_module =
-            device.create_shader_module(&wgpu::include_spirv!("../shaders/dima.frag.spv"));
+        // Both stages in a single WGSL module, which wgpu takes as is
+        let module = shaders::TRIANGLE_WGSL.create_module(device);
 
         let vertex_buffer =
             VertexBuffer::from_vertices(device, VERTICES_3, Some("Funny Triangle - Vertex Buffer"));
@@ -94,39 +101,12 @@ impl Scene for TriangleScene {
             sample_mask: !0,
             alpha_to_coverage_enabled: false,
         });*/
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("San Cheese Is Laying Your Pipes"),
-            layout: Some(&pipeline_layout1),
-            vertex: wgpu::VertexState {
-                module: &vert3_module,
-                entry_point: "main",
-                buffers: &[vertex_buffer.descriptor()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &frag3_module,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                clamp_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-        });
+        let pipeline = PipelineBuilder::new(&pipeline_layout1, &module, &module, sc.format)
+            .label("San Cheese Is Laying Your Pipes")
+            .vertex_shader("triangle.wgsl")
+            .entry_points("vs_main", "fs_main")
+            .buffers(&[vertex_buffer.descriptor()])
+            .build(device);
 
         Self {
             pipeline,
@@ -134,12 +114,15 @@ impl Scene for TriangleScene {
         }
     }
 
-    fn input(&mut self, _event: &winit::event::WindowEvent) -> bool {
-        false
+    fn update(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _input: &Input,
+        _time: Time,
+    ) {
     }
 
-    fn update(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue) {}
-
     fn render(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
@@ -0,0 +1,465 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use cgmath::{Deg, Matrix4, Rad, Vector3};
+
+use crate::{
+    assets::Assets,
+    buffer::{
+        DynamicUniformBuffer, IndexedVertexBuffer, StagerHandle, StagingFactory, Uniformable,
+    },
+    cache::{CachedPipelineLayout, LayoutCache, PipelineCache, PipelineSources},
+    camera::{Camera, CameraController, CameraUniform, Projection},
+    clock::Time,
+    input::Input,
+    layout::Std140,
+    mesh::primitives,
+    model::ModelVertex,
+    pipeline::PipelineBuilder,
+    shader::{shaders, ShaderVariants},
+    texture::DepthTexture,
+    vertex::Descriptable,
+};
+
+use super::Scene;
+
+/// Objects in every ring, and rings stacked on top of each other
+const RING_SIZE: u32 = 16;
+const RINGS: u32 = 3;
+const OBJECT_COUNT: u32 = RING_SIZE * RINGS;
+const RING_RADIUS: f32 = 5.0;
+const RING_SPACING: f32 = 1.5;
+
+/// Samples per pixel with MSAA on
+const MSAA_SAMPLES: u32 = 4;
+
+/// Everything an object gets drawn with, which lives at its own dynamic offset
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+struct ObjectUniform {
+    model: [[f32; 4]; 4],
+    color: [f32; 4],
+}
+
+/// Cosine palette going all around the hue wheel
+fn palette(t: f32) -> [f32; 4] {
+    let channel = |phase: f32| 0.5 + 0.5 * (2.0 * PI * (t + phase)).cos();
+    [channel(0.0), channel(1.0 / 3.0), channel(2.0 / 3.0), 1.0]
+}
+
+/// Everything the render pipeline gets built out of, other than the options
+/// that can be toggled
+struct ObjectPipeline {
+    layout: CachedPipelineLayout,
+    vert_module: wgpu::ShaderModule,
+    /// With and without `ENABLE_FOG`
+    frag_variants: ShaderVariants,
+    format: wgpu::TextureFormat,
+}
+
+impl ObjectPipeline {
+    /// Only builds the pipeline the first time a combination of options gets
+    /// asked for, the cache hands out the same one afterwards
+    fn build(
+        &self,
+        device: &wgpu::Device,
+        pipelines: &PipelineCache,
+        wireframe: bool,
+        sample_count: u32,
+        fog: bool,
+    ) -> Arc<wgpu::RenderPipeline> {
+        let defines: &[&str] = if fog { &["ENABLE_FOG"] } else { &[] };
+        let frag_module = self.frag_variants.get(defines);
+
+        PipelineBuilder::new(&self.layout, &self.vert_module, frag_module, self.format)
+            .label("Uniforms - Render Pipeline")
+            .vertex_shader("uniforms.vert")
+            .buffers(&[ModelVertex::descriptor()])
+            .polygon_mode(if wireframe {
+                wgpu::PolygonMode::Line
+            } else {
+                wgpu::PolygonMode::Fill
+            })
+            .depth(true)
+            .sample_count(sample_count)
+            .build_cached(
+                device,
+                pipelines,
+                PipelineSources {
+                    layout: self.layout.id,
+                    vertex: shaders::UNIFORMS_VERT.id(),
+                    fragment: Some(self.frag_variants.id(defines)),
+                },
+            )
+    }
+}
+
+pub struct DynamicUniformsScene {
+    object_pipeline: ObjectPipeline,
+    render_pipeline: Arc<wgpu::RenderPipeline>,
+    pipelines: PipelineCache,
+    wireframe: bool,
+    /// Whether the device can draw wireframes at all
+    wireframe_supported: bool,
+    msaa: bool,
+    fog: bool,
+    /// Rebuilds the pipeline and the attachments on the next update
+    options_changed: bool,
+    /// Drawn into instead of the frame with MSAA on, and resolved into it
+    msaa_view: Option<wgpu::TextureView>,
+    size: winit::dpi::PhysicalSize<u32>,
+    shapes: Vec<IndexedVertexBuffer<ModelVertex>>,
+    objects: DynamicUniformBuffer<ObjectUniform>,
+    /// A single bind group for every object, bound at a different dynamic
+    /// offset for each of them
+    object_bind_group: wgpu::BindGroup,
+    time: f32,
+    camera: Camera,
+    camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    depth_texture: DepthTexture,
+    camera_belt: StagerHandle,
+    object_belt: StagerHandle,
+}
+
+impl DynamicUniformsScene {
+    fn sample_count(&self) -> u32 {
+        if self.msaa {
+            MSAA_SAMPLES
+        } else {
+            1
+        }
+    }
+
+    /// (Re)creates the depth texture, and the multisampled color target with
+    /// MSAA on, at the current size and sample count
+    fn create_attachments(&mut self, device: &wgpu::Device) {
+        let sample_count = self.sample_count();
+        self.depth_texture = DepthTexture::multisampled(
+            device,
+            self.size.width,
+            self.size.height,
+            sample_count,
+            Some("Uniforms - Depth Texture"),
+        );
+
+        self.msaa_view = if self.msaa {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Uniforms - MSAA Color Texture"),
+                size: wgpu::Extent3d {
+                    width: self.size.width,
+                    height: self.size.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.object_pipeline.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            });
+            Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+        } else {
+            None
+        };
+    }
+
+    /// Spins every object around its own axis, and every ring around the
+    /// center, in alternating directions
+    fn update_objects(&mut self) {
+        for i in 0..OBJECT_COUNT {
+            let ring = i / RING_SIZE;
+            let slot = i % RING_SIZE;
+            let direction = if ring.is_multiple_of(2) { 1.0 } else { -1.0 };
+
+            let angle = 2.0 * PI * slot as f32 / RING_SIZE as f32 + direction * self.time * 0.2;
+            let height = (ring as f32 - (RINGS - 1) as f32 / 2.0) * RING_SPACING;
+            let bob = (self.time * 2.0 + slot as f32).sin() * 0.15;
+            let position = Vector3::new(
+                angle.cos() * RING_RADIUS,
+                height + bob,
+                angle.sin() * RING_RADIUS,
+            );
+
+            let model = Matrix4::from_translation(position)
+                * Matrix4::from_angle_y(Rad(self.time * (1.0 + slot as f32 * 0.1)))
+                * Matrix4::from_angle_x(Deg(30.0 * direction));
+
+            self.objects.set(
+                i,
+                &ObjectUniform {
+                    model: model.into(),
+                    color: palette(i as f32 / OBJECT_COUNT as f32),
+                },
+            );
+        }
+    }
+}
+
+impl Scene for DynamicUniformsScene {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        pipelines: &PipelineCache,
+        _assets: &Assets,
+    ) -> Self {
+        let vert_module = shaders::UNIFORMS_VERT.create_module(device);
+        let frag_variants = ShaderVariants::new(device, "uniforms.frag");
+
+        let shapes = vec![
+            primitives::cube(0.6, 0).to_buffer(device, Some("Uniforms - Cube")),
+            primitives::uv_sphere(0.4, 24, 12).to_buffer(device, Some("Uniforms - UV Sphere")),
+            primitives::icosphere(0.4, 1).to_buffer(device, Some("Uniforms - Icosphere")),
+            primitives::cylinder(0.3, 0.7, 24).to_buffer(device, Some("Uniforms - Cylinder")),
+            primitives::torus(0.3, 0.12, 24, 12).to_buffer(device, Some("Uniforms - Torus")),
+        ];
+
+        let camera = Camera {
+            eye: (0.0, 6.0, 12.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: sc.width as f32 / sc.height as f32,
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 100.0,
+        };
+
+        let camera_controller = CameraController::new(12.0);
+
+        let mut camera_uniform = CameraUniform::default();
+        camera_uniform.update(&camera);
+        let camera_belt = staging.create_stager(
+            "uniforms.camera",
+            std::mem::size_of::<CameraUniform>() as u64,
+        );
+
+        let camera_uniform_buf =
+            camera_uniform.create_buffer(device, Some("Uniforms - Camera Uniform Buffer"));
+
+        let camera_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Uniforms - Camera Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Uniforms - Camera Bind Group"),
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_uniform_buf.as_entire_binding(),
+            }],
+        });
+
+        let objects = DynamicUniformBuffer::new(
+            device,
+            OBJECT_COUNT,
+            Some("Uniforms - Object Uniform Buffer"),
+        );
+        let object_belt = staging.create_stager("uniforms.objects", objects.size());
+
+        let object_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Uniforms - Object Bind Group Layout"),
+                entries: &[DynamicUniformBuffer::<ObjectUniform>::layout_entry(
+                    0,
+                    wgpu::ShaderStages::VERTEX,
+                )],
+            },
+        );
+
+        let object_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Uniforms - Object Bind Group"),
+            layout: &object_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: objects.binding(),
+            }],
+        });
+
+        let render_pipeline_layout = layouts.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Uniforms - Render Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout, &object_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let object_pipeline = ObjectPipeline {
+            layout: render_pipeline_layout,
+            vert_module,
+            frag_variants,
+            format: sc.format,
+        };
+        let render_pipeline = object_pipeline.build(device, pipelines, false, 1, false);
+
+        let depth_texture = DepthTexture::from_screen(
+            device,
+            sc.width,
+            sc.height,
+            Some("Uniforms - Depth Texture"),
+        );
+
+        let mut scene = Self {
+            object_pipeline,
+            render_pipeline,
+            pipelines: pipelines.clone(),
+            wireframe: false,
+            wireframe_supported: device
+                .features()
+                .contains(wgpu::Features::NON_FILL_POLYGON_MODE),
+            msaa: false,
+            fog: false,
+            options_changed: false,
+            msaa_view: None,
+            size: winit::dpi::PhysicalSize::new(sc.width, sc.height),
+            shapes,
+            objects,
+            object_bind_group,
+            time: 0.0,
+            camera,
+            camera_controller,
+            camera_uniform,
+            camera_uniform_buffer: camera_uniform_buf,
+            camera_bind_group,
+            depth_texture,
+            camera_belt,
+            object_belt,
+        };
+        scene.update_objects();
+        scene
+    }
+
+    fn update(&mut self, device: &wgpu::Device, _queue: &wgpu::Queue, input: &Input, time: Time) {
+        if let Some(key) = input.just_pressed("uniforms.toggle_wireframe") {
+            println!("Pressed {:?}", key);
+            if self.wireframe_supported {
+                self.wireframe = !self.wireframe;
+                self.options_changed = true;
+                println!("Wireframe: {:?}", self.wireframe);
+            } else {
+                println!("Wireframe: unsupported (no NON_FILL_POLYGON_MODE)");
+            }
+        }
+        if let Some(key) = input.just_pressed("uniforms.toggle_msaa") {
+            println!("Pressed {:?}", key);
+            self.msaa = !self.msaa;
+            self.options_changed = true;
+            println!("MSAA: {:?}", self.msaa);
+        }
+        if let Some(key) = input.just_pressed("uniforms.toggle_fog") {
+            println!("Pressed {:?}", key);
+            self.fog = !self.fog;
+            self.options_changed = true;
+            println!("Fog: {:?}", self.fog);
+        }
+        if let Some(key) = input.just_pressed("uniforms.print_stats") {
+            println!("Pressed {:?}", key);
+            println!(
+                "{} objects in a single {}-byte buffer: {} bytes of uniforms every {} bytes",
+                self.objects.capacity(),
+                self.objects.size(),
+                std::mem::size_of::<ObjectUniform>(),
+                self.objects.stride()
+            );
+        }
+
+        if self.options_changed {
+            self.options_changed = false;
+            self.render_pipeline = self.object_pipeline.build(
+                device,
+                &self.pipelines,
+                self.wireframe,
+                self.sample_count(),
+                self.fog,
+            );
+            self.create_attachments(device);
+        }
+
+        self.camera_controller.update(&mut self.camera, input);
+        self.camera_uniform.update(&self.camera);
+
+        self.time += time.delta;
+        self.update_objects();
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut camera_stager = staging.fetch_stager(self.camera_belt);
+        camera_stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+
+        let mut object_stager = staging.fetch_stager(self.object_belt);
+        self.objects.upload(encoder, &mut object_stager);
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("Uniforms - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: self.msaa_view.as_ref().unwrap_or(frame_view),
+                resolve_target: self.msaa_view.as_ref().map(|_| frame_view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        };
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+
+        // Same bind group every time, only the offset changes
+        for i in 0..OBJECT_COUNT {
+            let shape = &self.shapes[i as usize % self.shapes.len()];
+            render_pass.set_bind_group(1, &self.object_bind_group, &[self.objects.offset(i)]);
+            shape.bind(&mut render_pass, 0);
+            render_pass.draw_indexed(0..shape.num_indices, 0, 0..1);
+        }
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+        self.create_attachments(device);
+    }
+}
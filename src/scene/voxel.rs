@@ -0,0 +1,383 @@
+use cgmath::Vector3;
+
+use crate::{
+    assets::Assets,
+    batch::{MeshBatch, MeshBatchBuilder},
+    buffer::{DrawIndexedIndirectArgs, StagerHandle, StagingFactory, Uniformable},
+    cache::{LayoutCache, PipelineCache},
+    camera::{Camera, CameraController, CameraUniform, Projection, Smoothing},
+    clock::Time,
+    input::Input,
+    shader::shaders,
+    texture::{ColorSpace, DepthTexture, SamplerDesc, Texture},
+    vertex::Descriptable,
+    voxel::{self, VoxelVertex, World, CHUNK_SIZE},
+};
+
+use super::Scene;
+
+/// Size of the world, in chunks
+const WORLD_SIZE: Vector3<i32> = Vector3::new(8, 2, 8);
+
+struct ChunkMesh {
+    min: cgmath::Point3<f32>,
+    max: cgmath::Point3<f32>,
+    /// Mesh in the world's batch, `None` for chunks without any visible face
+    mesh: Option<usize>,
+}
+
+/// Every chunk gets packed into the same vertex and index buffers, with room
+/// for drawing all of them at once
+fn mesh_world(device: &wgpu::Device, world: &World) -> (Vec<ChunkMesh>, MeshBatch<VoxelVertex>) {
+    let mut batch = MeshBatchBuilder::new();
+    let chunks = world
+        .chunks
+        .iter()
+        .map(|chunk| {
+            let (min, max) = chunk.aabb();
+            let (vertices, indices) = world.mesh_chunk(chunk);
+            let mesh = if indices.is_empty() {
+                None
+            } else {
+                Some(batch.push(&vertices, &indices))
+            };
+
+            ChunkMesh { min, max, mesh }
+        })
+        .collect::<Vec<_>>();
+
+    let batch = batch.build(device, chunks.len() as u32, "Voxel - Chunk Batch");
+    (chunks, batch)
+}
+
+pub struct VoxelScene {
+    render_pipeline: wgpu::RenderPipeline,
+    atlas_bind_group: wgpu::BindGroup,
+    seed: u32,
+    /// Set when the world needs to be regenerated and remeshed
+    regenerate: bool,
+    chunks: Vec<ChunkMesh>,
+    /// Holds the meshes of every chunk, and the draws of the visible ones
+    batch: Option<MeshBatch<VoxelVertex>>,
+    culling: bool,
+    /// Whether visible chunks are drawn out of indirect arguments, or with a
+    /// `draw_indexed` each
+    indirect: bool,
+    camera: Camera,
+    camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_uniform_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    camera_belt: StagerHandle,
+    draw_belt: StagerHandle,
+}
+
+impl Scene for VoxelScene {
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc: &wgpu::SurfaceConfiguration,
+        staging: &mut StagingFactory,
+        layouts: &LayoutCache,
+        _pipelines: &PipelineCache,
+        assets: &Assets,
+    ) -> Self {
+        let vert_module = shaders::VOXEL_VERT.create_module(device);
+        let frag_module = shaders::VOXEL_FRAG.create_module(device);
+
+        let atlas = Texture::from_image(
+            device,
+            queue,
+            &voxel::atlas(),
+            ColorSpace::Srgb,
+            Some("Voxel - Atlas"),
+        )
+        .expect("Failed to create the block atlas");
+        // Keep the blocks crisp, and avoid bleeding between atlas tiles
+        let atlas_sampler = assets.sampler(device, &SamplerDesc::nearest()).get();
+
+        let atlas_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Voxel - Atlas Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: false,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Voxel - Atlas Bind Group"),
+            layout: &atlas_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&atlas.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&atlas_sampler),
+                },
+            ],
+        });
+
+        let center = (WORLD_SIZE * CHUNK_SIZE).map(|c| c as f32) / 2.0;
+        let camera = Camera {
+            eye: (center.x + 70.0, 60.0, center.z + 70.0).into(),
+            target: (center.x, center.y / 2.0, center.z).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: sc.width as f32 / sc.height as f32,
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 400.0,
+        };
+
+        // Crossing the whole world takes a while, so ease in and out of it
+        // slower, and zoom faster
+        let camera_controller = CameraController::new(60.0)
+            .with_smoothing(Smoothing {
+                acceleration: 4.0,
+                damping: 3.0,
+            })
+            .with_zoom(0.2, 4.0);
+
+        let mut camera_uniform = CameraUniform::default();
+        camera_uniform.update(&camera);
+        let camera_belt =
+            staging.create_stager("voxel.camera", std::mem::size_of::<CameraUniform>() as u64);
+        let draw_belt = staging.create_stager(
+            "voxel.draws",
+            (WORLD_SIZE.x * WORLD_SIZE.y * WORLD_SIZE.z) as u64
+                * std::mem::size_of::<DrawIndexedIndirectArgs>() as u64,
+        );
+
+        let camera_uniform_buf =
+            camera_uniform.create_buffer(device, Some("Voxel - Camera Uniform Buffer"));
+
+        let camera_bind_group_layout = layouts.get(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Voxel - Camera Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Voxel - Camera Bind Group"),
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_uniform_buf.as_entire_binding(),
+            }],
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Voxel - Render Pipeline Layout"),
+                bind_group_layouts: &[&atlas_bind_group_layout, &camera_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Voxel - Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[VoxelVertex::descriptor()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DepthTexture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            render_pipeline,
+            atlas_bind_group,
+            seed: 0,
+            regenerate: true,
+            chunks: Vec::new(),
+            batch: None,
+            culling: true,
+            indirect: true,
+            camera,
+            camera_controller,
+            camera_uniform,
+            camera_uniform_buffer: camera_uniform_buf,
+            camera_bind_group,
+            camera_belt,
+            draw_belt,
+        }
+    }
+
+    fn destroy(&mut self) {
+        if let Some(batch) = self.batch.take() {
+            batch.destroy();
+        }
+    }
+
+    fn update(&mut self, device: &wgpu::Device, _queue: &wgpu::Queue, input: &Input, _time: Time) {
+        if let Some(key) = input.just_pressed("voxel.reseed") {
+            println!("Pressed {:?}", key);
+            self.seed = self.seed.wrapping_add(1);
+            self.regenerate = true;
+            println!("Seed: {}", self.seed);
+        }
+        if let Some(key) = input.just_pressed("voxel.toggle_culling") {
+            println!("Pressed {:?}", key);
+            self.culling = !self.culling;
+            println!("Frustum culling: {:?}", self.culling);
+        }
+        if let Some(key) = input.just_pressed("voxel.toggle_indirect") {
+            println!("Pressed {:?}", key);
+            self.indirect = !self.indirect;
+            let multi_draw = self.batch.as_ref().is_some_and(MeshBatch::multi_draw);
+            println!(
+                "Indirect draws: {:?} (multi_draw_indirect: {:?})",
+                self.indirect, multi_draw
+            );
+        }
+
+        if self.regenerate {
+            self.regenerate = false;
+            let world = World::generate(WORLD_SIZE, self.seed);
+            let (chunks, batch) = mesh_world(device, &world);
+            self.chunks = chunks;
+            self.batch = Some(batch);
+        }
+
+        self.camera_controller.update(&mut self.camera, input);
+        self.camera_uniform.update(&self.camera);
+
+        let batch = match &mut self.batch {
+            Some(batch) => batch,
+            None => return,
+        };
+        let frustum = self.camera.frustum();
+        batch.clear();
+        for chunk in self.chunks.iter() {
+            if let Some(mesh) = chunk.mesh {
+                if !self.culling || frustum.intersects_aabb(chunk.min, chunk.max) {
+                    batch.draw(mesh, 0..1);
+                }
+            }
+        }
+    }
+
+    fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        state: &crate::GlobalState,
+        staging: &StagingFactory,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut camera_stager = staging.fetch_stager(self.camera_belt);
+        camera_stager.write(
+            encoder,
+            &self.camera_uniform_buffer,
+            0,
+            &self.camera_uniform,
+        );
+
+        if let (true, Some(batch)) = (self.indirect, &self.batch) {
+            let mut draw_stager = staging.fetch_stager(self.draw_belt);
+            batch.upload(encoder, &mut draw_stager);
+        }
+
+        let rp_desc = &wgpu::RenderPassDescriptor {
+            label: Some("Voxel - Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.bg_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &state.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        };
+
+        let mut render_pass = encoder.begin_render_pass(rp_desc);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.atlas_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+
+        match &self.batch {
+            Some(batch) if self.indirect => batch.render(&mut render_pass),
+            Some(batch) => batch.render_direct(&mut render_pass),
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    fn resize(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+    }
+}
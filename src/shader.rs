@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+/// A shader's file name, the defines a variant of it got compiled with and
+/// its module
+type ShaderVariant = (
+    &'static str,
+    &'static [&'static str],
+    fn() -> wgpu::ShaderModuleDescriptor<'static>,
+);
+/// A vertex shader's file name, an entry point and its inputs' locations,
+/// kinds and component counts
+type VertexInputs = (&'static str, &'static str, &'static [(u32, InputKind, u8)]);
+
+include!(concat!(env!("OUT_DIR"), "/shader_variants.rs"));
+include!(concat!(env!("OUT_DIR"), "/shader_inputs.rs"));
+
+/// Every shader `build.rs` compiled (or validated, for WGSL), as constants
+/// named after their file names (`instancing.vert` is `INSTANCING_VERT`)
+// Some only ever get loaded through their variants
+#[allow(dead_code)]
+pub mod shaders {
+    use super::CompiledShader;
+
+    include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
+}
+
+/// Identifies a shader module by what it was created from: the shader's file
+/// name and, for a variant, its defines
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderId {
+    name: &'static str,
+    defines: String,
+}
+
+/// A shader embedded into the executable, ready to be turned into a module
+pub enum CompiledShader {
+    Spirv {
+        name: &'static str,
+        spirv: &'static [u8],
+    },
+    Wgsl {
+        name: &'static str,
+        source: &'static str,
+    },
+}
+
+impl CompiledShader {
+    /// File name of the shader, e.g. `instancing.vert`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Self::Spirv { name, .. } | Self::Wgsl { name, .. } => name,
+        }
+    }
+
+    /// What modules out of `create_module` are told apart by
+    pub fn id(&self) -> ShaderId {
+        ShaderId {
+            name: self.name(),
+            defines: String::new(),
+        }
+    }
+
+    pub fn create_module(&self, device: &wgpu::Device) -> wgpu::ShaderModule {
+        let (name, source) = match *self {
+            Self::Spirv { name, spirv } => (name, wgpu::util::make_spirv(spirv)),
+            Self::Wgsl { name, source } => (name, wgpu::ShaderSource::Wgsl(source.into())),
+        };
+
+        device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some(name),
+            source,
+        })
+    }
+}
+
+/// Scalar type of a vertex shader input, which the vertex format feeding it
+/// has to agree with
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputKind {
+    Float,
+    Sint,
+    Uint,
+}
+
+impl InputKind {
+    /// What a shader reading an attribute of the given format sees. Normalized
+    /// and half formats read as floats
+    fn of_format(format: wgpu::VertexFormat) -> Self {
+        use wgpu::VertexFormat::*;
+
+        match format {
+            Uint8x2 | Uint8x4 | Uint16x2 | Uint16x4 | Uint32 | Uint32x2 | Uint32x3 | Uint32x4 => {
+                Self::Uint
+            }
+            Sint8x2 | Sint8x4 | Sint16x2 | Sint16x4 | Sint32 | Sint32x2 | Sint32x3 | Sint32x4 => {
+                Self::Sint
+            }
+            _ => Self::Float,
+        }
+    }
+}
+
+/// How many components an attribute of the given format has
+fn format_components(format: wgpu::VertexFormat) -> u8 {
+    use wgpu::VertexFormat::*;
+
+    match format {
+        Float32 | Uint32 | Sint32 | Float64 => 1,
+        Uint8x2 | Sint8x2 | Unorm8x2 | Snorm8x2 | Uint16x2 | Sint16x2 | Unorm16x2 | Snorm16x2
+        | Float16x2 | Float32x2 | Uint32x2 | Sint32x2 | Float64x2 => 2,
+        Float32x3 | Uint32x3 | Sint32x3 | Float64x3 => 3,
+        _ => 4,
+    }
+}
+
+/// Checks that `buffers` provide every input the vertex shader named `name`
+/// (its file name, e.g. `instancing.vert`) reads in `entry_point`, in formats
+/// of the kind it reads them as and with at least as many components. Extra
+/// components just get dropped, but missing ones would silently read as 0
+/// (or 1, for `w`). Panics naming the attribute otherwise, rather than leaving
+/// it to a validation error further down the line, or to nothing at all
+pub fn validate_vertex_buffers(
+    name: &str,
+    entry_point: &str,
+    buffers: &[wgpu::VertexBufferLayout],
+) {
+    let inputs = VERTEX_INPUTS
+        .iter()
+        .find(|(shader, entry, _)| *shader == name && *entry == entry_point)
+        .map(|(_, _, inputs)| *inputs)
+        .unwrap_or_else(|| panic!("No vertex shader named {} with a `{}`", name, entry_point));
+
+    for &(location, kind, components) in inputs {
+        let attribute = buffers.iter().enumerate().find_map(|(slot, buffer)| {
+            buffer
+                .attributes
+                .iter()
+                .find(|attribute| attribute.shader_location == location)
+                .map(|attribute| (slot, attribute))
+        });
+
+        match attribute {
+            None => panic!(
+                "{} reads a {}-component {:?} input at location {}, which none of its vertex buffers provide",
+                name, components, kind, location
+            ),
+            Some((slot, attribute)) if InputKind::of_format(attribute.format) != kind => panic!(
+                "{} reads a {}-component {:?} input at location {}, but vertex buffer {} provides it as {:?}",
+                name, components, kind, location, slot, attribute.format
+            ),
+            Some((slot, attribute)) if format_components(attribute.format) < components => panic!(
+                "{} reads a {}-component {:?} input at location {}, but vertex buffer {} only provides {:?}",
+                name, components, kind, location, slot, attribute.format
+            ),
+            Some(_) => {}
+        }
+    }
+}
+
+/// Every variant of a GLSL shader with a `// variants: A B ...` line, which
+/// `build.rs` compiles once for every combination of those defines
+pub struct ShaderVariants {
+    name: &'static str,
+    /// Keyed on the defines of every variant, sorted and joined with `+`
+    modules: HashMap<String, wgpu::ShaderModule>,
+}
+
+fn variant_key(defines: &[&str]) -> String {
+    let mut defines = defines.to_vec();
+    defines.sort_unstable();
+    defines.join("+")
+}
+
+impl ShaderVariants {
+    /// Creates a module for every variant of the shader named `name` (e.g.
+    /// `uniforms.frag`)
+    pub fn new(device: &wgpu::Device, name: &'static str) -> Self {
+        let modules = VARIANTS
+            .iter()
+            .filter(|(shader, _, _)| *shader == name)
+            .map(|(_, defines, descriptor)| {
+                (
+                    variant_key(defines),
+                    device.create_shader_module(&descriptor()),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        assert!(
+            !modules.is_empty(),
+            "{} has no variants, is it missing a `// variants:` line?",
+            name
+        );
+
+        Self { name, modules }
+    }
+
+    /// The variant compiled with exactly `defines`, in whatever order
+    pub fn get(&self, defines: &[&str]) -> &wgpu::ShaderModule {
+        self.modules
+            .get(&variant_key(defines))
+            .unwrap_or_else(|| panic!("{} has no variant with {:?}", self.name, defines))
+    }
+
+    /// What the variant `get` hands out for `defines` is told apart by
+    pub fn id(&self, defines: &[&str]) -> ShaderId {
+        let defines = variant_key(defines);
+        assert!(
+            self.modules.contains_key(&defines),
+            "{} has no variant with {:?}",
+            self.name,
+            defines
+        );
+        ShaderId {
+            name: self.name,
+            defines,
+        }
+    }
+}
@@ -0,0 +1,49 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec2 a_tex_coords;
+
+layout(location=5) in vec4 i_model_matrix_0;
+layout(location=6) in vec4 i_model_matrix_1;
+layout(location=7) in vec4 i_model_matrix_2;
+layout(location=8) in vec4 i_model_matrix_3;
+
+layout(location=0) out vec3 v_color;
+
+#include "common/camera.glsl"
+// One level per instance: a row per analyzed window, newest first
+layout(set=0, binding=1) readonly buffer Levels {
+    float levels[];
+};
+layout(set=0, binding=2) uniform Params {
+    uint u_bands;
+    uint u_history;
+    float u_max_height;
+    float _padding;
+};
+
+vec3 hue(float h) {
+    return clamp(abs(fract(h + vec3(0.0, 2.0, 1.0) / 3.0) * 6.0 - 3.0) - 1.0, 0.0, 1.0);
+}
+
+void main() {
+    uint band = gl_InstanceIndex % u_bands;
+    uint row = gl_InstanceIndex / u_bands;
+    float level = levels[gl_InstanceIndex];
+
+    // The hexagon's thickness runs along Z; stretch it from 0 up to the level
+    vec3 position = a_position;
+    position.z = (position.z + 0.5) * max(level * u_max_height, 0.05);
+
+    // Bass in red through treble in blue, fading out as rows get older
+    float age = float(row) / float(u_history);
+    v_color = hue(float(band) / float(u_bands) * 0.7) * (0.25 + 0.75 * level) * (1.0 - 0.8 * age);
+
+    mat4 i_model_matrix = mat4(
+        i_model_matrix_0,
+        i_model_matrix_1,
+        i_model_matrix_2,
+        i_model_matrix_3
+    );
+    gl_Position = u_view_proj * i_model_matrix * vec4(position, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,29 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec3 a_color;
+
+// Written by the compute shader
+layout(location=5) in vec2 i_position;
+layout(location=6) in vec2 i_velocity;
+
+layout(location=0) out vec3 v_color;
+
+layout(set = 0, binding = 0) uniform View {
+    float u_aspect;
+};
+
+void main() {
+    // Point the triangle (modelled facing +y) towards where the boid is heading
+    float speed = length(i_velocity);
+    vec2 forward = speed > 0.0 ? i_velocity / speed : vec2(0.0, 1.0);
+    vec2 right = vec2(forward.y, -forward.x);
+    vec2 position = i_position + a_position.x * right + a_position.y * forward;
+
+    // Letterbox the [-1, 1] world into the viewport
+    vec2 scale = u_aspect > 1.0 ? vec2(1.0 / u_aspect, 1.0) : vec2(1.0, u_aspect);
+    gl_Position = vec4(position * scale, 0.0, 1.0);
+
+    // Tint by heading
+    v_color = a_color * vec3(0.6 + 0.4 * forward, 1.0);
+}
\ No newline at end of file
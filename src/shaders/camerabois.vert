@@ -5,9 +5,8 @@ layout(location=1) in vec2 a_tex_coords;
 
 layout(location=0) out vec2 v_tex_coords;
 
-layout(set=1, binding=0) uniform Uniforms {
-    mat4 u_view_proj;
-};
+#define CAMERA_SET 1
+#include "common/camera.glsl"
 layout(set=1, binding=1) uniform ModelUniforms {
     mat4 u_model_proj;
 };
@@ -0,0 +1,21 @@
+#ifndef COMMON_CAMERA_GLSL
+#define COMMON_CAMERA_GLSL
+
+// The camera's matrices and position, as `camera::CameraUniform` lays them out.
+// Goes in set 0 unless CAMERA_SET is defined before the include
+#ifndef CAMERA_SET
+#define CAMERA_SET 0
+#endif
+
+layout(set=CAMERA_SET, binding=0) uniform Camera {
+    mat4 u_view_proj;
+    mat4 u_view;
+    mat4 u_proj;
+    mat4 u_inv_view_proj;
+    mat4 u_inv_view;
+    mat4 u_inv_proj;
+    // w is always 1
+    vec4 u_eye;
+};
+
+#endif
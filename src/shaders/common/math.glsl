@@ -0,0 +1,11 @@
+#ifndef COMMON_MATH_GLSL
+#define COMMON_MATH_GLSL
+
+const float PI = 3.14159265359;
+
+// Rotates a vector by a unit quaternion (xyz: vector part, w: scalar part)
+vec3 quat_rotate(vec4 q, vec3 v) {
+    return v + 2.0 * cross(q.xyz, cross(q.xyz, v) + q.w * v);
+}
+
+#endif
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=1) in vec4 v_color;
+
+layout(location=0) out vec4 f_color;
+
+void main() {
+    float falloff = 1.0 - smoothstep(0.0, 0.5, distance(v_tex_coords, vec2(0.5)));
+    f_color = vec4(v_color.rgb, v_color.a * falloff);
+}
\ No newline at end of file
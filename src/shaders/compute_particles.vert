@@ -0,0 +1,37 @@
+#version 450
+
+// Both come straight from the storage buffer the compute pass writes to
+layout(location=0) in vec4 i_position;
+layout(location=1) in vec4 i_velocity;
+
+layout(location=0) out vec2 v_tex_coords;
+layout(location=1) out vec4 v_color;
+
+#include "common/camera.glsl"
+layout(set=0, binding=1) uniform BillboardUniforms {
+    vec4 u_right;
+    vec4 u_up;
+};
+
+const vec2 corners[6] = vec2[6](
+    vec2(-0.5, -0.5),
+    vec2(0.5, -0.5),
+    vec2(0.5, 0.5),
+    vec2(-0.5, -0.5),
+    vec2(0.5, 0.5),
+    vec2(-0.5, 0.5)
+);
+
+void main() {
+    vec2 corner = corners[gl_VertexIndex];
+    float t = clamp(i_position.w / i_velocity.w, 0.0, 1.0);
+
+    // Particles that haven't spawned yet collapse into a degenerate quad
+    float size = i_position.w < 0.0 ? 0.0 : mix(0.12, 0.03, t);
+
+    v_tex_coords = corner + 0.5;
+    v_color = mix(vec4(0.4, 0.7, 1.0, 1.0), vec4(1.0, 0.3, 0.1, 0.0), t);
+
+    vec3 world_position = i_position.xyz + (u_right.xyz * corner.x + u_up.xyz * corner.y) * size;
+    gl_Position = u_view_proj * vec4(world_position, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,35 @@
+#version 450
+
+#include "common/math.glsl"
+
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform Face {
+    // Layer of the cube map being drawn: +X, -X, +Y, -Y, +Z, -Z
+    uint u_face;
+    float u_size;
+};
+layout(set=0, binding=1) uniform texture2D t_equirect;
+layout(set=0, binding=2) uniform sampler s_equirect;
+
+// Direction a texel of a cube map face points at, with face coordinates
+// going from -1 to 1 and t downwards, as cube map sampling has it
+vec3 face_direction(uint face, vec2 st) {
+    switch (face) {
+        case 0: return vec3(1.0, -st.y, -st.x);
+        case 1: return vec3(-1.0, -st.y, st.x);
+        case 2: return vec3(st.x, 1.0, st.y);
+        case 3: return vec3(st.x, -1.0, -st.y);
+        case 4: return vec3(st.x, -st.y, 1.0);
+        default: return vec3(-st.x, -st.y, -1.0);
+    }
+}
+
+void main() {
+    vec2 st = gl_FragCoord.xy / u_size * 2.0 - 1.0;
+    vec3 dir = normalize(face_direction(u_face, st));
+
+    // Longitude across, latitude down from +Y
+    vec2 uv = vec2(atan(dir.z, dir.x) / (2.0 * PI) + 0.5, acos(clamp(dir.y, -1.0, 1.0)) / PI);
+    f_color = vec4(texture(sampler2D(t_equirect, s_equirect), uv).rgb, 1.0);
+}
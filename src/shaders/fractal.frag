@@ -0,0 +1,130 @@
+#version 450
+
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform Fractal {
+    // The view center and the size of a pixel are double-floats: the value
+    // is hi + lo, with lo holding the bits that don't fit in hi
+    vec2 u_center_hi;
+    vec2 u_center_lo;
+    vec2 u_julia_c;
+    vec2 u_viewport;
+    float u_scale_hi;
+    float u_scale_lo;
+    uint u_max_iterations;
+    uint u_flags;
+};
+layout(set = 0, binding = 1) uniform Palette {
+    vec4 u_a;
+    vec4 u_b;
+    vec4 u_c;
+    vec4 u_d;
+};
+
+const uint FLAG_JULIA = 1;
+const uint FLAG_DOUBLE = 2;
+const float ESCAPE_RADIUS_SQ = 256.0;
+
+// Double-float arithmetic, after Dekker and Knuth. `precise` keeps the
+// compiler from simplifying away the rounding errors we are tracking.
+
+vec2 df_quick_two_sum(float a, float b) {
+    precise float s = a + b;
+    precise float e = b - (s - a);
+    return vec2(s, e);
+}
+
+vec2 df_two_sum(float a, float b) {
+    precise float s = a + b;
+    precise float v = s - a;
+    precise float e = (a - (s - v)) + (b - v);
+    return vec2(s, e);
+}
+
+vec2 df_split(float a) {
+    precise float t = 4097.0 * a;
+    precise float hi = t - (t - a);
+    precise float lo = a - hi;
+    return vec2(hi, lo);
+}
+
+vec2 df_two_prod(float a, float b) {
+    precise float p = a * b;
+    vec2 sa = df_split(a);
+    vec2 sb = df_split(b);
+    precise float e = ((sa.x * sb.x - p) + sa.x * sb.y + sa.y * sb.x) + sa.y * sb.y;
+    return vec2(p, e);
+}
+
+vec2 df_add(vec2 a, vec2 b) {
+    vec2 s = df_two_sum(a.x, b.x);
+    precise float e = s.y + a.y + b.y;
+    return df_quick_two_sum(s.x, e);
+}
+
+vec2 df_mul(vec2 a, vec2 b) {
+    vec2 p = df_two_prod(a.x, b.x);
+    precise float e = p.y + (a.x * b.y + a.y * b.x);
+    return df_quick_two_sum(p.x, e);
+}
+
+// Iterates z = z² + c, returning the (smoothed) iteration count it escaped
+// at, or a negative value if it never did
+float iterate_single(vec2 z, vec2 c) {
+    for (uint i = 0; i < u_max_iterations; i++) {
+        z = vec2(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y) + c;
+        float r2 = dot(z, z);
+        if (r2 > ESCAPE_RADIUS_SQ) {
+            return float(i) - log2(log2(r2)) + 4.0;
+        }
+    }
+    return -1.0;
+}
+
+float iterate_double(vec2 zx, vec2 zy, vec2 cx, vec2 cy) {
+    for (uint i = 0; i < u_max_iterations; i++) {
+        vec2 xx = df_mul(zx, zx);
+        vec2 yy = df_mul(zy, zy);
+        vec2 xy = df_mul(zx, zy);
+        zx = df_add(df_add(xx, -yy), cx);
+        zy = df_add(df_add(xy, xy), cy);
+        // The escape test doesn't need the extra precision
+        float r2 = zx.x * zx.x + zy.x * zy.x;
+        if (r2 > ESCAPE_RADIUS_SQ) {
+            return float(i) - log2(log2(r2)) + 4.0;
+        }
+    }
+    return -1.0;
+}
+
+void main() {
+    // Offset from the center in pixels, which is exact as a float. Y grows
+    // upwards, so that the imaginary axis isn't flipped.
+    vec2 offset = vec2(gl_FragCoord.x - u_viewport.x * 0.5, u_viewport.y * 0.5 - gl_FragCoord.y);
+    vec2 scale = vec2(u_scale_hi, u_scale_lo);
+    vec2 px = df_add(vec2(u_center_hi.x, u_center_lo.x), df_mul(vec2(offset.x, 0.0), scale));
+    vec2 py = df_add(vec2(u_center_hi.y, u_center_lo.y), df_mul(vec2(offset.y, 0.0), scale));
+
+    bool julia = (u_flags & FLAG_JULIA) != 0;
+    vec2 cx = julia ? vec2(u_julia_c.x, 0.0) : px;
+    vec2 cy = julia ? vec2(u_julia_c.y, 0.0) : py;
+    vec2 zx = julia ? px : vec2(0.0);
+    vec2 zy = julia ? py : vec2(0.0);
+
+    float n;
+    if ((u_flags & FLAG_DOUBLE) != 0) {
+        n = iterate_double(zx, zy, cx, cy);
+    } else {
+        n = iterate_single(vec2(zx.x, zy.x), vec2(cx.x, cy.x));
+    }
+
+    if (n < 0.0) {
+        f_color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    // Cosine palette: a + b * cos(2π(c * t + d))
+    float t = n * 0.02;
+    vec3 color = u_a.rgb + u_b.rgb * cos(6.28318 * (u_c.rgb * t + u_d.rgb));
+    f_color = vec4(color, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,72 @@
+#version 450
+
+layout(location=0) in vec2 v_ndc;
+
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform Grid {
+    mat4 u_view_proj;
+    mat4 u_inv_view_proj;
+    vec4 u_eye;
+    // xyz: normal, w: offset along it
+    vec4 u_plane;
+    // World-space directions of the grid's two axes
+    vec4 u_tangent;
+    vec4 u_bitangent;
+    float u_minor_spacing;
+    float u_major_spacing;
+    float u_fade_distance;
+    float _padding;
+};
+
+const vec3 LINE_COLOR = vec3(0.6, 0.6, 0.6);
+const vec3 TANGENT_AXIS_COLOR = vec3(0.9, 0.25, 0.25);
+const vec3 BITANGENT_AXIS_COLOR = vec3(0.25, 0.4, 0.9);
+
+// 1 on a line, falling off to 0 over about a pixel either side of it
+float lines(vec2 coords, float spacing) {
+    vec2 scaled = coords / spacing;
+    vec2 distance = abs(fract(scaled - 0.5) - 0.5) / fwidth(scaled);
+    return 1.0 - min(min(distance.x, distance.y), 1.0);
+}
+
+void main() {
+    vec4 near = u_inv_view_proj * vec4(v_ndc, 0.0, 1.0);
+    vec4 far = u_inv_view_proj * vec4(v_ndc, 1.0, 1.0);
+    vec3 origin = near.xyz / near.w;
+    vec3 ray = far.xyz / far.w - origin;
+
+    // Intersect the view ray with the plane. Misses only get thrown away at
+    // the end, as derivatives need every fragment around until then.
+    float t = (u_plane.w - dot(origin, u_plane.xyz)) / dot(ray, u_plane.xyz);
+    bool hit = t > 0.0 && t < 1.0;
+    vec3 position = origin + ray * t;
+    vec2 coords = vec2(dot(position, u_tangent.xyz), dot(position, u_bitangent.xyz));
+
+    float minor = lines(coords, u_minor_spacing);
+    float major = lines(coords, u_major_spacing);
+
+    vec3 color = LINE_COLOR;
+    float alpha = max(minor * 0.3, major * 0.7);
+
+    // Paint the axes themselves, if they go through the plane
+    vec2 axis_distance = abs(coords) / fwidth(coords);
+    if (axis_distance.y < 1.0) {
+        color = TANGENT_AXIS_COLOR;
+        alpha = 1.0;
+    } else if (axis_distance.x < 1.0) {
+        color = BITANGENT_AXIS_COLOR;
+        alpha = 1.0;
+    }
+
+    alpha *= 1.0 - smoothstep(0.0, u_fade_distance, distance(position, u_eye.xyz));
+    if (!hit || alpha <= 0.0) {
+        discard;
+    }
+
+    // Put the grid at its actual depth, so that geometry can cover it
+    vec4 clip = u_view_proj * vec4(position, 1.0);
+    gl_FragDepth = clip.z / clip.w;
+
+    f_color = vec4(color, alpha);
+}
\ No newline at end of file
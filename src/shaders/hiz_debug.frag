@@ -0,0 +1,25 @@
+#version 450
+
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_pyramid;
+layout(set = 0, binding = 1) uniform sampler s_pyramid;
+layout(set = 0, binding = 2) uniform HiZDebug {
+    vec2 u_viewport;
+    float u_near;
+    float u_far;
+    uint u_level;
+};
+
+void main() {
+    // The level is stretched over the whole viewport, blocky as it is
+    int level = int(u_level);
+    ivec2 size = textureSize(sampler2D(t_pyramid, s_pyramid), level);
+    vec2 uv = gl_FragCoord.xy / u_viewport;
+    ivec2 texel = min(ivec2(uv * vec2(size)), size - 1);
+    float depth = texelFetch(sampler2D(t_pyramid, s_pyramid), texel, level).r;
+
+    // Linear depth, as most of the range is crammed right next to 1
+    float linear = u_near * u_far / (u_far - depth * (u_far - u_near));
+    f_color = vec4(vec3(linear / u_far), 1.0);
+}
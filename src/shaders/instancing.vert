@@ -4,18 +4,23 @@ layout(location=0) in vec3 a_position;
 layout(location=1) in vec2 a_tex_coords;
 
 layout(location=0) out vec2 v_tex_coords;
+layout(location=1) out vec4 v_color;
+layout(location=2) flat out uint v_layer;
 
 layout(location=5) in vec4 i_model_matrix_0;
 layout(location=6) in vec4 i_model_matrix_1;
 layout(location=7) in vec4 i_model_matrix_2;
 layout(location=8) in vec4 i_model_matrix_3;
+layout(location=9) in vec4 i_color;
+layout(location=10) in uint i_layer;
 
-layout(set=1, binding=0) uniform Uniforms {
-    mat4 u_view_proj;
-};
+#define CAMERA_SET 1
+#include "common/camera.glsl"
 
 void main() {
     v_tex_coords = a_tex_coords;
+    v_color = i_color;
+    v_layer = i_layer;
     mat4 i_model_matrix = mat4(
         i_model_matrix_0,
         i_model_matrix_1,
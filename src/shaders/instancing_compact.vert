@@ -0,0 +1,30 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec2 a_tex_coords;
+
+layout(location=0) out vec2 v_tex_coords;
+layout(location=1) out vec4 v_color;
+layout(location=2) flat out uint v_layer;
+
+layout(location=5) in vec3 i_translation;
+layout(location=6) in vec4 i_rotation;
+layout(location=7) in vec3 i_scale;
+layout(location=9) in vec4 i_color;
+layout(location=10) in uint i_layer;
+
+#define CAMERA_SET 1
+#include "common/camera.glsl"
+
+#include "common/math.glsl"
+
+void main() {
+    v_tex_coords = a_tex_coords;
+    v_color = i_color;
+    v_layer = i_layer;
+
+    // Same order as `Transform`'s model matrix: rotation, then scale, then
+    // translation
+    vec3 position = i_translation + i_scale * quat_rotate(i_rotation, a_position);
+    gl_Position = u_view_proj * vec4(position, 1.0);
+}
@@ -4,21 +4,31 @@ layout(location = 0) in vec2 v_tex_coords;
 
 layout(location = 0) out vec4 f_color;
 
-layout(set = 0, binding = 0) uniform texture2D t_diffuse;
-layout(set = 0, binding = 1) uniform samplerShadow s_diffuse;
+layout(set = 0, binding = 0) uniform texture2D t_depth;
+layout(set = 0, binding = 1) uniform sampler s_depth;
+layout(set = 0, binding = 2) uniform DepthView {
+    float u_near;
+    float u_far;
+    // 0 for raw depth, 1 for linear depth, 2 for linear depth as a heatmap
+    uint u_mode;
+};
 
 void main() {
-    float near = 0.1;
-    float far = 100.0;
-
-    // This returns a number between 0 and 1
-    float depth = texture(sampler2DShadow(t_diffuse, s_diffuse), vec3(v_tex_coords, 1));
-
-    // This normalizes it to the [-1, 1] scale
-    depth = 2.0 * depth - 1.0;
-
-    // Gradient definition
-    float color = (2.0 * near) / (far + near - depth * (far - near));
-
-    f_color = vec4(vec3(depth), 1.0);
+    float depth = texelFetch(sampler2D(t_depth, s_depth), ivec2(gl_FragCoord.xy), 0).r;
+    if (u_mode == 0) {
+        // Most of the range is crammed right next to 1, so it's nearly all white
+        f_color = vec4(vec3(depth), 1.0);
+        return;
+    }
+
+    // Distance from the camera, back into [0, 1] between the planes
+    float linear = u_near * u_far / (u_far - depth * (u_far - u_near));
+    float t = (linear - u_near) / (u_far - u_near);
+    if (u_mode == 1) {
+        f_color = vec4(vec3(t), 1.0);
+    } else {
+        // Blue up close, through green and yellow, to red far away
+        vec3 heat = clamp(1.5 - abs(4.0 * t - vec3(3.0, 2.0, 1.0)), 0.0, 1.0);
+        f_color = vec4(heat, 1.0);
+    }
 }
@@ -0,0 +1,36 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec2 a_tex_coords;
+
+layout(location=0) out vec2 v_tex_coords;
+layout(location=1) out vec4 v_color;
+layout(location=2) flat out uint v_layer;
+
+#define CAMERA_SET 1
+#include "common/camera.glsl"
+
+// Instances come straight out of the instance buffer, through the slots the
+// culling pass let through
+struct Instance {
+    mat4 transform;
+    vec4 color;
+    uint layer;
+};
+
+layout(std430, set=3, binding=0) readonly buffer Instances {
+    Instance instances[];
+};
+
+layout(std430, set=3, binding=1) readonly buffer Visible {
+    uint visible[];
+};
+
+void main() {
+    Instance instance = instances[visible[gl_InstanceIndex]];
+    v_tex_coords = a_tex_coords;
+    v_color = instance.color;
+    v_layer = instance.layer;
+    mat4 i_model_matrix = instance.transform;
+    gl_Position = u_view_proj * i_model_matrix * vec4(a_position, 1.0);
+}
@@ -0,0 +1,21 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=1) in vec4 v_color;
+layout(location=2) flat in uint v_layer;
+
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2DArray t_diffuse;
+layout(set = 0, binding = 1) uniform sampler s_diffuse;
+
+layout(set = 2, binding = 0) uniform LodTint {
+    vec4 u_lod_tint;
+};
+
+const float OPACITY = 0.5;
+
+void main() {
+    vec4 color = texture(sampler2DArray(t_diffuse, s_diffuse), vec3(v_tex_coords, v_layer));
+    f_color = vec4(color.rgb * v_color.rgb * u_lod_tint.rgb, color.a * v_color.a * OPACITY);
+}
\ No newline at end of file
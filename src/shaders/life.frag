@@ -0,0 +1,23 @@
+#version 450
+
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform utexture2D t_cells;
+layout(set = 0, binding = 1) uniform sampler s_cells;
+layout(set = 0, binding = 2) uniform Viewport {
+    vec2 u_viewport;
+    vec2 _padding;
+};
+
+const vec3 ALIVE_COLOR = vec3(0.95, 0.8, 0.25);
+const vec3 DEAD_COLOR = vec3(0.05, 0.05, 0.08);
+
+void main() {
+    // The grid is stretched over the whole viewport
+    ivec2 size = textureSize(usampler2D(t_cells, s_cells), 0);
+    vec2 uv = gl_FragCoord.xy / u_viewport;
+    ivec2 cell = min(ivec2(uv * vec2(size)), size - 1);
+
+    uint alive = texelFetch(usampler2D(t_cells, s_cells), cell, 0).r;
+    f_color = vec4(mix(DEAD_COLOR, ALIVE_COLOR, float(alive)), 1.0);
+}
\ No newline at end of file
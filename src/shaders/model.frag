@@ -0,0 +1,86 @@
+#version 450
+
+layout(location=0) in vec3 v_position;
+layout(location=1) in vec2 v_tex_coords;
+layout(location=2) in vec3 v_normal;
+layout(location=3) in vec4 v_tangent;
+
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=1) uniform Light {
+    vec4 u_eye;
+    // Direction towards the light
+    vec4 u_light_direction;
+};
+
+layout(set=1, binding=0) uniform Material {
+    vec4 u_base_color_factor;
+    float u_metallic_factor;
+    float u_roughness_factor;
+    uint u_has_normal_texture;
+};
+layout(set=1, binding=1) uniform sampler s_material;
+layout(set=1, binding=2) uniform texture2D t_base_color;
+layout(set=1, binding=3) uniform texture2D t_normal;
+layout(set=1, binding=4) uniform texture2D t_metallic_roughness;
+
+#include "common/math.glsl"
+
+// Builds a tangent frame out of screen-space derivatives, for vertices
+// without a tangent
+mat3 cotangent_frame(vec3 n, vec3 p, vec2 uv) {
+    vec3 dp1 = dFdx(p);
+    vec3 dp2 = dFdy(p);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+
+    vec3 dp2perp = cross(dp2, n);
+    vec3 dp1perp = cross(n, dp1);
+    vec3 t = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 b = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    float invmax = inversesqrt(max(max(dot(t, t), dot(b, b)), 1e-12));
+    return mat3(t * invmax, b * invmax, n);
+}
+
+void main() {
+    vec4 base_color = texture(sampler2D(t_base_color, s_material), v_tex_coords) * u_base_color_factor;
+    // glTF keeps roughness in green and metalness in blue
+    vec4 metallic_roughness = texture(sampler2D(t_metallic_roughness, s_material), v_tex_coords);
+    float metallic = clamp(metallic_roughness.b * u_metallic_factor, 0.0, 1.0);
+    float roughness = clamp(metallic_roughness.g * u_roughness_factor, 0.04, 1.0);
+
+    vec3 n = normalize(v_normal);
+    vec3 mapped = texture(sampler2D(t_normal, s_material), v_tex_coords).xyz * 2.0 - 1.0;
+    // Derivatives have to be taken outside of non-uniform control flow
+    mat3 tbn = cotangent_frame(n, v_position, v_tex_coords);
+    vec3 t = v_tangent.xyz - n * dot(n, v_tangent.xyz);
+    if (dot(t, t) > 1e-12) {
+        t = normalize(t);
+        tbn = mat3(t, cross(n, t) * v_tangent.w, n);
+    }
+    if (u_has_normal_texture != 0) {
+        n = normalize(tbn * mapped);
+    }
+
+    vec3 v = normalize(u_eye.xyz - v_position);
+    vec3 l = normalize(u_light_direction.xyz);
+    vec3 h = normalize(v + l);
+    float n_dot_l = max(dot(n, l), 0.0);
+    float n_dot_v = max(dot(n, v), 1e-4);
+    float n_dot_h = max(dot(n, h), 0.0);
+
+    // GGX distribution, Schlick fresnel and a Smith-ish visibility term
+    float a2 = roughness * roughness * roughness * roughness;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    float distribution = a2 / (PI * d * d);
+    vec3 f0 = mix(vec3(0.04), base_color.rgb, metallic);
+    vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(h, v), 0.0), 5.0);
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float visibility = 1.0 / ((n_dot_l * (1.0 - k) + k) * (n_dot_v * (1.0 - k) + k) * 4.0);
+
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * base_color.rgb / PI;
+    vec3 specular = fresnel * distribution * visibility;
+    vec3 ambient = base_color.rgb * 0.15;
+
+    f_color = vec4(ambient + (diffuse + specular) * n_dot_l * 3.0, base_color.a);
@@ -0,0 +1,36 @@
+#version 450
+
+// Halves and normalized bytes come in with a fourth component, which is
+// ignored
+layout(location=0) in vec4 a_position;
+layout(location=1) in vec2 a_tex_coords;
+layout(location=2) in vec4 a_normal;
+layout(location=3) in vec4 a_tangent;
+
+layout(location=5) in vec4 i_model_matrix_0;
+layout(location=6) in vec4 i_model_matrix_1;
+layout(location=7) in vec4 i_model_matrix_2;
+layout(location=8) in vec4 i_model_matrix_3;
+
+layout(location=0) out vec3 v_position;
+layout(location=1) out vec2 v_tex_coords;
+layout(location=2) out vec3 v_normal;
+layout(location=3) out vec4 v_tangent;
+
+#include "common/camera.glsl"
+
+void main() {
+    mat4 i_model_matrix = mat4(
+        i_model_matrix_0,
+        i_model_matrix_1,
+        i_model_matrix_2,
+        i_model_matrix_3
+    );
+    vec4 world_position = i_model_matrix * vec4(a_position.xyz, 1.0);
+
+    v_position = world_position.xyz;
+    v_tex_coords = a_tex_coords;
+    // Good enough as long as nodes don't use non-uniform scaling
+    v_normal = mat3(i_model_matrix) * a_normal.xyz;
+    v_tangent = vec4(mat3(i_model_matrix) * a_tangent.xyz, a_tangent.w);
+    gl_Position = u_view_proj * world_position;
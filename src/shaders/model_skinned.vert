@@ -0,0 +1,34 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec2 a_tex_coords;
+layout(location=2) in vec3 a_normal;
+layout(location=3) in vec4 a_tangent;
+layout(location=4) in uvec4 a_joints;
+layout(location=5) in vec4 a_weights;
+
+layout(location=0) out vec3 v_position;
+layout(location=1) out vec2 v_tex_coords;
+layout(location=2) out vec3 v_normal;
+layout(location=3) out vec4 v_tangent;
+
+#include "common/camera.glsl"
+
+layout(set=2, binding=0) readonly buffer Joints {
+    mat4 s_joints[];
+};
+
+void main() {
+    // Joint matrices already take the skin to world space
+    mat4 skin_matrix =
+        a_weights.x * s_joints[a_joints.x] +
+        a_weights.y * s_joints[a_joints.y] +
+        a_weights.z * s_joints[a_joints.z] +
+        a_weights.w * s_joints[a_joints.w];
+    vec4 world_position = skin_matrix * vec4(a_position, 1.0);
+
+    v_position = world_position.xyz;
+    v_tex_coords = a_tex_coords;
+    v_normal = mat3(skin_matrix) * a_normal;
+    v_tangent = vec4(mat3(skin_matrix) * a_tangent.xyz, a_tangent.w);
+    gl_Position = u_view_proj * world_position;
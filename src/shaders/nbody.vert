@@ -0,0 +1,37 @@
+#version 450
+
+// Both come straight from the storage buffer the compute pass writes to
+layout(location=0) in vec4 i_position;
+layout(location=1) in vec4 i_velocity;
+
+layout(location=0) out vec2 v_tex_coords;
+layout(location=1) out vec4 v_color;
+
+#include "common/camera.glsl"
+layout(set=0, binding=1) uniform BillboardUniforms {
+    vec4 u_right;
+    vec4 u_up;
+};
+
+const vec2 corners[6] = vec2[6](
+    vec2(-0.5, -0.5),
+    vec2(0.5, -0.5),
+    vec2(0.5, 0.5),
+    vec2(-0.5, -0.5),
+    vec2(0.5, 0.5),
+    vec2(-0.5, 0.5)
+);
+
+void main() {
+    vec2 corner = corners[gl_VertexIndex];
+
+    // Heavier bodies get bigger sprites, faster ones get hotter colors
+    float size = 0.06 * clamp(pow(i_position.w * 4096.0, 0.25), 1.0, 6.0);
+    float heat = clamp(length(i_velocity.xyz) / 3.0, 0.0, 1.0);
+
+    v_tex_coords = corner + 0.5;
+    v_color = mix(vec4(0.3, 0.5, 1.0, 0.8), vec4(1.0, 0.6, 0.2, 0.8), heat);
+
+    vec3 world_position = i_position.xyz + (u_right.xyz * corner.x + u_up.xyz * corner.y) * size;
+    gl_Position = u_view_proj * vec4(world_position, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,25 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec3 a_color;
+
+layout(location=5) in vec4 i_model_matrix_0;
+layout(location=6) in vec4 i_model_matrix_1;
+layout(location=7) in vec4 i_model_matrix_2;
+layout(location=8) in vec4 i_model_matrix_3;
+layout(location=9) in vec4 i_color;
+
+layout(location=0) out vec4 v_color;
+
+#include "common/camera.glsl"
+
+void main() {
+    v_color = vec4(a_color, 1.0) * i_color;
+    mat4 i_model_matrix = mat4(
+        i_model_matrix_0,
+        i_model_matrix_1,
+        i_model_matrix_2,
+        i_model_matrix_3
+    );
+    gl_Position = u_view_proj * i_model_matrix * vec4(a_position, 1.0);
+}
\ No newline at end of file
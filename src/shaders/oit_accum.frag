@@ -0,0 +1,21 @@
+#version 450
+
+layout(location=0) in vec4 v_color;
+
+layout(location=0) out vec4 f_accum;
+layout(location=1) out float f_reveal;
+
+void main() {
+    float alpha = v_color.a;
+
+    // Depth-based weight from McGuire & Bavoil's paper (eq. 10), so that
+    // closer fragments dominate the average
+    float weight = clamp(
+        pow(min(1.0, alpha * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0),
+        1e-2,
+        3e3
+    );
+
+    f_accum = vec4(v_color.rgb * alpha, alpha) * weight;
+    f_reveal = alpha;
+}
\ No newline at end of file
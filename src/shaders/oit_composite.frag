@@ -0,0 +1,15 @@
+#version 450
+
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_accum;
+layout(set = 0, binding = 1) uniform texture2D t_reveal;
+layout(set = 0, binding = 2) uniform sampler s_target;
+
+void main() {
+    ivec2 coords = ivec2(gl_FragCoord.xy);
+    vec4 accum = texelFetch(sampler2D(t_accum, s_target), coords, 0);
+    float reveal = texelFetch(sampler2D(t_reveal, s_target), coords, 0).r;
+
+    f_color = vec4(accum.rgb / max(accum.a, 1e-5), 1.0 - reveal);
+}
\ No newline at end of file
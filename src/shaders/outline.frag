@@ -0,0 +1,9 @@
+#version 450
+
+layout(location=0) out vec4 f_color;
+
+const vec3 OUTLINE_COLOR = vec3(1.0, 0.6, 0.1);
+
+void main() {
+    f_color = vec4(OUTLINE_COLOR, 1.0);
+}
@@ -0,0 +1,17 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=1) in vec4 v_color;
+
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_diffuse;
+layout(set = 0, binding = 1) uniform sampler s_diffuse;
+
+void main() {
+    vec4 color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords) * v_color;
+
+    // Fade out towards the edges so that sprites look round
+    float edge = 1.0 - smoothstep(0.4, 0.5, distance(v_tex_coords, vec2(0.5)));
+    f_color = vec4(color.rgb, color.a * edge);
+}
\ No newline at end of file
@@ -0,0 +1,28 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec2 a_tex_coords;
+
+layout(location=5) in vec3 i_position;
+layout(location=6) in float i_size;
+layout(location=7) in vec4 i_color;
+
+layout(location=0) out vec2 v_tex_coords;
+layout(location=1) out vec4 v_color;
+
+#define CAMERA_SET 1
+#include "common/camera.glsl"
+layout(set=1, binding=1) uniform BillboardUniforms {
+    vec4 u_right;
+    vec4 u_up;
+};
+
+void main() {
+    v_tex_coords = a_tex_coords;
+    v_color = i_color;
+
+    // Expand the particle into a quad facing the camera
+    vec3 world_position = i_position
+        + (u_right.xyz * a_position.x + u_up.xyz * a_position.y) * i_size;
+    gl_Position = u_view_proj * vec4(world_position, 1.0);
+}
\ No newline at end of file
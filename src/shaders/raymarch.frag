@@ -0,0 +1,119 @@
+#version 450
+
+layout(location=0) out vec4 f_color;
+
+#include "common/camera.glsl"
+
+layout(set = 0, binding = 1) uniform Frame {
+    vec2 u_viewport;
+    float u_time;
+    float _padding;
+};
+
+const int MAX_STEPS = 128;
+const float MAX_DISTANCE = 100.0;
+const float SURFACE_DISTANCE = 1e-3;
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.6, 0.8, 0.4));
+const vec3 SKY_COLOR = vec3(0.55, 0.7, 0.9);
+
+float sd_sphere(vec3 p, float r) {
+    return length(p) - r;
+}
+
+float sd_torus(vec3 p, vec2 t) {
+    vec2 q = vec2(length(p.xz) - t.x, p.y);
+    return length(q) - t.y;
+}
+
+float sd_box(vec3 p, vec3 b) {
+    vec3 q = abs(p) - b;
+    return length(max(q, 0.0)) + min(max(q.x, max(q.y, q.z)), 0.0);
+}
+
+float smooth_union(float a, float b, float k) {
+    float h = clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0);
+    return mix(b, a, h) - k * h * (1.0 - h);
+}
+
+// x: distance, y: material (0 = ground, 1 = objects)
+vec2 map(vec3 p) {
+    float ground = p.y + 1.0;
+
+    vec3 sphere_p = p - vec3(0.0, 0.2 + sin(u_time) * 0.5, 0.0);
+    float blob = smooth_union(sd_sphere(sphere_p, 0.6), sd_box(p - vec3(0.0, -0.5, 0.0), vec3(0.5)), 0.3);
+
+    float c = cos(u_time * 0.7);
+    float s = sin(u_time * 0.7);
+    vec3 torus_p = p - vec3(2.0, 0.0, 0.0);
+    torus_p.xy = mat2(c, -s, s, c) * torus_p.xy;
+    float objects = min(blob, sd_torus(torus_p, vec2(0.6, 0.2)));
+
+    return objects < ground ? vec2(objects, 1.0) : vec2(ground, 0.0);
+}
+
+vec3 normal(vec3 p) {
+    const vec2 e = vec2(1e-3, 0.0);
+    return normalize(vec3(
+        map(p + e.xyy).x - map(p - e.xyy).x,
+        map(p + e.yxy).x - map(p - e.yxy).x,
+        map(p + e.yyx).x - map(p - e.yyx).x
+    ));
+}
+
+float soft_shadow(vec3 origin, vec3 direction) {
+    float shadow = 1.0;
+    float t = 0.02;
+    for (int i = 0; i < 32 && t < 10.0; i++) {
+        float d = map(origin + direction * t).x;
+        shadow = min(shadow, 8.0 * d / t);
+        t += clamp(d, 0.02, 0.5);
+    }
+    return clamp(shadow, 0.0, 1.0);
+}
+
+void main() {
+    // Unproject the pixel onto the far plane to get the ray through it
+    vec2 uv = gl_FragCoord.xy / u_viewport;
+    vec2 ndc = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    vec4 far = u_inv_view_proj * vec4(ndc, 1.0, 1.0);
+    vec3 origin = u_eye.xyz;
+    vec3 direction = normalize(far.xyz / far.w - origin);
+
+    float t = 0.0;
+    vec2 hit = vec2(MAX_DISTANCE, -1.0);
+    for (int i = 0; i < MAX_STEPS; i++) {
+        vec2 d = map(origin + direction * t);
+        if (d.x < SURFACE_DISTANCE) {
+            hit = vec2(t, d.y);
+            break;
+        }
+        t += d.x;
+        if (t > MAX_DISTANCE) {
+            break;
+        }
+    }
+
+    if (hit.y < 0.0) {
+        f_color = vec4(SKY_COLOR, 1.0);
+        return;
+    }
+
+    vec3 p = origin + direction * hit.x;
+    vec3 n = normal(p);
+
+    vec3 albedo;
+    if (hit.y < 0.5) {
+        // Checkerboard ground
+        float checker = mod(floor(p.x) + floor(p.z), 2.0);
+        albedo = mix(vec3(0.3), vec3(0.6), checker);
+    } else {
+        albedo = vec3(0.9, 0.55, 0.3);
+    }
+
+    float diffuse = max(dot(n, LIGHT_DIRECTION), 0.0) * soft_shadow(p + n * 0.01, LIGHT_DIRECTION);
+    vec3 color = albedo * (0.15 + 0.85 * diffuse);
+
+    // Fade into the sky with distance
+    float fog = 1.0 - exp(-0.002 * hit.x * hit.x);
+    f_color = vec4(mix(color, SKY_COLOR, fog), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,24 @@
+#version 450
+
+// variants: TONEMAP
+
+layout(location=0) in vec2 v_ndc;
+
+layout(location=0) out vec4 f_color;
+
+#include "common/camera.glsl"
+
+layout(set=0, binding=1) uniform textureCube t_environment;
+layout(set=0, binding=2) uniform sampler s_environment;
+
+void main() {
+    vec4 far = u_inv_view_proj * vec4(v_ndc, 1.0, 1.0);
+    vec3 dir = far.xyz / far.w - u_eye.xyz;
+
+    vec3 color = texture(samplerCube(t_environment, s_environment), dir).rgb;
+#ifdef TONEMAP
+    // Reinhard, as HDR environments go way past 1.0
+    color = color / (1.0 + color);
+#endif
+    f_color = vec4(color, 1.0);
+}
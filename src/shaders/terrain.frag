@@ -0,0 +1,27 @@
+#version 450
+
+layout(location=0) in float v_height;
+layout(location=1) in vec3 v_normal;
+
+layout(location=0) out vec4 f_color;
+
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.5, 0.8, 0.3));
+
+void main() {
+    // Height ramp, v_height goes from -1 to 1
+    vec3 color;
+    if (v_height < -0.2) {
+        color = vec3(0.15, 0.3, 0.6);
+    } else if (v_height < -0.1) {
+        color = vec3(0.8, 0.75, 0.5);
+    } else if (v_height < 0.3) {
+        color = vec3(0.25, 0.55, 0.2);
+    } else if (v_height < 0.55) {
+        color = vec3(0.45, 0.4, 0.35);
+    } else {
+        color = vec3(0.95);
+    }
+
+    float diffuse = max(dot(normalize(v_normal), LIGHT_DIRECTION), 0.0);
+    f_color = vec4(color * (0.25 + 0.75 * diffuse), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,46 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec2 a_tex_coords;
+
+layout(location=0) out float v_height;
+layout(location=1) out vec3 v_normal;
+
+#include "common/camera.glsl"
+
+// Written by terrain.comp, indexed by vertex
+layout(std430, set = 1, binding = 0) readonly buffer Heights {
+    float heights[];
+};
+
+layout(set = 1, binding = 1) uniform TerrainParams {
+    uint u_seed;
+    uint u_octaves;
+    uint u_resolution;
+    uint _padding0;
+    float u_frequency;
+    float u_amplitude;
+    float u_cell_size;
+    float _padding1;
+};
+
+float height_at(int x, int y) {
+    int last = int(u_resolution) - 1;
+    return heights[clamp(y, 0, last) * int(u_resolution) + clamp(x, 0, last)];
+}
+
+void main() {
+    // The grid is indexed, so the vertex index is the vertex' position in it
+    int x = gl_VertexIndex % int(u_resolution);
+    int y = gl_VertexIndex / int(u_resolution);
+
+    float height = height_at(x, y);
+
+    // Central differences over the neighbouring vertices
+    float dx = height_at(x + 1, y) - height_at(x - 1, y);
+    float dz = height_at(x, y + 1) - height_at(x, y - 1);
+    v_normal = normalize(vec3(-dx, 2.0 * u_cell_size, -dz));
+    v_height = height / max(u_amplitude, 1e-5);
+
+    gl_Position = u_view_proj * vec4(a_position.x, height, a_position.z, 1.0);
+}
\ No newline at end of file
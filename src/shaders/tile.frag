@@ -0,0 +1,11 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_tile;
+layout(set = 0, binding = 1) uniform sampler s_tile;
+
+void main() {
+    f_color = vec4(texture(sampler2D(t_tile, s_tile), v_tex_coords).rgb, 1.0);
+}
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location=0) out vec2 v_tex_coords;
+
+// Same triangle as fullscreen.vert, covering whatever viewport is set, along
+// with where it samples the tile from
+void main() {
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    v_tex_coords = vec2(uv.x, 1.0 - uv.y);
+    gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+}
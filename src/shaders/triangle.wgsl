@@ -0,0 +1,20 @@
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] color: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] a_position: vec3<f32>,
+    [[location(1)]] a_color: vec3<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.color = a_color;
+    out.position = vec4<f32>(a_position, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
@@ -0,0 +1,28 @@
+#version 450
+
+// variants: ENABLE_FOG
+
+layout(location=0) in vec3 v_normal;
+layout(location=1) in vec4 v_color;
+layout(location=2) in float v_depth;
+
+layout(location=0) out vec4 f_color;
+
+const vec3 LIGHT_DIR = vec3(0.3, 0.8, 0.5);
+const float AMBIENT = 0.2;
+
+#ifdef ENABLE_FOG
+// Fades into the default background color with the distance to the camera
+const vec3 FOG_COLOR = vec3(0.0);
+const float FOG_START = 8.0;
+const float FOG_END = 20.0;
+#endif
+
+void main() {
+    float diffuse = max(dot(normalize(v_normal), normalize(LIGHT_DIR)), 0.0);
+    vec3 color = v_color.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse);
+#ifdef ENABLE_FOG
+    color = mix(color, FOG_COLOR, smoothstep(FOG_START, FOG_END, v_depth));
+#endif
+    f_color = vec4(color, v_color.a);
+}
@@ -0,0 +1,26 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=2) in vec3 a_normal;
+
+layout(location=0) out vec3 v_normal;
+layout(location=1) out vec4 v_color;
+// Distance from the camera along its view direction, for fog
+layout(location=2) out float v_depth;
+
+#include "common/camera.glsl"
+
+// Bound at a different dynamic offset for every object
+layout(set=1, binding=0) uniform Object {
+    mat4 u_model;
+    vec4 u_color;
+};
+
+void main() {
+    // Objects are only ever rotated and moved around, so the model matrix
+    // works for normals too
+    v_normal = mat3(u_model) * a_normal;
+    v_color = u_color;
+    gl_Position = u_view_proj * u_model * vec4(a_position, 1.0);
+    v_depth = gl_Position.w;
+}
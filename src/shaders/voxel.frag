@@ -0,0 +1,14 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=1) in float v_shade;
+
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_atlas;
+layout(set = 0, binding = 1) uniform sampler s_atlas;
+
+void main() {
+    vec4 color = texture(sampler2D(t_atlas, s_atlas), v_tex_coords);
+    f_color = vec4(color.rgb * v_shade, color.a);
+}
\ No newline at end of file
@@ -0,0 +1,17 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec2 a_tex_coords;
+layout(location=2) in float a_shade;
+
+layout(location=0) out vec2 v_tex_coords;
+layout(location=1) out float v_shade;
+
+#define CAMERA_SET 1
+#include "common/camera.glsl"
+
+void main() {
+    v_tex_coords = a_tex_coords;
+    v_shade = a_shade;
+    gl_Position = u_view_proj * vec4(a_position, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,194 @@
+//! Allocations carved out of a few large buffers, or slabs, rather than every
+//! vertex, index or uniform buffer being a buffer of its own. Allocations are
+//! bound by offset into their slab, and new slabs only get created when none
+//! of the current ones has a free range large enough.
+
+use std::{num::NonZeroU64, ops::Range};
+
+use wgpu::{BufferAddress, CommandEncoder};
+
+use crate::buffer::Stager;
+
+/// Part of a slab handed out by `SlabAllocator::allocate`
+#[derive(Copy, Clone, Debug)]
+pub struct SlabAllocation {
+    slab: usize,
+    pub offset: BufferAddress,
+    pub size: BufferAddress,
+}
+
+struct Slab {
+    buffer: wgpu::Buffer,
+    size: BufferAddress,
+    /// Free ranges, sorted and never touching each other
+    free: Vec<Range<BufferAddress>>,
+}
+
+impl Slab {
+    /// Takes `size` bytes out of the first free range they fit in
+    fn take(&mut self, size: BufferAddress, align: BufferAddress) -> Option<BufferAddress> {
+        let (i, offset) = self.free.iter().enumerate().find_map(|(i, range)| {
+            let offset = range.start.div_ceil(align) * align;
+            if offset + size <= range.end {
+                Some((i, offset))
+            } else {
+                None
+            }
+        })?;
+
+        let range = self.free.remove(i);
+        let after = offset + size..range.end;
+        if !after.is_empty() {
+            self.free.insert(i, after);
+        }
+        let before = range.start..offset;
+        if !before.is_empty() {
+            self.free.insert(i, before);
+        }
+        Some(offset)
+    }
+
+    /// Puts a range back, merging it with the free ones around it
+    fn give_back(&mut self, range: Range<BufferAddress>) {
+        let i = self.free.partition_point(|free| free.start < range.start);
+        self.free.insert(i, range);
+        if i + 1 < self.free.len() && self.free[i].end == self.free[i + 1].start {
+            self.free[i].end = self.free.remove(i + 1).end;
+        }
+        if i > 0 && self.free[i - 1].end == self.free[i].start {
+            self.free[i - 1].end = self.free.remove(i).end;
+        }
+    }
+}
+
+pub struct SlabAllocator {
+    usage: wgpu::BufferUsages,
+    slab_size: BufferAddress,
+    /// Every allocation starts at a multiple of it
+    align: BufferAddress,
+    label: String,
+    slabs: Vec<Slab>,
+}
+
+impl SlabAllocator {
+    /// Allocator for buffers with `usage`, out of slabs `slab_size` bytes
+    /// long, or as long as the allocation if it's larger. Allocations get
+    /// aligned to `align`, which is at least `COPY_BUFFER_ALIGNMENT` so that
+    /// they can be written through a stager
+    pub fn new(
+        usage: wgpu::BufferUsages,
+        slab_size: BufferAddress,
+        align: BufferAddress,
+        label: &str,
+    ) -> Self {
+        Self {
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            slab_size,
+            align: align.max(wgpu::COPY_BUFFER_ALIGNMENT),
+            label: label.to_owned(),
+            slabs: Vec::new(),
+        }
+    }
+
+    /// Allocates `size` bytes, creating a new slab if none has room for them.
+    /// Sizes get padded up to a multiple of `COPY_BUFFER_ALIGNMENT`, and
+    /// empty allocations still take up that much, as empty bindings aren't
+    /// valid
+    pub fn allocate(&mut self, device: &wgpu::Device, size: BufferAddress) -> SlabAllocation {
+        let copy_align = wgpu::COPY_BUFFER_ALIGNMENT;
+        let size = (size.div_ceil(copy_align) * copy_align).max(copy_align);
+
+        let align = self.align;
+        let found = self
+            .slabs
+            .iter_mut()
+            .enumerate()
+            .find_map(|(slab, s)| s.take(size, align).map(|offset| (slab, offset)));
+        let (slab, offset) = match found {
+            Some(found) => found,
+            None => {
+                let slab_size = self.slab_size.max(size);
+                let mut slab = Slab {
+                    buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some(&self.label),
+                        size: slab_size,
+                        usage: self.usage,
+                        mapped_at_creation: false,
+                    }),
+                    size: slab_size,
+                    free: std::iter::once(0..slab_size).collect(),
+                };
+                let offset = slab.take(size, align).unwrap();
+                self.slabs.push(slab);
+                (self.slabs.len() - 1, offset)
+            }
+        };
+
+        SlabAllocation { slab, offset, size }
+    }
+
+    /// Same as `allocate`, writing `values` into the allocation through
+    /// `stager`
+    pub fn upload<T: bytemuck::Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut CommandEncoder,
+        stager: &mut Stager,
+        values: &[T],
+    ) -> SlabAllocation {
+        let data: &[u8] = bytemuck::cast_slice(values);
+        let allocation = self.allocate(device, data.len() as BufferAddress);
+        stager.write_padded(encoder, self.buffer(&allocation), allocation.offset, data);
+        allocation
+    }
+
+    /// Hands an allocation back, for later ones to reuse. Slabs are kept
+    /// around even when they're left empty
+    pub fn free(&mut self, allocation: SlabAllocation) {
+        self.slabs[allocation.slab]
+            .give_back(allocation.offset..allocation.offset + allocation.size);
+    }
+
+    /// Frees every slab right away, along with whatever is still allocated
+    /// out of them, see `Scene::destroy`
+    pub fn destroy(&mut self) {
+        for slab in self.slabs.drain(..) {
+            slab.buffer.destroy();
+        }
+    }
+
+    pub fn buffer(&self, allocation: &SlabAllocation) -> &wgpu::Buffer {
+        &self.slabs[allocation.slab].buffer
+    }
+
+    pub fn slice(&self, allocation: &SlabAllocation) -> wgpu::BufferSlice<'_> {
+        self.buffer(allocation)
+            .slice(allocation.offset..allocation.offset + allocation.size)
+    }
+
+    /// Binds just the allocation, i.e. as a uniform buffer
+    pub fn binding(&self, allocation: &SlabAllocation) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: self.buffer(allocation),
+            offset: allocation.offset,
+            size: NonZeroU64::new(allocation.size),
+        })
+    }
+
+    /// How many slabs there are, and how many of their bytes are allocated
+    /// out of how many
+    pub fn usage(&self) -> (usize, BufferAddress, BufferAddress) {
+        let size = self
+            .slabs
+            .iter()
+            .map(|slab| slab.size)
+            .sum::<BufferAddress>();
+        let free = self
+            .slabs
+            .iter()
+            .flat_map(|slab| slab.free.iter())
+            .map(|range| range.end - range.start)
+            .sum::<BufferAddress>();
+        (self.slabs.len(), size - free, size)
+    }
+}
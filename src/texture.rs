@@ -1,29 +1,59 @@
-use std::{num::NonZeroU32, ops::Deref};
+use std::{
+    borrow::Cow,
+    convert::TryInto,
+    num::{NonZeroU32, NonZeroU8},
+    ops::Deref,
+    path::PathBuf,
+    sync::mpsc::{self, TryRecvError},
+};
 
 use anyhow::*;
 use image::GenericImageView;
 
+use crate::vertex::f32s_to_f16s;
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
 }
 
+/// How the texels of an 8-bit image are meant to be read
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// Colors, as images are usually authored. Decoded to linear when sampled
+    Srgb,
+    /// Anything that isn't a color (normal maps, roughness, noise, lookup
+    /// tables), which sampling hands over untouched
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn format(self) -> wgpu::TextureFormat {
+        match self {
+            ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
 impl Texture {
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         bytes: &[u8],
+        color_space: ColorSpace,
         label: &str,
     ) -> Result<Self> {
         let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        Self::from_image(device, queue, &img, color_space, Some(label))
     }
 
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
+        color_space: ColorSpace,
         label: Option<&str>,
     ) -> Result<Self> {
         let rgba = img.as_rgba8().unwrap();
@@ -40,7 +70,7 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format: color_space.format(),
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         });
 
@@ -65,15 +95,284 @@ impl Texture {
         );
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+        let sampler = Self::create_image_sampler(device);
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    /// Binds the view at 0 and the sampler at 1, the way every scene lays
+    /// its textures out
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        label: &str,
+    ) -> wgpu::BindGroup {
+        self.create_bind_group_with_sampler(device, layout, &self.sampler, label)
+    }
+
+    /// Like `create_bind_group`, sampling with something other than the
+    /// texture's own sampler (i.e. one out of `Assets::sampler`)
+    pub fn create_bind_group_with_sampler(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        label: &str,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    /// Magenta and gray squares, for standing in for textures that aren't
+    /// there (yet)
+    pub fn checkerboard(device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> Self {
+        let img = image::RgbaImage::from_fn(64, 64, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 {
+                image::Rgba([255, 0, 255, 255])
+            } else {
+                image::Rgba([64, 64, 64, 255])
+            }
+        });
+        Self::from_image(
+            device,
+            queue,
+            &image::DynamicImage::ImageRgba8(img),
+            ColorSpace::Srgb,
+            Some(label),
+        )
+        .expect("Checkerboards should always upload")
+    }
+
+    /// Layers a few images into a texture array (`texture2DArray` in
+    /// shaders), in order. Layers all have the size of the first image, and
+    /// the other ones get resized to match
+    pub fn array_from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layers: &[&[u8]],
+        color_space: ColorSpace,
+        label: &str,
+    ) -> Result<Self> {
+        let images = layers
+            .iter()
+            .map(|bytes| image::load_from_memory(bytes))
+            .collect::<image::ImageResult<Vec<_>>>()?;
+        Self::array_from_images(device, queue, &images, color_space, Some(label))
+    }
+
+    pub fn array_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &[image::DynamicImage],
+        color_space: ColorSpace,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        ensure!(!images.is_empty(), "Texture arrays need at least one layer");
+        let texture = Self::layers_from_images(device, queue, images, color_space, label);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label,
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
             ..Default::default()
         });
+        let sampler = Self::create_image_sampler(device);
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    /// Six square images into a cube map (`textureCube` in shaders), in
+    /// layer order: +X, -X, +Y, -Y, +Z, -Z. Faces get resized to the size of
+    /// the first one, same as `array_from_bytes`
+    pub fn cubemap_from_faces(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[&[u8]; 6],
+        color_space: ColorSpace,
+        label: &str,
+    ) -> Result<Self> {
+        let images = faces
+            .iter()
+            .map(|bytes| image::load_from_memory(bytes))
+            .collect::<image::ImageResult<Vec<_>>>()?;
+        Self::cubemap_from_images(device, queue, &images, color_space, Some(label))
+    }
+
+    pub fn cubemap_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::DynamicImage],
+        color_space: ColorSpace,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        ensure!(
+            faces.len() == 6,
+            "Cube maps need 6 faces, got {}",
+            faces.len()
+        );
+        let (width, height) = faces[0].dimensions();
+        ensure!(
+            width == height,
+            "Cube map faces have to be square, not {}x{}",
+            width,
+            height
+        );
+        let texture = Self::layers_from_images(device, queue, faces, color_space, label);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label,
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = Self::create_cube_sampler(device);
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    /// Uploads every image into a layer of an 8-bit RGBA texture in the given
+    /// color space, for arrays and cube maps to view however they need
+    fn layers_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &[image::DynamicImage],
+        color_space: ColorSpace,
+        label: Option<&str>,
+    ) -> wgpu::Texture {
+        let (width, height) = images[0].dimensions();
+
+        let extent = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: images.len() as u32,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: color_space.format(),
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        for (layer, img) in images.iter().enumerate() {
+            let rgba = if img.dimensions() == (width, height) {
+                img.to_rgba8()
+            } else {
+                img.resize_exact(width, height, image::imageops::FilterType::Triangle)
+                    .to_rgba8()
+            };
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &rgba,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(
+                        NonZeroU32::new(4 * width).expect("Ok, who turned off the X dimension?"),
+                    ),
+                    rows_per_image: Some(
+                        NonZeroU32::new(height).expect("Ok, who turned off the Y dimension?"),
+                    ),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        texture
+    }
+
+    /// Decodes a Radiance HDR (`.hdr`) image into an `Rgba16Float` texture,
+    /// keeping whatever goes past 1.0, as environment maps need
+    pub fn from_hdr(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+    ) -> Result<Self> {
+        let decoder = image::codecs::hdr::HdrDecoder::new(bytes)?;
+        let metadata = decoder.metadata();
+        let (width, height) = (metadata.width, metadata.height);
+        let texels = decoder
+            .read_image_hdr()?
+            .iter()
+            .flat_map(|texel| f32s_to_f16s([texel[0], texel[1], texel[2], 1.0]))
+            .collect::<Vec<u16>>();
+
+        let extent = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&texels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(
+                    NonZeroU32::new(8 * width).expect("Ok, who turned off the X dimension?"),
+                ),
+                rows_per_image: Some(
+                    NonZeroU32::new(height).expect("Ok, who turned off the Y dimension?"),
+                ),
+            },
+            extent,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = Self::create_image_sampler(device);
 
         Ok(Self {
             texture,
@@ -81,6 +380,383 @@ impl Texture {
             sampler,
         })
     }
+
+    /// Uploads a KTX2 container as it is, pre-baked mip levels included,
+    /// instead of decoding an image on the CPU. Takes 2D textures, arrays
+    /// and cube maps in the uncompressed and block-compressed formats below
+    /// (the latter only if the device has the feature for them), either not
+    /// supercompressed or, with the `zstd` feature, supercompressed with
+    /// Zstandard. Basis Universal files have to be transcoded beforehand
+    pub fn from_ktx2(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+    ) -> Result<Self> {
+        ensure!(
+            bytes.starts_with(&KTX2_IDENTIFIER),
+            "{} isn't a KTX2 file",
+            label
+        );
+
+        let vk_format = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 20)?;
+        let height = read_u32(bytes, 24)?.max(1);
+        let depth = read_u32(bytes, 28)?;
+        // 0 layers means it's not an array
+        let layer_count = read_u32(bytes, 32)?;
+        let layers = layer_count.max(1);
+        let faces = read_u32(bytes, 36)?;
+        let levels = read_u32(bytes, 40)?.max(1);
+        let supercompression = read_u32(bytes, 44)?;
+
+        ensure!(depth == 0, "{}: 3D textures aren't supported", label);
+        ensure!(
+            faces == 1 || faces == 6,
+            "{}: bad face count {}",
+            label,
+            faces
+        );
+        let format = ktx2_format(vk_format)
+            .with_context(|| format!("{}: unsupported VkFormat {}", label, vk_format))?;
+        let info = format.describe();
+        ensure!(
+            device.features().contains(info.required_features),
+            "{}: {:?} needs {:?}, which the device doesn't have",
+            label,
+            format,
+            info.required_features
+        );
+
+        // Faces of cube map arrays are laid out one after the other, for
+        // every layer
+        let array_layers = layers * faces;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: array_layers,
+            },
+            mip_level_count: levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let (block_width, block_height) = (
+            info.block_dimensions.0 as u32,
+            info.block_dimensions.1 as u32,
+        );
+        for level in 0..levels {
+            // The level index comes right after the header, 3 u64s per level:
+            // offset, length and uncompressed length
+            let entry = 80 + level as usize * 24;
+            let offset = read_u64(bytes, entry)? as usize;
+            let length = read_u64(bytes, entry + 8)? as usize;
+            let data = bytes
+                .get(offset..offset + length)
+                .with_context(|| format!("{}: level {} is out of bounds", label, level))?;
+            let data = ktx2_level(data, supercompression, label)?;
+
+            // Compressed levels are whole blocks, even when smaller than one
+            let blocks_wide = (width >> level).max(1).div_ceil(block_width);
+            let blocks_high = (height >> level).max(1).div_ceil(block_height);
+            let level_length = (blocks_wide * blocks_high * info.block_size as u32) as usize;
+            ensure!(
+                data.len() == level_length * array_layers as usize,
+                "{}: level {} is {} bytes long, expected {}",
+                label,
+                level,
+                data.len(),
+                level_length * array_layers as usize
+            );
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(blocks_wide * info.block_size as u32),
+                    rows_per_image: NonZeroU32::new(blocks_high),
+                },
+                wgpu::Extent3d {
+                    width: blocks_wide * block_width,
+                    height: blocks_high * block_height,
+                    depth_or_array_layers: array_layers,
+                },
+            );
+        }
+
+        let dimension = match (faces, layer_count) {
+            (6, 0) => wgpu::TextureViewDimension::Cube,
+            (6, _) => wgpu::TextureViewDimension::CubeArray,
+            (_, 0) => wgpu::TextureViewDimension::D2,
+            _ => wgpu::TextureViewDimension::D2Array,
+        };
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(label),
+            dimension: Some(dimension),
+            ..Default::default()
+        });
+        let sampler = if faces == 6 {
+            Self::create_cube_sampler(device)
+        } else {
+            Self::create_image_sampler(device)
+        };
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    /// Linear all around, clamping so that faces don't bleed into each other
+    /// (as seams would show) when sampling close to their edges
+    pub fn create_cube_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+        SamplerDesc::default().create(device, None)
+    }
+
+    fn create_image_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+        SamplerDesc::nearest()
+            .mag_filter(wgpu::FilterMode::Linear)
+            .create(device, None)
+    }
+}
+
+/// Everything that tells samplers apart, so that `Assets::sampler` can hand
+/// out the same one to everything asking for the same combination. Starts out
+/// linear all around (mipmaps included), clamping to the edges
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerDesc {
+    /// u, v and w
+    address_modes: [wgpu::AddressMode; 3],
+    mag_filter: wgpu::FilterMode,
+    min_filter: wgpu::FilterMode,
+    mipmap_filter: wgpu::FilterMode,
+    anisotropy: Option<NonZeroU8>,
+    compare: Option<wgpu::CompareFunction>,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self {
+            address_modes: [wgpu::AddressMode::ClampToEdge; 3],
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy: None,
+            compare: None,
+        }
+    }
+}
+
+impl SamplerDesc {
+    /// No filtering whatsoever, for pixel art and for reading render targets
+    /// back texel by texel
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Self::default()
+        }
+    }
+
+    pub fn mag_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.mag_filter = filter;
+        self
+    }
+
+    pub fn mipmap_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.mipmap_filter = filter;
+        self
+    }
+
+    /// The same address mode along every axis
+    pub fn address_mode(self, mode: wgpu::AddressMode) -> Self {
+        self.address_modes(mode, mode, mode)
+    }
+
+    pub fn address_modes(
+        mut self,
+        u: wgpu::AddressMode,
+        v: wgpu::AddressMode,
+        w: wgpu::AddressMode,
+    ) -> Self {
+        self.address_modes = [u, v, w];
+        self
+    }
+
+    /// Up to how many samples to take along surfaces seen at an angle; one of
+    /// 1, 2, 4, 8 or 16. Ignored where anisotropic filtering isn't supported
+    pub fn anisotropy(mut self, clamp: u8) -> Self {
+        self.anisotropy = NonZeroU8::new(clamp);
+        self
+    }
+
+    /// Makes it a comparison sampler, for depth textures
+    pub fn compare(mut self, compare: wgpu::CompareFunction) -> Self {
+        self.compare = Some(compare);
+        self
+    }
+
+    pub fn create(&self, device: &wgpu::Device, label: Option<&str>) -> wgpu::Sampler {
+        let [address_mode_u, address_mode_v, address_mode_w] = self.address_modes;
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label,
+            address_mode_u,
+            address_mode_v,
+            address_mode_w,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            compare: self.compare,
+            anisotropy_clamp: self.anisotropy,
+            ..Default::default()
+        })
+    }
+}
+
+/// An image being read and decoded on a background thread, so that big ones
+/// don't hold up `Scene::new`. Only uploading it has to happen on the thread
+/// owning the queue, once `poll` finds it decoded; scenes are expected to
+/// draw with a placeholder (i.e. `Texture::checkerboard`) until then.
+pub struct TextureLoader {
+    /// Gone once the image has been handed over
+    receiver: Option<mpsc::Receiver<Result<image::DynamicImage>>>,
+    color_space: ColorSpace,
+    label: String,
+}
+
+impl TextureLoader {
+    pub fn spawn(path: impl Into<PathBuf>, color_space: ColorSpace, label: &str) -> Self {
+        let path = path.into();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let image = std::fs::read(&path)
+                .with_context(|| format!("Couldn't read {}", path.display()))
+                .and_then(|bytes| Ok(image::load_from_memory(&bytes)?))
+                // Converted here too, as `from_image` needs RGBA
+                .map(|image| image::DynamicImage::ImageRgba8(image.to_rgba8()));
+            // Whoever was waiting for it might have given up already
+            let _ = sender.send(image);
+        });
+
+        Self {
+            receiver: Some(receiver),
+            color_space,
+            label: label.to_owned(),
+        }
+    }
+
+    /// Uploads the image if it's been decoded since the last call, returning
+    /// the texture (or why it couldn't be loaded) only that once
+    pub fn poll(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Result<Texture>> {
+        let image = match self.receiver.as_ref()?.try_recv() {
+            Ok(image) => image,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(anyhow!("The loading thread panicked")),
+        };
+        self.receiver = None;
+
+        let (color_space, label) = (self.color_space, &self.label);
+        Some(
+            image
+                .and_then(|image| {
+                    Texture::from_image(device, queue, &image, color_space, Some(label))
+                })
+                .with_context(|| format!("Couldn't load {}", label)),
+        )
+    }
+}
+
+/// What every KTX2 file starts with
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    let field = bytes
+        .get(offset..offset + 4)
+        .context("Truncated KTX2 file")?;
+    Ok(u32::from_le_bytes(field.try_into().unwrap()))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64> {
+    let field = bytes
+        .get(offset..offset + 8)
+        .context("Truncated KTX2 file")?;
+    Ok(u64::from_le_bytes(field.try_into().unwrap()))
+}
+
+/// The `VkFormat`s there's a wgpu format for (and that are worth shipping
+/// pre-baked)
+fn ktx2_format(vk_format: u32) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat::*;
+
+    Some(match vk_format {
+        9 => R8Unorm,
+        16 => Rg8Unorm,
+        37 => Rgba8Unorm,
+        43 => Rgba8UnormSrgb,
+        44 => Bgra8Unorm,
+        50 => Bgra8UnormSrgb,
+        97 => Rgba16Float,
+        109 => Rgba32Float,
+        133 => Bc1RgbaUnorm,
+        134 => Bc1RgbaUnormSrgb,
+        137 => Bc3RgbaUnorm,
+        138 => Bc3RgbaUnormSrgb,
+        139 => Bc4RUnorm,
+        140 => Bc4RSnorm,
+        141 => Bc5RgUnorm,
+        142 => Bc5RgSnorm,
+        145 => Bc7RgbaUnorm,
+        146 => Bc7RgbaUnormSrgb,
+        147 => Etc2RgbUnorm,
+        148 => Etc2RgbUnormSrgb,
+        149 => Etc2RgbA1Unorm,
+        150 => Etc2RgbA1UnormSrgb,
+        157 => Astc4x4RgbaUnorm,
+        158 => Astc4x4RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+/// Undoes a level's supercompression, if there's any
+fn ktx2_level<'a>(data: &'a [u8], supercompression: u32, label: &str) -> Result<Cow<'a, [u8]>> {
+    match supercompression {
+        0 => Ok(Cow::Borrowed(data)),
+        1 => bail!(
+            "{}: BasisLZ files have to be transcoded before loading them",
+            label
+        ),
+        #[cfg(feature = "zstd")]
+        2 => {
+            use std::io::Read;
+
+            let mut source = data;
+            let mut decoder = ruzstd::StreamingDecoder::new(&mut source)
+                .map_err(|err| anyhow!("{}: {}", label, err))?;
+            let mut level = Vec::new();
+            decoder.read_to_end(&mut level)?;
+            Ok(Cow::Owned(level))
+        }
+        #[cfg(not(feature = "zstd"))]
+        2 => bail!(
+            "{}: loading Zstandard-supercompressed files needs the `zstd` feature",
+            label
+        ),
+        scheme => bail!("{}: unsupported supercompression scheme {}", label, scheme),
+    }
 }
 
 pub struct DepthTexture {
@@ -97,12 +773,56 @@ impl Deref for DepthTexture {
 
 impl DepthTexture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    /// For passes that test stencil along with depth
+    pub const DEPTH_STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
 
     pub fn from_screen(
         device: &wgpu::Device,
         width: u32,
         height: u32,
         label: Option<&str>,
+    ) -> Self {
+        Self::multisampled(device, width, height, 1, label)
+    }
+
+    /// Same as `from_screen`, for render passes drawing `sample_count` samples
+    /// per pixel
+    pub fn multisampled(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        label: Option<&str>,
+    ) -> Self {
+        Self::create(
+            device,
+            width,
+            height,
+            sample_count,
+            Self::DEPTH_FORMAT,
+            label,
+        )
+    }
+
+    /// Same as `from_screen`, with an 8-bit stencil buffer along with depth,
+    /// for pipelines built with `PipelineBuilder::stencil`. Render passes have
+    /// to give it `stencil_ops`
+    pub fn with_stencil(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        label: Option<&str>,
+    ) -> Self {
+        Self::create(device, width, height, 1, Self::DEPTH_STENCIL_FORMAT, label)
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        format: wgpu::TextureFormat,
+        label: Option<&str>,
     ) -> Self {
         let size = wgpu::Extent3d {
             width,
@@ -114,27 +834,19 @@ impl DepthTexture {
             label,
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
-            format: Self::DEPTH_FORMAT,
+            format,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
         };
 
         let texture = device.create_texture(&desc);
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: Some(wgpu::CompareFunction::LessEqual),
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            ..Default::default()
-        });
+        let sampler = SamplerDesc::default()
+            .mipmap_filter(wgpu::FilterMode::Nearest)
+            .compare(wgpu::CompareFunction::LessEqual)
+            .create(device, None);
 
         Self {
             tex: Texture {
@@ -145,3 +857,223 @@ impl DepthTexture {
         }
     }
 }
+
+/// Somewhere to draw offscreen and sample from (or `read_to_image`)
+/// afterwards, i.e. for post-processing, or for drawing a scene into a
+/// thumbnail: a color texture and, if asked for, a depth texture to go with
+/// it, both the same size
+pub struct RenderTarget {
+    pub color: Texture,
+    pub depth: Option<DepthTexture>,
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    label: String,
+}
+
+impl RenderTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        with_depth: bool,
+        label: &str,
+    ) -> Self {
+        let color = Self::create_color(device, width, height, format, label);
+        let depth = if with_depth {
+            Some(Self::create_depth(device, width, height, label))
+        } else {
+            None
+        };
+
+        Self {
+            color,
+            depth,
+            format,
+            width,
+            height,
+            label: label.to_owned(),
+        }
+    }
+
+    fn create_color(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Texture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = SamplerDesc::default().create(device, Some(label));
+
+        Texture {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    fn create_depth(device: &wgpu::Device, width: u32, height: u32, label: &str) -> DepthTexture {
+        DepthTexture::from_screen(device, width, height, Some(&format!("{} Depth", label)))
+    }
+
+    /// Recreates the textures at the new size, leaving them blank. Whatever
+    /// was bound to the old ones (i.e. bind groups) has to be created again
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+
+        self.width = width;
+        self.height = height;
+        self.color = Self::create_color(device, width, height, self.format, &self.label);
+        if self.depth.is_some() {
+            self.depth = Some(Self::create_depth(device, width, height, &self.label));
+        }
+    }
+
+    /// For drawing into the color texture, clearing it first with `clear`
+    /// (or keeping what's there without)
+    pub fn color_attachment(
+        &self,
+        clear: Option<wgpu::Color>,
+    ) -> wgpu::RenderPassColorAttachment<'_> {
+        wgpu::RenderPassColorAttachment {
+            view: &self.color.view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: match clear {
+                    Some(color) => wgpu::LoadOp::Clear(color),
+                    None => wgpu::LoadOp::Load,
+                },
+                store: true,
+            },
+        }
+    }
+
+    /// For depth testing against the depth texture, cleared to the far plane.
+    /// Missing if it was created without one
+    #[allow(dead_code)] // Nothing draws offscreen with depth yet
+    pub fn depth_attachment(&self) -> Option<wgpu::RenderPassDepthStencilAttachment<'_>> {
+        self.depth
+            .as_ref()
+            .map(|depth| wgpu::RenderPassDepthStencilAttachment {
+                view: &depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            })
+    }
+
+    /// Binds the color texture for sampling, the way `Texture::create_bind_group`
+    /// does. Has to be called again after `resize`
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        label: &str,
+    ) -> wgpu::BindGroup {
+        self.color.create_bind_group(device, layout, label)
+    }
+}
+
+/// Copies the first mip level of `texture` (`width` by `height`, in `format`)
+/// back from the GPU, waiting for everything submitted so far to finish. Has
+/// to be an 8-bit RGBA or BGRA format, created with `COPY_SRC`
+pub async fn read_to_image(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+) -> Result<image::RgbaImage> {
+    let bgra = match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        _ => bail!("Can't read {:?} textures back into images", format),
+    };
+
+    // Rows get copied into buffers at multiples of 256 bytes apart
+    let row_length = 4 * width;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_row_length = row_length.div_ceil(align) * align;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (padded_row_length * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Command Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_row_length),
+                rows_per_image: NonZeroU32::new(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    // Nothing else polls the device, so the mapping would never finish
+    // otherwise
+    device.poll(wgpu::Maintain::Wait);
+    mapping
+        .await
+        .map_err(|_| anyhow!("Couldn't map the readback buffer"))?;
+
+    let mut pixels = Vec::with_capacity((row_length * height) as usize);
+    {
+        let padded = slice.get_mapped_range();
+        for row in padded.chunks(padded_row_length as usize) {
+            pixels.extend_from_slice(&row[..row_length as usize]);
+        }
+    }
+    buffer.unmap();
+
+    if bgra {
+        for pixel in pixels.chunks_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    image::RgbaImage::from_raw(width, height, pixels)
+        .context("The readback buffer doesn't fit the image")
+}
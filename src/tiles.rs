@@ -0,0 +1,278 @@
+//! Grid mode, where every scene gets drawn each frame into a tile of its own
+//! and the tiles are put together on the window, side by side.
+//!
+//! Scenes don't know about it: they're resized to the size of a tile and
+//! render into a `RenderTarget` of that size instead of the frame, which then
+//! gets sampled into the tile's cell. Only the focused scene gets to see the
+//! input, with the cursor measured from the top left corner of its tile.
+//!
+//! The scene menu lays its thumbnails out the same way, out of smaller
+//! targets that scenes don't render into directly but get scaled down into
+//! with `TileGrid::capture`.
+
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    bindings::Bindings, input::Input, shader::shaders, texture::RenderTarget, viewport::Viewport,
+};
+
+/// Pixels left around every tile but the focused one, which fills its whole
+/// cell
+const TILE_MARGIN: u32 = 4;
+
+const BACKGROUND: wgpu::Color = wgpu::Color {
+    r: 0.02,
+    g: 0.02,
+    b: 0.02,
+    a: 1.0,
+};
+
+/// How big the targets tiles get drawn from are
+#[derive(Copy, Clone, Debug)]
+pub enum TileSize {
+    /// As big as a cell, for scenes to be resized to
+    Cell,
+    /// This many pixels wide, and as tall as the window's aspect makes them
+    Thumbnail(u32),
+}
+
+pub struct TileGrid {
+    /// One for every scene, in the order they're tiled in
+    targets: Vec<RenderTarget>,
+    bind_groups: Vec<wgpu::BindGroup>,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    columns: u32,
+    rows: u32,
+    window: PhysicalSize<u32>,
+    size: TileSize,
+    /// What scenes that aren't focused see of the input: nothing at all
+    pub idle_input: Input,
+}
+
+impl TileGrid {
+    /// Grid of `count` tiles, laid out as close to a square as they fit,
+    /// drawn into a window of `window` pixels and sampled into its surface
+    pub fn new(
+        device: &wgpu::Device,
+        sc: &wgpu::SurfaceConfiguration,
+        count: usize,
+        window: PhysicalSize<u32>,
+        size: TileSize,
+    ) -> Self {
+        let vert_module = shaders::TILE_VERT.create_module(device);
+        let frag_module = shaders::TILE_FRAG.create_module(device);
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tiles - Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tiles - Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Tiles - Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vert_module,
+                entry_point: "main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        let columns = (count as f64).sqrt().ceil().max(1.0) as u32;
+        let rows = (count as u32).div_ceil(columns);
+
+        let mut grid = Self {
+            targets: Vec::new(),
+            bind_groups: Vec::new(),
+            layout,
+            pipeline,
+            columns,
+            rows,
+            window,
+            size,
+            idle_input: Input::new(Bindings::default()),
+        };
+        let tile = grid.target_size();
+        grid.targets = (0..count)
+            .map(|i| {
+                RenderTarget::new(
+                    device,
+                    tile.width,
+                    tile.height,
+                    sc.format,
+                    false,
+                    &format!("Tiles - Tile {}", i),
+                )
+            })
+            .collect();
+        grid.create_bind_groups(device);
+        grid
+    }
+
+    fn create_bind_groups(&mut self, device: &wgpu::Device) {
+        let layout = &self.layout;
+        self.bind_groups = self
+            .targets
+            .iter()
+            .map(|target| target.create_bind_group(device, layout, "Tiles - Bind Group"))
+            .collect();
+    }
+
+    /// What every scene gets resized to while tiled
+    pub fn tile_size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(
+            (self.window.width / self.columns).max(1),
+            (self.window.height / self.rows).max(1),
+        )
+    }
+
+    /// Size of the targets, which only matches the cells with `TileSize::Cell`
+    fn target_size(&self) -> PhysicalSize<u32> {
+        match self.size {
+            TileSize::Cell => self.tile_size(),
+            TileSize::Thumbnail(width) => PhysicalSize::new(
+                width,
+                (width * self.window.height / self.window.width.max(1)).max(1),
+            ),
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, window: PhysicalSize<u32>) {
+        self.window = window;
+        let tile = self.target_size();
+        for target in self.targets.iter_mut() {
+            target.resize(device, tile.width, tile.height);
+        }
+        self.create_bind_groups(device);
+    }
+
+    /// What the scene drawn in the `index`-th tile renders into
+    pub fn target(&self, index: usize) -> &RenderTarget {
+        &self.targets[index]
+    }
+
+    /// Cell of the `index`-th tile, left to right and then top to bottom
+    pub fn cell(&self, index: usize) -> Viewport {
+        let tile = self.tile_size();
+        let index = index as u32;
+        Viewport {
+            x: index % self.columns * tile.width,
+            y: index / self.columns * tile.height,
+            width: tile.width,
+            height: tile.height,
+        }
+    }
+
+    /// Scales `source` down into the `index`-th target, for scenes that
+    /// render at some other size than the tiles
+    pub fn capture(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        index: usize,
+        source: &RenderTarget,
+    ) {
+        let bind_group =
+            source.create_bind_group(device, &self.layout, "Tiles - Capture Bind Group");
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tiles - Capture Pass"),
+            color_attachments: &[self.targets[index].color_attachment(Some(BACKGROUND))],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Which tile the cursor is over, if any
+    pub fn tile_at(&self, cursor: (f64, f64)) -> Option<usize> {
+        (0..self.targets.len()).find(|&i| self.cell(i).contains(cursor))
+    }
+
+    /// Samples every tile into its cell of `frame_view`, the `focused` one
+    /// filling all of it so that it stands out
+    pub fn composite(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        focused: usize,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tiles - Composite Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(BACKGROUND),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+
+        for (i, bind_group) in self.bind_groups.iter().enumerate() {
+            let mut cell = self.cell(i);
+            if i != focused {
+                let margin = TILE_MARGIN.min(cell.width / 4).min(cell.height / 4);
+                cell.x += margin;
+                cell.y += margin;
+                cell.width -= 2 * margin;
+                cell.height -= 2 * margin;
+            }
+            cell.apply(&mut render_pass);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
@@ -1,65 +1,145 @@
+/// Generates `Descriptable::descriptor()` out of `#[location(n)]` field
+/// attributes, see the `vertex-derive` crate
+pub use vertex_derive::Vertex;
+
 pub trait VertexBufferable: bytemuck::Pod + bytemuck::Zeroable {}
 
 pub trait Descriptable {
     fn descriptor<'a>() -> wgpu::VertexBufferLayout<'a>;
 }
 
+/// Vertices with a model-space position, which is all that's needed to
+/// compute bounding volumes out of them
+pub trait Positioned {
+    fn position(&self) -> [f32; 3];
+}
+
+/// Vertices carrying a normal, which can be generated out of their positions
+pub trait Normaled: Positioned {
+    fn normal(&self) -> [f32; 3];
+    fn set_normal(&mut self, normal: [f32; 3]);
+}
+
+/// Vertices that can be told apart up to some tolerance, so that nearly
+/// identical ones can be welded together
+pub trait Weldable: Positioned + Copy {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool;
+}
+
+/// Compares vertices made out of nothing but `f32`s, component by component
+pub fn f32s_approx_eq<T: bytemuck::Pod>(a: &T, b: &T, epsilon: f32) -> bool {
+    let a: &[f32] = bytemuck::cast_slice(std::slice::from_ref(a));
+    let b: &[f32] = bytemuck::cast_slice(std::slice::from_ref(b));
+    a.iter().zip(b).all(|(a, b)| (a - b).abs() <= epsilon)
+}
+
+/// Converts to an IEEE 754 half, as read by `VertexFormat::Float16x*`,
+/// rounding to nearest even. Out of range values become infinities
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinities stay infinities, NaNs stay (quiet) NaNs
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        sign | 0x7c00
+    } else if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // Subnormal, with the implicit leading one made explicit
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let rounded = (mantissa + (1 << (shift - 1)) - 1 + ((mantissa >> shift) & 1)) >> shift;
+        sign | rounded as u16
+    } else {
+        // A carry out of the mantissa bumps the exponent, as it should
+        let rounded = (mantissa + 0xfff + ((mantissa >> 13) & 1)) >> 13;
+        sign | (((exponent as u32) << 10) + rounded) as u16
+    }
+}
+
+pub fn f32s_to_f16s<const N: usize>(values: [f32; N]) -> [u16; N] {
+    let mut halves = [0; N];
+    for (half, &value) in halves.iter_mut().zip(values.iter()) {
+        *half = f32_to_f16(value);
+    }
+    halves
+}
+
+/// Converts a value in `[-1, 1]` to the signed normalized byte read by
+/// `VertexFormat::Snorm8x*`
+pub fn f32_to_snorm8(value: f32) -> i8 {
+    (value.clamp(-1.0, 1.0) * 127.0).round() as i8
+}
+
+pub fn f32s_to_snorm8s<const N: usize>(values: [f32; N]) -> [i8; N] {
+    let mut bytes = [0; N];
+    for (byte, &value) in bytes.iter_mut().zip(values.iter()) {
+        *byte = f32_to_snorm8(value);
+    }
+    bytes
+}
+
+pub trait Textured {
+    fn tex_coords(&self) -> [f32; 2];
+}
+
+/// Vertices carrying a tangent for normal mapping, which can be generated out
+/// of their positions, normals and texture coordinates. The fourth component
+/// holds the sign of the bitangent, `cross(normal, tangent.xyz) * tangent.w`
+pub trait Tangented: Normaled + Textured {
+    fn set_tangent(&mut self, tangent: [f32; 4]);
+}
+
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
 pub struct FlatVertex {
+    #[location(0)]
     pub position: [f32; 3],
+    #[location(1)]
     pub color: [f32; 3],
 }
 
 impl VertexBufferable for FlatVertex {}
 
-impl Descriptable for FlatVertex {
-    fn descriptor<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<FlatVertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-            ],
-        }
+impl Positioned for FlatVertex {
+    fn position(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
+impl Weldable for FlatVertex {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        f32s_approx_eq(self, other, epsilon)
     }
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
 pub struct TexturedVertex {
+    #[location(0)]
     pub position: [f32; 3],
+    #[location(1)]
     pub tex_coords: [f32; 2],
 }
 
 impl VertexBufferable for TexturedVertex {}
 
-impl Descriptable for TexturedVertex {
-    fn descriptor<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<TexturedVertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-            ],
-        }
+impl Positioned for TexturedVertex {
+    fn position(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
+impl Weldable for TexturedVertex {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        f32s_approx_eq(self, other, epsilon)
     }
 }
@@ -0,0 +1,66 @@
+/// Rectangle of the surface to draw into, in physical pixels from the top
+/// left corner. Rendering the same scene into several of them within a
+/// single render pass only takes setting each one before its draws
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    /// The whole surface
+    pub fn full(size: winit::dpi::PhysicalSize<u32>) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: size.width,
+            height: size.height,
+        }
+    }
+
+    /// The left and right halves of the surface
+    pub fn split(size: winit::dpi::PhysicalSize<u32>) -> [Self; 2] {
+        let left = size.width / 2;
+        [
+            Self {
+                x: 0,
+                y: 0,
+                width: left,
+                height: size.height,
+            },
+            Self {
+                x: left,
+                y: 0,
+                width: size.width - left,
+                height: size.height,
+            },
+        ]
+    }
+
+    /// For the camera drawing into it
+    pub fn aspect(&self) -> f32 {
+        self.width.max(1) as f32 / self.height.max(1) as f32
+    }
+
+    /// Whether a point, such as the cursor, lies inside
+    pub fn contains(&self, (x, y): (f64, f64)) -> bool {
+        let (left, top) = (self.x as f64, self.y as f64);
+        x >= left && x < left + self.width as f64 && y >= top && y < top + self.height as f64
+    }
+
+    /// Restricts the pass' upcoming draws to the rectangle, mapping clip
+    /// space onto it and cutting off anything that would spill outside
+    pub fn apply(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_viewport(
+            self.x as f32,
+            self.y as f32,
+            self.width as f32,
+            self.height as f32,
+            0.0,
+            1.0,
+        );
+        render_pass.set_scissor_rect(self.x, self.y, self.width, self.height);
+    }
+}
@@ -0,0 +1,289 @@
+use cgmath::{Point3, Vector3};
+
+use crate::vertex::{Vertex, VertexBufferable};
+
+/// Blocks per side of a chunk
+pub const CHUNK_SIZE: i32 = 16;
+const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Tiles laid out horizontally in the block atlas
+pub const ATLAS_TILES: u32 = 4;
+/// Pixels per side of an atlas tile
+pub const ATLAS_TILE_SIZE: u32 = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Block {
+    Air,
+    Grass,
+    Dirt,
+    Stone,
+}
+
+impl Block {
+    fn is_solid(self) -> bool {
+        self != Block::Air
+    }
+
+    /// Atlas tile used by the face pointing towards `normal`
+    fn tile(self, normal: Vector3<i32>) -> u32 {
+        match (self, normal.y) {
+            (Block::Grass, 1) => 0,
+            (Block::Grass, -1) => 2,
+            (Block::Grass, _) => 1,
+            (Block::Dirt, _) => 2,
+            (Block::Stone, _) | (Block::Air, _) => 3,
+        }
+    }
+}
+
+/// Generates the block atlas: grass top, grass side, dirt and stone
+pub fn atlas() -> image::DynamicImage {
+    let image =
+        image::RgbaImage::from_fn(ATLAS_TILES * ATLAS_TILE_SIZE, ATLAS_TILE_SIZE, |x, y| {
+            let tile = x / ATLAS_TILE_SIZE;
+            let (tx, ty) = (x % ATLAS_TILE_SIZE, y);
+
+            // Cheap per-pixel noise so that the tiles aren't flat colors
+            let mut h = x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263);
+            h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+            let noise = (h >> 24) as f32 / 255.0 * 0.25 + 0.75;
+
+            let grass = [0.35, 0.65, 0.25];
+            let dirt = [0.5, 0.35, 0.2];
+            let stone = [0.5, 0.5, 0.52];
+
+            let color = match tile {
+                0 => grass,
+                // Grass side: a band of grass on top of dirt, with a ragged edge
+                1 if ty < 3 + (tx * 7 + 3) % 3 => grass,
+                1 | 2 => dirt,
+                _ => stone,
+            };
+
+            image::Rgba([
+                (color[0] * noise * 255.0) as u8,
+                (color[1] * noise * 255.0) as u8,
+                (color[2] * noise * 255.0) as u8,
+                255,
+            ])
+        });
+
+    image::DynamicImage::ImageRgba8(image)
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
+pub struct VoxelVertex {
+    #[location(0)]
+    pub position: [f32; 3],
+    #[location(1)]
+    pub tex_coords: [f32; 2],
+    /// Fake directional lighting, baked per face
+    #[location(2)]
+    pub shade: f32,
+}
+
+impl VertexBufferable for VoxelVertex {}
+
+struct Face {
+    normal: Vector3<i32>,
+    /// Up direction of the face's texture
+    up: Vector3<i32>,
+    shade: f32,
+}
+
+const FACES: [Face; 6] = [
+    Face {
+        normal: Vector3::new(1, 0, 0),
+        up: Vector3::new(0, 1, 0),
+        shade: 0.8,
+    },
+    Face {
+        normal: Vector3::new(-1, 0, 0),
+        up: Vector3::new(0, 1, 0),
+        shade: 0.8,
+    },
+    Face {
+        normal: Vector3::new(0, 1, 0),
+        up: Vector3::new(0, 0, -1),
+        shade: 1.0,
+    },
+    Face {
+        normal: Vector3::new(0, -1, 0),
+        up: Vector3::new(0, 0, 1),
+        shade: 0.5,
+    },
+    Face {
+        normal: Vector3::new(0, 0, 1),
+        up: Vector3::new(0, 1, 0),
+        shade: 0.9,
+    },
+    Face {
+        normal: Vector3::new(0, 0, -1),
+        up: Vector3::new(0, 1, 0),
+        shade: 0.7,
+    },
+];
+
+pub struct Chunk {
+    /// Position of the chunk in chunk coordinates
+    pub coords: Vector3<i32>,
+    blocks: Vec<Block>,
+}
+
+impl Chunk {
+    fn index(local: Vector3<i32>) -> usize {
+        (local.x + (local.y + local.z * CHUNK_SIZE) * CHUNK_SIZE) as usize
+    }
+
+    /// World-space bounds of the chunk
+    pub fn aabb(&self) -> (Point3<f32>, Point3<f32>) {
+        let min = self.coords * CHUNK_SIZE;
+        let max = min + Vector3::new(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE);
+        (
+            Point3::new(min.x as f32, min.y as f32, min.z as f32),
+            Point3::new(max.x as f32, max.y as f32, max.z as f32),
+        )
+    }
+}
+
+/// A fixed-size grid of chunks
+pub struct World {
+    /// Size of the world, in chunks
+    pub size: Vector3<i32>,
+    pub chunks: Vec<Chunk>,
+}
+
+impl World {
+    /// Generates rolling hills out of a few layered sine waves
+    pub fn generate(size: Vector3<i32>, seed: u32) -> Self {
+        let phase = seed as f32 * 1.618;
+        let max_height = size.y * CHUNK_SIZE;
+        let height_at = |x: i32, z: i32| {
+            let (x, z) = (x as f32, z as f32);
+            let h = 0.45
+                + 0.2 * (x * 0.08 + phase).sin() * (z * 0.06 + phase * 0.7).cos()
+                + 0.1 * ((x + z) * 0.05 + phase * 1.3).sin()
+                + 0.05 * (x * 0.21 - z * 0.17 + phase * 2.1).sin();
+            (h * max_height as f32) as i32
+        };
+
+        let mut chunks = Vec::with_capacity((size.x * size.y * size.z) as usize);
+        for cz in 0..size.z {
+            for cy in 0..size.y {
+                for cx in 0..size.x {
+                    let coords = Vector3::new(cx, cy, cz);
+                    let mut blocks = vec![Block::Air; CHUNK_VOLUME];
+                    for z in 0..CHUNK_SIZE {
+                        for x in 0..CHUNK_SIZE {
+                            let height = height_at(cx * CHUNK_SIZE + x, cz * CHUNK_SIZE + z);
+                            for y in 0..CHUNK_SIZE {
+                                let world_y = cy * CHUNK_SIZE + y;
+                                let block = if world_y >= height {
+                                    Block::Air
+                                } else if world_y == height - 1 {
+                                    Block::Grass
+                                } else if world_y >= height - 4 {
+                                    Block::Dirt
+                                } else {
+                                    Block::Stone
+                                };
+                                blocks[Chunk::index(Vector3::new(x, y, z))] = block;
+                            }
+                        }
+                    }
+                    chunks.push(Chunk { coords, blocks });
+                }
+            }
+        }
+
+        Self { size, chunks }
+    }
+
+    /// Block at the given world position, air if it's outside of the world
+    pub fn block(&self, position: Vector3<i32>) -> Block {
+        let chunk = position.map(|c| c.div_euclid(CHUNK_SIZE));
+        if chunk.x < 0
+            || chunk.y < 0
+            || chunk.z < 0
+            || chunk.x >= self.size.x
+            || chunk.y >= self.size.y
+            || chunk.z >= self.size.z
+        {
+            return Block::Air;
+        }
+
+        let index = chunk.x + (chunk.y + chunk.z * self.size.y) * self.size.x;
+        let local = position.map(|c| c.rem_euclid(CHUNK_SIZE));
+        self.chunks[index as usize].blocks[Chunk::index(local)]
+    }
+
+    /// Naive meshing: one quad per block face that isn't hidden by a solid
+    /// neighbour (neighbouring chunks included)
+    pub fn mesh_chunk(&self, chunk: &Chunk) -> (Vec<VoxelVertex>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let origin = chunk.coords * CHUNK_SIZE;
+
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let local = Vector3::new(x, y, z);
+                    let block = chunk.blocks[Chunk::index(local)];
+                    if !block.is_solid() {
+                        continue;
+                    }
+
+                    let position = origin + local;
+                    for face in FACES.iter() {
+                        if self.block(position + face.normal).is_solid() {
+                            continue;
+                        }
+
+                        // A chunk with every other block missing has more
+                        // faces than `u16` indices can address
+                        let base = vertices.len() as u32;
+                        push_face(&mut vertices, position, face, block.tile(face.normal));
+                        indices.extend_from_slice(&[
+                            base,
+                            base + 1,
+                            base + 2,
+                            base,
+                            base + 2,
+                            base + 3,
+                        ]);
+                    }
+                }
+            }
+        }
+
+        (vertices, indices)
+    }
+}
+
+fn push_face(vertices: &mut Vec<VoxelVertex>, position: Vector3<i32>, face: &Face, tile: u32) {
+    let normal = face.normal.map(|c| c as f32);
+    let up = face.up.map(|c| c as f32);
+    // Right, as seen from outside the block
+    let right = (-normal).cross(up);
+
+    let center = position.map(|c| c as f32) + Vector3::new(0.5, 0.5, 0.5) + normal * 0.5;
+    let u0 = tile as f32 / ATLAS_TILES as f32;
+    let u1 = (tile + 1) as f32 / ATLAS_TILES as f32;
+
+    // Counter-clockwise, starting from the bottom left corner
+    let corners = [
+        (-0.5, -0.5, [u0, 1.0]),
+        (0.5, -0.5, [u1, 1.0]),
+        (0.5, 0.5, [u1, 0.0]),
+        (-0.5, 0.5, [u0, 0.0]),
+    ];
+    for (r, u, tex_coords) in corners.iter() {
+        let corner = center + right * *r + up * *u;
+        vertices.push(VoxelVertex {
+            position: corner.into(),
+            tex_coords: *tex_coords,
+            shade: face.shade,
+        });
+    }
+}
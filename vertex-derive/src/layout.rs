@@ -0,0 +1,251 @@
+//! `#[derive(Std140)]` and `#[derive(Std430)]`, implementing
+//! `layout::Std140` or `layout::Std430` for structs that get shared with
+//! shaders, after checking at compile time that they're laid out the way
+//! GLSL's rules expect: uniform blocks follow std140, storage buffers std430
+//!
+//! Every field has to start at the offset the shader will read it from, and
+//! the struct has to be padded up to a multiple of its own alignment. Under
+//! both rules vectors of two components align to 8 bytes, and vectors of
+//! three or four to 16. std140 also aligns arrays, matrices and structs to 16
+//! bytes, and needs every element of an array to start 16 bytes apart.
+//!
+//! Scalars and vectors of `f32`, `u32` and `i32` are supported, as are arrays
+//! of them (matrices being arrays of vectors) and other structs deriving the
+//! same layout. Like with `derive(Vertex)`, arrays of two to four scalars are
+//! taken to be vectors. Fields that only pad the struct, and which the shader doesn't
+//! declare, take a `#[padding]` attribute: a `[u32; 3]` padding field doesn't
+//! get aligned like a vector would.
+//!
+//! ```ignore
+//! #[repr(C, packed)]
+//! #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Std140)]
+//! struct ViewUniform {
+//!     aspect: f32,
+//!     #[padding]
+//!     _padding: [f32; 3],
+//! }
+//! ```
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, quote_spanned};
+use syn::{spanned::Spanned, Data, DeriveInput, Error, Fields, LitStr, Type};
+
+use crate::{array_len, repr, scalar};
+
+#[derive(Copy, Clone)]
+pub enum Rules {
+    Std140,
+    Std430,
+}
+
+impl Rules {
+    fn name(self) -> &'static str {
+        match self {
+            Rules::Std140 => "std140",
+            Rules::Std430 => "std430",
+        }
+    }
+
+    /// Least alignment of arrays, matrices and structs
+    fn min_align(self) -> usize {
+        match self {
+            Rules::Std140 => 16,
+            Rules::Std430 => 1,
+        }
+    }
+}
+
+pub fn expand(input: &DeriveInput, rules: Rules) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let rules_name = rules.name();
+    let rules_trait = format_ident!(
+        "{}",
+        match rules {
+            Rules::Std140 => "Std140",
+            Rules::Std430 => "Std430",
+        }
+    );
+    let min_align = rules.min_align();
+
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "shader-visible structs can't have generic parameters",
+        ));
+    }
+
+    let packed = repr(input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "shader-visible structs need named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "shader-visible structs need named fields",
+            ))
+        }
+    };
+    if fields.is_empty() {
+        return Err(Error::new(
+            input.span(),
+            "shader-visible structs need at least one field",
+        ));
+    }
+
+    // The Rust offset of every field is computed the way `derive(Vertex)`
+    // does, then checked against where the rules would put the field, right
+    // after the previous one
+    let mut checks = Vec::new();
+    let mut aligns = Vec::new();
+    let mut end = quote! { 0 };
+
+    for (i, field) in fields.iter().enumerate() {
+        let ty = &field.ty;
+        let field_name = field.ident.as_ref().unwrap();
+        let offset = format_ident!("OFFSET_{}", i);
+        let align = format_ident!("ALIGN_{}", i);
+
+        let rust_align = if packed {
+            quote! { 1 }
+        } else {
+            quote! { ::std::mem::align_of::<#ty>() }
+        };
+        let (field_align, stride) = if field.attrs.iter().any(|a| a.path.is_ident("padding")) {
+            (padding_align(ty)?, None)
+        } else {
+            field_align(ty, rules, &rules_trait)?
+        };
+
+        let misplaced = LitStr::new(
+            &format!(
+                "`{}::{}` isn't where {} puts it, it needs to be padded to a multiple of its alignment",
+                name, field_name, rules_name
+            ),
+            field.span(),
+        );
+        checks.push(quote_spanned! {field.span()=>
+            const #offset: usize = align(#end, #rust_align);
+            const #align: usize = #field_align;
+            assert!(#offset == align(#end, #align), #misplaced);
+        });
+
+        if let Some(element) = stride {
+            let strided = LitStr::new(
+                &format!(
+                    "the elements of `{}::{}` aren't as far apart as {} puts them",
+                    name, field_name, rules_name
+                ),
+                field.span(),
+            );
+            checks.push(quote_spanned! {field.span()=>
+                assert!(::std::mem::size_of::<#element>() % #align == 0, #strided);
+            });
+        }
+
+        aligns.push(align);
+        end = quote! { #offset + ::std::mem::size_of::<#ty>() };
+    }
+
+    let unpadded = LitStr::new(
+        &format!(
+            "`{}` needs to be padded to a multiple of its {} alignment",
+            name, rules_name
+        ),
+        name.span(),
+    );
+
+    Ok(quote! {
+        impl crate::layout::#rules_trait for #name {
+            const ALIGN: usize = {
+                const fn align(offset: usize, align: usize) -> usize {
+                    offset.div_ceil(align) * align
+                }
+                const fn max(a: usize, b: usize) -> usize {
+                    if a > b {
+                        a
+                    } else {
+                        b
+                    }
+                }
+
+                #(#checks)*
+
+                let struct_align = #min_align;
+                #(let struct_align = max(struct_align, #aligns);)*
+                assert!(
+                    ::std::mem::size_of::<#name>() == align(#end, struct_align),
+                    #unpadded
+                );
+                struct_align
+            };
+        }
+
+        // Associated constants only get evaluated when used, and with them
+        // the checks
+        const _: usize = <#name as crate::layout::#rules_trait>::ALIGN;
+    })
+}
+
+/// Alignment a field takes under `rules`, along with the element type of
+/// arrays, whose stride has to be a multiple of that alignment
+fn field_align(
+    ty: &Type,
+    rules: Rules,
+    rules_trait: &syn::Ident,
+) -> syn::Result<(TokenStream, Option<Type>)> {
+    let min_align = rules.min_align();
+    let unsupported = || {
+        Error::new(
+            ty.span(),
+            "unsupported field type, expected a scalar, vector or matrix of f32, u32 or i32, an array, or a struct with the same layout",
+        )
+    };
+
+    match ty {
+        Type::Path(_) if scalar(ty).is_some() => Ok((quote! { 4 }, None)),
+        // Some other struct, which has to check its own layout
+        Type::Path(_) => Ok((quote! { <#ty as crate::layout::#rules_trait>::ALIGN }, None)),
+        Type::Array(array) => {
+            let len = array_len(&array.len).ok_or_else(unsupported)?;
+            let element = &*array.elem;
+            let element_align = if scalar(element).is_some() {
+                match len {
+                    2 => return Ok((quote! { 8 }, None)),
+                    3 | 4 => return Ok((quote! { 16 }, None)),
+                    _ => quote! { 4 },
+                }
+            } else {
+                field_align(element, rules, rules_trait)?.0
+            };
+            Ok((
+                quote! { max(#element_align, #min_align) },
+                Some(element.clone()),
+            ))
+        }
+        _ => Err(unsupported()),
+    }
+}
+
+/// Padding fields are only made out of scalars, and aren't aligned like
+/// vectors
+fn padding_align(ty: &Type) -> syn::Result<TokenStream> {
+    let padding = match ty {
+        Type::Array(array) => scalar(&array.elem).is_some(),
+        _ => scalar(ty).is_some(),
+    };
+    if !padding {
+        return Err(Error::new(
+            ty.span(),
+            "padding needs to be a scalar or an array of scalars",
+        ));
+    }
+    Ok(quote! { 4 })
+}
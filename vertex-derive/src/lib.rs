@@ -0,0 +1,298 @@
+//! `#[derive(Vertex)]`, generating `Descriptable` implementations for the
+//! vertex types of `wgpu-experiments`, along with `#[derive(Std140)]` and
+//! `#[derive(Std430)]`, checking the layout of the structs shared with shaders
+//! through uniform and storage buffers (see the `layout` module)
+//!
+//! Every field that should be visible to the shader gets a
+//! `#[location(n)]` attribute. The attribute format is inferred from the
+//! field's type, and matrices (arrays of vectors, like `[[f32; 4]; 4]`) take
+//! one location per column, starting at `n`. Fields without a location are
+//! skipped, but still count towards the offsets of the ones after them.
+//!
+//! Packed fields, whose format can't be told from their type alone (`u16`s
+//! holding halves, `i8`s holding normalized values...), take their format
+//! from a `#[format(...)]` attribute naming a `wgpu::VertexFormat` variant.
+//!
+//! Vertices step per vertex by default; `#[vertex(instance)]` on the struct
+//! makes them step per instance instead.
+//!
+//! ```ignore
+//! #[repr(C, packed)]
+//! #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
+//! #[vertex(instance)]
+//! pub struct InstanceVertex {
+//!     #[location(5)]
+//!     transform: [[f32; 4]; 4],
+//! }
+//! ```
+
+mod layout;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Expr, Fields, Ident, Lit,
+    LitInt, Type,
+};
+
+#[proc_macro_derive(Vertex, attributes(location, format, vertex))]
+pub fn derive_vertex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+#[proc_macro_derive(Std140, attributes(padding))]
+pub fn derive_std140(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match layout::expand(&input, layout::Rules::Std140) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+#[proc_macro_derive(Std430, attributes(padding))]
+pub fn derive_std430(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match layout::expand(&input, layout::Rules::Std430) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "vertices can't have generic parameters",
+        ));
+    }
+
+    let packed = repr(input)?;
+    let step_mode = step_mode(input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "vertices need to be structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "vertices need to be structs with named fields",
+            ))
+        }
+    };
+
+    // Offsets are computed by the compiler out of the field types, following
+    // the rules of `repr(C)`: each field starts right after the previous one,
+    // rounded up to its alignment unless the struct is packed
+    let mut offsets = Vec::new();
+    let mut attributes = Vec::new();
+    let mut previous: Option<(Ident, &Type)> = None;
+
+    for (i, field) in fields.iter().enumerate() {
+        let ty = &field.ty;
+        let offset = format_ident!("OFFSET_{}", i);
+        let end = match &previous {
+            Some((previous, previous_ty)) => {
+                quote! { #previous + ::std::mem::size_of::<#previous_ty>() }
+            }
+            None => quote! { 0 },
+        };
+        let align = if packed {
+            quote! { 1 }
+        } else {
+            quote! { ::std::mem::align_of::<#ty>() }
+        };
+        offsets.push(quote! {
+            #[allow(dead_code)]
+            const #offset: usize = align(#end, #align);
+        });
+        previous = Some((offset.clone(), ty));
+
+        let location = match location(field)? {
+            Some(location) => location,
+            None => continue,
+        };
+
+        let (format, size, columns) = match explicit_format(field)? {
+            Some(format) => (format, 0, 1),
+            None => format(ty)?,
+        };
+        for column in 0..columns {
+            let shader_location = location + column as u32;
+            let column_offset = column * size;
+            attributes.push(quote! {
+                ::wgpu::VertexAttribute {
+                    offset: (#offset + #column_offset) as ::wgpu::BufferAddress,
+                    shader_location: #shader_location,
+                    format: ::wgpu::VertexFormat::#format,
+                }
+            });
+        }
+    }
+
+    Ok(quote! {
+        impl crate::vertex::Descriptable for #name {
+            fn descriptor<'a>() -> ::wgpu::VertexBufferLayout<'a> {
+                const fn align(offset: usize, align: usize) -> usize {
+                    offset.div_ceil(align) * align
+                }
+
+                #(#offsets)*
+
+                const ATTRIBUTES: &[::wgpu::VertexAttribute] = &[#(#attributes),*];
+
+                ::wgpu::VertexBufferLayout {
+                    array_stride: ::std::mem::size_of::<#name>() as ::wgpu::BufferAddress,
+                    step_mode: ::wgpu::VertexStepMode::#step_mode,
+                    attributes: ATTRIBUTES,
+                }
+            }
+        }
+    })
+}
+
+/// Checks for `repr(C)`, without which field offsets can't be known, and
+/// returns whether the struct is also packed
+fn repr(input: &DeriveInput) -> syn::Result<bool> {
+    let mut c = false;
+    let mut packed = false;
+
+    for attr in input.attrs.iter().filter(|a| a.path.is_ident("repr")) {
+        attr.parse_args_with(|stream: syn::parse::ParseStream| {
+            let reprs =
+                syn::punctuated::Punctuated::<Ident, syn::Token![,]>::parse_terminated(stream)?;
+            for repr in reprs {
+                if repr == "C" {
+                    c = true;
+                } else if repr == "packed" {
+                    packed = true;
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    if !c {
+        return Err(Error::new(
+            input.ident.span(),
+            "structs need to be #[repr(C)] for their layout to be known",
+        ));
+    }
+
+    Ok(packed)
+}
+
+fn step_mode(input: &DeriveInput) -> syn::Result<Ident> {
+    let mut step_mode = Ident::new("Vertex", Span::call_site());
+
+    for attr in input.attrs.iter().filter(|a| a.path.is_ident("vertex")) {
+        let mode: Ident = attr.parse_args()?;
+        step_mode = match mode.to_string().as_str() {
+            "vertex" => Ident::new("Vertex", mode.span()),
+            "instance" => Ident::new("Instance", mode.span()),
+            _ => {
+                return Err(Error::new(
+                    mode.span(),
+                    "expected #[vertex(vertex)] or #[vertex(instance)]",
+                ))
+            }
+        };
+    }
+
+    Ok(step_mode)
+}
+
+fn location(field: &syn::Field) -> syn::Result<Option<u32>> {
+    let mut attrs = field.attrs.iter().filter(|a| a.path.is_ident("location"));
+
+    let attr = match attrs.next() {
+        Some(attr) => attr,
+        None => return Ok(None),
+    };
+    if let Some(duplicate) = attrs.next() {
+        return Err(Error::new(
+            duplicate.span(),
+            "fields can only have one location",
+        ));
+    }
+
+    let location: LitInt = attr.parse_args()?;
+    location.base10_parse().map(Some)
+}
+
+fn explicit_format(field: &syn::Field) -> syn::Result<Option<Ident>> {
+    match field.attrs.iter().find(|a| a.path.is_ident("format")) {
+        Some(attr) => attr.parse_args().map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Attribute format for a field's type, along with the size in bytes of one
+/// attribute and how many consecutive locations it takes
+fn format(ty: &Type) -> syn::Result<(Ident, usize, usize)> {
+    let unsupported = || {
+        Error::new(
+            ty.span(),
+            "unsupported vertex attribute type, expected a scalar, vector or matrix of f32, u32 or i32",
+        )
+    };
+
+    let (scalar, len) = match ty {
+        Type::Path(_) => (scalar(ty).ok_or_else(unsupported)?, 1),
+        Type::Array(array) => match scalar(&array.elem) {
+            Some(scalar) => (scalar, array_len(&array.len).ok_or_else(unsupported)?),
+            // A matrix, laid out as one vector per column
+            None => {
+                let columns = array_len(&array.len).ok_or_else(unsupported)?;
+                let (format, size, inner) = format(&array.elem)?;
+                if inner != 1 {
+                    return Err(unsupported());
+                }
+                return Ok((format, size, columns));
+            }
+        },
+        _ => return Err(unsupported()),
+    };
+
+    let format = match len {
+        1 => scalar.to_string(),
+        2..=4 => format!("{}x{}", scalar, len),
+        _ => return Err(unsupported()),
+    };
+
+    Ok((Ident::new(&format, ty.span()), 4 * len, 1))
+}
+
+fn scalar(ty: &Type) -> Option<&'static str> {
+    match ty {
+        Type::Path(path) if path.path.is_ident("f32") => Some("Float32"),
+        Type::Path(path) if path.path.is_ident("u32") => Some("Uint32"),
+        Type::Path(path) if path.path.is_ident("i32") => Some("Sint32"),
+        _ => None,
+    }
+}
+
+fn array_len(len: &Expr) -> Option<usize> {
+    match len {
+        Expr::Lit(syn::ExprLit {
+            lit: Lit::Int(len), ..
+        }) => len.base10_parse().ok(),
+        _ => None,
+    }
+}